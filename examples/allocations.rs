@@ -0,0 +1,90 @@
+//! Counts the allocations made to parse, serialize and clone a typical account document.
+//!
+//! ```text
+//! cargo run --release --example allocations
+//! ```
+//!
+//! It is the measurement behind keeping `String` fields in the data model: serialization borrows
+//! the strings of the document, and only allocates to grow its output buffer, while parsing and
+//! cloning allocate for each string. Small-string types would only help the latter, and only
+//! for the strings that are not already stored without allocating, like well-known `rel`s.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use webfinger::Webfinger;
+
+/// The number of times each operation is repeated.
+const ROUNDS: usize = 10_000;
+
+/// A typical document, as served by Mastodon for an account.
+const DOCUMENT: &str = r#"{
+    "subject": "acct:alice@example.org",
+    "aliases": ["https://example.org/@alice", "https://example.org/users/alice"],
+    "links": [
+        {"rel": "http://webfinger.net/rel/profile-page", "type": "text/html", "href": "https://example.org/@alice"},
+        {"rel": "self", "type": "application/activity+json", "href": "https://example.org/users/alice"},
+        {"rel": "http://ostatus.org/schema/1.0/subscribe", "template": "https://example.org/authorize_interaction?uri={uri}"},
+        {"rel": "http://webfinger.net/rel/avatar", "type": "image/png", "href": "https://example.org/avatars/alice.png"}
+    ]
+}"#;
+
+/// The system allocator, counting the allocations it makes.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Returns the average number of allocations made by `operation`.
+fn count(mut operation: impl FnMut()) -> f64 {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..ROUNDS {
+        operation();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / ROUNDS as f64
+}
+
+fn main() {
+    let document: Webfinger = serde_json::from_str(DOCUMENT).unwrap();
+    let mut buffer = Vec::new();
+    serde_json::to_writer(&mut buffer, &document).unwrap();
+
+    let parse = count(|| {
+        serde_json::from_str::<Webfinger>(DOCUMENT).unwrap();
+    });
+    let serialize = count(|| {
+        serde_json::to_string(&document).unwrap();
+    });
+    let serialize_reused = count(|| {
+        buffer.clear();
+        serde_json::to_writer(&mut buffer, &document).unwrap();
+    });
+    let clone = count(|| {
+        let _ = document.clone();
+    });
+
+    println!("allocations per document ({} rounds)", ROUNDS);
+    println!("parse\t{:.1}", parse);
+    println!("serialize\t{:.1}", serialize);
+    println!("serialize into a reused buffer\t{:.1}", serialize_reused);
+    println!("clone\t{:.1}", clone);
+}
@@ -0,0 +1,132 @@
+//! Resolves a known account on many live instances, and prints how each of them answered.
+//!
+//! ```text
+//! cargo run --example interop -- [--concurrency N] [ACCOUNT...]
+//! ```
+//!
+//! Accounts are given as `user@domain`. A bare domain stands for `domain@domain`, the instance
+//! actor of Mastodon and of many other servers. When no account is given, they are read from the
+//! standard input, one per line; empty lines and lines starting with `#` are ignored.
+//!
+//! The output is a tab-separated matrix, with one line per account: the HTTP status, the
+//! `Content-Type` of the response, the problems that were tolerated while parsing it, and the
+//! result. Run it before and after a change to see which servers it affects.
+
+use futures_util::stream::{self, StreamExt};
+use std::io::{self, BufRead};
+use webfinger::{ParseWarning, RequestOptions, WebfingerClient, WebfingerError};
+
+/// The number of lookups running at the same time, when not given with `--concurrency`.
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// One line of the matrix.
+struct Row {
+    account: String,
+    status: Option<u16>,
+    content_type: Option<String>,
+    warnings: Vec<String>,
+    result: String,
+}
+
+fn describe(warning: &ParseWarning) -> String {
+    match warning {
+        ParseWarning::UnwrappedArray => "array".to_string(),
+        ParseWarning::UnwrappedObject(key) => format!("wrapped in {}", key),
+        ParseWarning::DroppedLink(issue) => format!("link {} dropped", issue.index),
+        ParseWarning::NotAcceptable => "406 with default Accept".to_string(),
+        ParseWarning::MissingSubject => "no subject".to_string(),
+        ParseWarning::ByteOrderMark => "byte order mark".to_string(),
+        ParseWarning::UnexpectedContentType(content_type) => format!("served as {}", content_type),
+        other => format!("{:?}", other),
+    }
+}
+
+async fn check(client: &WebfingerClient, account: String) -> Row {
+    let acct = if account.contains('@') {
+        account.clone()
+    } else {
+        format!("{}@{}", account, account)
+    };
+    match client.resolve_detailed(acct, &RequestOptions::new()).await {
+        Ok(resolved) => Row {
+            account,
+            status: Some(resolved.metadata.status),
+            content_type: resolved.metadata.content_type,
+            warnings: resolved.metadata.warnings.iter().map(describe).collect(),
+            result: format!("ok ({} links)", resolved.document.links.len()),
+        },
+        Err(error) => Row {
+            account,
+            status: match error {
+                WebfingerError::HttpStatus(status) => Some(status),
+                WebfingerError::NotFound => Some(404),
+                _ => None,
+            },
+            content_type: match error {
+                WebfingerError::JsonError(ref snippet) => snippet.content_type.clone(),
+                _ => None,
+            },
+            warnings: vec![],
+            result: error.to_string(),
+        },
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: interop [--concurrency N] [ACCOUNT...]");
+    std::process::exit(2);
+}
+
+#[tokio::main]
+async fn main() {
+    let mut concurrency = DEFAULT_CONCURRENCY;
+    let mut accounts = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--concurrency" => {
+                concurrency = match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) if n > 0 => n,
+                    _ => usage(),
+                }
+            }
+            "-h" | "--help" => usage(),
+            _ => accounts.push(arg),
+        }
+    }
+    if accounts.is_empty() {
+        accounts = io::stdin()
+            .lock()
+            .lines()
+            .map_while(Result::ok)
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+    }
+
+    let client = WebfingerClient::new();
+    let mut rows: Vec<Row> = stream::iter(accounts)
+        .map(|account| check(&client, account))
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    rows.sort_by(|a, b| a.account.cmp(&b.account));
+
+    println!("account\tstatus\tcontent-type\twarnings\tresult");
+    for row in rows {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            row.account,
+            row.status
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| "-".into()),
+            row.content_type.as_deref().unwrap_or("-"),
+            if row.warnings.is_empty() {
+                "-".to_string()
+            } else {
+                row.warnings.join(", ")
+            },
+            row.result,
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{parse_resource, Prefix, Resource, Webfinger};
+use url::{ParseError, Url};
+
+/// Why an alias was refused by [`Webfinger::valid_aliases`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AliasProblem {
+    /// The alias is a relative URL, that can't be used without knowing where it comes from.
+    Relative,
+
+    /// The alias is neither an absolute URL with a host, nor an `acct:` URI.
+    Invalid,
+}
+
+/// An alias refused by [`Webfinger::valid_aliases`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AliasWarning {
+    /// The alias, as it was in the document.
+    pub alias: String,
+
+    /// Why it was refused.
+    pub problem: AliasProblem,
+}
+
+impl Webfinger {
+    /// Returns the aliases that are absolute URLs or `acct:` URIs, in a normalized form.
+    ///
+    /// URLs are normalized by the `url` crate (lowercase scheme and host, default port removed,
+    /// etc), and the prefix and domain of `acct:` URIs are lowercased. The other aliases are
+    /// returned as warnings, so that they can be reported instead of being stored blindly.
+    pub fn valid_aliases(&self) -> (Vec<String>, Vec<AliasWarning>) {
+        let mut valid = vec![];
+        let mut warnings = vec![];
+        for alias in &self.aliases {
+            match normalize_alias(alias) {
+                Ok(normalized) => valid.push(normalized),
+                Err(problem) => warnings.push(AliasWarning {
+                    alias: alias.clone(),
+                    problem,
+                }),
+            }
+        }
+        (valid, warnings)
+    }
+
+    /// Returns a handle to display for this resource, like `someone@example.org`.
+    ///
+    /// It is taken from the subject if it is an `acct:` URI, or from the first `acct:` alias
+    /// otherwise. Identifiers without a prefix (`someone@example.org` or `@someone@example.org`)
+    /// are accepted too, since some servers use them. URLs and identifiers that are not valid
+    /// (see [`Resource::parse`]) are skipped. The domain is lowercased.
+    pub fn handle(&self) -> Option<String> {
+        std::iter::once(&self.subject)
+            .chain(&self.aliases)
+            .find_map(|id| handle_of(id))
+    }
+}
+
+fn handle_of(id: &str) -> Option<String> {
+    let id = id.trim();
+    let acct = match id.split_once(':') {
+        Some((prefix, acct)) if !prefix.contains('@') => {
+            if !prefix.eq_ignore_ascii_case("acct") {
+                return None;
+            }
+            acct
+        }
+        // No prefix, or a port after the domain
+        _ => id.strip_prefix('@').unwrap_or(id),
+    };
+    let mut resource = Resource::parse(&format!("acct:{}", acct)).ok()?;
+    resource.domain = resource.domain.to_lowercase();
+    Some(format!("{}@{}", resource.user, resource.host()))
+}
+
+fn normalize_alias(alias: &str) -> Result<String, AliasProblem> {
+    if let Ok(mut resource) = parse_resource(alias) {
+        if resource.prefix == Prefix::Acct {
+            if resource.user.is_empty() || resource.domain.is_empty() {
+                return Err(AliasProblem::Invalid);
+            }
+            resource.domain = resource.domain.to_lowercase();
+            return Ok(format!("acct:{}@{}", resource.user, resource.host()));
+        }
+    }
+
+    match Url::parse(alias) {
+        Ok(url) if url.has_host() => Ok(url.into()),
+        Ok(_) => Err(AliasProblem::Invalid),
+        Err(ParseError::RelativeUrlWithoutBase) => Err(AliasProblem::Relative),
+        Err(_) => Err(AliasProblem::Invalid),
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    parse_target, AsyncResolver, Caller, FilteredWebfinger, InstanceDomain, Prefix, ResolverError,
+    Target, Webfinger,
+};
+use std::{collections::HashMap, future::Future, marker::PhantomData};
+use url::Url;
+
+/// A variant of [`AsyncResolver`] borrowing its resource repository.
+///
+/// [`AsyncResolver`] takes its repository by value, which often means cloning a connection pool
+/// or an `Arc` for every request. This trait takes a reference instead, so that the application
+/// state can be shared cheaply.
+///
+/// Existing [`AsyncResolver`]s can be used where an `AsyncRefResolver` is expected with the
+/// [`CloneRepo`] wrapper, so that they can be migrated one at a time. Like the ones of
+/// [`AsyncResolver`], the methods of this trait return futures that are `Send`, and can be
+/// implemented with `async fn`.
+pub trait AsyncRefResolver: Sync {
+    /// The resource repository, that is borrowed for every request.
+    type Repo: Sync + ?Sized;
+
+    /// Returns the domain name of the current instance.
+    ///
+    /// It should include the port if it is not the default one. Resources on other domains are
+    /// refused with [`ResolverError::WrongDomain`].
+    fn instance_domain(&self) -> impl Future<Output = InstanceDomain> + Send;
+
+    /// Tells if the resources on `domain` are served by this resolver.
+    ///
+    /// By default, only the [`instance_domain`](AsyncRefResolver::instance_domain) is accepted.
+    /// See [`Resolver::accepts_domain`](crate::Resolver::accepts_domain).
+    fn accepts_domain(&self, domain: &InstanceDomain) -> impl Future<Output = bool> + Send {
+        async move { *domain == self.instance_domain().await }
+    }
+
+    /// Tries to find a resource, `acct`, in the repository `resource_repo`.
+    ///
+    /// `acct` is not a complete `acct:` URI, it only contains the identifier of the requested resource
+    /// (e.g. `test` for `acct:test@example.org`)
+    ///
+    /// If the resource couldn't be found, you may probably want to return a [`ResolverError::NotFound`].
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: &Self::Repo,
+    ) -> impl Future<Output = Result<Webfinger, ResolverError>> + Send;
+
+    /// Tries to find a resource for a given caller.
+    ///
+    /// It allows to give more information (like private links) to some callers. By default, the
+    /// caller is ignored and [`find`](AsyncRefResolver::find) is used.
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        _caller: Option<&Caller>,
+        resource_repo: &Self::Repo,
+    ) -> impl Future<Output = Result<Webfinger, ResolverError>> + Send {
+        self.find(prefix, acct, resource_repo)
+    }
+
+    /// Tries to find a resource from its URL, like `https://example.org/@someone`.
+    ///
+    /// By default, all these resources are reported as [`ResolverError::NotFound`]. See
+    /// [`Resolver::find_by_url`](crate::Resolver::find_by_url).
+    fn find_by_url(
+        &self,
+        _url: &Url,
+        _resource_repo: &Self::Repo,
+    ) -> impl Future<Output = Result<Webfinger, ResolverError>> + Send {
+        async { Err(ResolverError::NotFound) }
+    }
+
+    /// Tells if [`endpoint_with_rels`](AsyncRefResolver::endpoint_with_rels) removes the links whose
+    /// `rel` was not requested. See [`Resolver::filter_by_rels`](crate::Resolver::filter_by_rels).
+    fn filter_by_rels(&self) -> bool {
+        true
+    }
+
+    /// Returns a WebFinger result for a requested resource.
+    fn endpoint<R: Into<String> + Send>(
+        &self,
+        resource: R,
+        resource_repo: &Self::Repo,
+    ) -> impl Future<Output = Result<Webfinger, ResolverError>> + Send {
+        self.endpoint_for_caller(resource, None, resource_repo)
+    }
+
+    /// Returns a WebFinger result for a requested resource, keeping only the links with one of the
+    /// requested `rels`.
+    ///
+    /// All the links are kept if [`filter_by_rels`](AsyncRefResolver::filter_by_rels) is `false`.
+    fn endpoint_with_rels<R: Into<String> + Send>(
+        &self,
+        resource: R,
+        rels: &[&str],
+        resource_repo: &Self::Repo,
+    ) -> impl Future<Output = Result<FilteredWebfinger, ResolverError>> + Send {
+        let rels = if self.filter_by_rels() { rels } else { &[] };
+        async move {
+            self.endpoint(resource, resource_repo)
+                .await
+                .map(|full| FilteredWebfinger::new(full, rels))
+        }
+    }
+
+    /// Resolves many resources at once, keeping only the links with one of the requested `rels`.
+    ///
+    /// Resources are resolved one after the other, with the same repository. See
+    /// [`AsyncResolver::endpoint_batch`].
+    fn endpoint_batch(
+        &self,
+        resources: &[&str],
+        rels: &[&str],
+        resource_repo: &Self::Repo,
+    ) -> impl Future<Output = HashMap<String, Result<Webfinger, ResolverError>>> + Send {
+        async move {
+            let mut results = HashMap::with_capacity(resources.len());
+            for resource in resources {
+                let result = self
+                    .endpoint_with_rels(*resource, rels, resource_repo)
+                    .await
+                    .map(|result| result.filtered);
+                results.insert(resource.to_string(), result);
+            }
+            results
+        }
+    }
+
+    /// Returns a WebFinger result for a request made by `caller`.
+    ///
+    /// `caller` is `None` for anonymous requests.
+    fn endpoint_for_caller<R: Into<String> + Send>(
+        &self,
+        resource: R,
+        caller: Option<&Caller>,
+        resource_repo: &Self::Repo,
+    ) -> impl Future<Output = Result<Webfinger, ResolverError>> + Send {
+        async move {
+            let (target, host) = parse_target(&resource.into())?;
+            if !self.accepts_domain(&host).await {
+                return Err(ResolverError::WrongDomain);
+            }
+            match target {
+                Target::Url(url) => self.find_by_url(&url, resource_repo).await,
+                Target::Resource(resource) => {
+                    self.find_for_caller(resource.prefix, resource.user, caller, resource_repo)
+                        .await
+                }
+            }
+        }
+    }
+}
+
+/// A wrapper allowing to use an [`AsyncResolver`] as an [`AsyncRefResolver`].
+///
+/// The borrowed repository is cloned for each call to the wrapped resolver. `R` is the repository
+/// of the wrapped resolver, and the [`Repo`](AsyncRefResolver::Repo) of this one.
+pub struct CloneRepo<T, R> {
+    inner: T,
+    repo: PhantomData<fn(R)>,
+}
+
+impl<T, R> CloneRepo<T, R> {
+    /// Wraps a resolver taking its repository by value.
+    pub fn new(inner: T) -> CloneRepo<T, R> {
+        CloneRepo {
+            inner,
+            repo: PhantomData,
+        }
+    }
+
+    /// Returns the wrapped resolver.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Clone, R> Clone for CloneRepo<T, R> {
+    fn clone(&self) -> Self {
+        CloneRepo::new(self.inner.clone())
+    }
+}
+
+impl<T: std::fmt::Debug, R> std::fmt::Debug for CloneRepo<T, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CloneRepo")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<T, R> AsyncRefResolver for CloneRepo<T, R>
+where
+    T: AsyncResolver<R> + Sync,
+    R: Clone + Send + Sync,
+{
+    type Repo = R;
+
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain().await
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain).await
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: &R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner.find(prefix, acct, resource_repo.clone()).await
+    }
+
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: &R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find_for_caller(prefix, acct, caller, resource_repo.clone())
+            .await
+    }
+
+    async fn find_by_url(&self, url: &Url, resource_repo: &R) -> Result<Webfinger, ResolverError> {
+        self.inner.find_by_url(url, resource_repo.clone()).await
+    }
+}
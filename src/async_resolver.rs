@@ -1,15 +1,36 @@
-use crate::{Prefix, ResolverError, Webfinger};
-use async_trait::async_trait;
+use crate::{
+    parse_target, Caller, FilteredWebfinger, InstanceDomain, Prefix, ResolverError, Target,
+    Webfinger,
+};
+use std::{collections::HashMap, future::Future};
+use url::Url;
 
 /// A trait to easily generate a WebFinger endpoint for any resource repository.
 ///
 /// The `R` type is your resource repository (a database for instance) that will be passed to the
-/// [`find`](Resolver::find) and [`endpoint`](Resolver::endpoint) functions.
-#[async_trait]
-pub trait AsyncResolver {
-    type Repo: Send;
+/// [`find`](AsyncResolver::find) and [`endpoint`](AsyncResolver::endpoint) functions. Like
+/// [`Resolver`](crate::Resolver), a type can implement this trait for several repositories, and
+/// the repository can be borrowed, like `&'a Database`.
+///
+/// The methods of this trait return futures that are `Send`, so that endpoints can be served by
+/// multi-threaded runtimes. They can be implemented with `async fn`, as long as the futures are
+/// `Send`: this is why the repository must be `Send`, and the resolver `Sync`. Implement
+/// [`LocalAsyncResolver`](crate::LocalAsyncResolver) instead for single-threaded runtimes, that
+/// don't need these bounds.
+pub trait AsyncResolver<R: Send>: Sync {
     /// Returns the domain name of the current instance.
-    async fn instance_domain<'a>(&self) -> &'a str;
+    ///
+    /// It should include the port if it is not the default one. Resources on other domains are
+    /// refused with [`ResolverError::WrongDomain`].
+    fn instance_domain(&self) -> impl Future<Output = InstanceDomain> + Send;
+
+    /// Tells if the resources on `domain` are served by this resolver.
+    ///
+    /// By default, only the [`instance_domain`](AsyncResolver::instance_domain) is accepted. See
+    /// [`Resolver::accepts_domain`](crate::Resolver::accepts_domain).
+    fn accepts_domain(&self, domain: &InstanceDomain) -> impl Future<Output = bool> + Send {
+        async move { *domain == self.instance_domain().await }
+    }
 
     /// Tries to find a resource, `acct`, in the repository `resource_repo`.
     ///
@@ -17,31 +38,125 @@ pub trait AsyncResolver {
     /// (e.g. `test` for `acct:test@example.org`)
     ///
     /// If the resource couldn't be found, you may probably want to return a [`ResolverError::NotFound`].
-    async fn find(
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> impl Future<Output = Result<Webfinger, ResolverError>> + Send;
+
+    /// Tries to find a resource for a given caller.
+    ///
+    /// It allows to give more information (like private links) to some callers. By default, the
+    /// caller is ignored and [`find`](AsyncResolver::find) is used.
+    fn find_for_caller(
         &self,
         prefix: Prefix,
         acct: String,
-        resource_repo: Self::Repo,
-    ) -> Result<Webfinger, ResolverError>;
+        _caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> impl Future<Output = Result<Webfinger, ResolverError>> + Send {
+        self.find(prefix, acct, resource_repo)
+    }
+
+    /// Tries to find a resource from its URL, like `https://example.org/@someone`.
+    ///
+    /// It is used when the requested resource is an `https:` URL on the current instance, to
+    /// answer requests for the profile page of an account, as Mastodon does. By default, all
+    /// these resources are reported as [`ResolverError::NotFound`].
+    fn find_by_url(
+        &self,
+        _url: &Url,
+        _resource_repo: R,
+    ) -> impl Future<Output = Result<Webfinger, ResolverError>> + Send {
+        async { Err(ResolverError::NotFound) }
+    }
+
+    /// Tells if [`endpoint_with_rels`](AsyncResolver::endpoint_with_rels) removes the links whose
+    /// `rel` was not requested. See [`Resolver::filter_by_rels`](crate::Resolver::filter_by_rels).
+    fn filter_by_rels(&self) -> bool {
+        true
+    }
 
     /// Returns a WebFinger result for a requested resource.
-    async fn endpoint<R: Into<String> + Send>(
+    fn endpoint<S: Into<String> + Send>(
+        &self,
+        resource: S,
+        resource_repo: R,
+    ) -> impl Future<Output = Result<Webfinger, ResolverError>> + Send {
+        self.endpoint_for_caller(resource, None, resource_repo)
+    }
+
+    /// Returns a WebFinger result for a requested resource, keeping only the links with one of the
+    /// requested `rels`.
+    ///
+    /// The complete document is returned too, without having to call
+    /// [`find`](AsyncResolver::find) twice. All the links are kept if
+    /// [`filter_by_rels`](AsyncResolver::filter_by_rels) is `false`.
+    fn endpoint_with_rels<S: Into<String> + Send>(
+        &self,
+        resource: S,
+        rels: &[&str],
+        resource_repo: R,
+    ) -> impl Future<Output = Result<FilteredWebfinger, ResolverError>> + Send {
+        let rels = if self.filter_by_rels() { rels } else { &[] };
+        async move {
+            self.endpoint(resource, resource_repo)
+                .await
+                .map(|full| FilteredWebfinger::new(full, rels))
+        }
+    }
+
+    /// Resolves many resources at once, keeping only the links with one of the requested `rels`.
+    ///
+    /// This is not part of the WebFinger protocol, but it is useful for tools that need to check
+    /// many local accounts, like administration commands or migration scripts. Resources are
+    /// resolved one after the other, and the results are indexed by resource as given in
+    /// `resources`.
+    fn endpoint_batch(
+        &self,
+        resources: &[&str],
+        rels: &[&str],
+        resource_repo: R,
+    ) -> impl Future<Output = HashMap<String, Result<Webfinger, ResolverError>>> + Send
+    where
+        R: Clone,
+    {
+        async move {
+            let mut results = HashMap::with_capacity(resources.len());
+            for resource in resources {
+                let repo = resource_repo.clone();
+                let result = self
+                    .endpoint_with_rels(*resource, rels, repo)
+                    .await
+                    .map(|result| result.filtered);
+                results.insert(resource.to_string(), result);
+            }
+            results
+        }
+    }
+
+    /// Returns a WebFinger result for a request made by `caller`.
+    ///
+    /// `caller` is `None` for anonymous requests.
+    fn endpoint_for_caller<S: Into<String> + Send>(
         &self,
-        resource: R,
-        resource_repo: Self::Repo,
-    ) -> Result<Webfinger, ResolverError> {
-        let resource = resource.into();
-        let mut parsed_query = resource.splitn(2, ':');
-        let res_prefix = Prefix::from(parsed_query.next().ok_or(ResolverError::InvalidResource)?);
-        let res = parsed_query.next().ok_or(ResolverError::InvalidResource)?;
-
-        let mut parsed_res = res.splitn(2, '@');
-        let user = parsed_res.next().ok_or(ResolverError::InvalidResource)?;
-        let domain = parsed_res.next().ok_or(ResolverError::InvalidResource)?;
-        if domain == self.instance_domain().await {
-            self.find(res_prefix, user.to_string(), resource_repo).await
-        } else {
-            Err(ResolverError::WrongDomain)
+        resource: S,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> impl Future<Output = Result<Webfinger, ResolverError>> + Send {
+        async move {
+            let (target, host) = parse_target(&resource.into())?;
+            if !self.accepts_domain(&host).await {
+                return Err(ResolverError::WrongDomain);
+            }
+            match target {
+                Target::Url(url) => self.find_by_url(&url, resource_repo).await,
+                Target::Resource(resource) => {
+                    self.find_for_caller(resource.prefix, resource.user, caller, resource_repo)
+                        .await
+                }
+            }
         }
     }
 }
@@ -0,0 +1,88 @@
+use crate::consts;
+use url::{ParseError, Url};
+
+/// The public URL of an instance, that may be served under a sub-path by a reverse proxy.
+///
+/// It should be used to generate all the URLs a WebFinger server publishes, so that they stay
+/// consistent when the application is mounted under `/app` for instance.
+///
+/// Note that clients always look for WebFinger at the root of the domain: the reverse proxy should
+/// forward `/.well-known/webfinger` to [`BaseUrl::endpoint_path`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaseUrl {
+    url: Url,
+}
+
+impl BaseUrl {
+    /// Parses a base URL, like `https://example.org/app`.
+    ///
+    /// Any query string or fragment is ignored.
+    pub fn parse(base: &str) -> Result<BaseUrl, ParseError> {
+        let mut url = Url::parse(base)?;
+        if url.cannot_be_a_base() || url.host_str().is_none() {
+            return Err(ParseError::RelativeUrlWithCannotBeABaseBase);
+        }
+        url.set_query(None);
+        url.set_fragment(None);
+        if !url.path().ends_with('/') {
+            let path = format!("{}/", url.path());
+            url.set_path(&path);
+        }
+        Ok(BaseUrl { url })
+    }
+
+    /// The domain of the instance, including the port if it is not the default one.
+    ///
+    /// This is what [`Resolver::instance_domain`](crate::Resolver::instance_domain) should return,
+    /// once converted with [`InstanceDomain::new`](crate::InstanceDomain::new).
+    pub fn domain(&self) -> String {
+        let host = self.url.host_str().unwrap_or_default();
+        match self.url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }
+    }
+
+    /// The path under which the application is mounted, always ending with a `/`.
+    pub fn path_prefix(&self) -> &str {
+        self.url.path()
+    }
+
+    /// Builds an absolute URL for a path of the application.
+    ///
+    /// `path` is relative to the base URL, even if it starts with a `/`.
+    pub fn url_for(&self, path: &str) -> String {
+        format!("{}{}", self.url, path.trim_start_matches('/'))
+    }
+
+    /// The path of the WebFinger endpoint, as seen by the application behind the proxy.
+    pub fn endpoint_path(&self) -> String {
+        format!(
+            "{}{}",
+            self.path_prefix(),
+            consts::WELL_KNOWN_PATH.trim_start_matches('/')
+        )
+    }
+
+    /// The public URL of the WebFinger endpoint, at the root of the domain.
+    pub fn endpoint_url(&self) -> String {
+        format!(
+            "{}://{}{}",
+            self.url.scheme(),
+            self.domain(),
+            consts::WELL_KNOWN_PATH
+        )
+    }
+
+    /// Generates a `/.well-known/host-meta` document pointing to the WebFinger endpoint.
+    pub fn host_meta(&self) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <XRD xmlns=\"http://docs.oasis-open.org/ns/xri/xrd-1.0\">\n  \
+             <Link rel=\"lrdd\" type=\"{}\" template=\"{}?resource={{uri}}\"/>\n\
+             </XRD>\n",
+            consts::JRD_MIME,
+            self.endpoint_url()
+        )
+    }
+}
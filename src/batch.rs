@@ -0,0 +1,86 @@
+use crate::{split_prefix, Webfinger, WebfingerClient, WebfingerError};
+use futures_util::stream::{self, StreamExt};
+use std::collections::{HashMap, VecDeque};
+
+/// The order in which [`WebfingerClient::resolve_many`] starts its lookups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scheduling {
+    /// Lookups are started in the order they were given.
+    InOrder,
+
+    /// Lookups are interleaved by host: one for each host in turn, in the order in which the
+    /// hosts first appear.
+    ///
+    /// It avoids a host with many resources (or a slow one) using all the concurrency budget
+    /// while the lookups for the other hosts wait.
+    #[default]
+    RoundRobin,
+}
+
+impl Scheduling {
+    /// Returns the indices of `accts` in the order in which they should be looked up.
+    fn order(self, accts: &[String]) -> Vec<usize> {
+        match self {
+            Scheduling::InOrder => (0..accts.len()).collect(),
+            Scheduling::RoundRobin => {
+                let mut hosts = vec![];
+                let mut queues: HashMap<String, VecDeque<usize>> = HashMap::new();
+                for (i, acct) in accts.iter().enumerate() {
+                    let host = host_of(acct);
+                    if !queues.contains_key(&host) {
+                        hosts.push(host.clone());
+                    }
+                    queues.entry(host).or_default().push_back(i);
+                }
+
+                let mut order = Vec::with_capacity(accts.len());
+                while order.len() < accts.len() {
+                    for host in &hosts {
+                        if let Some(i) = queues.get_mut(host).and_then(VecDeque::pop_front) {
+                            order.push(i);
+                        }
+                    }
+                }
+                order
+            }
+        }
+    }
+}
+
+/// Returns the lowercased host of a resource, or an empty string if it is invalid.
+fn host_of(acct: &str) -> String {
+    split_prefix(acct)
+        .ok()
+        .and_then(|(_, acct)| acct.split_once('@'))
+        .map(|(_, host)| host.to_lowercase())
+        .unwrap_or_default()
+}
+
+impl WebfingerClient {
+    /// Fetches many resources, running at most `concurrency` lookups at the same time.
+    ///
+    /// The lookups are started in the order given by `scheduling`, but the results are returned
+    /// in the order of `accts`, each with its resource. The other settings of the client (cache,
+    /// rate limits, etc) apply to every lookup.
+    pub async fn resolve_many<S: Into<String>>(
+        &self,
+        accts: impl IntoIterator<Item = S>,
+        concurrency: usize,
+        scheduling: Scheduling,
+    ) -> Vec<(String, Result<Webfinger, WebfingerError>)> {
+        let accts: Vec<String> = accts.into_iter().map(Into::into).collect();
+        let mut results: Vec<_> = stream::iter(scheduling.order(&accts))
+            .map(|i| {
+                let acct = accts[i].clone();
+                async move { (i, self.resolve(acct).await) }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+        results.sort_by_key(|(i, _)| *i);
+        results
+            .into_iter()
+            .map(|(i, result)| (accts[i].clone(), result))
+            .collect()
+    }
+}
@@ -0,0 +1,129 @@
+//! A blocking HTTP client based on `ureq`, for applications that don't need an async runtime.
+
+use crate::{
+    resolve_with, AddressFamily, HttpFetch, HttpRequest, HttpResponse, RequestOptions, Timings,
+    TransportSettings, Webfinger, WebfingerError, MAX_BODY_SIZE,
+};
+use futures_util::{
+    future::{BoxFuture, FutureExt},
+    task::noop_waker_ref,
+};
+use std::{
+    future::Future,
+    io::{self, Read},
+    net::{SocketAddr, ToSocketAddrs},
+    pin::pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+/// Note that `ureq` is blocking: the returned future blocks the current thread when it is polled.
+impl HttpFetch for ureq::Agent {
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        async move {
+            let start = Instant::now();
+            let mut ureq_request = self.get(&request.url);
+            for (name, value) in &request.headers {
+                ureq_request = ureq_request.set(name, value);
+            }
+
+            let response = match ureq_request.call() {
+                Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+                Err(ureq::Error::Transport(_)) => return Err(WebfingerError::HttpError),
+            };
+            let ttfb = start.elapsed();
+            let status = response.status();
+            let headers = response
+                .headers_names()
+                .into_iter()
+                .filter_map(|name| {
+                    let value = response.header(&name)?.to_string();
+                    Some((name, value))
+                })
+                .collect();
+            let mut body = Vec::new();
+            // One more byte is read to tell a body of the maximum size from a larger one
+            response
+                .into_reader()
+                .take(MAX_BODY_SIZE as u64 + 1)
+                .read_to_end(&mut body)
+                .map_err(|_| WebfingerError::HttpError)?;
+            if body.len() > MAX_BODY_SIZE {
+                return Err(WebfingerError::HttpError);
+            }
+            Ok(HttpResponse {
+                status,
+                headers,
+                body,
+                timings: Timings {
+                    ttfb: Some(ttfb),
+                    ..Timings::default()
+                },
+            })
+        }
+        .boxed()
+    }
+}
+
+/// Creates a `ureq` agent with the given connection settings.
+pub(crate) fn ureq_agent(settings: &TransportSettings) -> Result<ureq::Agent, WebfingerError> {
+    let address_family = settings.address_family;
+    let connect_to = settings.connect_to.clone();
+    let mut builder = ureq::AgentBuilder::new().resolver(move |addr: &str| {
+        if let Some((host, port)) = addr.rsplit_once(':') {
+            if let (Some(ip), Ok(port)) = (connect_to.get(host), port.parse()) {
+                return Ok(vec![SocketAddr::new(*ip, port)]);
+            }
+        }
+        let addresses = addr.to_socket_addrs()?;
+        Ok::<Vec<SocketAddr>, io::Error>(
+            addresses
+                .filter(|a| match address_family {
+                    AddressFamily::Any => true,
+                    AddressFamily::Ipv4Only => a.is_ipv4(),
+                    AddressFamily::Ipv6Only => a.is_ipv6(),
+                })
+                .collect(),
+        )
+    });
+    if let Some(timeout) = settings.connect_timeout {
+        builder = builder.timeout_connect(timeout);
+    }
+    if let Some(timeout) = settings.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(ref proxy) = settings.proxy {
+        let proxy = ureq::Proxy::new(proxy).map_err(|_| WebfingerError::ParseError)?;
+        builder = builder.proxy(proxy);
+    }
+    Ok(builder.build())
+}
+
+/// Runs a future that never waits for anything, like the ones of the `ureq` client.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(noop_waker_ref());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        std::thread::yield_now();
+    }
+}
+
+/// Fetches a WebFinger resource, blocking the current thread.
+///
+/// If the resource doesn't have a prefix, `acct:` will be used. It uses `ureq`, so it doesn't need
+/// any async runtime.
+pub fn resolve_blocking(
+    acct: impl Into<String>,
+    with_https: bool,
+) -> Result<Webfinger, WebfingerError> {
+    let agent = ureq_agent(&TransportSettings::default())?;
+    block_on(resolve_with(
+        &agent,
+        acct,
+        with_https,
+        &RequestOptions::default(),
+    ))
+}
@@ -0,0 +1,464 @@
+use crate::{Freshness, RequestOptions, Resolved, WebfingerError};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use url::{form_urlencoded, Position, Url};
+
+/// Identifies a request: its URL, and its options without the request ID.
+pub(crate) type RequestKey = (String, RequestOptions);
+
+/// A cached result, with its expiration date.
+type CacheEntry = (Expiry, Result<Resolved, WebfingerError>);
+
+/// When a cached result expires.
+///
+/// A TTL too large to be added to the current time, like `Duration::MAX` used to mean "forever",
+/// never expires. These entries are ordered after all the other ones, so they are evicted last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Expiry {
+    At(Instant),
+    Never,
+}
+
+impl Expiry {
+    /// Returns when a result stored now for `ttl` expires.
+    fn after(ttl: Duration) -> Expiry {
+        Instant::now()
+            .checked_add(ttl)
+            .map_or(Expiry::Never, Expiry::At)
+    }
+
+    fn has_passed(self) -> bool {
+        match self {
+            Expiry::At(expires) => expires <= Instant::now(),
+            Expiry::Never => false,
+        }
+    }
+}
+
+/// The entries of a [`Cache`], indexed by expiration date so that the ones that expire first can
+/// be evicted without looking at all the other ones.
+#[derive(Default)]
+struct Entries {
+    results: HashMap<RequestKey, (u64, CacheEntry)>,
+    /// The keys of the entries, by expiration date and then by insertion order.
+    by_expiration: BTreeMap<(Expiry, u64), RequestKey>,
+    next_id: u64,
+}
+
+impl fmt::Debug for Entries {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.results.iter().map(|(key, (_, entry))| (key, entry)))
+            .finish()
+    }
+}
+
+impl Entries {
+    fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    fn get(&self, key: &RequestKey) -> Option<&CacheEntry> {
+        self.results.get(key).map(|(_, entry)| entry)
+    }
+
+    fn contains_key(&self, key: &RequestKey) -> bool {
+        self.results.contains_key(key)
+    }
+
+    fn insert(&mut self, key: RequestKey, entry: CacheEntry) {
+        self.remove(&key);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.by_expiration.insert((entry.0, id), key.clone());
+        self.results.insert(key, (id, entry));
+    }
+
+    fn remove(&mut self, key: &RequestKey) -> Option<CacheEntry> {
+        let (id, entry) = self.results.remove(key)?;
+        self.by_expiration.remove(&(entry.0, id));
+        Some(entry)
+    }
+
+    /// Removes the entry that expires first.
+    fn evict(&mut self) -> Option<CacheEntry> {
+        let (_, key) = self.by_expiration.pop_first()?;
+        self.results.remove(&key).map(|(_, entry)| entry)
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&RequestKey) -> bool) {
+        let by_expiration = &mut self.by_expiration;
+        self.results.retain(|key, (id, (expires, _))| {
+            let kept = keep(key);
+            if !kept {
+                by_expiration.remove(&(*expires, *id));
+            }
+            kept
+        });
+    }
+}
+
+/// Hashes the keys of the cache of a [`WebfingerClient`](crate::WebfingerClient), so that it
+/// doesn't contain the requested handles.
+///
+/// Implement it to use the same keys as the other services of a deployment, that may store their
+/// own data in a shared cache like Redis. With the `ring` feature, it is implemented by
+/// [`HmacKeys`].
+pub trait CacheKeyHasher: Send + Sync {
+    /// Hashes the URL of a request.
+    ///
+    /// The result should be made of characters that are valid in a URL path, like hexadecimal
+    /// digits.
+    fn hash(&self, url: &str) -> String;
+}
+
+impl<T: CacheKeyHasher + ?Sized> CacheKeyHasher for Arc<T> {
+    fn hash(&self, url: &str) -> String {
+        (**self).hash(url)
+    }
+}
+
+/// Hashes cache keys with HMAC-SHA256 and a secret key, in hexadecimal.
+///
+/// The same key gives the same hashes: share it between processes that need to find the entries
+/// of each other, and keep it secret so that handles can't be guessed from the hashes.
+#[cfg(feature = "ring")]
+pub struct HmacKeys {
+    key: ring::hmac::Key,
+}
+
+#[cfg(feature = "ring")]
+impl HmacKeys {
+    /// Creates a hasher with a secret key.
+    pub fn new(secret: &[u8]) -> HmacKeys {
+        HmacKeys {
+            key: ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret),
+        }
+    }
+}
+
+#[cfg(feature = "ring")]
+impl fmt::Debug for HmacKeys {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HmacKeys").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "ring")]
+impl CacheKeyHasher for HmacKeys {
+    fn hash(&self, url: &str) -> String {
+        ring::hmac::sign(&self.key, url.as_bytes())
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Stores the failed lookups of a [`WebfingerClient`](crate::WebfingerClient), separately from
+/// the fetched documents.
+///
+/// Missing resources usually need to be remembered longer, and for many more resources, than
+/// documents: a large instance receives activities mentioning accounts that were deleted long ago,
+/// or that never existed. Implement this trait to keep them in a shared store, like Redis, while
+/// documents stay in memory. Use it with
+/// [`WebfingerClientBuilder::negative_store`](crate::WebfingerClientBuilder::negative_store).
+///
+/// Keys are the requested URLs, hashed if
+/// [`hash_cache_keys`](crate::WebfingerClientBuilder::hash_cache_keys) is used. The extra query
+/// parameters and headers of the [`RequestOptions`](crate::RequestOptions) of a request, if any,
+/// follow the URL after a `#`, so that an error for a request with options doesn't answer the same
+/// request without them. They are hashed too with `hash_cache_keys`, which keeps headers that may
+/// be secret out of the store. A store may only keep some errors, like
+/// [`WebfingerError::NotFound`], and ignore the other ones.
+pub trait NegativeStore: Send + Sync {
+    /// Returns the error stored for `key`, if it didn't expire yet.
+    fn get(&self, key: &str) -> Option<WebfingerError>;
+
+    /// Stores `error` for `key` during `ttl`.
+    ///
+    /// `ttl` may be too large to be added to the current time, when the client is configured to
+    /// keep errors forever with `Duration::MAX`.
+    fn insert(&self, key: &str, error: &WebfingerError, ttl: Duration);
+
+    /// Removes the errors whose key matches `predicate`, and returns how many were removed.
+    ///
+    /// It is used by [`WebfingerClient::purge`](crate::WebfingerClient::purge) and
+    /// [`WebfingerClient::purge_host`](crate::WebfingerClient::purge_host).
+    fn remove_where(&self, predicate: &dyn Fn(&str) -> bool) -> usize;
+}
+
+impl<T: NegativeStore + ?Sized> NegativeStore for Arc<T> {
+    fn get(&self, key: &str) -> Option<WebfingerError> {
+        (**self).get(key)
+    }
+
+    fn insert(&self, key: &str, error: &WebfingerError, ttl: Duration) {
+        (**self).insert(key, error, ttl)
+    }
+
+    fn remove_where(&self, predicate: &dyn Fn(&str) -> bool) -> usize {
+        (**self).remove_where(predicate)
+    }
+}
+
+/// Statistics about the cache of a [`WebfingerClient`](crate::WebfingerClient).
+///
+/// They are returned by [`WebfingerClient::cache_stats`](crate::WebfingerClient::cache_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of lookups that were answered from the cache.
+    pub hits: u64,
+
+    /// The number of lookups that were not in the cache, or that expired.
+    pub misses: u64,
+
+    /// The number of entries that were removed to make room for new ones.
+    pub evictions: u64,
+
+    /// The number of entries currently in the cache, including expired ones that can still be
+    /// served stale.
+    pub entries: usize,
+}
+
+/// The results of previous requests of a [`WebfingerClient`](crate::WebfingerClient).
+pub(crate) struct Cache {
+    ttl: Option<Duration>,
+    negative_ttl: Option<Duration>,
+    max_staleness: Option<Duration>,
+    max_entries: Option<usize>,
+    hasher: Option<Arc<dyn CacheKeyHasher>>,
+    negative_store: Option<Arc<dyn NegativeStore>>,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("ttl", &self.ttl)
+            .field("negative_ttl", &self.negative_ttl)
+            .field("max_staleness", &self.max_staleness)
+            .field("max_entries", &self.max_entries)
+            .field("hashed_keys", &self.hasher.is_some())
+            .field("negative_store", &self.negative_store.is_some())
+            .field("entries", &self.entries)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Cache {
+    pub(crate) fn new(
+        ttl: Option<Duration>,
+        negative_ttl: Option<Duration>,
+        max_staleness: Option<Duration>,
+        max_entries: Option<usize>,
+        hasher: Option<Arc<dyn CacheKeyHasher>>,
+        negative_store: Option<Arc<dyn NegativeStore>>,
+    ) -> Cache {
+        Cache {
+            ttl,
+            negative_ttl,
+            max_staleness,
+            max_entries,
+            hasher,
+            negative_store,
+            entries: Mutex::new(Entries::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the URL under which the results for `url` are stored.
+    ///
+    /// When keys are hashed, the scheme and the host are kept, so that the entries of a host can
+    /// still be found.
+    pub(crate) fn stored_url(&self, url: &str) -> String {
+        match self.hasher {
+            Some(ref hasher) => match Url::parse(url) {
+                Ok(parsed) => format!(
+                    "{}://{}/{}",
+                    parsed.scheme(),
+                    &parsed[Position::BeforeHost..Position::AfterPort],
+                    hasher.hash(url)
+                ),
+                Err(_) => hasher.hash(url),
+            },
+            None => url.to_string(),
+        }
+    }
+
+    fn stored_key(&self, key: &RequestKey) -> RequestKey {
+        (self.stored_url(&key.0), key.1.clone())
+    }
+
+    /// Returns the key of a request in the [`NegativeStore`]: its stored URL, followed by its
+    /// options after a `#` if it has some.
+    fn negative_key(&self, key: &RequestKey) -> String {
+        let url = self.stored_url(&key.0);
+        let (query, headers) = (&key.1.query, &key.1.headers);
+        if query.is_empty() && headers.is_empty() {
+            return url;
+        }
+        let mut options = form_urlencoded::Serializer::new(String::new());
+        for (name, value) in query {
+            options.append_pair(&format!("query.{}", name), value);
+        }
+        for (name, value) in headers {
+            options.append_pair(&format!("header.{}", name), value);
+        }
+        let options = options.finish();
+        match self.hasher {
+            Some(ref hasher) => format!("{}#{}", url, hasher.hash(&options)),
+            None => format!("{}#{}", url, options),
+        }
+    }
+
+    /// Returns the cached result of a request, if it didn't expire yet.
+    pub(crate) fn get(&self, key: &RequestKey) -> Option<Result<Resolved, WebfingerError>> {
+        let stored_key = &self.stored_key(key);
+        let mut entries = self.entries.lock().unwrap();
+        let result = match entries.get(stored_key) {
+            Some((expires, result)) if !expires.has_passed() => Some(result.clone()),
+            // Expired documents are kept while they can still be served stale
+            Some((expires, Ok(_))) if self.is_servable(*expires) => None,
+            Some(_) => {
+                entries.remove(stored_key);
+                None
+            }
+            None => None,
+        };
+        drop(entries);
+        let result = result.or_else(|| {
+            self.negative_store
+                .as_ref()
+                .and_then(|store| store.get(&self.negative_key(key)))
+                .map(Err)
+        });
+        let counter = if result.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    /// Returns the cached document of a request, even if it expired, as long as it is not older
+    /// than the maximum staleness.
+    pub(crate) fn get_stale(&self, key: &RequestKey) -> Option<Resolved> {
+        match self.entries.lock().unwrap().get(&self.stored_key(key)) {
+            Some((expires, Ok(resolved))) if self.is_servable(*expires) => {
+                let mut resolved = resolved.clone();
+                resolved.metadata.freshness = Freshness::Stale;
+                Some(resolved)
+            }
+            _ => None,
+        }
+    }
+
+    fn is_servable(&self, expires: Expiry) -> bool {
+        self.max_staleness.is_some_and(|max| match expires {
+            Expiry::At(expires) => expires
+                .checked_add(max)
+                .map_or(true, |limit| limit > Instant::now()),
+            Expiry::Never => true,
+        })
+    }
+
+    /// Stores the result of a request.
+    ///
+    /// Network errors are never stored, as they are usually temporary. The client doesn't store
+    /// rate limiting and retryable HTTP statuses either. Other errors are stored in the
+    /// [`NegativeStore`] if there is one. Documents are stored at most for the configured TTL, and
+    /// less if the `Cache-Control` header of the response, or the `expires` field of the document
+    /// when there is no such header, asks for it.
+    pub(crate) fn insert(&self, key: RequestKey, result: &Result<Resolved, WebfingerError>) {
+        let ttl = match result {
+            Ok(resolved) => self.ttl.and_then(|ttl| document_ttl(resolved, ttl)),
+            Err(WebfingerError::HttpError) => None,
+            Err(_) => self.negative_ttl,
+        };
+        if let Some(ttl) = ttl {
+            if let (Err(error), Some(store)) = (result, &self.negative_store) {
+                store.insert(&self.negative_key(&key), error, ttl);
+                return;
+            }
+            let key = self.stored_key(&key);
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(max) = self.max_entries {
+                // The entries that expire first are evicted first
+                while entries.len() >= max && !entries.contains_key(&key) {
+                    if entries.evict().is_none() {
+                        return;
+                    }
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            entries.insert(key, (Expiry::after(ttl), result.clone()));
+        }
+    }
+
+    /// Removes the entries whose stored URL matches `predicate`, and returns how many were
+    /// removed.
+    ///
+    /// See [`Cache::stored_url`].
+    pub(crate) fn remove_where(&self, predicate: impl Fn(&str) -> bool) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|(url, _)| !predicate(url));
+        let removed = before - entries.len();
+        removed
+            + self.negative_store.as_ref().map_or(0, |store| {
+                // The predicate is given the stored URL, without the options
+                store.remove_where(&|key: &str| predicate(key.split('#').next().unwrap_or(key)))
+            })
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: self.entries.lock().unwrap().len(),
+        }
+    }
+}
+
+/// Returns how long a document can be cached, given the maximum TTL.
+fn document_ttl(resolved: &Resolved, max: Duration) -> Option<Duration> {
+    if let Some(ref cache_control) = resolved.metadata.cache_control {
+        let mut ttl = Some(max);
+        for directive in cache_control.split(',').map(str::trim) {
+            let (name, value) = match directive.find('=') {
+                Some(i) => (&directive[..i], Some(directive[i + 1..].trim_matches('"'))),
+                None => (directive, None),
+            };
+            if name.eq_ignore_ascii_case("no-store") || name.eq_ignore_ascii_case("no-cache") {
+                return None;
+            } else if name.eq_ignore_ascii_case("max-age") {
+                let secs = value.and_then(|v| v.parse().ok())?;
+                ttl = ttl.map(|ttl| ttl.min(Duration::from_secs(secs)));
+            }
+        }
+        return ttl.filter(|ttl| *ttl > Duration::from_secs(0));
+    }
+
+    match resolved.document.expires_at() {
+        Some(expires) => expires
+            .duration_since(SystemTime::now())
+            .ok()
+            .map(|left| left.min(max)),
+        None => Some(max),
+    }
+}
@@ -0,0 +1,195 @@
+#[cfg(feature = "server")]
+use crate::{Caller, InstanceDomain, Prefix, Resolver, ResolverError, Webfinger};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "server")]
+use url::Url;
+
+/// How the user part of resources (`user` in `acct:user@example.org`) is treated.
+///
+/// Some software, like Mastodon, consider that `Alice` and `alice` are the same user, while others
+/// don't. A policy can be given to a client with
+/// [`WebfingerClientBuilder::case_policy`](crate::WebfingerClientBuilder::case_policy), and to a
+/// server with [`ApplyCasePolicy`], so that requested resources, cache keys and subjects all
+/// follow the same rule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CasePolicy {
+    /// The user part is kept as is: `Alice` and `alice` are different users.
+    #[default]
+    Preserve,
+
+    /// ASCII letters are lowercased, other characters are kept as is.
+    Lowercase,
+
+    /// All letters are lowercased, including non-ASCII ones like `É`.
+    Fold,
+}
+
+impl CasePolicy {
+    /// Normalizes the user part of a resource.
+    pub fn normalize_user(&self, user: &str) -> String {
+        match self {
+            CasePolicy::Preserve => user.to_string(),
+            CasePolicy::Lowercase => user.to_ascii_lowercase(),
+            CasePolicy::Fold => user.to_lowercase(),
+        }
+    }
+
+    /// Normalizes the user part of a resource, like `acct:user@example.org` or `user@example.org`.
+    ///
+    /// The prefix and the domain are kept as they are. Resources without a `@`, like URLs, are
+    /// not changed.
+    pub fn normalize_resource(&self, resource: &str) -> String {
+        let (prefix, rest) = match resource.split_once(':') {
+            Some((prefix, rest)) if !prefix.contains('@') => (Some(prefix), rest),
+            _ => (None, resource),
+        };
+        match rest.split_once('@') {
+            Some((user, domain)) if *self != CasePolicy::Preserve && !user.contains('/') => {
+                let user = self.normalize_user(user);
+                match prefix {
+                    Some(prefix) => format!("{}:{}@{}", prefix, user, domain),
+                    None => format!("{}@{}", user, domain),
+                }
+            }
+            _ => resource.to_string(),
+        }
+    }
+
+    /// Returns `true` if the two user parts designate the same user according to this policy.
+    pub fn same_user(&self, a: &str, b: &str) -> bool {
+        self.normalize_user(a) == self.normalize_user(b)
+    }
+}
+
+/// A resolver wrapper applying a [`CasePolicy`] to the requested identifiers and to the subjects
+/// of the results.
+#[cfg(feature = "server")]
+#[derive(Debug, Clone)]
+pub struct ApplyCasePolicy<T> {
+    inner: T,
+    policy: CasePolicy,
+}
+
+#[cfg(feature = "server")]
+impl<T> ApplyCasePolicy<T> {
+    /// Wraps a resolver, normalizing identifiers and subjects with `policy`.
+    pub fn new(inner: T, policy: CasePolicy) -> ApplyCasePolicy<T> {
+        ApplyCasePolicy { inner, policy }
+    }
+
+    /// Returns the wrapped resolver.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn normalize(&self, mut webfinger: Webfinger) -> Webfinger {
+        webfinger.subject = self.policy.normalize_resource(&webfinger.subject);
+        webfinger
+    }
+}
+
+#[cfg(feature = "server")]
+impl<R, T: Resolver<R>> Resolver<R> for ApplyCasePolicy<T> {
+    fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain()
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain)
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find(prefix, self.policy.normalize_user(&acct), resource_repo)
+            .map(|webfinger| self.normalize(webfinger))
+    }
+
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find_for_caller(
+                prefix,
+                self.policy.normalize_user(&acct),
+                caller,
+                resource_repo,
+            )
+            .map(|webfinger| self.normalize(webfinger))
+    }
+
+    fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find_by_url(url, resource_repo)
+            .map(|webfinger| self.normalize(webfinger))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, R> crate::AsyncResolver<R> for ApplyCasePolicy<T>
+where
+    T: crate::AsyncResolver<R> + Send + Sync,
+    R: Send,
+{
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain().await
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain).await
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find(prefix, self.policy.normalize_user(&acct), resource_repo)
+            .await
+            .map(|webfinger| self.normalize(webfinger))
+    }
+
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find_for_caller(
+                prefix,
+                self.policy.normalize_user(&acct),
+                caller,
+                resource_repo,
+            )
+            .await
+            .map(|webfinger| self.normalize(webfinger))
+    }
+
+    async fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find_by_url(url, resource_repo)
+            .await
+            .map(|webfinger| self.normalize(webfinger))
+    }
+}
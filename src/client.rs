@@ -0,0 +1,1299 @@
+use crate::{
+    consts, default_transport, fetch_document, split_prefix, url_for, Cache, CacheKeyHasher,
+    CacheStats, CasePolicy, CertificatePin, ClientConfig, FetchFailure, HttpFetch, InstanceDomain,
+    NegativeStore, NodeInfoRequests, ParseWarning, Prefix, PrefixRegistry, RateLimit, Recorder,
+    RecordingFetch, RequestKey, Resolved, ResponseHook, SoftwareCache, StatusAction, StatusPolicy,
+    TeeFetch, TeedResponse, TransportSettings, TxtLookup, Webfinger, WebfingerError,
+};
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use url::Url;
+
+/// Extra query parameters and headers to send with a WebFinger request.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct RequestOptions {
+    /// Query parameters appended to the WebFinger URL, after `resource`.
+    pub query: Vec<(String, String)>,
+
+    /// Additional HTTP headers.
+    pub headers: Vec<(String, String)>,
+
+    /// An identifier for this request, sent in the `X-Request-Id` header.
+    ///
+    /// It allows to correlate the logs of the different services involved in a request.
+    pub request_id: Option<String>,
+}
+
+impl RequestOptions {
+    /// Creates empty options.
+    pub fn new() -> RequestOptions {
+        RequestOptions::default()
+    }
+
+    /// Adds a query parameter.
+    pub fn query(mut self, name: impl Into<String>, value: impl Into<String>) -> RequestOptions {
+        self.query.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds an HTTP header.
+    ///
+    /// An `Accept` header replaces the one the client sends by default.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> RequestOptions {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets the identifier of this request.
+    pub fn request_id(mut self, id: impl Into<String>) -> RequestOptions {
+        self.request_id = Some(id.into());
+        self
+    }
+
+    fn extend(&mut self, other: &RequestOptions) {
+        self.query.extend(other.query.iter().cloned());
+        self.headers.extend(other.headers.iter().cloned());
+        if other.request_id.is_some() {
+            self.request_id = other.request_id.clone();
+        }
+    }
+}
+
+/// A [`WebfingerError`], with the identifier of the request during which it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracedError {
+    /// The value of the `X-Request-Id` header that was sent.
+    pub request_id: String,
+
+    /// The actual error.
+    pub error: WebfingerError,
+}
+
+impl fmt::Display for TracedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (request {})", self.error, self.request_id)
+    }
+}
+
+impl std::error::Error for TracedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<TracedError> for WebfingerError {
+    fn from(traced: TracedError) -> WebfingerError {
+        traced.error
+    }
+}
+
+/// Generates a new request identifier, unique for this process.
+fn generate_request_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros())
+        .unwrap_or_default();
+    format!(
+        "{:x}-{:x}-{:x}",
+        now,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// The IP versions a [`WebfingerClient`] can use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressFamily {
+    /// Use both IPv6 and IPv4, racing connections if a host has both kind of addresses.
+    #[default]
+    Any,
+    /// Only connect over IPv4.
+    Ipv4Only,
+    /// Only connect over IPv6.
+    Ipv6Only,
+}
+
+/// A client to fetch WebFinger resources.
+///
+/// Contrary to [`resolve`](crate::resolve), it keeps its configuration and its HTTP connections
+/// between requests. Use [`WebfingerClient::builder`] to configure it.
+#[derive(Clone)]
+pub struct WebfingerClient {
+    pub(crate) http: Arc<dyn HttpFetch>,
+    pub(crate) with_https: bool,
+    options: RequestOptions,
+    host_options: HashMap<String, RequestOptions>,
+    in_flight: Option<Arc<InFlight>>,
+    cache: Option<Arc<Cache>>,
+    request_ids: bool,
+    retries: u32,
+    statuses: StatusPolicy,
+    accept_fallback: bool,
+    blocked_domains: HashSet<String>,
+    rate_limit: Option<Arc<dyn RateLimit>>,
+    prefixes: Option<PrefixRegistry>,
+    case_policy: CasePolicy,
+    software: Option<Arc<SoftwareCache>>,
+    txt_discovery: Option<Arc<dyn TxtLookup>>,
+    #[cfg(feature = "tokio")]
+    hedge: Option<Arc<crate::Hedge>>,
+    #[cfg(feature = "tokio")]
+    retry_backoff: Option<(Duration, Duration)>,
+}
+
+type SharedFetch = Shared<BoxFuture<'static, Result<Resolved, WebfingerError>>>;
+
+/// The requests that are currently being made by a client, to avoid making them twice.
+struct InFlight {
+    window: Duration,
+    requests: Mutex<HashMap<RequestKey, (Instant, SharedFetch)>>,
+}
+
+/// Removes a shared request from [`InFlight`] once it completes, or when the last task waiting for
+/// it is cancelled, so that the next lookups don't wait for a request nobody polls anymore.
+struct InFlightGuard<'a> {
+    in_flight: &'a InFlight,
+    key: &'a RequestKey,
+    started: Instant,
+    request: Option<SharedFetch>,
+    completed: bool,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        // Our own handle must not be counted as a waiter
+        self.request = None;
+        let mut requests = self.in_flight.requests.lock().unwrap();
+        let remove = requests.get(self.key).is_some_and(|(started, request)| {
+            *started == self.started
+                && (self.completed || request.strong_count().map_or(true, |count| count <= 1))
+        });
+        if remove {
+            requests.remove(self.key);
+        }
+    }
+}
+
+impl fmt::Debug for InFlight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InFlight")
+            .field("window", &self.window)
+            .finish()
+    }
+}
+
+impl fmt::Debug for WebfingerClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WebfingerClient")
+            .field("with_https", &self.with_https)
+            .field("options", &self.options)
+            .field("host_options", &self.host_options)
+            .field("in_flight", &self.in_flight)
+            .field("cache", &self.cache)
+            .field("request_ids", &self.request_ids)
+            .field("retries", &self.retries)
+            .field("statuses", &self.statuses)
+            .field("accept_fallback", &self.accept_fallback)
+            .field("blocked_domains", &self.blocked_domains)
+            .field("rate_limit", &self.rate_limit.is_some())
+            .field("prefixes", &self.prefixes)
+            .field("case_policy", &self.case_policy)
+            .field("software", &self.software)
+            .field("txt_discovery", &self.txt_discovery.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for WebfingerClient {
+    fn default() -> WebfingerClient {
+        WebfingerClient::builder().build()
+    }
+}
+
+impl WebfingerClient {
+    /// Creates a client with the default configuration.
+    pub fn new() -> WebfingerClient {
+        WebfingerClient::default()
+    }
+
+    /// Creates a client making its requests with an existing `reqwest` client, with the default
+    /// settings otherwise.
+    ///
+    /// See [`WebfingerClientBuilder::http_client`].
+    #[cfg(feature = "fetch-reqwest")]
+    pub fn with_http_client(http: reqwest::Client) -> WebfingerClient {
+        WebfingerClient::builder().http_client(http).build()
+    }
+
+    /// Starts configuring a new client.
+    ///
+    /// The builder starts with the settings given in environment variables (see
+    /// [`ClientConfig::from_env`]), that the methods of the builder override.
+    pub fn builder() -> WebfingerClientBuilder {
+        WebfingerClientBuilder::default()
+    }
+
+    /// Fetches a WebFinger resource.
+    ///
+    /// If the resource doesn't have a prefix, `acct:` will be used.
+    pub async fn resolve(&self, acct: impl Into<String>) -> Result<Webfinger, WebfingerError> {
+        self.resolve_with_options(acct, &RequestOptions::default())
+            .await
+    }
+
+    /// Fetches a WebFinger resource, sending the extra parameters and headers of `options`.
+    ///
+    /// They are added after the ones configured for the whole client and for the host.
+    pub async fn resolve_with_options(
+        &self,
+        acct: impl Into<String>,
+        options: &RequestOptions,
+    ) -> Result<Webfinger, WebfingerError> {
+        Ok(self.resolve_detailed(acct, options).await?.document)
+    }
+
+    /// Fetches a WebFinger resource, with information about the response it comes from.
+    pub async fn resolve_detailed(
+        &self,
+        acct: impl Into<String>,
+        options: &RequestOptions,
+    ) -> Result<Resolved, WebfingerError> {
+        self.resolve_inner(&acct.into(), options, true).await
+    }
+
+    /// Fetches a WebFinger resource, ignoring the cached result if there is one.
+    ///
+    /// The cache is then updated with the new result.
+    pub async fn refresh(&self, acct: impl Into<String>) -> Result<Resolved, WebfingerError> {
+        self.resolve_inner(&acct.into(), &RequestOptions::default(), false)
+            .await
+    }
+
+    /// Returns the number of hits, misses and evictions of the cache since the client was built.
+    ///
+    /// All the statistics are zero if the client doesn't cache results.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache
+            .as_ref()
+            .map(|cache| cache.stats())
+            .unwrap_or_default()
+    }
+
+    /// Removes the cached results for a resource, and returns how many were removed.
+    ///
+    /// Results fetched with different options are all removed.
+    pub fn purge(&self, acct: impl Into<String>) -> Result<usize, WebfingerError> {
+        let acct = acct.into();
+        let (prefix, acct) = split_prefix(&acct)?;
+        let prefix = match self.prefixes {
+            Some(ref registry) => registry.check(prefix, acct.split('@').next().unwrap_or(acct))?,
+            None => prefix,
+        };
+        let url = url_for(
+            prefix,
+            self.case_policy.normalize_resource(acct),
+            self.with_https,
+        )?;
+        Ok(self.cache.as_ref().map_or(0, |cache| {
+            let url = cache.stored_url(&url);
+            cache.remove_where(|key| key == url)
+        }))
+    }
+
+    /// Removes the cached results for all the resources of `domain` and of its subdomains, and
+    /// returns how many were removed.
+    ///
+    /// The software detected for these hosts is forgotten too. It is meant to be used when an
+    /// instance is defederated, along with [`block_domain`](WebfingerClientBuilder::block_domain)
+    /// on the next client that is built.
+    pub fn purge_host(&self, domain: &str) -> usize {
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        let matches = |url: &str| {
+            Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_lowercase))
+                .is_some_and(|host| {
+                    host == domain
+                        || host
+                            .strip_suffix(domain.as_str())
+                            .is_some_and(|sub| sub.ends_with('.'))
+                })
+        };
+        if let Some(ref software) = self.software {
+            software.remove_where(matches);
+        }
+        self.cache
+            .as_ref()
+            .map_or(0, |cache| cache.remove_where(matches))
+    }
+
+    async fn resolve_inner(
+        &self,
+        acct: &str,
+        options: &RequestOptions,
+        use_cache: bool,
+    ) -> Result<Resolved, WebfingerError> {
+        let (prefix, acct) = split_prefix(acct)?;
+        self.fetch_with_request_id(prefix, acct, options, use_cache)
+            .await
+    }
+
+    /// Fetches a resource, with a new request ID if the client generates them and `options`
+    /// doesn't have one.
+    async fn fetch_with_request_id(
+        &self,
+        prefix: Prefix,
+        acct: &str,
+        options: &RequestOptions,
+        use_cache: bool,
+    ) -> Result<Resolved, WebfingerError> {
+        if self.request_ids && options.request_id.is_none() {
+            let options = options.clone().request_id(generate_request_id());
+            self.fetch(prefix, acct, &options, use_cache).await
+        } else {
+            self.fetch(prefix, acct, options, use_cache).await
+        }
+    }
+
+    /// Fetches a WebFinger resource, making sure the request has an identifier.
+    ///
+    /// If `options` doesn't contain a request ID, a new one is generated. In case of error, this
+    /// ID is returned with the actual error.
+    pub async fn resolve_traced(
+        &self,
+        acct: impl Into<String>,
+        options: &RequestOptions,
+    ) -> Result<Webfinger, TracedError> {
+        let request_id = options
+            .request_id
+            .clone()
+            .unwrap_or_else(generate_request_id);
+        let options = options.clone().request_id(request_id.clone());
+        self.resolve_with_options(acct, &options)
+            .await
+            .map_err(|error| TracedError { request_id, error })
+    }
+
+    /// Fetches a WebFinger resource, identified by the `acct` parameter, a Webfinger URI.
+    pub async fn resolve_with_prefix(
+        &self,
+        prefix: Prefix,
+        acct: impl Into<String>,
+    ) -> Result<Webfinger, WebfingerError> {
+        Ok(self
+            .fetch_with_request_id(prefix, &acct.into(), &RequestOptions::default(), true)
+            .await?
+            .document)
+    }
+
+    async fn fetch(
+        &self,
+        prefix: Prefix,
+        acct: &str,
+        options: &RequestOptions,
+        use_cache: bool,
+    ) -> Result<Resolved, WebfingerError> {
+        let prefix = match self.prefixes {
+            Some(ref registry) => registry.check(prefix, acct.split('@').next().unwrap_or(acct))?,
+            None => prefix,
+        };
+        let acct = self.case_policy.normalize_resource(acct);
+        let url = url_for(prefix, &acct, self.with_https)?;
+        if self.is_blocked(&url) {
+            return Err(WebfingerError::BlockedDomain);
+        }
+
+        // Requests with different IDs should still be shared
+        let mut key_options = options.clone();
+        key_options.request_id = None;
+        let key = (url, key_options);
+        if use_cache {
+            if let Some(result) = self.cache.as_ref().and_then(|cache| cache.get(&key)) {
+                return result;
+            }
+        }
+
+        let mut result = match self.in_flight {
+            Some(ref in_flight) => self.fetch_shared(in_flight, &key, options).await,
+            None => self.send(key.0.clone(), options).await,
+        };
+        if let Ok(ref mut resolved) = result {
+            resolved.document.subject = self
+                .case_policy
+                .normalize_resource(&resolved.document.subject);
+        }
+        if let Some(ref cache) = self.cache {
+            let failed = matches!(
+                result,
+                Err(WebfingerError::HttpError)
+                    | Err(WebfingerError::HttpStatus(_))
+                    | Err(WebfingerError::RateLimited)
+            );
+            if failed {
+                if let Some(stale) = cache.get_stale(&key) {
+                    return Ok(stale);
+                }
+            }
+            // Temporary errors say nothing about the resource, and the next request may succeed
+            let temporary = match result {
+                Err(WebfingerError::RateLimited) => true,
+                Err(WebfingerError::HttpStatus(status)) => {
+                    self.statuses.action(status) == StatusAction::Retry
+                }
+                _ => false,
+            };
+            if !temporary {
+                cache.insert(key, &result);
+            }
+        }
+        result
+    }
+
+    async fn fetch_shared(
+        &self,
+        in_flight: &InFlight,
+        key: &RequestKey,
+        options: &RequestOptions,
+    ) -> Result<Resolved, WebfingerError> {
+        let (started, request) = {
+            let mut requests = in_flight.requests.lock().unwrap();
+            match requests.get(key) {
+                Some((started, request)) if started.elapsed() < in_flight.window => {
+                    (*started, request.clone())
+                }
+                _ => {
+                    let client = self.clone();
+                    let url = key.0.clone();
+                    let options = options.clone();
+                    let request = async move { client.send(url, &options).await }
+                        .boxed()
+                        .shared();
+                    let started = Instant::now();
+                    requests.insert(key.clone(), (started, request.clone()));
+                    (started, request)
+                }
+            }
+        };
+
+        let mut guard = InFlightGuard {
+            in_flight,
+            key,
+            started,
+            request: Some(request),
+            completed: false,
+        };
+        let result = guard.request.as_mut().unwrap().await;
+        guard.completed = true;
+        result
+    }
+
+    async fn send(
+        &self,
+        url: String,
+        options: &RequestOptions,
+    ) -> Result<Resolved, WebfingerError> {
+        let url = match self.txt_discovery {
+            Some(ref lookup) => match crate::discover_endpoint(&**lookup, &url).await {
+                Some(endpoint) if self.is_blocked(&endpoint) => {
+                    return Err(WebfingerError::BlockedDomain)
+                }
+                Some(endpoint) => endpoint,
+                None => url,
+            },
+            None => url,
+        };
+        let options = self.options_for(&url, options);
+        let mut attempts = 0;
+        loop {
+            if let Some(ref rate_limit) = self.rate_limit {
+                if !rate_limit.acquire(&host_of(&url)).await {
+                    return Err(WebfingerError::RateLimited);
+                }
+            }
+            let result = match self.fetch_once(&url, &options).await {
+                Err(FetchFailure {
+                    error: WebfingerError::HttpStatus(406),
+                    ..
+                }) if self.accept_fallback => {
+                    let options = options.clone().header("Accept", consts::JSON_MIME);
+                    self.fetch_once(&url, &options).await.map(|mut resolved| {
+                        resolved.metadata.warnings.push(ParseWarning::NotAcceptable);
+                        resolved
+                    })
+                }
+                result => result,
+            };
+            #[cfg_attr(not(feature = "tokio"), allow(unused_variables))]
+            let (result, retry_after) = match result {
+                Ok(resolved) => (Ok(resolved), None),
+                Err(failure) => (Err(failure.error), failure.retry_after),
+            };
+            let retryable = match result {
+                Err(WebfingerError::HttpError) => true,
+                Err(WebfingerError::HttpStatus(status)) => {
+                    self.statuses.action(status) == StatusAction::Retry
+                }
+                _ => false,
+            };
+            if !retryable || attempts >= self.retries {
+                return self.detect_software(result, &url, &options).await;
+            }
+            #[cfg(feature = "tokio")]
+            if let Some((initial, max)) = self.retry_backoff {
+                let delay = match retry_after {
+                    Some(delay) if delay > max => return result,
+                    Some(delay) => delay,
+                    None => initial
+                        .saturating_mul(2u32.saturating_pow(attempts))
+                        .min(max),
+                };
+                tokio::time::sleep(delay).await;
+            }
+            attempts += 1;
+        }
+    }
+
+    /// Adds the software of the server to the metadata of `result`, if the client is configured
+    /// to detect it.
+    async fn detect_software(
+        &self,
+        mut result: Result<Resolved, WebfingerError>,
+        url: &str,
+        options: &RequestOptions,
+    ) -> Result<Resolved, WebfingerError> {
+        if let (Ok(ref mut resolved), Some(ref software)) = (&mut result, &self.software) {
+            if let Ok(url) = Url::parse(url) {
+                let origin = url.origin().ascii_serialization();
+                let requests = NodeInfoRequests {
+                    http: &*self.http,
+                    headers: &options.headers,
+                    statuses: &self.statuses,
+                    rate_limit: self.rate_limit.as_deref(),
+                    is_blocked: &|url: &str| self.is_blocked(url),
+                };
+                resolved.metadata.server.software = software.get(&requests, &origin).await;
+            }
+        }
+        result
+    }
+
+    /// Makes a single request, hedging it if the client is configured to.
+    async fn fetch_once(
+        &self,
+        url: &str,
+        options: &RequestOptions,
+    ) -> Result<Resolved, FetchFailure> {
+        #[cfg(feature = "tokio")]
+        if let Some(ref hedge) = self.hedge {
+            return hedge
+                .run(&host_of(url), || {
+                    fetch_document(&*self.http, url.to_string(), options, &self.statuses)
+                })
+                .await;
+        }
+        fetch_document(&*self.http, url.to_string(), options, &self.statuses).await
+    }
+
+    /// Checks if the host of `url`, or one of its parent domains, is blocked.
+    fn is_blocked(&self, url: &str) -> bool {
+        if self.blocked_domains.is_empty() {
+            return false;
+        }
+        let host = match Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+        {
+            Some(host) => host,
+            None => return false,
+        };
+        let mut domain = host.as_str();
+        loop {
+            if self.blocked_domains.contains(domain) {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Merges the options of the client, of the host of `url`, and of the request.
+    pub(crate) fn options_for(&self, url: &str, options: &RequestOptions) -> RequestOptions {
+        let mut all_options = self.options.clone();
+        if let Some(host_options) = self.host_options.get(&host_of(url)) {
+            all_options.extend(host_options);
+        }
+        all_options.extend(options);
+        all_options
+    }
+}
+
+/// Returns the host of `url`, followed by its port if there is one.
+pub(crate) fn host_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            url.host_str().map(|host| match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// A builder to configure a [`WebfingerClient`].
+pub struct WebfingerClientBuilder {
+    http: Option<Arc<dyn HttpFetch>>,
+    with_https: bool,
+    options: RequestOptions,
+    host_options: HashMap<String, RequestOptions>,
+    dedup_window: Option<Duration>,
+    transport: TransportSettings,
+    request_ids: bool,
+    retries: u32,
+    statuses: StatusPolicy,
+    accept_fallback: bool,
+    cache_ttl: Option<Duration>,
+    negative_cache_ttl: Option<Duration>,
+    max_staleness: Option<Duration>,
+    cache_capacity: Option<usize>,
+    cache_key_hasher: Option<Arc<dyn CacheKeyHasher>>,
+    negative_store: Option<Arc<dyn NegativeStore>>,
+    blocked_domains: HashSet<String>,
+    rate_limit: Option<Arc<dyn RateLimit>>,
+    recorder: Option<Recorder>,
+    tee: Option<(usize, ResponseHook)>,
+    invalid_domain: Option<String>,
+    prefixes: Option<PrefixRegistry>,
+    case_policy: CasePolicy,
+    detect_software: bool,
+    txt_discovery: Option<Arc<dyn TxtLookup>>,
+    #[cfg(feature = "tokio")]
+    hedge: Option<(f64, Duration)>,
+    #[cfg(feature = "tokio")]
+    retry_backoff: Option<(Duration, Duration)>,
+}
+
+impl fmt::Debug for WebfingerClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WebfingerClientBuilder")
+            .field("with_https", &self.with_https)
+            .field("options", &self.options)
+            .field("host_options", &self.host_options)
+            .field("dedup_window", &self.dedup_window)
+            .field("transport", &self.transport)
+            .field("request_ids", &self.request_ids)
+            .field("retries", &self.retries)
+            .field("statuses", &self.statuses)
+            .field("accept_fallback", &self.accept_fallback)
+            .field("cache_ttl", &self.cache_ttl)
+            .field("negative_cache_ttl", &self.negative_cache_ttl)
+            .field("max_staleness", &self.max_staleness)
+            .field("cache_capacity", &self.cache_capacity)
+            .field("hashed_cache_keys", &self.cache_key_hasher.is_some())
+            .field("negative_store", &self.negative_store.is_some())
+            .field("blocked_domains", &self.blocked_domains)
+            .field("rate_limit", &self.rate_limit.is_some())
+            .field("recorder", &self.recorder)
+            .field(
+                "tee_responses",
+                &self.tee.as_ref().map(|(max_size, _)| max_size),
+            )
+            .field("invalid_domain", &self.invalid_domain)
+            .field("prefixes", &self.prefixes)
+            .field("case_policy", &self.case_policy)
+            .field("detect_software", &self.detect_software)
+            .field("txt_discovery", &self.txt_discovery.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for WebfingerClientBuilder {
+    fn default() -> WebfingerClientBuilder {
+        WebfingerClientBuilder {
+            http: None,
+            with_https: true,
+            options: RequestOptions::default(),
+            host_options: HashMap::new(),
+            dedup_window: None,
+            transport: TransportSettings::default(),
+            request_ids: false,
+            retries: 0,
+            statuses: StatusPolicy::default(),
+            accept_fallback: true,
+            cache_ttl: None,
+            negative_cache_ttl: None,
+            max_staleness: None,
+            cache_capacity: None,
+            cache_key_hasher: None,
+            negative_store: None,
+            blocked_domains: HashSet::new(),
+            rate_limit: None,
+            recorder: None,
+            tee: None,
+            invalid_domain: None,
+            prefixes: None,
+            case_policy: CasePolicy::Preserve,
+            detect_software: false,
+            txt_discovery: None,
+            #[cfg(feature = "tokio")]
+            hedge: None,
+            #[cfg(feature = "tokio")]
+            retry_backoff: None,
+        }
+        .config(&ClientConfig::from_env())
+    }
+}
+
+impl WebfingerClientBuilder {
+    /// Uses an existing HTTP client instead of creating a new one.
+    ///
+    /// `reqwest` clients are cheap to clone and share their connection pool, so WebFinger requests
+    /// can reuse the connections, proxies and TLS configuration of the rest of the application
+    /// (ActivityPub delivery for instance). The transport settings of this builder (timeouts,
+    /// proxy, address family and [`connect_to`](WebfingerClientBuilder::connect_to)) are ignored,
+    /// as they belong to the shared client.
+    #[cfg(feature = "fetch-reqwest")]
+    pub fn http_client(self, http: reqwest::Client) -> WebfingerClientBuilder {
+        self.http_fetch(http)
+    }
+
+    /// Makes requests with an [`HttpFetch`] implementation shared with the rest of the
+    /// application.
+    ///
+    /// It is the same as [`http_fetch`](WebfingerClientBuilder::http_fetch), without wrapping a
+    /// transport that is already shared in another [`Arc`]. The transport settings of this builder
+    /// are ignored.
+    pub fn shared_http_fetch(mut self, http: Arc<dyn HttpFetch>) -> WebfingerClientBuilder {
+        self.http = Some(http);
+        self
+    }
+
+    /// Makes requests with a custom [`HttpFetch`] implementation.
+    ///
+    /// By default, `reqwest` is used if the `fetch-reqwest` feature is enabled, and `ureq` if only
+    /// `fetch-ureq` is. If none of them is enabled, a custom implementation must be provided, or
+    /// all requests will fail with [`WebfingerError::HttpError`].
+    pub fn http_fetch(mut self, http: impl HttpFetch + 'static) -> WebfingerClientBuilder {
+        self.http = Some(Arc::new(http));
+        self
+    }
+
+    /// Indicates wether requests should be made on HTTPS (the default) or HTTP.
+    pub fn https(mut self, with_https: bool) -> WebfingerClientBuilder {
+        self.with_https = with_https;
+        self
+    }
+
+    /// Adds a query parameter to every request.
+    pub fn query_param(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> WebfingerClientBuilder {
+        self.options.query.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds an HTTP header to every request.
+    pub fn header(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> WebfingerClientBuilder {
+        self.options.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds query parameters and headers to the requests sent to `host`.
+    ///
+    /// `host` is the domain of the resources, including the port if there is one.
+    pub fn host_options(
+        mut self,
+        host: impl Into<String>,
+        options: RequestOptions,
+    ) -> WebfingerClientBuilder {
+        let host = match self.check_domain(host.into()) {
+            Some(host) => host.to_string(),
+            None => return self,
+        };
+        self.host_options.entry(host).or_default().extend(&options);
+        self
+    }
+
+    /// Shares the requests for the same resource that are made at the same time.
+    ///
+    /// When a resource is requested while another request for it is still running, the result of
+    /// the running request is used instead of making a new one. To avoid waiting forever for a
+    /// stuck request, requests are only shared during `window` after they started.
+    pub fn dedup_in_flight(mut self, window: Duration) -> WebfingerClientBuilder {
+        self.dedup_window = Some(window);
+        self
+    }
+
+    /// Chooses the IP versions to use to connect to remote hosts.
+    ///
+    /// By default, both IPv6 and IPv4 addresses are tried, racing connections when both are
+    /// available. Restricting to IPv4 helps with hosts that publish broken `AAAA` records.
+    ///
+    /// This setting is ignored if a custom HTTP client is used.
+    pub fn address_family(mut self, family: AddressFamily) -> WebfingerClientBuilder {
+        self.transport.address_family = family;
+        self
+    }
+
+    /// Sets a timeout for the connection to remote hosts.
+    ///
+    /// When connections are raced between IPv6 and IPv4, it applies to each attempt. This setting
+    /// is ignored if a custom HTTP client is used.
+    pub fn connect_timeout(mut self, timeout: Duration) -> WebfingerClientBuilder {
+        self.transport.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets a timeout for whole requests, from the connection to the end of the response.
+    ///
+    /// This setting is ignored if a custom HTTP client is used.
+    pub fn timeout(mut self, timeout: Duration) -> WebfingerClientBuilder {
+        self.transport.timeout = Some(timeout);
+        self
+    }
+
+    /// Sends all requests through a proxy.
+    ///
+    /// This setting is ignored if a custom HTTP client is used. An invalid URL makes
+    /// [`WebfingerClientBuilder::try_build`] fail.
+    pub fn proxy(mut self, url: impl Into<String>) -> WebfingerClientBuilder {
+        self.transport.proxy = Some(url.into());
+        self
+    }
+
+    /// Connects to `ip` instead of the addresses of `domain` in the DNS.
+    ///
+    /// The URL of the requests is unchanged, so the public domain is still used for the `Host`
+    /// header and for TLS (SNI and certificate validation). It allows to reach a host through an
+    /// internal load balancer in split-horizon setups. The port of the requested resource is used,
+    /// so `domain` can't include one: [`try_build`](WebfingerClientBuilder::try_build) fails with
+    /// [`WebfingerError::ParseError`] if it does.
+    ///
+    /// This setting is ignored if a custom HTTP client is used.
+    pub fn connect_to(mut self, domain: impl Into<String>, ip: IpAddr) -> WebfingerClientBuilder {
+        if let Some(domain) = self.check_domain_without_port(domain.into()) {
+            self.transport
+                .connect_to
+                .insert(domain.domain().to_string(), ip);
+        }
+        self
+    }
+
+    /// Only accepts the certificates of `domain` that match one of its pins.
+    ///
+    /// This check comes in addition to the usual validation of certificates, and is made during
+    /// the TLS handshake, before anything is sent to the host. It passes if any certificate of
+    /// the chain presented by the host matches one of the pins, so the key of an intermediate
+    /// authority can be pinned too. Pins apply to the host actually connected to, including
+    /// after a redirection or when the endpoint is found with
+    /// [`txt_discovery`](WebfingerClientBuilder::txt_discovery). Requests to `domain` fail with
+    /// [`WebfingerError::PinMismatch`] when its certificates don't match, or over plain HTTP.
+    /// Pins apply to all the ports of `domain`, which can't include one:
+    /// [`try_build`](WebfingerClientBuilder::try_build) fails with [`WebfingerError::ParseError`]
+    /// if it does.
+    ///
+    /// Pins are checked by the default `reqwest` client, using `rustls` with the root
+    /// certificates of the system, and need the `ring` feature:
+    /// [`try_build`](WebfingerClientBuilder::try_build) fails with
+    /// [`WebfingerError::HttpError`] otherwise, or if a custom HTTP client is used.
+    pub fn pin_certificate(
+        mut self,
+        domain: impl Into<String>,
+        pin: CertificatePin,
+    ) -> WebfingerClientBuilder {
+        if let Some(domain) = self.check_domain_without_port(domain.into()) {
+            self.transport
+                .pins
+                .entry(domain.domain().to_string())
+                .or_default()
+                .push(pin);
+        }
+        self
+    }
+
+    /// Sends `value` as the `Host` header of the requests to `host`, instead of `host` itself.
+    ///
+    /// `host` is the domain of the resources, including the port if there is one. TLS still uses
+    /// the domain of the resource.
+    pub fn host_header(
+        self,
+        host: impl Into<String>,
+        value: impl Into<String>,
+    ) -> WebfingerClientBuilder {
+        self.host_options(
+            host,
+            RequestOptions {
+                headers: vec![("Host".to_string(), value.into())],
+                ..RequestOptions::default()
+            },
+        )
+    }
+
+    /// Sets the `User-Agent` header of every request, replacing the previous one.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> WebfingerClientBuilder {
+        self.options
+            .headers
+            .retain(|(name, _)| !name.eq_ignore_ascii_case("User-Agent"));
+        self.header("User-Agent", user_agent)
+    }
+
+    /// Retries the requests that failed because of a network error, or with a status that the
+    /// [`StatusPolicy`] considers temporary, up to `retries` times.
+    ///
+    /// Retries are made right away, unless
+    /// [`retry_backoff`](WebfingerClientBuilder::retry_backoff) is used.
+    pub fn retries(mut self, retries: u32) -> WebfingerClientBuilder {
+        self.retries = retries;
+        self
+    }
+
+    /// Waits before each retry, `initial` before the first one, and twice as long before each of
+    /// the next ones, up to `max`.
+    ///
+    /// When the server answers with a `Retry-After` header, its delay is used instead. If it is
+    /// longer than `max`, the request is not retried and fails right away.
+    ///
+    /// It requires the `tokio` feature, and the client must be used in a Tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub fn retry_backoff(mut self, initial: Duration, max: Duration) -> WebfingerClientBuilder {
+        self.retry_backoff = Some((initial, max));
+        self
+    }
+
+    /// Sends a request again with `Accept: application/json` when a server refuses the default
+    /// `Accept` header with a `406 Not Acceptable` status.
+    ///
+    /// It is enabled by default, and documents fetched this way have a
+    /// [`ParseWarning::NotAcceptable`] in their metadata. Disable it to be strict about the media
+    /// types servers accept: `406` responses then fail with [`WebfingerError::HttpStatus`], or as
+    /// the [`StatusPolicy`] says.
+    pub fn accept_fallback(mut self, enabled: bool) -> WebfingerClientBuilder {
+        self.accept_fallback = enabled;
+        self
+    }
+
+    /// Changes how HTTP statuses are handled.
+    pub fn status_policy(mut self, statuses: StatusPolicy) -> WebfingerClientBuilder {
+        self.statuses = statuses;
+        self
+    }
+
+    /// Keeps the documents that were fetched in memory during `ttl`.
+    ///
+    /// Documents are kept for less time if the `Cache-Control` header of the response asks for
+    /// it, or if they expire earlier according to their `expires` field. They are not kept at all
+    /// if the response has a `no-store` or `no-cache` directive. A `ttl` too large to be added to
+    /// the current time, like `Duration::MAX`, keeps them until they are evicted.
+    pub fn cache(mut self, ttl: Duration) -> WebfingerClientBuilder {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Keeps the failed lookups in memory during `ttl`, to avoid requesting missing or broken
+    /// resources again and again.
+    ///
+    /// Network errors, rate limiting and the HTTP statuses that the [`StatusPolicy`] retries are
+    /// never cached, as the next request may succeed. Use
+    /// [`negative_store`](WebfingerClientBuilder::negative_store) to keep them somewhere else
+    /// than in memory.
+    pub fn negative_cache(mut self, ttl: Duration) -> WebfingerClientBuilder {
+        self.negative_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Serves the cached copy of a document when fetching it again fails, if it expired less than
+    /// `max_staleness` ago.
+    ///
+    /// Only network errors, temporary HTTP errors and rate limiting cause stale documents to be
+    /// served, after all the retries failed. These documents are marked as
+    /// [`Freshness::Stale`](crate::Freshness::Stale) in their metadata. It has no effect if
+    /// [`cache`](WebfingerClientBuilder::cache) is not used.
+    pub fn serve_stale(mut self, max_staleness: Duration) -> WebfingerClientBuilder {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
+
+    /// Keeps at most `max_entries` results in the cache.
+    ///
+    /// When the cache is full, the entries that expire first are evicted to make room for new
+    /// ones. It has no effect if neither [`cache`](WebfingerClientBuilder::cache) nor
+    /// [`negative_cache`](WebfingerClientBuilder::negative_cache) is used.
+    pub fn cache_capacity(mut self, max_entries: usize) -> WebfingerClientBuilder {
+        self.cache_capacity = Some(max_entries);
+        self
+    }
+
+    /// Keeps the failed lookups in `store` instead of in memory, during the TTL given to
+    /// [`negative_cache`](WebfingerClientBuilder::negative_cache).
+    ///
+    /// Documents are still kept in memory, during the TTL given to
+    /// [`cache`](WebfingerClientBuilder::cache) and within the limit of
+    /// [`cache_capacity`](WebfingerClientBuilder::cache_capacity), which only applies to them.
+    /// They are also not included in the [`entries`](crate::CacheStats::entries) of the cache
+    /// statistics.
+    ///
+    /// For a large instance, a short TTL for documents (an hour or less, so that key rotations
+    /// and moves are seen quickly) and a capacity of a few thousand entries are usually enough,
+    /// while missing resources can be remembered for a day or more in a shared store, since they
+    /// rarely come back and are requested by every worker.
+    pub fn negative_store(mut self, store: impl NegativeStore + 'static) -> WebfingerClientBuilder {
+        self.negative_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Hashes the keys of the cache with `hasher`, so that it doesn't contain the requested
+    /// handles.
+    ///
+    /// Only the scheme and the host of the requested URLs are kept in clear, so that
+    /// [`WebfingerClient::purge_host`] still works. Expiration, negative caching and stale
+    /// documents work as usual.
+    pub fn hash_cache_keys(
+        mut self,
+        hasher: impl CacheKeyHasher + 'static,
+    ) -> WebfingerClientBuilder {
+        self.cache_key_hasher = Some(Arc::new(hasher));
+        self
+    }
+
+    /// Records every request made by the client in `recorder`, including retries.
+    ///
+    /// Cached results are not recorded, as no request is made for them.
+    pub fn record(mut self, recorder: Recorder) -> WebfingerClientBuilder {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Gives every response received by the client to `hook`, before it is parsed.
+    ///
+    /// Only the first `max_size` bytes of the bodies are given to the hook. It allows to archive
+    /// raw responses, or to check them in conformance tests, while using the client as usual.
+    /// Network errors are not given to the hook: use [`record`](WebfingerClientBuilder::record)
+    /// to know about them.
+    pub fn tee_responses(
+        mut self,
+        max_size: usize,
+        hook: impl Fn(&TeedResponse<'_>) + Send + Sync + 'static,
+    ) -> WebfingerClientBuilder {
+        self.tee = Some((max_size, Arc::new(hook)));
+        self
+    }
+
+    /// Only resolves the resources whose prefix is known by `registry`.
+    ///
+    /// Other resources, and the ones whose identifier is refused by the registry, fail with
+    /// [`WebfingerError::ParseError`] without making any request. Custom prefixes are sent in
+    /// their canonical form.
+    pub fn prefixes(mut self, registry: PrefixRegistry) -> WebfingerClientBuilder {
+        self.prefixes = Some(registry);
+        self
+    }
+
+    /// Normalizes the user part of the requested resources and of the subjects of the results
+    /// with `policy`.
+    ///
+    /// Since resources are normalized before being requested, they also share the same cache
+    /// entries. By default, the case is preserved.
+    pub fn case_policy(mut self, policy: CasePolicy) -> WebfingerClientBuilder {
+        self.case_policy = policy;
+        self
+    }
+
+    /// Fetches the NodeInfo document of the servers to know which software they run.
+    ///
+    /// The result is available in [`ServerHints::software`](crate::ServerHints::software), in the
+    /// metadata of the documents returned by [`WebfingerClient::resolve_detailed`]. NodeInfo is
+    /// fetched once per server for the lifetime of the client, and failures are ignored. Network
+    /// errors and the statuses that are not [`StatusAction::Accept`] or
+    /// [`StatusAction::NotFound`] are not remembered, so NodeInfo is fetched again on the next
+    /// lookup.
+    ///
+    /// NodeInfo requests follow the blocked domains, the rate limit and the status policy of the
+    /// client. The NodeInfo document must be on the same origin as the WebFinger endpoint.
+    pub fn detect_software(mut self, detect: bool) -> WebfingerClientBuilder {
+        self.detect_software = detect;
+        self
+    }
+
+    /// Looks for an alternate endpoint in the `_webfinger` TXT record of a domain before
+    /// requesting its standard `/.well-known/webfinger` URL (experimental).
+    ///
+    /// It helps domains that can't serve `/.well-known` paths, like static sites hosted on object
+    /// storage. A record of `_webfinger.example.org` containing `https://files.example.net/wf` makes
+    /// the client request `https://files.example.net/wf?resource=acct:…` for the resources of
+    /// `example.org`. If the record contains a `{uri}` placeholder, like
+    /// `https://files.example.net/wf/{uri}.json`, the resource replaces it instead, and the subject
+    /// of the document isn't compared to the resource.
+    ///
+    /// Records must use the same scheme as the client (see [`https`](WebfingerClientBuilder::https)),
+    /// and other records are ignored. When there is no such record, or when the lookup fails, the
+    /// standard endpoint is used. The alternate endpoint is [blocked](WebfingerClientBuilder::block_domain)
+    /// and configured like any other host, but the results are cached under the resource.
+    pub fn txt_discovery(mut self, lookup: impl TxtLookup + 'static) -> WebfingerClientBuilder {
+        self.txt_discovery = Some(Arc::new(lookup));
+        self
+    }
+
+    /// Sends a second request when the first one is slower than the given `percentile` of the
+    /// previous requests to the same host (`0.95` for instance), and uses the first successful
+    /// response.
+    ///
+    /// It reduces the latency of interactive lookups when a host is sometimes slow, at the cost of
+    /// a few more requests. Until enough requests were made to a host, the second request is sent
+    /// after `initial_delay`. If one of the two requests fails, the result of the other one is
+    /// used. Hedged requests don't count against the [`RateLimit`].
+    ///
+    /// It requires the `tokio` feature, and the client must be used in a Tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub fn hedge(mut self, percentile: f64, initial_delay: Duration) -> WebfingerClientBuilder {
+        self.hedge = Some((percentile, initial_delay));
+        self
+    }
+
+    /// Refuses to fetch resources from `domain` and its subdomains.
+    ///
+    /// Lookups on these domains fail with [`WebfingerError::BlockedDomain`], without making any
+    /// request.
+    pub fn block_domain(mut self, domain: impl Into<String>) -> WebfingerClientBuilder {
+        if let Some(domain) = self.check_domain(domain.into()) {
+            self.blocked_domains.insert(domain.domain().to_string());
+        }
+        self
+    }
+
+    /// Sends an `X-Request-Id` header with every request, generating one when the
+    /// [`RequestOptions`] of a request don't specify it.
+    pub fn request_ids(mut self, enabled: bool) -> WebfingerClientBuilder {
+        self.request_ids = enabled;
+        self
+    }
+
+    /// Asks `rate_limit` for the permission to send each request, including retries.
+    pub fn rate_limit(mut self, rate_limit: impl RateLimit + 'static) -> WebfingerClientBuilder {
+        self.rate_limit = Some(Arc::new(rate_limit));
+        self
+    }
+
+    /// Creates the client.
+    ///
+    /// # Panics
+    ///
+    /// If the default HTTP client couldn't be created, for instance because the proxy URL is
+    /// invalid. Use [`WebfingerClientBuilder::try_build`] to handle these errors.
+    pub fn build(self) -> WebfingerClient {
+        self.try_build().expect("Couldn't create the HTTP client")
+    }
+
+    /// Validates a domain given to the builder, remembering it if it is invalid to make
+    /// [`WebfingerClientBuilder::try_build`] fail.
+    fn check_domain(&mut self, domain: String) -> Option<InstanceDomain> {
+        let checked = InstanceDomain::new(&domain).ok();
+        if checked.is_none() {
+            self.invalid_domain = Some(domain);
+        }
+        checked
+    }
+
+    /// Checks a domain for the settings that apply to all of its ports, like
+    /// [`connect_to`](WebfingerClientBuilder::connect_to), and that can't include one.
+    fn check_domain_without_port(&mut self, domain: String) -> Option<InstanceDomain> {
+        let checked = self.check_domain(domain.clone())?;
+        if checked.port().is_some() {
+            self.invalid_domain = Some(domain);
+            return None;
+        }
+        Some(checked)
+    }
+
+    /// Creates the client, failing if the default HTTP client couldn't be created.
+    ///
+    /// It also fails with [`WebfingerError::ParseError`] if one of the domains given to the
+    /// builder is not a valid [`InstanceDomain`].
+    pub fn try_build(self) -> Result<WebfingerClient, WebfingerError> {
+        if self.invalid_domain.is_some() {
+            return Err(WebfingerError::ParseError);
+        }
+        let mut http = match self.http {
+            Some(_) if !self.transport.pins.is_empty() => return Err(WebfingerError::HttpError),
+            Some(http) => http,
+            None => default_transport(&self.transport)?,
+        };
+        if let Some((max_size, hook)) = self.tee {
+            http = Arc::new(TeeFetch {
+                inner: http,
+                hook,
+                max_size,
+            });
+        }
+        if let Some(recorder) = self.recorder {
+            http = Arc::new(RecordingFetch {
+                inner: http,
+                recorder,
+            });
+        }
+        let cache = if self.cache_ttl.is_some() || self.negative_cache_ttl.is_some() {
+            Some(Arc::new(Cache::new(
+                self.cache_ttl,
+                self.negative_cache_ttl,
+                self.max_staleness,
+                self.cache_capacity,
+                self.cache_key_hasher,
+                self.negative_store,
+            )))
+        } else {
+            None
+        };
+        Ok(WebfingerClient {
+            http,
+            with_https: self.with_https,
+            options: self.options,
+            host_options: self.host_options,
+            request_ids: self.request_ids,
+            in_flight: self.dedup_window.map(|window| {
+                Arc::new(InFlight {
+                    window,
+                    requests: Mutex::new(HashMap::new()),
+                })
+            }),
+            cache,
+            retries: self.retries,
+            statuses: self.statuses,
+            accept_fallback: self.accept_fallback,
+            blocked_domains: self.blocked_domains,
+            rate_limit: self.rate_limit,
+            prefixes: self.prefixes,
+            case_policy: self.case_policy,
+            software: if self.detect_software {
+                Some(Arc::new(SoftwareCache::default()))
+            } else {
+                None
+            },
+            txt_discovery: self.txt_discovery,
+            #[cfg(feature = "tokio")]
+            hedge: self
+                .hedge
+                .map(|(percentile, delay)| Arc::new(crate::Hedge::new(percentile, delay))),
+            #[cfg(feature = "tokio")]
+            retry_backoff: self.retry_backoff,
+        })
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    AddressFamily, CasePolicy, CertificatePin, RequestOptions, StatusAction, StatusPolicy,
+    WebfingerClient, WebfingerClientBuilder, WebfingerError,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, net::IpAddr, time::Duration};
+
+/// The configuration of a [`WebfingerClient`], that can be loaded from a configuration file.
+///
+/// It can be deserialized with any `serde` format. All fields are optional, and durations are
+/// given in seconds. For instance, in TOML:
+///
+/// ```toml
+/// timeout = 10
+/// retries = 2
+/// user_agent = "MyInstance/1.0"
+/// cache_ttl = 3600
+/// cache_max_entries = 10000
+///
+/// [domains."spam.example"]
+/// blocked = true
+///
+/// [domains."example.org"]
+/// headers = { Authorization = "Bearer abc" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientConfig {
+    /// Wether requests should be made on HTTPS (the default) or HTTP.
+    pub https: Option<bool>,
+
+    /// The timeout for the connection to remote hosts, in seconds.
+    pub connect_timeout: Option<u64>,
+
+    /// The timeout for whole requests, in seconds.
+    pub timeout: Option<u64>,
+
+    /// The number of times requests are retried after a network error or a temporary HTTP error.
+    pub retries: Option<u32>,
+
+    /// How some HTTP statuses should be handled, instead of the default [`StatusPolicy`].
+    pub statuses: BTreeMap<u16, StatusAction>,
+
+    /// The URL of a proxy to send requests through.
+    pub proxy: Option<String>,
+
+    /// The value of the `User-Agent` header.
+    pub user_agent: Option<String>,
+
+    /// The IP versions to use.
+    pub address_family: Option<AddressFamily>,
+
+    /// The time during which concurrent requests for the same resource are shared, in seconds.
+    pub dedup_window: Option<u64>,
+
+    /// Wether an `X-Request-Id` header should be sent with every request.
+    pub request_ids: Option<bool>,
+
+    /// How long fetched documents are cached, in seconds.
+    pub cache_ttl: Option<u64>,
+
+    /// How long failed lookups are cached, in seconds.
+    pub negative_cache_ttl: Option<u64>,
+
+    /// How long expired documents can be served when fetching them again fails, in seconds.
+    pub max_staleness: Option<u64>,
+
+    /// The maximum number of cached results.
+    pub cache_max_entries: Option<usize>,
+
+    /// How the user part of resources is normalized.
+    pub case_policy: Option<CasePolicy>,
+
+    /// Settings for specific domains.
+    ///
+    /// Keys are domains. Extra query parameters and headers are only sent if the key includes the
+    /// port of the resource, when there is one.
+    pub domains: BTreeMap<String, DomainConfig>,
+}
+
+impl ClientConfig {
+    /// Reads the settings given in environment variables:
+    ///
+    /// - `WEBFINGER_TIMEOUT`: the timeout for whole requests, in seconds;
+    /// - `WEBFINGER_PROXY`: the URL of a proxy to send requests through;
+    /// - `WEBFINGER_USER_AGENT`: the value of the `User-Agent` header;
+    /// - `WEBFINGER_INSECURE_HTTP`: makes requests on HTTP instead of HTTPS if it is `1`, `true`
+    ///   or `yes`.
+    ///
+    /// Variables that are not set, or that have an invalid value, are ignored: a proxy URL that
+    /// the HTTP client would refuse is dropped, so that it can't make
+    /// [`WebfingerClientBuilder::build`] panic.
+    pub fn from_env() -> ClientConfig {
+        ClientConfig::from_vars(|name| std::env::var(name).ok())
+    }
+
+    pub(crate) fn from_vars(var: impl Fn(&str) -> Option<String>) -> ClientConfig {
+        let non_empty = |name| var(name).filter(|value| !value.trim().is_empty());
+        ClientConfig {
+            https: non_empty("WEBFINGER_INSECURE_HTTP")
+                .filter(|value| {
+                    matches!(
+                        value.trim().to_ascii_lowercase().as_str(),
+                        "1" | "true" | "yes"
+                    )
+                })
+                .map(|_| false),
+            timeout: non_empty("WEBFINGER_TIMEOUT").and_then(|value| value.trim().parse().ok()),
+            proxy: non_empty("WEBFINGER_PROXY")
+                .filter(|proxy| crate::transport::is_valid_proxy(proxy)),
+            user_agent: non_empty("WEBFINGER_USER_AGENT"),
+            ..ClientConfig::default()
+        }
+    }
+}
+
+/// The settings of a [`ClientConfig`] for a specific domain.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DomainConfig {
+    /// Refuses to fetch resources from this domain and its subdomains.
+    pub blocked: bool,
+
+    /// Extra query parameters to send to this domain.
+    pub query: BTreeMap<String, String>,
+
+    /// Extra HTTP headers to send to this domain.
+    pub headers: BTreeMap<String, String>,
+
+    /// The IP address to connect to instead of the ones of this domain in the DNS.
+    ///
+    /// The key must not include a port for this setting, or
+    /// [`WebfingerClientBuilder::try_build`] fails.
+    pub connect_to: Option<IpAddr>,
+
+    /// The value of the `Host` header to send to this domain.
+    pub host_header: Option<String>,
+
+    /// The certificates this domain may present, like `spki-sha256:4e2f…`.
+    ///
+    /// The key must not include a port for this setting, or
+    /// [`WebfingerClientBuilder::try_build`] fails. See
+    /// [`WebfingerClientBuilder::pin_certificate`].
+    pub pins: Vec<CertificatePin>,
+}
+
+impl WebfingerClientBuilder {
+    /// Applies the settings of a [`ClientConfig`].
+    ///
+    /// Settings that are not present in the configuration are left unchanged.
+    pub fn config(mut self, config: &ClientConfig) -> WebfingerClientBuilder {
+        if let Some(https) = config.https {
+            self = self.https(https);
+        }
+        if let Some(retries) = config.retries {
+            self = self.retries(retries);
+        }
+        if let Some(address_family) = config.address_family {
+            self = self.address_family(address_family);
+        }
+        if let Some(request_ids) = config.request_ids {
+            self = self.request_ids(request_ids);
+        }
+        if let Some(case_policy) = config.case_policy {
+            self = self.case_policy(case_policy);
+        }
+        if !config.statuses.is_empty() {
+            let statuses = config
+                .statuses
+                .iter()
+                .fold(StatusPolicy::new(), |policy, (status, action)| {
+                    policy.set(*status, *action)
+                });
+            self = self.status_policy(statuses);
+        }
+        if let Some(secs) = config.connect_timeout {
+            self = self.connect_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = config.timeout {
+            self = self.timeout(Duration::from_secs(secs));
+        }
+        if let Some(ref proxy) = config.proxy {
+            self = self.proxy(proxy.clone());
+        }
+        if let Some(ref user_agent) = config.user_agent {
+            self = self.user_agent(user_agent.clone());
+        }
+        if let Some(secs) = config.dedup_window {
+            self = self.dedup_in_flight(Duration::from_secs(secs));
+        }
+        if let Some(secs) = config.cache_ttl {
+            self = self.cache(Duration::from_secs(secs));
+        }
+        if let Some(secs) = config.negative_cache_ttl {
+            self = self.negative_cache(Duration::from_secs(secs));
+        }
+        if let Some(secs) = config.max_staleness {
+            self = self.serve_stale(Duration::from_secs(secs));
+        }
+        if let Some(max_entries) = config.cache_max_entries {
+            self = self.cache_capacity(max_entries);
+        }
+
+        for (domain, domain_config) in &config.domains {
+            if domain_config.blocked {
+                self = self.block_domain(domain.clone());
+            }
+            let options = RequestOptions {
+                query: domain_config.query.clone().into_iter().collect(),
+                headers: domain_config.headers.clone().into_iter().collect(),
+                request_id: None,
+            };
+            if options != RequestOptions::default() {
+                self = self.host_options(domain.clone(), options);
+            }
+            if let Some(ip) = domain_config.connect_to {
+                self = self.connect_to(domain.clone(), ip);
+            }
+            if let Some(ref host) = domain_config.host_header {
+                self = self.host_header(domain.clone(), host.clone());
+            }
+            for pin in &domain_config.pins {
+                self = self.pin_certificate(domain.clone(), *pin);
+            }
+        }
+        self
+    }
+}
+
+impl WebfingerClient {
+    /// Creates a client from a [`ClientConfig`].
+    ///
+    /// It fails with [`WebfingerError::ParseError`] if the proxy URL is invalid.
+    pub fn from_config(config: &ClientConfig) -> Result<WebfingerClient, WebfingerError> {
+        WebfingerClient::builder().config(config).try_build()
+    }
+}
@@ -0,0 +1,33 @@
+//! Well-known paths, media types and link relations used by WebFinger.
+//!
+//! These are the values used by this crate, so downstream code using them stays consistent with
+//! it.
+
+/// The path of the WebFinger endpoint, at the root of every domain.
+pub const WELL_KNOWN_PATH: &str = "/.well-known/webfinger";
+
+/// The media type of JSON Resource Descriptors, the format of WebFinger documents.
+pub const JRD_MIME: &str = "application/jrd+json";
+
+/// The generic JSON media type, that many servers use instead of [`JRD_MIME`].
+pub const JSON_MIME: &str = "application/json";
+
+/// The media type of the legacy XML Resource Descriptors.
+pub const XRD_MIME: &str = "application/xrd+xml";
+
+/// The media type of problem documents, describing why a request failed
+/// ([RFC 7807](https://tools.ietf.org/html/rfc7807)).
+pub const PROBLEM_JSON_MIME: &str = "application/problem+json";
+
+/// The relation of links to the profile page of a resource.
+pub const WEBFINGER_REL_PROFILE_PAGE: &str = "http://webfinger.net/rel/profile-page";
+
+/// The relation of links to the avatar of a resource.
+pub const WEBFINGER_REL_AVATAR: &str = "http://webfinger.net/rel/avatar";
+
+/// The relation of links to the Matrix account of a resource.
+pub const MATRIX_REL_IDENTITY: &str = "m.identity";
+
+/// The relation used by some Matrix clients (formerly Riot, now Element) for the Matrix account
+/// of a resource.
+pub const MATRIX_REL_VECTOR: &str = "im.vector";
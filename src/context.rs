@@ -0,0 +1,291 @@
+use crate::{Caller, InstanceDomain, Prefix, Resolver, ResolverError, Webfinger};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use url::Url;
+
+/// The deadline and cancellation state of an incoming WebFinger request.
+///
+/// Create one per request in the handler of your web framework, and pass it to the resolver along
+/// with the resource repository, in a [`WithContext`]. Lookups can then give their remaining time
+/// to the database (as a statement timeout for instance), and stop early once nobody is waiting
+/// for their result anymore.
+///
+/// Web frameworks like axum or actix-web drop the future of a handler when the client
+/// disconnects. Keep the guard returned by [`cancel_on_drop`](LookupContext::cancel_on_drop) in
+/// the handler so that lookups running on another thread (with `spawn_blocking` for instance)
+/// see the request as cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct LookupContext {
+    deadline: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl LookupContext {
+    /// Creates a context without deadline.
+    pub fn new() -> LookupContext {
+        LookupContext::default()
+    }
+
+    /// Sets the instant after which the lookup is abandoned.
+    pub fn with_deadline(mut self, deadline: Instant) -> LookupContext {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Abandons the lookup after `timeout`, starting now.
+    ///
+    /// The context is left unchanged if `timeout` is too large to be added to the current time,
+    /// like `Duration::MAX`.
+    pub fn with_timeout(self, timeout: Duration) -> LookupContext {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.with_deadline(deadline),
+            None => self,
+        }
+    }
+
+    /// The instant after which the lookup is abandoned, if there is one.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// The time left before the deadline, if there is one.
+    ///
+    /// It is zero once the deadline has passed.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Marks the request as cancelled, for this context and all its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Tells if the request was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns a guard cancelling the request when it is dropped, unless
+    /// [`CancelOnDrop::disarm`] was called before.
+    pub fn cancel_on_drop(&self) -> CancelOnDrop {
+        CancelOnDrop {
+            context: Some(self.clone()),
+        }
+    }
+
+    /// Fails with [`ResolverError::Cancelled`] if the request was cancelled or its deadline has
+    /// passed.
+    pub fn check(&self) -> Result<(), ResolverError> {
+        if self.is_cancelled() || self.remaining() == Some(Duration::ZERO) {
+            Err(ResolverError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Cancels a [`LookupContext`] when dropped.
+///
+/// It is returned by [`LookupContext::cancel_on_drop`].
+#[derive(Debug)]
+pub struct CancelOnDrop {
+    context: Option<LookupContext>,
+}
+
+impl CancelOnDrop {
+    /// Keeps the request going: call it once the response has been generated.
+    pub fn disarm(mut self) {
+        self.context = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(ref context) = self.context {
+            context.cancel();
+        }
+    }
+}
+
+/// A resource repository along with the [`LookupContext`] of the request.
+///
+/// Implement [`Resolver<WithContext<R>>`](Resolver) to receive the context in
+/// [`find`](Resolver::find). Since the repository is given as is to the wrapped resolvers, the
+/// context goes through all the resolver wrappers of this crate.
+#[derive(Debug, Clone)]
+pub struct WithContext<R> {
+    /// The resource repository.
+    pub repo: R,
+
+    /// The context of the request.
+    pub context: LookupContext,
+}
+
+impl<R> WithContext<R> {
+    /// Pairs a resource repository with the context of the request.
+    pub fn new(repo: R, context: LookupContext) -> WithContext<R> {
+        WithContext { repo, context }
+    }
+}
+
+impl<R> AsRef<LookupContext> for WithContext<R> {
+    fn as_ref(&self) -> &LookupContext {
+        &self.context
+    }
+}
+
+/// A resolver wrapper enforcing the [`LookupContext`] of the requests.
+///
+/// Lookups are not started when the request was cancelled or its deadline has passed, and their
+/// results are replaced by [`ResolverError::Cancelled`] if it happened while they were running,
+/// so that slow lookups don't produce responses nobody will read. With the `tokio` feature, the
+/// asynchronous lookups are also interrupted at the deadline.
+#[derive(Debug, Clone)]
+pub struct Deadlines<T> {
+    inner: T,
+}
+
+impl<T> Deadlines<T> {
+    /// Wraps a resolver, enforcing the deadline and the cancellation of the requests.
+    pub fn new(inner: T) -> Deadlines<T> {
+        Deadlines { inner }
+    }
+
+    /// Returns the wrapped resolver.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn guarded(
+        context: LookupContext,
+        lookup: impl FnOnce() -> Result<Webfinger, ResolverError>,
+    ) -> Result<Webfinger, ResolverError> {
+        context.check()?;
+        let result = lookup();
+        context.check()?;
+        result
+    }
+}
+
+impl<R, T: Resolver<WithContext<R>>> Resolver<WithContext<R>> for Deadlines<T> {
+    fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain()
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain)
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: WithContext<R>,
+    ) -> Result<Webfinger, ResolverError> {
+        Self::guarded(resource_repo.context.clone(), || {
+            self.inner.find(prefix, acct, resource_repo)
+        })
+    }
+
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: WithContext<R>,
+    ) -> Result<Webfinger, ResolverError> {
+        Self::guarded(resource_repo.context.clone(), || {
+            self.inner
+                .find_for_caller(prefix, acct, caller, resource_repo)
+        })
+    }
+
+    fn find_by_url(
+        &self,
+        url: &Url,
+        resource_repo: WithContext<R>,
+    ) -> Result<Webfinger, ResolverError> {
+        Self::guarded(resource_repo.context.clone(), || {
+            self.inner.find_by_url(url, resource_repo)
+        })
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> Deadlines<T> {
+    async fn guarded_async(
+        context: LookupContext,
+        lookup: impl std::future::Future<Output = Result<Webfinger, ResolverError>>,
+    ) -> Result<Webfinger, ResolverError> {
+        context.check()?;
+        #[cfg(feature = "tokio")]
+        let result = match context.deadline() {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), lookup)
+                .await
+                .unwrap_or(Err(ResolverError::Cancelled)),
+            None => lookup.await,
+        };
+        #[cfg(not(feature = "tokio"))]
+        let result = lookup.await;
+        context.check()?;
+        result
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, R> crate::AsyncResolver<R> for Deadlines<T>
+where
+    T: crate::AsyncResolver<R> + Send + Sync,
+    R: AsRef<LookupContext> + Send,
+{
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain().await
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain).await
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let context = resource_repo.as_ref().clone();
+        Self::guarded_async(context, self.inner.find(prefix, acct, resource_repo)).await
+    }
+
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let context = resource_repo.as_ref().clone();
+        let lookup = self
+            .inner
+            .find_for_caller(prefix, acct, caller, resource_repo);
+        Self::guarded_async(context, lookup).await
+    }
+
+    async fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        let context = resource_repo.as_ref().clone();
+        Self::guarded_async(context, self.inner.find_by_url(url, resource_repo)).await
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{Caller, InstanceDomain, Prefix, Resolver, ResolverError, Webfinger};
+use url::Url;
+
+/// A resolver wrapper removing some links from the results of another resolver.
+///
+/// It allows to avoid publishing some information (avatars, payment links, etc) without having to
+/// change every [`find`](Resolver::find) implementation. The links are only removed for anonymous
+/// requests: when a [`Caller`] is known, the results are left untouched.
+#[derive(Debug, Clone)]
+pub struct DenyRels<T> {
+    inner: T,
+    denied: Vec<String>,
+}
+
+impl<T> DenyRels<T> {
+    /// Wraps a resolver, removing the links with one of the `denied` relations from its results.
+    pub fn new<S: Into<String>>(inner: T, denied: impl IntoIterator<Item = S>) -> DenyRels<T> {
+        DenyRels {
+            inner,
+            denied: denied.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Returns the wrapped resolver.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn strip(&self, mut webfinger: Webfinger) -> Webfinger {
+        webfinger
+            .links
+            .retain(|link| !self.denied.iter().any(|rel| link.rel == *rel));
+        webfinger
+    }
+}
+
+impl<R, T: Resolver<R>> Resolver<R> for DenyRels<T> {
+    fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain()
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain)
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find(prefix, acct, resource_repo)
+            .map(|webfinger| self.strip(webfinger))
+    }
+
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        match caller {
+            Some(_) => self
+                .inner
+                .find_for_caller(prefix, acct, caller, resource_repo),
+            None => self.find(prefix, acct, resource_repo),
+        }
+    }
+
+    fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find_by_url(url, resource_repo)
+            .map(|webfinger| self.strip(webfinger))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, R> crate::AsyncResolver<R> for DenyRels<T>
+where
+    T: crate::AsyncResolver<R> + Send + Sync,
+    R: Send,
+{
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain().await
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain).await
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find(prefix, acct, resource_repo)
+            .await
+            .map(|webfinger| self.strip(webfinger))
+    }
+
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        match caller {
+            Some(_) => {
+                self.inner
+                    .find_for_caller(prefix, acct, caller, resource_repo)
+                    .await
+            }
+            None => self.find(prefix, acct, resource_repo).await,
+        }
+    }
+
+    async fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find_by_url(url, resource_repo)
+            .await
+            .map(|webfinger| self.strip(webfinger))
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt, str::FromStr};
+use url::Host;
+
+/// The domain of an instance, followed by its port if it is not the default one.
+///
+/// It is validated when it is created, so that configuration mistakes (like giving a URL instead
+/// of a domain) are detected right away, instead of making every request fail with
+/// [`ResolverError::WrongDomain`](crate::ResolverError::WrongDomain). Domains are normalized:
+/// they are lowercased, and internationalized domain names are converted to punycode.
+///
+/// ```rust
+/// use webfinger::InstanceDomain;
+///
+/// let domain = InstanceDomain::new("Bücher.example:8080").unwrap();
+/// assert_eq!(domain, "xn--bcher-kva.example:8080");
+/// assert!(InstanceDomain::new("https://example.org/").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct InstanceDomain {
+    domain: String,
+    port: Option<u16>,
+}
+
+/// The reason why a string is not a valid [`InstanceDomain`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum InstanceDomainError {
+    /// The domain is empty.
+    Empty,
+
+    /// The domain starts with a scheme, like `https://`.
+    HasScheme,
+
+    /// The domain is followed by a path, a query or a fragment.
+    HasPath,
+
+    /// The domain or the IP address is not valid.
+    InvalidHost,
+
+    /// The port is not a valid number.
+    InvalidPort,
+}
+
+impl InstanceDomainError {
+    /// A stable code identifying this error, from `WF0201` to `WF0299`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            InstanceDomainError::Empty => "WF0201",
+            InstanceDomainError::HasScheme => "WF0202",
+            InstanceDomainError::HasPath => "WF0203",
+            InstanceDomainError::InvalidHost => "WF0204",
+            InstanceDomainError::InvalidPort => "WF0205",
+        }
+    }
+}
+
+impl fmt::Display for InstanceDomainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            InstanceDomainError::Empty => "the domain is empty",
+            InstanceDomainError::HasScheme => "the domain should not start with a scheme",
+            InstanceDomainError::HasPath => "the domain should not contain a path",
+            InstanceDomainError::InvalidHost => "the domain is invalid",
+            InstanceDomainError::InvalidPort => "the port is invalid",
+        };
+        write!(f, "[{}] {}", self.code(), message)
+    }
+}
+
+impl std::error::Error for InstanceDomainError {}
+
+impl InstanceDomain {
+    /// Validates and normalizes a domain, that may end with a port.
+    pub fn new(domain: &str) -> Result<InstanceDomain, InstanceDomainError> {
+        if domain.is_empty() {
+            return Err(InstanceDomainError::Empty);
+        }
+        if domain.contains("://") {
+            return Err(InstanceDomainError::HasScheme);
+        }
+        if domain.contains(['/', '?', '#']) {
+            return Err(InstanceDomainError::HasPath);
+        }
+
+        // IPv6 addresses contain colons too, but they are between brackets
+        let (host, port) = match domain.rfind(':') {
+            Some(i) if !domain[i..].contains(']') => {
+                let port = domain[i + 1..]
+                    .parse()
+                    .map_err(|_| InstanceDomainError::InvalidPort)?;
+                (&domain[..i], Some(port))
+            }
+            _ => (domain, None),
+        };
+        let host = host.strip_suffix('.').unwrap_or(host);
+        let domain = match Host::parse(host).map_err(|_| InstanceDomainError::InvalidHost)? {
+            Host::Domain(domain) => domain,
+            Host::Ipv4(ip) => ip.to_string(),
+            Host::Ipv6(ip) => format!("[{}]", ip),
+        };
+        Ok(InstanceDomain { domain, port })
+    }
+
+    /// The domain, without the port.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// The port, if there is one.
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+}
+
+impl fmt::Display for InstanceDomain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.port {
+            Some(port) => write!(f, "{}:{}", self.domain, port),
+            None => f.write_str(&self.domain),
+        }
+    }
+}
+
+impl FromStr for InstanceDomain {
+    type Err = InstanceDomainError;
+
+    fn from_str(domain: &str) -> Result<InstanceDomain, InstanceDomainError> {
+        InstanceDomain::new(domain)
+    }
+}
+
+impl TryFrom<&str> for InstanceDomain {
+    type Error = InstanceDomainError;
+
+    fn try_from(domain: &str) -> Result<InstanceDomain, InstanceDomainError> {
+        InstanceDomain::new(domain)
+    }
+}
+
+impl TryFrom<String> for InstanceDomain {
+    type Error = InstanceDomainError;
+
+    fn try_from(domain: String) -> Result<InstanceDomain, InstanceDomainError> {
+        InstanceDomain::new(&domain)
+    }
+}
+
+impl From<InstanceDomain> for String {
+    fn from(domain: InstanceDomain) -> String {
+        domain.to_string()
+    }
+}
+
+impl PartialEq<str> for InstanceDomain {
+    fn eq(&self, other: &str) -> bool {
+        match self.port {
+            Some(port) => other
+                .rsplit_once(':')
+                .is_some_and(|(domain, p)| domain == self.domain && p == port.to_string()),
+            None => other == self.domain,
+        }
+    }
+}
+
+impl PartialEq<&str> for InstanceDomain {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
@@ -0,0 +1,172 @@
+#[cfg(all(feature = "client", feature = "server"))]
+use crate::ProxyError;
+#[cfg(feature = "server")]
+use crate::QueryError;
+#[cfg(feature = "client")]
+use crate::TracedError;
+use crate::{
+    HandleError, InstanceDomainError, LinkError, NdjsonError, ResolverError, ResourceError,
+    TemplateIssue, WebfingerError,
+};
+use std::fmt;
+
+/// Any error of this crate.
+///
+/// Every error type of the crate converts into it, so applications can use a single error type
+/// with `?` in functions that fetch, serve and validate resources. Each variant is transparent:
+/// its code, its message and its source are the ones of the wrapped error.
+///
+/// ```
+/// # use webfinger::{validate_handle, InstanceDomain};
+/// fn check(handle: &str, domain: &str) -> Result<(), webfinger::Error> {
+///     validate_handle(handle)?;
+///     InstanceDomain::new(domain)?;
+///     Ok(())
+/// }
+///
+/// assert!(check("test@example.org", "example.org").is_ok());
+/// assert_eq!(check("test", "example.org").unwrap_err().code(), "WF0803");
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An error while fetching a remote resource.
+    Webfinger(WebfingerError),
+
+    /// An error while fetching a remote resource, with the identifier of the request.
+    #[cfg(feature = "client")]
+    Traced(TracedError),
+
+    /// An error while serving a local resource.
+    Resolver(ResolverError),
+
+    /// An invalid resource.
+    Resource(ResourceError),
+
+    /// An invalid handle.
+    Handle(HandleError),
+
+    /// An invalid domain.
+    Domain(InstanceDomainError),
+
+    /// An invalid link.
+    Link(LinkError),
+
+    /// An invalid link template.
+    Template(TemplateIssue),
+
+    /// An error of a [`WebfingerProxy`](crate::WebfingerProxy).
+    #[cfg(all(feature = "client", feature = "server"))]
+    Proxy(ProxyError),
+
+    /// An error while reading newline-delimited documents.
+    Ndjson(NdjsonError),
+
+    /// An invalid query string.
+    #[cfg(feature = "server")]
+    Query(QueryError),
+
+    /// An error while checking the identity of an account.
+    #[cfg(feature = "fediverse")]
+    Identity(crate::fediverse::IdentityError),
+}
+
+impl Error {
+    /// The stable code of the wrapped error, like `WF0005`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Webfinger(error) => error.code(),
+            #[cfg(feature = "client")]
+            Error::Traced(error) => error.error.code(),
+            Error::Resolver(error) => error.code(),
+            Error::Resource(error) => error.code(),
+            Error::Handle(error) => error.code(),
+            Error::Domain(error) => error.code(),
+            Error::Link(error) => error.code(),
+            Error::Template(error) => error.code(),
+            #[cfg(all(feature = "client", feature = "server"))]
+            Error::Proxy(error) => error.code(),
+            Error::Ndjson(error) => error.code(),
+            #[cfg(feature = "server")]
+            Error::Query(error) => error.code(),
+            #[cfg(feature = "fediverse")]
+            Error::Identity(error) => error.code(),
+        }
+    }
+
+    fn inner(&self) -> &(dyn std::error::Error + 'static) {
+        match self {
+            Error::Webfinger(error) => error,
+            #[cfg(feature = "client")]
+            Error::Traced(error) => error,
+            Error::Resolver(error) => error,
+            Error::Resource(error) => error,
+            Error::Handle(error) => error,
+            Error::Domain(error) => error,
+            Error::Link(error) => error,
+            Error::Template(error) => error,
+            #[cfg(all(feature = "client", feature = "server"))]
+            Error::Proxy(error) => error,
+            Error::Ndjson(error) => error,
+            #[cfg(feature = "server")]
+            Error::Query(error) => error,
+            #[cfg(feature = "fediverse")]
+            Error::Identity(error) => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.inner(), f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner().source()
+    }
+}
+
+macro_rules! from_error {
+    ($($variant:ident($error:ty)),* $(,)?) => {
+        $(
+            impl From<$error> for Error {
+                fn from(error: $error) -> Error {
+                    Error::$variant(error)
+                }
+            }
+        )*
+    };
+}
+
+from_error! {
+    Webfinger(WebfingerError),
+    Resolver(ResolverError),
+    Resource(ResourceError),
+    Handle(HandleError),
+    Domain(InstanceDomainError),
+    Link(LinkError),
+    Template(TemplateIssue),
+    Ndjson(NdjsonError),
+}
+
+#[cfg(feature = "client")]
+from_error! {
+    Traced(TracedError),
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+from_error! {
+    Proxy(ProxyError),
+}
+
+#[cfg(feature = "server")]
+from_error! {
+    Query(QueryError),
+}
+
+#[cfg(feature = "fediverse")]
+from_error! {
+    Identity(crate::fediverse::IdentityError),
+}
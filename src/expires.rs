@@ -0,0 +1,159 @@
+use crate::Webfinger;
+use std::{
+    convert::TryFrom,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+impl Webfinger {
+    /// Returns the date at which this document expires, if it has a valid `expires` field.
+    ///
+    /// The field must be an [RFC 3339](https://tools.ietf.org/html/rfc3339) date, like
+    /// `2020-01-01T12:00:00Z`.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires.as_deref().and_then(parse_rfc3339)
+    }
+
+    /// Sets the `expires` field of this document to the given date.
+    pub fn set_expires_at(&mut self, date: SystemTime) {
+        self.expires = Some(format_rfc3339(date));
+    }
+}
+
+/// Parses a date like `2020-01-01T12:00:00.5+02:00`.
+pub(crate) fn parse_rfc3339(date: &str) -> Option<SystemTime> {
+    let number = |s: &str| -> Option<i64> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    };
+    let bytes = date.as_bytes();
+    if date.len() < 20
+        || !date.is_ascii()
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let (year, month, day) = (
+        number(&date[0..4])?,
+        number(&date[5..7])?,
+        number(&date[8..10])?,
+    );
+    let (hour, minute, second) = (
+        number(&date[11..13])?,
+        number(&date[14..16])?,
+        number(&date[17..19])?,
+    );
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    // Leap seconds are accepted, and counted as the next second
+    if second > 60 {
+        return None;
+    }
+
+    let mut rest = &date[19..];
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        let padded = format!("{:0<9}", &fraction[..digits.min(9)]);
+        nanos = number(&padded)? as u32;
+        rest = &fraction[digits..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+            let minutes = number(&rest[1..3])? * 60 + number(&rest[4..6])?;
+            match rest.as_bytes()[0] {
+                b'+' => minutes * 60,
+                b'-' => -minutes * 60,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    let timestamp =
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    let timestamp = u64::try_from(timestamp).ok()?;
+    Some(UNIX_EPOCH + Duration::new(timestamp, nanos))
+}
+
+/// Parses an HTTP date like `Sun, 06 Nov 1994 08:49:37 GMT`.
+///
+/// Only the preferred format of [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-5.6.7)
+/// is supported, not the obsolete ones.
+#[cfg(feature = "client")]
+pub(crate) fn parse_http_date(date: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let parts: Vec<_> = date.split_whitespace().collect();
+    let (day, month, year, time) = match parts[..] {
+        [_, day, month, year, time, "GMT"] if day.len() == 2 && year.len() == 4 => {
+            (day, month, year, time)
+        }
+        _ => return None,
+    };
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let mut time = time.split(':').map(|n| n.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if time.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let timestamp = days_from_civil(year.parse().ok()?, month, day.parse().ok()?) * 86400
+        + hour * 3600
+        + minute * 60
+        + second;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(timestamp).ok()?))
+}
+
+/// Formats a date like `2020-01-01T12:00:00Z`.
+pub(crate) fn format_rfc3339(date: SystemTime) -> String {
+    let secs = date
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let time = secs.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Returns the number of days since 1970-01-01.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,370 @@
+//! Helpers for the WebFinger conventions used by ActivityPub software (Plume, Mastodon, etc).
+//!
+//! This module is only available with the `fediverse` feature. Everything it contains, and the
+//! most common items of this crate, can be imported at once from the [`prelude`].
+
+use crate::{consts, BaseUrl, Link, Webfinger, WebfingerError};
+#[cfg(feature = "client")]
+use crate::{Rel, WebfingerClient};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use std::fmt;
+
+const SELF_REL: &str = "self";
+const PROFILE_PAGE_REL: &str = consts::WEBFINGER_REL_PROFILE_PAGE;
+const AVATAR_REL: &str = consts::WEBFINGER_REL_AVATAR;
+const SUBSCRIBE_REL: &str = "http://ostatus.org/schema/1.0/subscribe";
+const ACTIVITY_JSON: &str = "application/activity+json";
+const LD_JSON: &str = "application/ld+json";
+
+/// The usual names of instance actors, tried after `domain@domain` by
+/// [`WebfingerClient::resolve_instance_actor`].
+pub const INSTANCE_ACTOR_NAMES: &[&str] = &["mastodon.internal", "internal.actor", "relay"];
+
+/// Everything you need to fetch and serve WebFinger resources for an ActivityPub server.
+pub mod prelude {
+    pub use super::{normalize_acct, Identity, IdentityError};
+    pub use crate::{Link, Prefix, ResolverError, Webfinger, WebfingerError};
+
+    #[cfg(feature = "client")]
+    pub use crate::WebfingerClient;
+
+    #[cfg(feature = "server")]
+    pub use crate::Resolver;
+
+    #[cfg(feature = "fetch-reqwest")]
+    pub use crate::{resolve, resolve_with_prefix};
+
+    #[cfg(feature = "async")]
+    pub use crate::AsyncResolver;
+}
+
+impl Webfinger {
+    /// Returns the URL of the ActivityPub actor described by this document.
+    ///
+    /// It is the `href` of the first `self` link of type `application/activity+json`, or of type
+    /// `application/ld+json` if there is none.
+    pub fn actor_url(&self) -> Option<&str> {
+        self.links()
+            .rel(SELF_REL)
+            .mime(ACTIVITY_JSON)
+            .first_href()
+            .or_else(|| self.links().rel(SELF_REL).mime(LD_JSON).first_href())
+    }
+
+    /// Returns the URL of the HTML profile page of this resource, if any.
+    pub fn profile_url(&self) -> Option<&str> {
+        self.links().rel(PROFILE_PAGE_REL).first_href()
+    }
+
+    /// Returns the URL of the avatar of this resource, if any.
+    pub fn avatar_url(&self) -> Option<&str> {
+        self.links().rel(AVATAR_REL).first_href()
+    }
+
+    /// Expands the remote follow template of this document for `uri`.
+    ///
+    /// `uri` is usually the identifier of the account to follow, and is percent-encoded before
+    /// replacing the `{uri}` placeholder.
+    pub fn subscribe_url(&self, uri: &str) -> Option<String> {
+        self.links()
+            .rel(SUBSCRIBE_REL)
+            .first_template()
+            .map(|template| {
+                template.replace(
+                    "{uri}",
+                    &utf8_percent_encode(uri, NON_ALPHANUMERIC).to_string(),
+                )
+            })
+    }
+
+    /// Builds the document an ActivityPub server usually serves for one of its accounts.
+    ///
+    /// # Parameters
+    ///
+    /// - `acct`: the identifier of the account, for instance `someone@example.org`
+    /// - `actor_url`: the ID of the ActivityPub actor of this account
+    /// - `profile_url`: the URL of the HTML profile page of this account
+    pub fn for_account(
+        acct: impl Into<String>,
+        actor_url: impl Into<String>,
+        profile_url: impl Into<String>,
+    ) -> Webfinger {
+        let actor_url = actor_url.into();
+        let profile_url = profile_url.into();
+        Webfinger {
+            aliases: vec![actor_url.clone(), profile_url.clone()],
+            links: vec![
+                Link::profile_page(profile_url),
+                Link::activitypub_self(actor_url),
+            ],
+            ..Webfinger::new(format!("acct:{}", acct.into()))
+        }
+    }
+
+    /// Builds the document an ActivityPub server usually serves for one of its accounts, with
+    /// URLs relative to the public URL of the instance.
+    ///
+    /// The links use the scheme of `base`, so that instances only reachable over HTTP (onion
+    /// services or development instances) publish coherent documents.
+    ///
+    /// # Parameters
+    ///
+    /// - `base`: the public URL of the instance
+    /// - `user`: the name of the account, the domain being the one of `base`
+    /// - `actor_path`: the path of the ActivityPub actor of this account, like `users/someone`
+    /// - `profile_path`: the path of the HTML profile page of this account, like `@someone`
+    pub fn for_account_at(
+        base: &BaseUrl,
+        user: &str,
+        actor_path: &str,
+        profile_path: &str,
+    ) -> Webfinger {
+        Webfinger::for_account(
+            format!("{}@{}", user, base.domain()),
+            base.url_for(actor_path),
+            base.url_for(profile_path),
+        )
+    }
+
+    /// Builds a minimal document for an account that is served under another identifier, for
+    /// instance on an alias domain or after the user was renamed.
+    ///
+    /// # Parameters
+    ///
+    /// - `requested`: the identifier that was requested, like `someone@old.example`
+    /// - `canonical_acct`: the identifier of the account, like `someone@example.org`, that is
+    ///   added as an alias
+    /// - `canonical_url`: the ID of the ActivityPub actor of the account
+    pub fn redirect_to(
+        requested: impl Into<String>,
+        canonical_acct: impl Into<String>,
+        canonical_url: impl Into<String>,
+    ) -> Webfinger {
+        Webfinger {
+            aliases: vec![format!("acct:{}", canonical_acct.into())],
+            links: vec![Link::activitypub_self(canonical_url)],
+            ..Webfinger::new(format!("acct:{}", requested.into()))
+        }
+    }
+
+    /// Builds the document of the instance actor of `domain`, as `acct:domain@domain`.
+    ///
+    /// `actor_url` is the ID of the ActivityPub actor representing the instance itself, used to
+    /// sign the requests that are not made on behalf of a specific user.
+    pub fn for_instance_actor(domain: &str, actor_url: impl Into<String>) -> Webfinger {
+        let actor_url = actor_url.into();
+        Webfinger {
+            aliases: vec![actor_url.clone()],
+            links: vec![Link::activitypub_self(actor_url)],
+            ..Webfinger::new(format!("acct:{}@{}", domain, domain))
+        }
+    }
+
+    /// Builds the document of the instance actor of the instance at `base`, with an actor URL
+    /// relative to it.
+    ///
+    /// See [`Webfinger::for_instance_actor`].
+    pub fn for_instance_actor_at(base: &BaseUrl, actor_path: &str) -> Webfinger {
+        Webfinger::for_instance_actor(&base.domain(), base.url_for(actor_path))
+    }
+}
+
+#[cfg(feature = "client")]
+impl WebfingerClient {
+    /// Finds the instance actor of `domain`.
+    ///
+    /// `domain@domain` is tried first, and then the names of [`INSTANCE_ACTOR_NAMES`]. The first
+    /// document with an [`actor_url`](Webfinger::actor_url) is returned. If none is found, the
+    /// error of the last attempt is returned.
+    pub async fn resolve_instance_actor(&self, domain: &str) -> Result<Webfinger, WebfingerError> {
+        let names = std::iter::once(domain).chain(INSTANCE_ACTOR_NAMES.iter().copied());
+        let mut last_error = WebfingerError::NotFound;
+        for name in names {
+            match self.resolve(format!("{}@{}", name, domain)).await {
+                Ok(webfinger) if webfinger.actor_url().is_some() => return Ok(webfinger),
+                Ok(_) => last_error = WebfingerError::NotFound,
+                Err(error) => last_error = error,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+/// The most useful information about an account, taken from its WebFinger document.
+///
+/// It is returned by [`WebfingerClient::resolve_identity`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    /// The canonical identifier of the account, in the `user@domain` form.
+    pub acct: String,
+
+    /// The URL of the ActivityPub actor of the account (see [`Webfinger::actor_url`]).
+    pub actor_url: Option<String>,
+
+    /// The URL of the HTML profile page of the account.
+    pub profile_url: Option<String>,
+
+    /// The URL of the avatar of the account.
+    pub avatar_url: Option<String>,
+
+    /// The Atom and RSS feeds of the account, in the order of the document.
+    pub feeds: Vec<Link>,
+
+    /// The complete WebFinger document of the account.
+    pub webfinger: Webfinger,
+}
+
+#[cfg(feature = "client")]
+impl Identity {
+    fn new(acct: String, webfinger: Webfinger) -> Identity {
+        Identity {
+            acct,
+            actor_url: webfinger.actor_url().map(String::from),
+            profile_url: webfinger.profile_url().map(String::from),
+            avatar_url: webfinger.avatar_url().map(String::from),
+            feeds: webfinger
+                .links()
+                .rel(Rel::UpdatesFrom)
+                .filter(|link| link.href.is_some())
+                .cloned()
+                .collect(),
+            webfinger,
+        }
+    }
+}
+
+/// An error that occured while resolving an [`Identity`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum IdentityError {
+    /// The WebFinger lookup failed.
+    Webfinger(WebfingerError),
+
+    /// The document of the requested account has another subject, whose own document doesn't
+    /// describe the same account.
+    SubjectMismatch {
+        /// The requested account.
+        requested: String,
+
+        /// The subject of its document.
+        subject: String,
+    },
+}
+
+impl IdentityError {
+    /// A stable code identifying this error, from `WF0601` to `WF0699`.
+    ///
+    /// Lookup errors have the code of the [`WebfingerError`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            IdentityError::Webfinger(error) => error.code(),
+            IdentityError::SubjectMismatch { .. } => "WF0601",
+        }
+    }
+}
+
+impl fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentityError::Webfinger(error) => error.fmt(f),
+            IdentityError::SubjectMismatch { requested, subject } => write!(
+                f,
+                "[{}] {} claims to be {}, which doesn't confirm it",
+                self.code(),
+                requested,
+                subject
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IdentityError {}
+
+impl From<WebfingerError> for IdentityError {
+    fn from(error: WebfingerError) -> IdentityError {
+        IdentityError::Webfinger(error)
+    }
+}
+
+#[cfg(feature = "client")]
+impl WebfingerClient {
+    /// Finds the [`Identity`] of an account, as typed by a user (see [`normalize_acct`]).
+    ///
+    /// When the subject of the document is another account (because the server uses a different
+    /// domain for WebFinger and for its accounts, for instance), this account is resolved too, and
+    /// must have the same subject and actor. Its identifier is then used as the canonical one.
+    ///
+    /// Lookups are made with this client, and thus use its cache if it has one.
+    pub async fn resolve_identity(&self, acct: &str) -> Result<Identity, IdentityError> {
+        let requested = normalize_acct(acct)?;
+        let webfinger = self.resolve(requested.clone()).await?;
+        let subject = match webfinger.subject.strip_prefix("acct:") {
+            Some(subject) => {
+                normalize_acct(subject).map_err(|_| IdentityError::SubjectMismatch {
+                    requested: requested.clone(),
+                    subject: webfinger.subject.clone(),
+                })?
+            }
+            None => return Ok(Identity::new(requested, webfinger)),
+        };
+        if subject == requested {
+            return Ok(Identity::new(requested, webfinger));
+        }
+
+        let canonical = self.resolve(subject.clone()).await?;
+        let confirmed = canonical.subject.strip_prefix("acct:").map(normalize_acct)
+            == Some(Ok(subject.clone()))
+            && canonical.actor_url() == webfinger.actor_url();
+        if confirmed {
+            Ok(Identity::new(subject, canonical))
+        } else {
+            Err(IdentityError::SubjectMismatch {
+                requested,
+                subject: webfinger.subject,
+            })
+        }
+    }
+}
+
+impl Link {
+    /// Creates a `self` link pointing to an ActivityPub actor.
+    ///
+    /// [`BaseUrl::url_for`] can be used to build `href` with the scheme of the instance.
+    pub fn activitypub_self(href: impl Into<String>) -> Link {
+        Link {
+            href: Some(href.into()),
+            mime_type: Some(ACTIVITY_JSON.to_string()),
+            ..Link::new(SELF_REL)
+        }
+    }
+
+    /// Creates a link to an HTML profile page.
+    pub fn profile_page(href: impl Into<String>) -> Link {
+        Link {
+            href: Some(href.into()),
+            mime_type: Some("text/html".to_string()),
+            ..Link::new(PROFILE_PAGE_REL)
+        }
+    }
+}
+
+/// Normalizes an account identifier, as typed by a user, to the `user@domain` form.
+///
+/// A leading `@` or `acct:` is removed, as well as surrounding whitespace, and the domain is
+/// lowercased. The local part is kept as is, since some software treats it as case sensitive.
+pub fn normalize_acct(input: &str) -> Result<String, WebfingerError> {
+    let input = input.trim();
+    let input = input
+        .strip_prefix("acct:")
+        .or_else(|| input.strip_prefix('@'))
+        .unwrap_or(input);
+
+    let mut parts = input.splitn(2, '@');
+    let user = parts.next().ok_or(WebfingerError::ParseError)?;
+    let domain = parts.next().ok_or(WebfingerError::ParseError)?;
+    if user.is_empty() || domain.is_empty() || domain.contains('@') {
+        return Err(WebfingerError::ParseError);
+    }
+
+    Ok(format!("{}@{}", user, domain.to_lowercase()))
+}
@@ -0,0 +1,157 @@
+use crate::{InstanceDomain, InstanceDomainError, Resource, ResourceError};
+use std::{fmt, str::FromStr};
+
+/// A validated account handle, like `someone@example.org`.
+///
+/// It is returned by [`validate_handle`]. The domain is normalized like an [`InstanceDomain`]:
+/// it is lowercased, and internationalized domain names are converted to punycode. The user is
+/// kept as is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Acct {
+    user: String,
+    domain: InstanceDomain,
+}
+
+impl Acct {
+    /// The user part of the handle.
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// The domain of the handle, with its port if there is one.
+    pub fn domain(&self) -> &InstanceDomain {
+        &self.domain
+    }
+
+    /// The handle as an `acct:` resource, like `acct:someone@example.org`.
+    pub fn to_resource(&self) -> String {
+        format!("acct:{}", self)
+    }
+}
+
+impl fmt::Display for Acct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.user, self.domain)
+    }
+}
+
+impl FromStr for Acct {
+    type Err = HandleError;
+
+    fn from_str(input: &str) -> Result<Acct, HandleError> {
+        validate_handle(input)
+    }
+}
+
+/// The reason why a handle was refused by [`validate_handle`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum HandleError {
+    /// The handle is not made of a user and a domain separated by a single `@`.
+    Resource(ResourceError),
+
+    /// The domain is not valid.
+    Domain(InstanceDomainError),
+
+    /// The user contains a `%` that is not followed by two hexadecimal digits.
+    InvalidPercentEncoding,
+
+    /// The user contains a space, a control character, or a character that can't be sent in a
+    /// WebFinger request without being percent-encoded (`#`, `&` or `?`).
+    InvalidCharacter(char),
+}
+
+impl HandleError {
+    /// A stable code identifying this error, from `WF0901` to `WF0999`.
+    ///
+    /// Resource and domain errors have the code of the [`ResourceError`] or of the
+    /// [`InstanceDomainError`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            HandleError::Resource(error) => error.code(),
+            HandleError::Domain(error) => error.code(),
+            HandleError::InvalidPercentEncoding => "WF0901",
+            HandleError::InvalidCharacter(_) => "WF0902",
+        }
+    }
+}
+
+impl fmt::Display for HandleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandleError::Resource(error) => error.fmt(f),
+            HandleError::Domain(error) => error.fmt(f),
+            HandleError::InvalidPercentEncoding => write!(
+                f,
+                "[{}] the user contains an invalid percent-encoded character",
+                self.code()
+            ),
+            HandleError::InvalidCharacter(c) => write!(
+                f,
+                "[{}] the user contains an invalid character: {:?}",
+                self.code(),
+                c
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HandleError {}
+
+impl From<ResourceError> for HandleError {
+    fn from(error: ResourceError) -> HandleError {
+        HandleError::Resource(error)
+    }
+}
+
+impl From<InstanceDomainError> for HandleError {
+    fn from(error: InstanceDomainError) -> HandleError {
+        HandleError::Domain(error)
+    }
+}
+
+/// Validates an account handle, as typed by a user, without fetching it.
+///
+/// Surrounding whitespace and a leading `@` or `acct:` are removed. The rest must be a user and a
+/// domain separated by a single `@` (see [`Resource::parse`]), the domain must be a valid
+/// [`InstanceDomain`], and the user must not contain characters that would make the request
+/// invalid. It allows forms to refuse invalid handles right away, before any request is made.
+///
+/// ```rust
+/// use webfinger::validate_handle;
+///
+/// let acct = validate_handle(" @alice@Bücher.example ").unwrap();
+/// assert_eq!(acct.to_string(), "alice@xn--bcher-kva.example");
+/// assert!(validate_handle("alice@sub@example.org").is_err());
+/// ```
+pub fn validate_handle(input: &str) -> Result<Acct, HandleError> {
+    let input = input.trim();
+    let handle = match input.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("acct:") => &input[5..],
+        _ => input.strip_prefix('@').unwrap_or(input),
+    };
+    let resource = Resource::parse(&format!("acct:{}", handle))?;
+
+    if let Some(c) = resource
+        .user
+        .chars()
+        .find(|c| c.is_whitespace() || c.is_control() || matches!(c, '#' | '&' | '?'))
+    {
+        return Err(HandleError::InvalidCharacter(c));
+    }
+    let mut bytes = resource.user.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let valid = bytes.next().is_some_and(|b| b.is_ascii_hexdigit())
+                && bytes.next().is_some_and(|b| b.is_ascii_hexdigit());
+            if !valid {
+                return Err(HandleError::InvalidPercentEncoding);
+            }
+        }
+    }
+
+    Ok(Acct {
+        domain: InstanceDomain::new(&resource.host())?,
+        user: resource.user,
+    })
+}
@@ -0,0 +1,135 @@
+use crate::{consts, prepare_request, url_for, Prefix, RequestOptions, Webfinger, WebfingerClient};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// The result of a health check of the WebFinger endpoint of an instance.
+///
+/// It can be serialized, to be exposed to monitoring tools for instance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthReport {
+    /// The domain of the instance that was checked.
+    pub domain: String,
+
+    /// The resource that was requested.
+    pub probe: String,
+
+    /// The URL that was fetched.
+    pub url: String,
+
+    /// `true` if the request was made over HTTPS.
+    pub https: bool,
+
+    /// The HTTP status of the response, if one was received.
+    pub status: Option<u16>,
+
+    /// The `Content-Type` of the response.
+    pub content_type: Option<String>,
+
+    /// The time it took to get a response.
+    pub latency: Duration,
+
+    /// `true` if the response was a valid WebFinger document.
+    pub valid_document: bool,
+
+    /// The expected relations that were not in the document.
+    pub missing_rels: Vec<String>,
+
+    /// A description of the error that prevented to get a response, if any.
+    pub error: Option<String>,
+}
+
+impl HealthReport {
+    /// Returns `true` if the response had a JSON content type.
+    ///
+    /// RFC 7033 requires `application/jrd+json`, but `application/json` is widely used and
+    /// understood too.
+    pub fn content_type_ok(&self) -> bool {
+        self.content_type.as_deref().is_some_and(|t| {
+            let t = t.split(';').next().unwrap_or_default().trim();
+            t.eq_ignore_ascii_case(consts::JRD_MIME) || t.eq_ignore_ascii_case(consts::JSON_MIME)
+        })
+    }
+
+    /// Returns `true` if every check passed.
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none()
+            && self.https
+            && self.status.is_some_and(|s| (200..300).contains(&s))
+            && self.content_type_ok()
+            && self.valid_document
+            && self.missing_rels.is_empty()
+    }
+}
+
+impl WebfingerClient {
+    /// Checks that the WebFinger endpoint of an instance works correctly.
+    ///
+    /// `probe` is the account to look for, by default the instance actor (`domain@domain`), as
+    /// used by Mastodon and Plume. The returned document is expected to contain links with every
+    /// relation of `required_rels`.
+    pub async fn check_instance(
+        &self,
+        domain: &str,
+        probe: Option<&str>,
+        required_rels: &[&str],
+    ) -> HealthReport {
+        let probe = probe
+            .map(String::from)
+            .unwrap_or_else(|| format!("{}@{}", domain, domain));
+        let mut report = HealthReport {
+            domain: domain.to_string(),
+            probe: format!("acct:{}", probe),
+            url: String::new(),
+            https: self.with_https,
+            status: None,
+            content_type: None,
+            latency: Duration::default(),
+            valid_document: false,
+            missing_rels: required_rels.iter().map(|r| r.to_string()).collect(),
+            error: None,
+        };
+
+        let request = url_for(Prefix::Acct, probe, self.with_https).and_then(|url| {
+            report.url = url.clone();
+            let options = self.options_for(&url, &RequestOptions::default());
+            prepare_request(url, &options)
+        });
+        let request = match request {
+            Ok(request) => request,
+            Err(error) => {
+                report.error = Some(format!("{:?}", error));
+                return report;
+            }
+        };
+
+        let start = Instant::now();
+        let response = self.http.fetch(request).await;
+        report.latency = start.elapsed();
+        let response = match response {
+            Ok(response) => response,
+            Err(error) => {
+                report.error = Some(format!("{:?}", error));
+                return report;
+            }
+        };
+
+        report.status = Some(response.status);
+        report.content_type = response.header("Content-Type").map(String::from);
+        if let Ok(webfinger) = serde_json::from_slice::<Webfinger>(&response.body) {
+            report.valid_document = true;
+            report
+                .missing_rels
+                .retain(|rel| webfinger.links().rel(rel).next().is_none());
+        }
+        report
+    }
+}
+
+/// Checks that the WebFinger endpoint of an instance works, using its instance actor.
+///
+/// See [`WebfingerClient::check_instance`] for more control over the checks.
+pub async fn check_instance(domain: &str) -> HealthReport {
+    WebfingerClient::new()
+        .check_instance(domain, None, &["self"])
+        .await
+}
@@ -0,0 +1,102 @@
+use crate::Resolved;
+use futures_util::future::{select, Either};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::pin,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The number of latencies of each host used to compute its hedging delay.
+const MAX_SAMPLES: usize = 100;
+
+/// The number of hosts whose latencies are kept.
+const MAX_HOSTS: usize = 1024;
+
+/// The number of latencies needed before the percentile is used instead of the initial delay.
+const MIN_SAMPLES: usize = 10;
+
+/// Sends a second request when the first one is slower than most of the previous ones to the
+/// same host.
+#[derive(Debug)]
+pub(crate) struct Hedge {
+    percentile: f64,
+    initial_delay: Duration,
+    samples: Mutex<HashMap<String, VecDeque<Duration>>>,
+}
+
+impl Hedge {
+    pub(crate) fn new(percentile: f64, initial_delay: Duration) -> Hedge {
+        Hedge {
+            percentile: percentile.clamp(0.0, 1.0),
+            initial_delay,
+            samples: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns how long to wait for the first request to `host` before sending the second one.
+    pub(crate) fn delay(&self, host: &str) -> Duration {
+        let samples = self.samples.lock().unwrap();
+        let samples = match samples.get(host) {
+            Some(samples) if samples.len() >= MIN_SAMPLES => samples,
+            _ => return self.initial_delay,
+        };
+        let mut sorted: Vec<_> = samples.iter().copied().collect();
+        sorted.sort();
+        let index = ((sorted.len() - 1) as f64 * self.percentile).round() as usize;
+        sorted[index]
+    }
+
+    pub(crate) fn record(&self, host: &str, latency: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() == MAX_HOSTS && !samples.contains_key(host) {
+            // The host with the fewest samples is the one whose delay is the least known
+            let fewest = samples
+                .iter()
+                .min_by_key(|(_, samples)| samples.len())
+                .map(|(host, _)| host.clone());
+            if let Some(fewest) = fewest {
+                samples.remove(&fewest);
+            }
+        }
+        let samples = samples
+            .entry(host.to_string())
+            .or_insert_with(|| VecDeque::with_capacity(MAX_SAMPLES));
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(latency);
+    }
+
+    /// Runs `request` to `host`, running it a second time if it takes too long, and returns the
+    /// first successful response.
+    ///
+    /// When one of the two requests fails, the other one is still awaited, and its result is
+    /// returned.
+    pub(crate) async fn run<F, Fut, E>(&self, host: &str, mut request: F) -> Result<Resolved, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<Resolved, E>>,
+    {
+        let start = Instant::now();
+        let first = pin!(request());
+        let timer = pin!(tokio::time::sleep(self.delay(host)));
+        let result = match select(first, timer).await {
+            Either::Left((result, _)) => result,
+            Either::Right((_, first)) => {
+                let second = pin!(request());
+                match select(first, second).await {
+                    Either::Left((Ok(resolved), _)) | Either::Right((Ok(resolved), _)) => {
+                        Ok(resolved)
+                    }
+                    Either::Left((Err(_), other)) | Either::Right((Err(_), other)) => other.await,
+                }
+            }
+        };
+        if result.is_ok() {
+            self.record(host, start.elapsed());
+        }
+        result
+    }
+}
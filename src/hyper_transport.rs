@@ -0,0 +1,62 @@
+//! Support for `hyper` clients, to use custom connectors (Unix sockets, Tor, SOCKS proxies, etc).
+
+use crate::{HttpFetch, HttpRequest, HttpResponse, Timings, WebfingerError, MAX_BODY_SIZE};
+use futures_util::future::BoxFuture;
+use http_body_util::{BodyExt, Empty, Limited};
+use hyper::body::Bytes;
+use hyper_util::client::legacy::{connect::Connect, Client};
+use std::time::Instant;
+
+/// Makes requests with a `hyper` client, whatever its connector is.
+///
+/// The client must be built with an `Empty<Bytes>` body type. The connector is responsible for
+/// TLS: a plain `HttpConnector` can only make requests over HTTP.
+impl<C> HttpFetch for Client<C, Empty<Bytes>>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let mut builder = hyper::Request::get(&request.url);
+            for (name, value) in &request.headers {
+                builder = builder.header(&name[..], &value[..]);
+            }
+            let hyper_request = builder
+                .body(Empty::new())
+                .map_err(|_| WebfingerError::ParseError)?;
+
+            let response = self
+                .request(hyper_request)
+                .await
+                .map_err(|_| WebfingerError::HttpError)?;
+            let ttfb = start.elapsed();
+            let status = response.status().as_u16();
+            let headers = response
+                .headers()
+                .iter()
+                .filter_map(|(name, value)| {
+                    value
+                        .to_str()
+                        .ok()
+                        .map(|value| (name.as_str().to_string(), value.to_string()))
+                })
+                .collect();
+            let body = Limited::new(response.into_body(), MAX_BODY_SIZE)
+                .collect()
+                .await
+                .map_err(|_| WebfingerError::HttpError)?
+                .to_bytes()
+                .to_vec();
+            Ok(HttpResponse {
+                status,
+                headers,
+                body,
+                timings: Timings {
+                    ttfb: Some(ttfb),
+                    ..Timings::default()
+                },
+            })
+        })
+    }
+}
@@ -0,0 +1,345 @@
+use crate::{Caller, InstanceDomain, Prefix, Resolver, ResolverError, Webfinger};
+use url::Url;
+
+/// The characters allowed in identifiers by an [`IdentifierPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Charset {
+    /// Only ASCII letters and digits.
+    Ascii,
+    /// Any Unicode letter or digit.
+    Unicode,
+}
+
+/// Rules that the identifiers of requested resources must follow.
+///
+/// The identifier is the part before the `@` (`user` in `acct:user@example.org`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdentifierPolicy {
+    /// The letters and digits that are allowed.
+    pub charset: Charset,
+
+    /// Other characters that are allowed, like `_` or `.`.
+    pub extra_chars: String,
+
+    /// The maximum length of identifiers, in characters.
+    pub max_length: usize,
+
+    /// Refuses identifiers mixing letters from different scripts.
+    ///
+    /// Such identifiers are often used to impersonate someone, as `аdmin` (with a Cyrillic `а`)
+    /// looks exactly like `admin`. With the `unicode-security` feature, identifiers must be
+    /// single-script as defined by [Unicode TR39](https://www.unicode.org/reports/tr39/), which
+    /// covers all scripts (and still accepts Japanese mixing kanji and kana). Without it, only
+    /// identifiers mixing Latin, Greek and Cyrillic letters are refused.
+    pub reject_mixed_scripts: bool,
+}
+
+impl Default for IdentifierPolicy {
+    fn default() -> IdentifierPolicy {
+        IdentifierPolicy {
+            charset: Charset::Unicode,
+            extra_chars: "_-.".to_string(),
+            max_length: 64,
+            reject_mixed_scripts: true,
+        }
+    }
+}
+
+impl IdentifierPolicy {
+    /// Checks that an identifier follows this policy.
+    pub fn check(&self, identifier: &str) -> Result<(), ResolverError> {
+        let allowed = |c: char| {
+            self.extra_chars.contains(c)
+                || match self.charset {
+                    Charset::Ascii => c.is_ascii_alphanumeric(),
+                    Charset::Unicode => c.is_alphanumeric(),
+                }
+        };
+
+        if identifier.is_empty()
+            || identifier.chars().count() > self.max_length
+            || !identifier.chars().all(allowed)
+            || (self.reject_mixed_scripts && has_mixed_scripts(identifier))
+        {
+            Err(ResolverError::InvalidIdentifier)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(feature = "unicode-security"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+}
+
+#[cfg(not(feature = "unicode-security"))]
+pub(crate) fn script_of(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}' => {
+            Some(Script::Latin)
+        }
+        '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Some(Script::Greek),
+        '\u{0400}'..='\u{052F}' | '\u{2DE0}'..='\u{2DFF}' | '\u{A640}'..='\u{A69F}' => {
+            Some(Script::Cyrillic)
+        }
+        _ => None,
+    }
+}
+
+/// Returns `true` if `s` is not single-script, according to Unicode TR39.
+#[cfg(feature = "unicode-security")]
+pub(crate) fn has_mixed_scripts(s: &str) -> bool {
+    use unicode_security::MixedScript;
+
+    !s.is_single_script()
+}
+
+/// Returns `true` if `s` contains letters from more than one of the Latin, Greek and Cyrillic
+/// scripts.
+#[cfg(not(feature = "unicode-security"))]
+pub(crate) fn has_mixed_scripts(s: &str) -> bool {
+    let mut scripts = s.chars().filter_map(script_of);
+    match scripts.next() {
+        Some(first) => scripts.any(|script| script != first),
+        None => false,
+    }
+}
+
+/// Characters commonly used to imitate Latin letters, with the letters they look like.
+///
+/// This is a small subset of the confusables of [Unicode TR39](https://www.unicode.org/reports/tr39/),
+/// covering the scripts that are most often used for impersonation.
+const CONFUSABLES: &[(char, &str)] = &[
+    // Cyrillic
+    ('а', "a"),
+    ('е', "e"),
+    ('о', "o"),
+    ('р', "p"),
+    ('с', "c"),
+    ('у', "y"),
+    ('х', "x"),
+    ('і', "i"),
+    ('ј', "j"),
+    ('ѕ', "s"),
+    ('ԁ', "d"),
+    ('ԛ', "q"),
+    ('ԝ', "w"),
+    ('һ', "h"),
+    ('ӏ', "l"),
+    ('А', "A"),
+    ('В', "B"),
+    ('Е', "E"),
+    ('К', "K"),
+    ('М', "M"),
+    ('Н', "H"),
+    ('О', "O"),
+    ('Р', "P"),
+    ('С', "C"),
+    ('Т', "T"),
+    ('Х', "X"),
+    ('У', "Y"),
+    ('І', "l"),
+    ('Ј', "J"),
+    ('Ѕ', "S"),
+    // Greek
+    ('α', "a"),
+    ('ο', "o"),
+    ('ν', "v"),
+    ('ρ', "p"),
+    ('ι', "i"),
+    ('Α', "A"),
+    ('Β', "B"),
+    ('Ε', "E"),
+    ('Ζ', "Z"),
+    ('Η', "H"),
+    ('Ι', "l"),
+    ('Κ', "K"),
+    ('Μ', "M"),
+    ('Ν', "N"),
+    ('Ο', "O"),
+    ('Ρ', "P"),
+    ('Τ', "T"),
+    ('Υ', "Y"),
+    ('Χ', "X"),
+    // Latin and digits
+    ('ı', "i"),
+    ('ℓ', "l"),
+    ('I', "l"),
+    ('1', "l"),
+    ('|', "l"),
+    ('0', "O"),
+];
+
+/// Sequences of characters imitating a single letter, with the letter they look like.
+const CONFUSABLE_SEQUENCES: &[(&str, &str)] = &[("rn", "m")];
+
+/// Computes a "skeleton" of `s`: two strings that look the same have the same skeleton.
+///
+/// The skeleton is case-folded, so that a confusable character imitates both cases of a letter:
+/// `Admin` and `аdmin` (with a Cyrillic `а`) have the same skeleton. Confusables are replaced
+/// before folding, so that `I` still imitates `l`, and again after it, for the characters that
+/// only have a confusable lowercase form. Sequences like `rn` (for `m`) are folded before each
+/// pass on single characters.
+fn skeleton(s: &str) -> String {
+    let replace = |s: &str| -> String {
+        let s = CONFUSABLE_SEQUENCES
+            .iter()
+            .fold(s.to_string(), |s, (sequence, latin)| {
+                s.replace(sequence, latin)
+            });
+        s.chars()
+            .map(|c| {
+                CONFUSABLES
+                    .iter()
+                    .find(|(confusable, _)| *confusable == c)
+                    .map(|(_, latin)| latin.to_string())
+                    .unwrap_or_else(|| c.to_string())
+            })
+            .collect()
+    };
+    replace(&replace(s).to_lowercase()).to_lowercase()
+}
+
+/// Splits a handle in its user and lowercased domain, ignoring a leading `acct:` or `@`.
+fn handle_parts(handle: &str) -> (&str, String) {
+    let handle = handle.trim();
+    let handle = handle
+        .strip_prefix("acct:")
+        .or_else(|| handle.strip_prefix('@'))
+        .unwrap_or(handle);
+    let mut parts = handle.splitn(2, '@');
+    let user = parts.next().unwrap_or_default();
+    let domain = parts.next().unwrap_or_default().to_lowercase();
+    (user, domain)
+}
+
+/// Returns `true` if two different handles look the same.
+///
+/// It can be used to warn users that a handle they pasted may be used to impersonate someone
+/// else, like `аdmin@example.org` (with a Cyrillic `а`) and `admin@example.org`. Handles that are
+/// actually the same (`acct:admin@Example.org` and `@admin@example.org` for instance) are not
+/// considered confusable. Neither are handles that only differ by case (`Admin@example.org` and
+/// `admin@example.org`): most servers treat them as the same account, and the difference is
+/// visible anyway.
+pub fn acct_confusable_with(a: &str, b: &str) -> bool {
+    let (a_user, a_domain) = handle_parts(a);
+    let (b_user, b_domain) = handle_parts(b);
+    if a_user.to_lowercase() == b_user.to_lowercase() && a_domain == b_domain {
+        return false;
+    }
+
+    skeleton(a_user) == skeleton(b_user) && skeleton(&a_domain) == skeleton(&b_domain)
+}
+
+/// A resolver wrapper refusing requests for identifiers that don't follow an
+/// [`IdentifierPolicy`].
+///
+/// Invalid identifiers are refused with [`ResolverError::InvalidIdentifier`], before reaching the
+/// wrapped resolver.
+#[derive(Debug, Clone)]
+pub struct StrictIdentifiers<T> {
+    inner: T,
+    policy: IdentifierPolicy,
+}
+
+impl<T> StrictIdentifiers<T> {
+    /// Wraps a resolver, checking the requested identifiers with `policy`.
+    pub fn new(inner: T, policy: IdentifierPolicy) -> StrictIdentifiers<T> {
+        StrictIdentifiers { inner, policy }
+    }
+
+    /// Returns the wrapped resolver.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<R, T: Resolver<R>> Resolver<R> for StrictIdentifiers<T> {
+    fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain()
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain)
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.policy.check(&acct)?;
+        self.inner.find(prefix, acct, resource_repo)
+    }
+
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.policy.check(&acct)?;
+        self.inner
+            .find_for_caller(prefix, acct, caller, resource_repo)
+    }
+
+    fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.inner.find_by_url(url, resource_repo)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, R> crate::AsyncResolver<R> for StrictIdentifiers<T>
+where
+    T: crate::AsyncResolver<R> + Send + Sync,
+    R: Send,
+{
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain().await
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain).await
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.policy.check(&acct)?;
+        self.inner.find(prefix, acct, resource_repo).await
+    }
+
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.policy.check(&acct)?;
+        self.inner
+            .find_for_caller(prefix, acct, caller, resource_repo)
+            .await
+    }
+
+    async fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.inner.find_by_url(url, resource_repo).await
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{parse_resource, InstanceDomain, Prefix, Resolver, ResolverError, Webfinger};
+use std::collections::{BTreeSet, HashMap};
+
+/// A resolver serving documents loaded in memory.
+///
+/// Documents are indexed by their subject, so that [`find`](Resolver::find) runs in constant time
+/// even with hundreds of thousands of them. It is meant for read-only mirrors and archives, that
+/// serve a large static dataset. It works with any resource repository, that is ignored.
+///
+/// ```rust
+/// use webfinger::{IndexedResolver, InstanceDomain, Prefix, Resolver, Webfinger};
+///
+/// let domain = InstanceDomain::new("example.org").unwrap();
+/// let mut resolver = IndexedResolver::new(domain).with_prefix_search();
+/// resolver.insert(Webfinger::new("acct:alice@example.org")).unwrap();
+///
+/// assert!(resolver.endpoint("acct:alice@example.org", ()).is_ok());
+/// assert_eq!(resolver.search(&Prefix::Acct, "al").count(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct IndexedResolver {
+    domain: InstanceDomain,
+    documents: HashMap<String, Webfinger>,
+    sorted: Option<BTreeSet<String>>,
+}
+
+impl IndexedResolver {
+    /// Creates an empty index for the resources of `domain`.
+    pub fn new(domain: InstanceDomain) -> IndexedResolver {
+        IndexedResolver {
+            domain,
+            documents: HashMap::new(),
+            sorted: None,
+        }
+    }
+
+    /// Also indexes the identifiers in order, to allow [`IndexedResolver::search`].
+    ///
+    /// It uses more memory, so it is disabled by default.
+    pub fn with_prefix_search(mut self) -> IndexedResolver {
+        self.sorted = Some(self.documents.keys().cloned().collect());
+        self
+    }
+
+    /// Adds a document to the index, replacing the one with the same subject if any.
+    ///
+    /// The subject must be a resource of the domain of this index.
+    pub fn insert(&mut self, webfinger: Webfinger) -> Result<(), ResolverError> {
+        let resource = parse_resource(&webfinger.subject)?;
+        let host =
+            InstanceDomain::new(&resource.host()).map_err(|_| ResolverError::InvalidResource)?;
+        if host != self.domain {
+            return Err(ResolverError::WrongDomain);
+        }
+        let key = index_key(&resource.prefix, &resource.user);
+        if let Some(ref mut sorted) = self.sorted {
+            sorted.insert(key.clone());
+        }
+        self.documents.insert(key, webfinger);
+        Ok(())
+    }
+
+    /// Returns the number of documents in the index.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Returns `true` if the index contains no documents.
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Returns the documents with the given prefix whose identifier starts with `start`, sorted by
+    /// identifier.
+    ///
+    /// Nothing is returned if prefix search was not enabled with
+    /// [`IndexedResolver::with_prefix_search`].
+    pub fn search<'a>(
+        &'a self,
+        prefix: &Prefix,
+        start: &str,
+    ) -> impl Iterator<Item = &'a Webfinger> + 'a {
+        let start = index_key(prefix, start);
+        self.sorted
+            .iter()
+            .flat_map(move |sorted| {
+                let start = start.clone();
+                sorted
+                    .range(start.clone()..)
+                    .take_while(move |key| key.starts_with(&start))
+            })
+            .filter_map(move |key| self.documents.get(key))
+    }
+}
+
+fn index_key(prefix: &Prefix, user: &str) -> String {
+    format!("{}:{}", String::from(prefix.clone()), user)
+}
+
+impl<R> Resolver<R> for IndexedResolver {
+    fn instance_domain(&self) -> InstanceDomain {
+        self.domain.clone()
+    }
+
+    fn find(&self, prefix: Prefix, acct: String, _: R) -> Result<Webfinger, ResolverError> {
+        self.documents
+            .get(&index_key(&prefix, &acct))
+            .cloned()
+            .ok_or(ResolverError::NotFound)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: Send> crate::AsyncResolver<R> for IndexedResolver {
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.domain.clone()
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        Resolver::find(self, prefix, acct, repo)
+    }
+}
@@ -1,23 +1,251 @@
 //! A crate to help you fetch and serve WebFinger resources.
 //!
 //! Use [`resolve`] to fetch remote resources, and [`Resolver`] to serve your own resources.
+//! If you need more control over how resources are fetched, use a [`WebfingerClient`].
+//!
+//! Every error has a stable code, like `WF0005`, returned by its `code` method and included in its
+//! `Display` output. Applications can use them to show translated messages, while their logs
+//! stay easy to search.
+//!
+//! The client is behind the `client` feature, enabled by the `fetch-*` features, and the resolver
+//! traits are behind the `server` feature. Both are enabled by default. With
+//! `default-features = false`, only the data model is built: the documents, their links and
+//! their validation, with serde but without any HTTP or async dependency.
 
-use reqwest::{header::ACCEPT, Client};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, convert::TryFrom, fmt};
+use url::Url;
+
+mod aliases;
+pub use crate::aliases::*;
+
+mod base_url;
+pub use crate::base_url::*;
+
+#[cfg(feature = "client")]
+mod batch;
+#[cfg(feature = "client")]
+pub use crate::batch::*;
+
+#[cfg(feature = "client")]
+mod cache;
+#[cfg(feature = "client")]
+pub use crate::cache::*;
+
+mod case;
+pub use crate::case::*;
+
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "client")]
+pub use crate::client::*;
+
+#[cfg(feature = "client")]
+mod config;
+#[cfg(feature = "client")]
+pub use crate::config::*;
+
+#[cfg(feature = "server")]
+mod context;
+#[cfg(feature = "server")]
+pub use crate::context::*;
+
+#[cfg(feature = "server")]
+mod deny_rels;
+#[cfg(feature = "server")]
+pub use crate::deny_rels::*;
+
+mod domain;
+pub use crate::domain::*;
+
+mod error;
+pub use crate::error::*;
+
+mod expires;
+#[cfg(feature = "client")]
+pub(crate) use crate::expires::*;
+
+mod handle;
+pub use crate::handle::*;
+
+#[cfg(feature = "client")]
+mod health;
+#[cfg(feature = "client")]
+pub use crate::health::*;
+
+#[cfg(feature = "server")]
+mod identifier;
+#[cfg(feature = "server")]
+pub use crate::identifier::*;
+
+#[cfg(feature = "server")]
+mod indexed;
+#[cfg(feature = "server")]
+pub use crate::indexed::*;
+
+mod links;
+pub use crate::links::*;
+
+mod matrix;
+
+#[cfg(feature = "client")]
+mod metadata;
+#[cfg(feature = "client")]
+pub use crate::metadata::*;
+
+mod ndjson;
+pub use crate::ndjson::*;
+
+mod parse;
+pub use crate::parse::*;
 
+#[cfg(feature = "client")]
+mod pinning;
+#[cfg(feature = "client")]
+pub use crate::pinning::*;
+
+mod prefix_registry;
+pub use crate::prefix_registry::*;
+
+#[cfg(feature = "server")]
+mod prefix_router;
+#[cfg(feature = "server")]
+pub use crate::prefix_router::*;
+
+#[cfg(all(feature = "client", feature = "server"))]
+mod proxy;
+#[cfg(all(feature = "client", feature = "server"))]
+pub use crate::proxy::*;
+
+#[cfg(feature = "server")]
+mod query;
+#[cfg(feature = "server")]
+pub use crate::query::*;
+
+#[cfg(feature = "client")]
+mod rate_limit;
+#[cfg(feature = "client")]
+pub use crate::rate_limit::*;
+
+#[cfg(feature = "client")]
+mod recorder;
+#[cfg(feature = "client")]
+pub use crate::recorder::*;
+
+#[cfg(feature = "server")]
 mod resolver;
+#[cfg(feature = "server")]
 pub use crate::resolver::*;
 
+#[cfg(feature = "server")]
+mod lookup_cache;
+#[cfg(feature = "server")]
+pub use crate::lookup_cache::*;
+
+mod resource;
+pub use crate::resource::*;
+
+#[cfg(feature = "server")]
+mod response;
+#[cfg(feature = "server")]
+pub use crate::response::*;
+
+mod set;
+pub use crate::set::*;
+
+#[cfg(feature = "client")]
+mod software;
+#[cfg(feature = "client")]
+pub use crate::software::*;
+
+#[cfg(feature = "client")]
+mod status;
+#[cfg(feature = "client")]
+pub use crate::status::*;
+
+#[cfg(feature = "server")]
+mod subject;
+#[cfg(feature = "server")]
+pub use crate::subject::*;
+
+mod template;
+pub use crate::template::*;
+
+#[cfg(feature = "server")]
+mod transform;
+#[cfg(feature = "server")]
+pub use crate::transform::*;
+
+#[cfg(feature = "client")]
+mod transport;
+#[cfg(feature = "client")]
+pub use crate::transport::*;
+
+#[cfg(feature = "client")]
+mod txt_discovery;
+#[cfg(feature = "client")]
+pub use crate::txt_discovery::*;
+
+#[cfg(feature = "server")]
+mod web_domain;
+#[cfg(feature = "server")]
+pub use crate::web_domain::*;
+
 #[cfg(feature = "async")]
 mod async_resolver;
 #[cfg(feature = "async")]
 pub use crate::async_resolver::*;
 
-#[cfg(test)]
+#[cfg(feature = "async")]
+mod async_ref_resolver;
+#[cfg(feature = "async")]
+pub use crate::async_ref_resolver::*;
+
+#[cfg(feature = "async")]
+mod local_async_resolver;
+#[cfg(feature = "async")]
+pub use crate::local_async_resolver::*;
+
+#[cfg(all(feature = "async", feature = "tokio"))]
+mod sync_as_async;
+#[cfg(all(feature = "async", feature = "tokio"))]
+pub use crate::sync_as_async::*;
+
+#[cfg(feature = "fetch-ureq")]
+mod blocking;
+#[cfg(feature = "fetch-ureq")]
+pub use crate::blocking::*;
+
+#[cfg(feature = "fetch-hyper")]
+mod hyper_transport;
+
+#[cfg(all(feature = "tokio", feature = "client"))]
+mod hedge;
+#[cfg(all(feature = "tokio", feature = "client"))]
+pub(crate) use crate::hedge::*;
+
+#[cfg(all(feature = "tokio", feature = "client"))]
+mod warmer;
+#[cfg(all(feature = "tokio", feature = "client"))]
+pub use crate::warmer::*;
+
+pub mod consts;
+
+#[cfg(feature = "fediverse")]
+pub mod fediverse;
+
+#[cfg(feature = "test-support")]
+pub mod test_support;
+
+#[cfg(all(test, feature = "client", feature = "server"))]
 mod tests;
 
+#[cfg(test)]
+mod types_tests;
+
 /// WebFinger result that may serialized or deserialized to JSON
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Webfinger {
     /// The subject of this WebFinger result.
     ///
@@ -29,14 +257,79 @@ pub struct Webfinger {
     pub aliases: Vec<String>,
 
     /// Links to places where you may find more information about this resource.
+    ///
+    /// Links keep the order they have in the document, which servers may use to express their
+    /// preferences: parsing, merging, transforming and serializing documents never reorder them.
+    /// See [`Webfinger::preferred_link`] to select a link in a deterministic way.
     pub links: Vec<Link>,
+
+    /// Additional information about the subject, as a map from property URIs to values.
+    ///
+    /// Values may be `null`, which is kept as `None`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub properties: HashMap<String, Option<String>>,
+
+    /// When this document should be considered outdated, as an RFC 3339 date.
+    ///
+    /// This field is not part of RFC 7033, but some implementations use it to help caches. See
+    /// [`Webfinger::expires_at`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+
+    /// The members of the document that are not described above, like the extensions of some
+    /// servers.
+    ///
+    /// They are kept as is, so that a parsed document is serialized again without losing
+    /// anything.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Webfinger {
+    /// Creates a document about `subject`, without any alias, link or property.
+    ///
+    /// The other fields can be set with the struct update syntax, which keeps compiling when new
+    /// fields are added:
+    ///
+    /// ```
+    /// # use webfinger::{Link, Webfinger};
+    /// let webfinger = Webfinger {
+    ///     links: vec![Link::new("self")],
+    ///     ..Webfinger::new("acct:test@example.org")
+    /// };
+    /// assert_eq!(webfinger.links[0].rel, "self");
+    /// ```
+    pub fn new(subject: impl Into<String>) -> Webfinger {
+        Webfinger {
+            subject: subject.into(),
+            aliases: vec![],
+            links: vec![],
+            properties: HashMap::new(),
+            expires: None,
+            extra: Map::new(),
+        }
+    }
+
+    /// Groups the links of this document by their `rel`.
+    ///
+    /// Links sharing the same `rel` keep the order they have in the document. This is useful when
+    /// you need to look for many different relations in the same result.
+    pub fn link_map(&self) -> HashMap<&str, Vec<&Link>> {
+        let mut map: HashMap<&str, Vec<&Link>> = HashMap::new();
+        for link in &self.links {
+            map.entry(link.rel.as_str()).or_default().push(link);
+        }
+        map
+    }
 }
 
 /// Structure to represent a WebFinger link
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Link {
     /// Tells what this link represents
-    pub rel: String,
+    ///
+    /// It can be compared to strings and used as a `&str`, see [`Rel`].
+    pub rel: Rel,
 
     /// The actual URL of the link
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -52,10 +345,27 @@ pub struct Link {
     /// request.
     #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
     pub mime_type: Option<String>,
+
+    /// Human-readable titles of the link, indexed by language tag (like `en-us`).
+    ///
+    /// The `und` tag is used when the language is not known.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub titles: HashMap<String, String>,
+
+    /// Additional information about the link, as a map from property URIs to values.
+    ///
+    /// Values may be `null`, which is kept as `None`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub properties: HashMap<String, Option<String>>,
+
+    /// The members of the link that are not described above, kept as is.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// An error that occured while fetching a WebFinger resource.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum WebfingerError {
     /// The error came from the HTTP client.
     HttpError,
@@ -64,20 +374,164 @@ pub enum WebfingerError {
     ParseError,
 
     /// The received JSON couldn't be parsed into a valid [`Webfinger`] struct.
-    JsonError,
+    ///
+    /// The beginning of the response is included, to help understanding what went wrong.
+    JsonError(ResponseSnippet),
+
+    /// The domain of the resource is blocked by the [`WebfingerClient`].
+    BlockedDomain,
+
+    /// The resource doesn't exist, according to the [`StatusPolicy`] of the client.
+    NotFound,
+
+    /// The server answered with an HTTP status that the [`StatusPolicy`] of the client doesn't
+    /// accept.
+    HttpStatus(u16),
+
+    /// The request was not sent, because the [`RateLimit`] of the client refused it.
+    RateLimited,
+
+    /// The server answered as if no resource was requested.
+    ///
+    /// It either refused the request with a `400` problem document mentioning the `resource`
+    /// parameter, or sent a directory of resources (a JSON array of strings) instead of a
+    /// document. This usually means that the `resource` parameter was dropped on the way, by a
+    /// redirection or a misconfigured proxy.
+    MissingResource,
+
+    /// The certificate of the host didn't match any of its pins.
+    ///
+    /// See [`WebfingerClientBuilder::pin_certificate`].
+    PinMismatch,
+}
+
+impl WebfingerError {
+    /// A stable code identifying this error, from `WF0001` to `WF0099`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            WebfingerError::HttpError => "WF0001",
+            WebfingerError::ParseError => "WF0002",
+            WebfingerError::JsonError(_) => "WF0003",
+            WebfingerError::BlockedDomain => "WF0004",
+            WebfingerError::NotFound => "WF0005",
+            WebfingerError::HttpStatus(_) => "WF0006",
+            WebfingerError::RateLimited => "WF0007",
+            WebfingerError::MissingResource => "WF0008",
+            WebfingerError::PinMismatch => "WF0009",
+        }
+    }
+}
+
+impl fmt::Display for WebfingerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] ", self.code())?;
+        match self {
+            WebfingerError::HttpError => write!(f, "the request failed"),
+            WebfingerError::ParseError => write!(f, "the resource is invalid"),
+            WebfingerError::JsonError(_) => {
+                write!(f, "the response is not a valid WebFinger document")
+            }
+            WebfingerError::BlockedDomain => write!(f, "the domain of the resource is blocked"),
+            WebfingerError::NotFound => write!(f, "the resource doesn't exist"),
+            WebfingerError::HttpStatus(status) => {
+                write!(f, "the server answered with the HTTP status {}", status)
+            }
+            WebfingerError::RateLimited => write!(f, "too many requests were made to this domain"),
+            WebfingerError::MissingResource => {
+                write!(f, "the server didn't receive the requested resource")
+            }
+            WebfingerError::PinMismatch => {
+                write!(f, "the certificate of the server doesn't match its pins")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WebfingerError {}
+
+/// The beginning of a response that couldn't be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseSnippet {
+    /// The `Content-Type` of the response, if there was one.
+    pub content_type: Option<String>,
+
+    /// The first bytes of the body, with control characters replaced by spaces.
+    ///
+    /// It is at most [`ResponseSnippet::MAX_LENGTH`] bytes long.
+    pub body: String,
+}
+
+impl ResponseSnippet {
+    /// The maximum length of [`ResponseSnippet::body`].
+    pub const MAX_LENGTH: usize = 256;
+
+    /// Creates a snippet from a full response body.
+    pub fn new(content_type: Option<&str>, body: &[u8]) -> ResponseSnippet {
+        let body = String::from_utf8_lossy(&body[..body.len().min(Self::MAX_LENGTH)]);
+        // The cut may have happened in the middle of a character
+        let body = body.trim_end_matches('\u{FFFD}');
+        ResponseSnippet {
+            content_type: content_type.map(String::from),
+            body: body
+                .chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect(),
+        }
+    }
 }
 
 /// A prefix for a resource, either `acct:`, `group:` or some custom type.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Prefix {
     /// `acct:` resource
     Acct,
     /// `group:` resource
     Group,
     /// Another type of resource
+    ///
+    /// A [`PrefixRegistry`] can be used to only accept some of them, in a canonical form.
     Custom(String),
 }
 
+impl Prefix {
+    /// Parses a prefix, making sure it is a valid URI scheme.
+    ///
+    /// Contrary to the `From<&str>` implementation, this function refuses prefixes that are not
+    /// valid according to [RFC 3986][rfc], like `ht tp`, as they would produce broken URLs.
+    ///
+    /// [rfc]: https://tools.ietf.org/html/rfc3986#section-3.1
+    pub fn parse(s: &str) -> Result<Prefix, WebfingerError> {
+        if is_valid_scheme(s) {
+            Ok(Prefix::from(s))
+        } else {
+            Err(WebfingerError::ParseError)
+        }
+    }
+
+    /// Checks that this prefix is a valid URI scheme.
+    ///
+    /// It is always the case for prefixes created with [`Prefix::parse`], but custom prefixes
+    /// created with `From<&str>` may be empty or contain invalid characters.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Prefix::Acct | Prefix::Group => true,
+            Prefix::Custom(prefix) => is_valid_scheme(prefix),
+        }
+    }
+}
+
+/// Checks that `s` matches `ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )`
+pub(crate) fn is_valid_scheme(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+}
+
+/// Converts any string to a prefix, even an invalid one like `""`.
+///
+/// It is kept for backwards compatibility, and it also provides an infallible `TryFrom<&str>`
+/// implementation: prefer [`Prefix::parse`], that refuses invalid prefixes instead of producing
+/// broken URLs later.
 impl From<&str> for Prefix {
     fn from(s: &str) -> Prefix {
         match s.to_lowercase().as_ref() {
@@ -88,9 +542,9 @@ impl From<&str> for Prefix {
     }
 }
 
-impl Into<String> for Prefix {
-    fn into(self) -> String {
-        match self {
+impl From<Prefix> for String {
+    fn from(prefix: Prefix) -> String {
+        match prefix {
             Prefix::Acct => "acct".into(),
             Prefix::Group => "group".into(),
             Prefix::Custom(x) => x,
@@ -106,6 +560,8 @@ impl Into<String> for Prefix {
 /// - `acct`: the identifier of the resource, for instance: `someone@example.org`
 /// - `with_https`: indicates wether the URL should be on HTTPS or HTTP
 ///
+/// It fails with [`WebfingerError::ParseError`] if `acct` doesn't contain exactly one `@` between
+/// a user and a domain. An `@` that is part of the user must be percent-encoded (`%40`).
 pub fn url_for(
     prefix: Prefix,
     acct: impl Into<String>,
@@ -115,59 +571,115 @@ pub fn url_for(
     let scheme = if with_https { "https" } else { "http" };
 
     let prefix: String = prefix.into();
-    acct.split('@')
-        .nth(1)
-        .ok_or(WebfingerError::ParseError)
-        .map(|instance| {
-            format!(
-                "{}://{}/.well-known/webfinger?resource={}:{}",
-                scheme, instance, prefix, acct
-            )
-        })
+    if !is_valid_scheme(&prefix) {
+        return Err(WebfingerError::ParseError);
+    }
+
+    // Any @ in the user must be percent-encoded, so the domain is never ambiguous
+    match acct.split_once('@') {
+        Some((user, instance))
+            if !user.is_empty() && !instance.is_empty() && !instance.contains('@') =>
+        {
+            Ok(format!(
+                "{}://{}{}?resource={}:{}",
+                scheme,
+                instance,
+                consts::WELL_KNOWN_PATH,
+                prefix,
+                // Keeps the percent-encoded characters of the user encoded once received
+                acct.replace('%', "%25")
+            ))
+        }
+        _ => Err(WebfingerError::ParseError),
+    }
+}
+
+/// Computes the canonical URL of the WebFinger endpoint of `domain` for a resource, as a client
+/// would query it.
+///
+/// # Parameters
+///
+/// - `domain`: the domain of the instance, with its port if there is one
+/// - `scheme`: `https`, or `http` for local development
+/// - `resource`: the complete resource, for instance: `acct:someone@example.org`
+/// - `rels`: the link relations to ask for, sent as `rel` parameters
+///
+/// It is the URL [`WebfingerClient`] requests for this resource, so it can be used to publish
+/// links to WebFinger documents (in sitemaps or HTML pages for instance) that match the ones
+/// clients use. It fails with [`WebfingerError::ParseError`] if the URL is invalid.
+pub fn well_known_url(
+    domain: &str,
+    scheme: &str,
+    resource: &str,
+    rels: &[&str],
+) -> Result<String, WebfingerError> {
+    let mut url = Url::parse(&format!(
+        "{}://{}{}?resource={}",
+        scheme,
+        domain,
+        consts::WELL_KNOWN_PATH,
+        resource.replace('%', "%25")
+    ))
+    .map_err(|_| WebfingerError::ParseError)?;
+    if url.host_str().is_none() || url.path() != consts::WELL_KNOWN_PATH {
+        return Err(WebfingerError::ParseError);
+    }
+    if !rels.is_empty() {
+        url.query_pairs_mut()
+            .extend_pairs(rels.iter().map(|rel| ("rel", rel)));
+    }
+    Ok(url.into())
 }
 
 /// Fetches a WebFinger resource, identified by the `acct` parameter, a Webfinger URI.
+#[cfg(feature = "fetch-reqwest")]
 pub async fn resolve_with_prefix(
     prefix: Prefix,
     acct: impl Into<String>,
     with_https: bool,
 ) -> Result<Webfinger, WebfingerError> {
-    let url = url_for(prefix, acct, with_https)?;
-    Client::new()
-        .get(&url[..])
-        .header(ACCEPT, "application/jrd+json, application/json")
-        .send()
-        .await
-        .map_err(|_| WebfingerError::HttpError)?
-        .json()
+    WebfingerClient::builder()
+        .https(with_https)
+        .try_build()?
+        .resolve_with_prefix(prefix, acct)
         .await
-        .map_err(|_| WebfingerError::JsonError)
 }
 
 /// Fetches a Webfinger resource.
 ///
 /// If the resource doesn't have a prefix, `acct:` will be used.
+#[cfg(feature = "fetch-reqwest")]
 pub async fn resolve(
     acct: impl Into<String>,
     with_https: bool,
 ) -> Result<Webfinger, WebfingerError> {
-    let acct = acct.into();
+    WebfingerClient::builder()
+        .https(with_https)
+        .try_build()?
+        .resolve(acct)
+        .await
+}
+
+/// Splits a resource into its prefix and its identifier, defaulting to `acct:`.
+#[cfg(feature = "client")]
+fn split_prefix(acct: &str) -> Result<(Prefix, &str), WebfingerError> {
     let mut parsed = acct.splitn(2, ':');
     let first = parsed.next().ok_or(WebfingerError::ParseError)?;
 
     if first.contains('@') {
         // This : was a port number, not a prefix
-        resolve_with_prefix(Prefix::Acct, acct, with_https).await
+        Ok((Prefix::Acct, acct))
     } else if let Some(other) = parsed.next() {
-        resolve_with_prefix(Prefix::from(first), other, with_https).await
+        Ok((Prefix::parse(first)?, other))
     } else {
         // fallback to acct:
-        resolve_with_prefix(Prefix::Acct, first, with_https).await
+        Ok((Prefix::Acct, first))
     }
 }
 
 /// An error that occured while handling an incoming WebFinger request.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum ResolverError {
     /// The requested resource was not correctly formatted
     InvalidResource,
@@ -177,4 +689,132 @@ pub enum ResolverError {
 
     /// The requested resource was not found.
     NotFound,
+
+    /// The identifier of the requested resource is not allowed by the [`IdentifierPolicy`] of
+    /// the server.
+    InvalidIdentifier,
+
+    /// The resolver returned a document with an incomplete subject, refused by
+    /// [`NormalizeSubjects`].
+    InvalidSubject,
+
+    /// The request has no `resource` parameter, or an empty one.
+    ///
+    /// [`EndpointResponse`] answers it with a problem document, as described in
+    /// [RFC 7807](https://tools.ietf.org/html/rfc7807).
+    MissingResource,
+
+    /// The request was cancelled, or its deadline passed, before the lookup ended (see
+    /// [`LookupContext`]).
+    Cancelled,
+}
+
+impl ResolverError {
+    /// A stable code identifying this error, from `WF0101` to `WF0199`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ResolverError::InvalidResource => "WF0101",
+            ResolverError::WrongDomain => "WF0102",
+            ResolverError::NotFound => "WF0103",
+            ResolverError::InvalidIdentifier => "WF0104",
+            ResolverError::InvalidSubject => "WF0105",
+            ResolverError::MissingResource => "WF0106",
+            ResolverError::Cancelled => "WF0107",
+        }
+    }
+
+    /// The HTTP status to answer with.
+    pub fn status(&self) -> u16 {
+        match self {
+            ResolverError::InvalidResource
+            | ResolverError::InvalidIdentifier
+            | ResolverError::MissingResource => 400,
+            ResolverError::WrongDomain | ResolverError::NotFound => 404,
+            ResolverError::InvalidSubject => 500,
+            ResolverError::Cancelled => 503,
+        }
+    }
+}
+
+impl fmt::Display for ResolverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            ResolverError::InvalidResource => "the requested resource is invalid",
+            ResolverError::WrongDomain => "the requested resource is on another domain",
+            ResolverError::NotFound => "the requested resource doesn't exist",
+            ResolverError::InvalidIdentifier => "the requested identifier is not allowed",
+            ResolverError::InvalidSubject => "the subject of the document is incomplete",
+            ResolverError::MissingResource => "no resource was requested",
+            ResolverError::Cancelled => "the lookup was cancelled",
+        };
+        write!(f, "[{}] {}", self.code(), message)
+    }
+}
+
+impl std::error::Error for ResolverError {}
+
+/// Converts the error of a local lookup to the one a client would get for the same resource.
+///
+/// - [`InvalidResource`](ResolverError::InvalidResource) and
+///   [`InvalidIdentifier`](ResolverError::InvalidIdentifier) become
+///   [`ParseError`](WebfingerError::ParseError);
+/// - [`MissingResource`](ResolverError::MissingResource) becomes
+///   [`MissingResource`](WebfingerError::MissingResource);
+/// - [`WrongDomain`](ResolverError::WrongDomain) and [`NotFound`](ResolverError::NotFound)
+///   become [`NotFound`](WebfingerError::NotFound);
+/// - [`InvalidSubject`](ResolverError::InvalidSubject) and
+///   [`Cancelled`](ResolverError::Cancelled) become an
+///   [`HttpStatus`](WebfingerError::HttpStatus) error with the status a server answers with.
+impl From<ResolverError> for WebfingerError {
+    fn from(error: ResolverError) -> WebfingerError {
+        match error {
+            ResolverError::InvalidResource | ResolverError::InvalidIdentifier => {
+                WebfingerError::ParseError
+            }
+            ResolverError::WrongDomain | ResolverError::NotFound => WebfingerError::NotFound,
+            ResolverError::InvalidSubject | ResolverError::Cancelled => {
+                WebfingerError::HttpStatus(error.status())
+            }
+            ResolverError::MissingResource => WebfingerError::MissingResource,
+        }
+    }
+}
+
+/// Converts the error of a remote lookup to the one a resolver would return, when there is one.
+///
+/// [`ParseError`](WebfingerError::ParseError) becomes
+/// [`InvalidResource`](ResolverError::InvalidResource), and
+/// [`NotFound`](WebfingerError::NotFound) becomes [`NotFound`](ResolverError::NotFound), and
+/// [`MissingResource`](WebfingerError::MissingResource) becomes
+/// [`MissingResource`](ResolverError::MissingResource). The other
+/// errors come from the network or from the remote server, and are given back unchanged.
+impl TryFrom<WebfingerError> for ResolverError {
+    type Error = WebfingerError;
+
+    fn try_from(error: WebfingerError) -> Result<ResolverError, WebfingerError> {
+        match error {
+            WebfingerError::ParseError => Ok(ResolverError::InvalidResource),
+            WebfingerError::NotFound => Ok(ResolverError::NotFound),
+            WebfingerError::MissingResource => Ok(ResolverError::MissingResource),
+            error => Err(error),
+        }
+    }
+}
+
+/// The identity of the author of an incoming WebFinger request.
+///
+/// How it is determined (HTTP signatures, OAuth tokens, etc) is up to the HTTP layer of your
+/// application. It is passed to [`Resolver::find_for_caller`] so that more information can be
+/// given to authorized requesters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caller {
+    /// An identifier for the caller, like the ID of its ActivityPub actor.
+    pub id: String,
+}
+
+impl Caller {
+    /// Creates a new caller identity.
+    pub fn new(id: impl Into<String>) -> Caller {
+        Caller { id: id.into() }
+    }
 }
@@ -0,0 +1,450 @@
+use crate::{consts, Link, Webfinger};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Map;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    slice,
+};
+use url::Url;
+
+/// Link relations commonly found in WebFinger results.
+///
+/// It is the type of [`Link::rel`]: the well-known relations don't need any allocation, which
+/// matters when millions of links are kept in a cache, while any other relation is stored as
+/// [`Rel::Custom`]. It dereferences to `&str`, compares to strings, and is serialized as a plain
+/// string, so the JSON of the links is unchanged. Relations are compared by their string value:
+/// `Rel::Custom("self".into())` is equal to `Rel::SelfRel`.
+#[derive(Debug, Clone)]
+pub enum Rel {
+    /// `self`, usually pointing to an ActivityPub actor.
+    SelfRel,
+    /// `http://webfinger.net/rel/profile-page`
+    ProfilePage,
+    /// `http://webfinger.net/rel/avatar`
+    Avatar,
+    /// `http://ostatus.org/schema/1.0/subscribe`, the remote follow template.
+    Subscribe,
+    /// `http://schemas.google.com/g/2010#updates-from`, usually an Atom feed.
+    UpdatesFrom,
+    /// `describedby`, a machine-readable description of the resource (JSON, RDF, etc).
+    DescribedBy,
+    /// `http://www.w3.org/2000/01/rdf-schema#seeAlso`, other documents about the resource.
+    SeeAlso,
+    /// Any other relation.
+    Custom(String),
+}
+
+impl Rel {
+    /// The relation, as it appears in the `rel` field of links.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Rel::SelfRel => "self",
+            Rel::ProfilePage => consts::WEBFINGER_REL_PROFILE_PAGE,
+            Rel::Avatar => consts::WEBFINGER_REL_AVATAR,
+            Rel::Subscribe => "http://ostatus.org/schema/1.0/subscribe",
+            Rel::UpdatesFrom => "http://schemas.google.com/g/2010#updates-from",
+            Rel::DescribedBy => "describedby",
+            Rel::SeeAlso => "http://www.w3.org/2000/01/rdf-schema#seeAlso",
+            Rel::Custom(rel) => rel,
+        }
+    }
+}
+
+impl AsRef<str> for Rel {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Deref for Rel {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for Rel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialEq for Rel {
+    fn eq(&self, other: &Rel) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Rel {}
+
+impl PartialEq<str> for Rel {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Rel {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Rel {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl Hash for Rel {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialOrd for Rel {
+    fn partial_cmp(&self, other: &Rel) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rel {
+    fn cmp(&self, other: &Rel) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl From<String> for Rel {
+    fn from(s: String) -> Rel {
+        match Rel::from(s.as_str()) {
+            Rel::Custom(_) => Rel::Custom(s),
+            rel => rel,
+        }
+    }
+}
+
+impl From<Rel> for String {
+    fn from(rel: Rel) -> String {
+        match rel {
+            Rel::Custom(rel) => rel,
+            rel => rel.as_str().to_string(),
+        }
+    }
+}
+
+impl Serialize for Rel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Rel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Rel, D::Error> {
+        String::deserialize(deserializer).map(Rel::from)
+    }
+}
+
+impl From<&str> for Rel {
+    fn from(s: &str) -> Rel {
+        [
+            Rel::SelfRel,
+            Rel::ProfilePage,
+            Rel::Avatar,
+            Rel::Subscribe,
+            Rel::UpdatesFrom,
+            Rel::DescribedBy,
+            Rel::SeeAlso,
+        ]
+        .iter()
+        .find(|rel| rel.as_str() == s)
+        .cloned()
+        .unwrap_or_else(|| Rel::Custom(s.to_string()))
+    }
+}
+
+/// An error that occured while building a [`Link`] with one of its validating constructors.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum LinkError {
+    /// The `href` is not a valid absolute URL.
+    InvalidUrl,
+
+    /// The `href` is not an `http:` or `https:` URL.
+    UnsupportedScheme(String),
+}
+
+impl LinkError {
+    /// A stable code identifying this error, from `WF0301` to `WF0399`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LinkError::InvalidUrl => "WF0301",
+            LinkError::UnsupportedScheme(_) => "WF0302",
+        }
+    }
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] ", self.code())?;
+        match self {
+            LinkError::InvalidUrl => write!(f, "the link is not a valid URL"),
+            LinkError::UnsupportedScheme(scheme) => {
+                write!(f, "the link should use HTTP(S), not {}", scheme)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+impl Link {
+    /// Creates a link with the relation `rel`, and no other field.
+    ///
+    /// Like [`Webfinger::new`], it can be used with the struct update syntax:
+    ///
+    /// ```
+    /// # use webfinger::Link;
+    /// let link = Link {
+    ///     href: Some("https://example.org/@test".to_string()),
+    ///     ..Link::new("http://webfinger.net/rel/profile-page")
+    /// };
+    /// assert!(link.template.is_none());
+    /// ```
+    pub fn new(rel: impl Into<Rel>) -> Link {
+        Link {
+            rel: rel.into(),
+            href: None,
+            template: None,
+            mime_type: None,
+            titles: HashMap::new(),
+            properties: HashMap::new(),
+            extra: Map::new(),
+        }
+    }
+
+    /// Creates a link to the Atom feed of a resource, as used by OStatus.
+    ///
+    /// Fails if `href` is not an HTTP(S) URL.
+    pub fn atom(href: impl AsRef<str>) -> Result<Link, LinkError> {
+        Link::validated(Rel::UpdatesFrom, href.as_ref(), "application/atom+xml")
+    }
+
+    /// Creates a link to the RSS feed of a resource.
+    ///
+    /// Fails if `href` is not an HTTP(S) URL.
+    pub fn rss(href: impl AsRef<str>) -> Result<Link, LinkError> {
+        Link::validated(Rel::UpdatesFrom, href.as_ref(), "application/rss+xml")
+    }
+
+    /// Creates a `self` link to the ActivityStreams representation of a resource.
+    ///
+    /// Fails if `href` is not an HTTP(S) URL.
+    pub fn activity_stream(href: impl AsRef<str>) -> Result<Link, LinkError> {
+        Link::validated(Rel::SelfRel, href.as_ref(), "application/activity+json")
+    }
+
+    /// Creates a `describedby` link to a description of a resource, of the given media type.
+    ///
+    /// Fails if `href` is not an HTTP(S) URL.
+    pub fn described_by(href: impl AsRef<str>, mime_type: &str) -> Result<Link, LinkError> {
+        Link::validated(Rel::DescribedBy, href.as_ref(), mime_type)
+    }
+
+    /// The title of this link in the language `lang`, like `fr` or `pt-BR`.
+    ///
+    /// Language tags are compared without case. If there is no title in this exact language, a
+    /// title in the same primary language is used (`pt-PT` for `pt-BR`), and then the `und`
+    /// title, meant for any language.
+    pub fn title(&self, lang: &str) -> Option<&str> {
+        let primary = |tag: &str| {
+            tag.split('-')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase()
+        };
+        let find = |matches: &dyn Fn(&str) -> bool| {
+            let mut found: Vec<_> = self.titles.iter().filter(|(tag, _)| matches(tag)).collect();
+            found.sort();
+            found.first().map(|(_, title)| title.as_str())
+        };
+        find(&|tag| tag.eq_ignore_ascii_case(lang))
+            .or_else(|| find(&|tag| primary(tag) == primary(lang)))
+            .or_else(|| find(&|tag| tag.eq_ignore_ascii_case("und")))
+    }
+
+    fn validated(rel: Rel, href: &str, mime_type: &str) -> Result<Link, LinkError> {
+        let url = Url::parse(href).map_err(|_| LinkError::InvalidUrl)?;
+        match url.scheme() {
+            "http" | "https" => Ok(Link {
+                href: Some(url.into()),
+                mime_type: Some(mime_type.to_string()),
+                ..Link::new(rel)
+            }),
+            scheme => Err(LinkError::UnsupportedScheme(scheme.to_string())),
+        }
+    }
+}
+
+/// An iterator over the links of a WebFinger result, that can be filtered.
+///
+/// It is created with [`Webfinger::links`]:
+///
+/// ```
+/// # use webfinger::{Rel, Webfinger};
+/// # fn actor(webfinger: &Webfinger) -> Option<&str> {
+/// webfinger
+///     .links()
+///     .rel(Rel::SelfRel)
+///     .mime("application/activity+json")
+///     .first_href()
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Links<'a> {
+    iter: slice::Iter<'a, Link>,
+    rel: Option<String>,
+    mime: Option<String>,
+    scheme: Option<String>,
+}
+
+impl<'a> Links<'a> {
+    /// Only keeps the links with the given `rel`.
+    pub fn rel(mut self, rel: impl AsRef<str>) -> Links<'a> {
+        self.rel = Some(rel.as_ref().to_string());
+        self
+    }
+
+    /// Only keeps the links with the given media type.
+    ///
+    /// Parameters of the type of the links (like `; charset=utf-8`) and case are ignored.
+    pub fn mime(mut self, mime: impl AsRef<str>) -> Links<'a> {
+        self.mime = Some(mime.as_ref().to_lowercase());
+        self
+    }
+
+    /// Only keeps the links whose `href` uses the given URI scheme, like `xmpp` or `matrix`.
+    ///
+    /// The scheme is compared without case.
+    pub fn scheme(mut self, scheme: impl AsRef<str>) -> Links<'a> {
+        self.scheme = Some(scheme.as_ref().to_lowercase());
+        self
+    }
+
+    /// Returns the `href` of the first remaining link that has one.
+    pub fn first_href(self) -> Option<&'a str> {
+        self.filter_map(|l| l.href.as_deref()).next()
+    }
+
+    /// Returns the `template` of the first remaining link that has one.
+    pub fn first_template(self) -> Option<&'a str> {
+        self.filter_map(|l| l.template.as_deref()).next()
+    }
+
+    fn matches(&self, link: &Link) -> bool {
+        self.rel.as_ref().map_or(true, |rel| &link.rel == rel)
+            && self.mime.as_ref().map_or(true, |mime| {
+                link.mime_type.as_deref().is_some_and(|t| {
+                    t.split(';')
+                        .next()
+                        .unwrap_or_default()
+                        .trim()
+                        .to_lowercase()
+                        == *mime
+                })
+            })
+            && self.scheme.as_ref().map_or(true, |scheme| {
+                link.href
+                    .as_deref()
+                    .and_then(|href| href.split_once(':'))
+                    .is_some_and(|(s, _)| s.eq_ignore_ascii_case(scheme))
+            })
+    }
+}
+
+impl<'a> Iterator for Links<'a> {
+    type Item = &'a Link;
+
+    fn next(&mut self) -> Option<&'a Link> {
+        while let Some(link) = self.iter.next() {
+            if self.matches(link) {
+                return Some(link);
+            }
+        }
+        None
+    }
+}
+
+impl Webfinger {
+    /// Iterates over the links of this document, allowing to filter them.
+    pub fn links(&self) -> Links<'_> {
+        Links {
+            iter: self.links.iter(),
+            rel: None,
+            mime: None,
+            scheme: None,
+        }
+    }
+
+    /// Returns the first `describedby` link of this document.
+    pub fn described_by(&self) -> Option<&Link> {
+        self.links().rel(Rel::DescribedBy).next()
+    }
+
+    /// Returns the `describedby` link with the preferred media type.
+    ///
+    /// `mime_types` are given by order of preference. Links of other types are ignored.
+    pub fn described_by_type(&self, mime_types: &[&str]) -> Option<&Link> {
+        mime_types
+            .iter()
+            .find_map(|mime| self.links().rel(Rel::DescribedBy).mime(mime).next())
+    }
+
+    /// Selects the link with the given `rel` that clients should use.
+    ///
+    /// `mime_types` are given by order of preference: the first link of the first of these types
+    /// that is present is returned. If there is no link of these types, the first link with this
+    /// `rel` is returned instead. Since links keep the order of the document, this selection is the
+    /// same for every consumer of a given document.
+    pub fn preferred_link(&self, rel: impl AsRef<str>, mime_types: &[&str]) -> Option<&Link> {
+        let rel = rel.as_ref();
+        mime_types
+            .iter()
+            .find_map(|mime| self.links().rel(rel).mime(mime).next())
+            .or_else(|| self.links().rel(rel).next())
+    }
+
+    /// Iterates over the links to other services of this resource, using the given URI `scheme`.
+    ///
+    /// It allows to discover the accounts of a resource on other protocols, for instance with
+    /// `xmpp` or `matrix`.
+    pub fn service_links(&self, scheme: impl AsRef<str>) -> Links<'_> {
+        self.links().scheme(scheme)
+    }
+
+    /// Iterates over the `seeAlso` links of this document.
+    pub fn see_also(&self) -> Links<'_> {
+        self.links().rel(Rel::SeeAlso)
+    }
+}
+
+impl<'a> IntoIterator for &'a Webfinger {
+    type Item = &'a Link;
+    type IntoIter = Links<'a>;
+
+    fn into_iter(self) -> Links<'a> {
+        self.links()
+    }
+}
+
+impl IntoIterator for Webfinger {
+    type Item = Link;
+    type IntoIter = std::vec::IntoIter<Link>;
+
+    fn into_iter(self) -> std::vec::IntoIter<Link> {
+        self.links.into_iter()
+    }
+}
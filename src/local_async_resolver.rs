@@ -0,0 +1,146 @@
+use crate::{
+    parse_target, Caller, FilteredWebfinger, InstanceDomain, Prefix, ResolverError, Target,
+    Webfinger,
+};
+use std::collections::HashMap;
+use url::Url;
+
+/// A variant of [`AsyncResolver`](crate::AsyncResolver) for single-threaded runtimes.
+///
+/// The futures of [`AsyncResolver`](crate::AsyncResolver) must be `Send`, so its repository must
+/// be `Send` and the resolver `Sync`. The futures of this trait don't have to be, so it can be
+/// used with a repository like `Rc<RefCell<Database>>`, on a runtime that never moves tasks to
+/// another thread (a Tokio `LocalSet` or a current thread runtime for instance). Its methods are
+/// the same.
+///
+/// The wrappers of this crate only implement [`AsyncResolver`](crate::AsyncResolver).
+#[allow(async_fn_in_trait)]
+pub trait LocalAsyncResolver<R> {
+    /// Returns the domain name of the current instance.
+    ///
+    /// It should include the port if it is not the default one. Resources on other domains are
+    /// refused with [`ResolverError::WrongDomain`].
+    async fn instance_domain(&self) -> InstanceDomain;
+
+    /// Tells if the resources on `domain` are served by this resolver.
+    ///
+    /// By default, only the [`instance_domain`](LocalAsyncResolver::instance_domain) is accepted.
+    /// See [`Resolver::accepts_domain`](crate::Resolver::accepts_domain).
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        *domain == self.instance_domain().await
+    }
+
+    /// Tries to find a resource, `acct`, in the repository `resource_repo`.
+    ///
+    /// `acct` is not a complete `acct:` URI, it only contains the identifier of the requested resource
+    /// (e.g. `test` for `acct:test@example.org`)
+    ///
+    /// If the resource couldn't be found, you may probably want to return a [`ResolverError::NotFound`].
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError>;
+
+    /// Tries to find a resource for a given caller.
+    ///
+    /// It allows to give more information (like private links) to some callers. By default, the
+    /// caller is ignored and [`find`](LocalAsyncResolver::find) is used.
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        _caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.find(prefix, acct, resource_repo).await
+    }
+
+    /// Tries to find a resource from its URL, like `https://example.org/@someone`.
+    ///
+    /// By default, all these resources are reported as [`ResolverError::NotFound`]. See
+    /// [`AsyncResolver::find_by_url`](crate::AsyncResolver::find_by_url).
+    async fn find_by_url(&self, _url: &Url, _resource_repo: R) -> Result<Webfinger, ResolverError> {
+        Err(ResolverError::NotFound)
+    }
+
+    /// Tells if [`endpoint_with_rels`](LocalAsyncResolver::endpoint_with_rels) removes the links
+    /// whose `rel` was not requested. See [`Resolver::filter_by_rels`](crate::Resolver::filter_by_rels).
+    fn filter_by_rels(&self) -> bool {
+        true
+    }
+
+    /// Returns a WebFinger result for a requested resource.
+    async fn endpoint<S: Into<String>>(
+        &self,
+        resource: S,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.endpoint_for_caller(resource, None, resource_repo)
+            .await
+    }
+
+    /// Returns a WebFinger result for a requested resource, keeping only the links with one of the
+    /// requested `rels`.
+    ///
+    /// All the links are kept if [`filter_by_rels`](LocalAsyncResolver::filter_by_rels) is
+    /// `false`.
+    async fn endpoint_with_rels<S: Into<String>>(
+        &self,
+        resource: S,
+        rels: &[&str],
+        resource_repo: R,
+    ) -> Result<FilteredWebfinger, ResolverError> {
+        let rels = if self.filter_by_rels() { rels } else { &[] };
+        self.endpoint(resource, resource_repo)
+            .await
+            .map(|full| FilteredWebfinger::new(full, rels))
+    }
+
+    /// Resolves many resources at once, keeping only the links with one of the requested `rels`.
+    ///
+    /// See [`AsyncResolver::endpoint_batch`](crate::AsyncResolver::endpoint_batch).
+    async fn endpoint_batch(
+        &self,
+        resources: &[&str],
+        rels: &[&str],
+        resource_repo: R,
+    ) -> HashMap<String, Result<Webfinger, ResolverError>>
+    where
+        R: Clone,
+    {
+        let mut results = HashMap::with_capacity(resources.len());
+        for resource in resources {
+            let repo = resource_repo.clone();
+            let result = self
+                .endpoint_with_rels(*resource, rels, repo)
+                .await
+                .map(|result| result.filtered);
+            results.insert(resource.to_string(), result);
+        }
+        results
+    }
+
+    /// Returns a WebFinger result for a request made by `caller`.
+    ///
+    /// `caller` is `None` for anonymous requests.
+    async fn endpoint_for_caller<S: Into<String>>(
+        &self,
+        resource: S,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let (target, host) = parse_target(&resource.into())?;
+        if !self.accepts_domain(&host).await {
+            return Err(ResolverError::WrongDomain);
+        }
+        match target {
+            Target::Url(url) => self.find_by_url(&url, resource_repo).await,
+            Target::Resource(resource) => {
+                self.find_for_caller(resource.prefix, resource.user, caller, resource_repo)
+                    .await
+            }
+        }
+    }
+}
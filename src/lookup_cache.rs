@@ -0,0 +1,205 @@
+use crate::{Caller, InstanceDomain, Prefix, Resolver, ResolverError, Webfinger};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use url::Url;
+
+type Lookup = Result<Webfinger, ResolverError>;
+
+/// A resolver wrapper keeping the results of another resolver for a short time.
+///
+/// Some clients send a `HEAD` request before the `GET` one, or request the same resource many
+/// times in a row. With this wrapper, the resource repository is only queried once for all of
+/// them. Missing resources are remembered too. Requests made by a known [`Caller`] are never
+/// cached, since their results may depend on who is asking.
+#[derive(Debug)]
+pub struct CacheLookups<T> {
+    inner: T,
+    ttl: Duration,
+    /// The results, with their expiration date. It is `None` when the TTL is too large to be
+    /// added to the current time, and the result never expires.
+    results: Mutex<HashMap<String, (Option<Instant>, Lookup)>>,
+}
+
+impl<T> CacheLookups<T> {
+    /// Wraps a resolver, keeping its results during `ttl`.
+    ///
+    /// A `ttl` too large to be added to the current time, like `Duration::MAX`, keeps them until
+    /// [`clear`](CacheLookups::clear) is called.
+    pub fn new(inner: T, ttl: Duration) -> CacheLookups<T> {
+        CacheLookups {
+            inner,
+            ttl,
+            results: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the wrapped resolver.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Forgets all the results.
+    pub fn clear(&self) {
+        self.results.lock().unwrap().clear();
+    }
+
+    fn get(&self, key: &str) -> Option<Lookup> {
+        match self.results.lock().unwrap().get(key) {
+            Some((expires, result)) if is_fresh(*expires, Instant::now()) => Some(result.clone()),
+            _ => None,
+        }
+    }
+
+    fn insert(&self, key: String, result: &Lookup) {
+        // Only the results of the repository are kept, not the errors of the other wrappers
+        if matches!(result, Ok(_) | Err(ResolverError::NotFound)) {
+            let now = Instant::now();
+            let mut results = self.results.lock().unwrap();
+            results.retain(|_, (expires, _)| is_fresh(*expires, now));
+            results.insert(key, (now.checked_add(self.ttl), result.clone()));
+        }
+    }
+
+    fn cached(&self, key: String, lookup: impl FnOnce() -> Lookup) -> Lookup {
+        if let Some(result) = self.get(&key) {
+            return result;
+        }
+        let result = lookup();
+        self.insert(key, &result);
+        result
+    }
+}
+
+/// Tells if a result that expires at `expires` can still be used at `now`.
+fn is_fresh(expires: Option<Instant>, now: Instant) -> bool {
+    expires.map_or(true, |expires| expires > now)
+}
+
+/// The key of a lookup by resource.
+fn resource_key(prefix: &Prefix, acct: &str) -> String {
+    format!("{}:{}", String::from(prefix.clone()), acct)
+}
+
+impl<R, T: Resolver<R>> Resolver<R> for CacheLookups<T> {
+    fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain()
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain)
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.cached(resource_key(&prefix, &acct), || {
+            self.inner.find(prefix, acct, resource_repo)
+        })
+    }
+
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        match caller {
+            Some(_) => self
+                .inner
+                .find_for_caller(prefix, acct, caller, resource_repo),
+            None => self.cached(resource_key(&prefix, &acct), || {
+                self.inner
+                    .find_for_caller(prefix, acct, None, resource_repo)
+            }),
+        }
+    }
+
+    fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.cached(url.to_string(), || {
+            self.inner.find_by_url(url, resource_repo)
+        })
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, R> crate::AsyncResolver<R> for CacheLookups<T>
+where
+    T: crate::AsyncResolver<R> + Send + Sync,
+    R: Send,
+{
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain().await
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain).await
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let key = resource_key(&prefix, &acct);
+        if let Some(result) = self.get(&key) {
+            return result;
+        }
+        let result = self.inner.find(prefix, acct, resource_repo).await;
+        self.insert(key, &result);
+        result
+    }
+
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        match caller {
+            Some(_) => {
+                self.inner
+                    .find_for_caller(prefix, acct, caller, resource_repo)
+                    .await
+            }
+            None => {
+                let key = resource_key(&prefix, &acct);
+                if let Some(result) = self.get(&key) {
+                    return result;
+                }
+                let result = self
+                    .inner
+                    .find_for_caller(prefix, acct, None, resource_repo)
+                    .await;
+                self.insert(key, &result);
+                result
+            }
+        }
+    }
+
+    async fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        let key = url.to_string();
+        if let Some(result) = self.get(&key) {
+            return result;
+        }
+        let result = self.inner.find_by_url(url, resource_repo).await;
+        self.insert(key, &result);
+        result
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{consts, Webfinger};
+#[cfg(feature = "client")]
+use crate::{Prefix, WebfingerClient, WebfingerError};
+use url::Url;
+
+/// Extracts a Matrix user ID (`@user:server`) from a link or an alias.
+///
+/// `matrix:u/user:server` URIs, `https://matrix.to/#/@user:server` links and bare user IDs are
+/// supported.
+fn parse_matrix_id(href: &str) -> Option<String> {
+    let id = if let Some(path) = href.strip_prefix("matrix:u/") {
+        format!("@{}", path.split(['?', '#']).next().unwrap_or_default())
+    } else if href.starts_with('@') {
+        href.to_string()
+    } else {
+        let url = Url::parse(href).ok()?;
+        if url.host_str() != Some("matrix.to") {
+            return None;
+        }
+        let fragment = url.fragment()?.strip_prefix('/')?;
+        fragment
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .replace("%40", "@")
+            .replace("%3A", ":")
+    };
+    let (user, server) = id.strip_prefix('@')?.split_once(':')?;
+    if user.is_empty() || server.is_empty() {
+        return None;
+    }
+    Some(id)
+}
+
+impl Webfinger {
+    /// Returns the Matrix user ID (`@user:server`) of this resource, if it publishes one.
+    ///
+    /// The links with the [`m.identity`](consts::MATRIX_REL_IDENTITY) or
+    /// [`im.vector`](consts::MATRIX_REL_VECTOR) relations are used first, then the other
+    /// `matrix:` links, and finally the aliases.
+    pub fn matrix_id(&self) -> Option<String> {
+        let rels = [consts::MATRIX_REL_IDENTITY, consts::MATRIX_REL_VECTOR];
+        rels.iter()
+            .flat_map(|rel| self.links().rel(rel))
+            .chain(self.service_links("matrix"))
+            .filter_map(|link| link.href.as_deref())
+            .chain(self.aliases.iter().map(String::as_str))
+            .find_map(parse_matrix_id)
+    }
+}
+
+#[cfg(feature = "client")]
+impl WebfingerClient {
+    /// Finds the Matrix user ID of someone from their email address.
+    ///
+    /// The `mailto:` resource is looked up on the domain of the address. It returns `Ok(None)` if
+    /// the document doesn't contain any Matrix user ID (see [`Webfinger::matrix_id`]).
+    pub async fn resolve_matrix_id(
+        &self,
+        email: impl Into<String>,
+    ) -> Result<Option<String>, WebfingerError> {
+        self.resolve_with_prefix(Prefix::Custom("mailto".into()), email)
+            .await
+            .map(|webfinger| webfinger.matrix_id())
+    }
+}
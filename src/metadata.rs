@@ -0,0 +1,84 @@
+use crate::{ParseWarning, ServerHints, Webfinger};
+use std::time::Duration;
+
+/// A fetched WebFinger document, with information about how it was obtained.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolved {
+    /// The document itself.
+    pub document: Webfinger,
+
+    /// Information about the response the document comes from.
+    pub metadata: ResolveMetadata,
+}
+
+/// Information about the response a [`Resolved`] document comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveMetadata {
+    /// The URL that was requested, including the extra query parameters.
+    pub url: String,
+
+    /// The HTTP status of the response.
+    pub status: u16,
+
+    /// The `Content-Type` of the response, if there was one.
+    pub content_type: Option<String>,
+
+    /// The `Cache-Control` header of the response, if there was one.
+    pub cache_control: Option<String>,
+
+    /// Hints about the software of the server, to work around the bugs of some of them.
+    pub server: ServerHints,
+
+    /// Wether the document was just fetched, or is an outdated copy served after a failure.
+    pub freshness: Freshness,
+
+    /// The problems of the document that were tolerated.
+    ///
+    /// Without the `lenient` feature, only [`ParseWarning::ByteOrderMark`],
+    /// [`ParseWarning::UnexpectedContentType`] and [`ParseWarning::NotAcceptable`] can appear: the
+    /// other warnings are about problems that make the document invalid when parsing strictly.
+    pub warnings: Vec<ParseWarning>,
+
+    /// How long the request took.
+    ///
+    /// Documents served from the cache keep the timings of the request they come from.
+    pub timings: Timings,
+}
+
+/// The durations of the steps of a request, to monitor the latency of remote servers.
+///
+/// The HTTP clients supported by this crate only measure the time until the headers of the
+/// response are received. Custom [`HttpFetch`](crate::HttpFetch) implementations can give the
+/// other durations in [`HttpResponse::timings`](crate::HttpResponse::timings).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// The time spent resolving the domain, if it was measured.
+    pub dns: Option<Duration>,
+
+    /// The time spent opening the connection, if it was measured.
+    pub connect: Option<Duration>,
+
+    /// The time spent on the TLS handshake, if it was measured.
+    pub tls: Option<Duration>,
+
+    /// The time until the headers of the response were received, if it was measured.
+    pub ttfb: Option<Duration>,
+
+    /// The time from sending the request to receiving and parsing the whole response.
+    ///
+    /// It is always measured by the client, whatever the transport reports.
+    pub total: Duration,
+}
+
+/// Tells if a [`Resolved`] document is up to date.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Freshness {
+    /// The document was fetched successfully, now or during the cache TTL.
+    #[default]
+    Fresh,
+
+    /// Fetching the document failed, and an expired copy from the cache was returned instead.
+    ///
+    /// See [`WebfingerClientBuilder::serve_stale`](crate::WebfingerClientBuilder::serve_stale).
+    Stale,
+}
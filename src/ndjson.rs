@@ -0,0 +1,184 @@
+use crate::Webfinger;
+#[cfg(any(feature = "client", feature = "async"))]
+use futures_util::{
+    io::{AsyncBufRead, AsyncBufReadExt},
+    stream::{self, Stream},
+};
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+};
+
+/// An error that occured while reading newline-delimited JSON documents.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum NdjsonError {
+    /// Reading failed.
+    Io(io::Error),
+
+    /// A line is not a valid [`Webfinger`] document.
+    InvalidDocument {
+        /// The number of the line, starting at 1.
+        line: usize,
+
+        /// A description of the problem.
+        error: String,
+    },
+}
+
+impl NdjsonError {
+    /// A stable code identifying this error, from `WF0701` to `WF0799`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            NdjsonError::Io(_) => "WF0701",
+            NdjsonError::InvalidDocument { .. } => "WF0702",
+        }
+    }
+}
+
+impl fmt::Display for NdjsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] ", self.code())?;
+        match self {
+            NdjsonError::Io(error) => write!(f, "reading the documents failed: {}", error),
+            NdjsonError::InvalidDocument { line, error } => {
+                write!(f, "line {} is not a valid document: {}", line, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NdjsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NdjsonError::Io(error) => Some(error),
+            NdjsonError::InvalidDocument { .. } => None,
+        }
+    }
+}
+
+/// Writes documents as newline-delimited JSON, one document per line.
+///
+/// Nothing is buffered by this writer: wrap files in a [`std::io::BufWriter`] to write many
+/// documents efficiently.
+#[derive(Debug)]
+pub struct NdjsonWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    /// Creates a writer writing to `writer`.
+    pub fn new(writer: W) -> NdjsonWriter<W> {
+        NdjsonWriter { writer }
+    }
+
+    /// Writes a document, followed by a new line.
+    pub fn write(&mut self, webfinger: &Webfinger) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, webfinger)?;
+        self.writer.write_all(b"\n")
+    }
+
+    /// Writes all the given documents.
+    pub fn write_all<'a>(
+        &mut self,
+        documents: impl IntoIterator<Item = &'a Webfinger>,
+    ) -> io::Result<()> {
+        documents
+            .into_iter()
+            .try_for_each(|webfinger| self.write(webfinger))
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads documents written as newline-delimited JSON, one document per line.
+///
+/// Empty lines are ignored. With a [`BufRead`] reader, it is an iterator over the documents. With
+/// an `AsyncBufRead` reader, use `NdjsonReader::next_async` or `NdjsonReader::into_stream`, that
+/// are only available with the `client` or `async` feature. Reading continues after an invalid
+/// line.
+#[derive(Debug)]
+pub struct NdjsonReader<R> {
+    reader: R,
+    line: usize,
+    buffer: String,
+}
+
+impl<R> NdjsonReader<R> {
+    /// Creates a reader reading from `reader`.
+    pub fn new(reader: R) -> NdjsonReader<R> {
+        NdjsonReader {
+            reader,
+            line: 0,
+            buffer: String::new(),
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Parses the line in the buffer, returning `None` if it is empty.
+    fn parse_line(&mut self) -> Option<Result<Webfinger, NdjsonError>> {
+        self.line += 1;
+        let line = self.buffer.trim();
+        if line.is_empty() {
+            return None;
+        }
+        Some(
+            serde_json::from_str(line).map_err(|error| NdjsonError::InvalidDocument {
+                line: self.line,
+                error: error.to_string(),
+            }),
+        )
+    }
+}
+
+impl<R: BufRead> Iterator for NdjsonReader<R> {
+    type Item = Result<Webfinger, NdjsonError>;
+
+    fn next(&mut self) -> Option<Result<Webfinger, NdjsonError>> {
+        loop {
+            self.buffer.clear();
+            match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    if let Some(result) = self.parse_line() {
+                        return Some(result);
+                    }
+                }
+                Err(error) => return Some(Err(NdjsonError::Io(error))),
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "client", feature = "async"))]
+impl<R: AsyncBufRead + Unpin> NdjsonReader<R> {
+    /// Reads the next document, or returns `None` at the end of the input.
+    pub async fn next_async(&mut self) -> Option<Result<Webfinger, NdjsonError>> {
+        loop {
+            self.buffer.clear();
+            match self.reader.read_line(&mut self.buffer).await {
+                Ok(0) => return None,
+                Ok(_) => {
+                    if let Some(result) = self.parse_line() {
+                        return Some(result);
+                    }
+                }
+                Err(error) => return Some(Err(NdjsonError::Io(error))),
+            }
+        }
+    }
+
+    /// Converts this reader to a stream of documents.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Webfinger, NdjsonError>> {
+        stream::unfold(self, |mut reader| async move {
+            reader.next_async().await.map(|result| (result, reader))
+        })
+    }
+}
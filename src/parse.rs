@@ -0,0 +1,258 @@
+#[cfg(feature = "client")]
+use crate::consts;
+use crate::{Link, ResponseSnippet, Webfinger, WebfingerError};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// A problem in a document that was accepted anyway.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ParseWarning {
+    /// The document was wrapped in a JSON array.
+    UnwrappedArray,
+
+    /// The document was wrapped in an object, under the given key.
+    UnwrappedObject(String),
+
+    /// An invalid link was removed from the document.
+    DroppedLink(LinkParseIssue),
+
+    /// The document had no subject, or an empty one, and the requested resource was used
+    /// instead.
+    MissingSubject,
+
+    /// The server answered `406 Not Acceptable` to the default `Accept` header, and the document
+    /// was fetched again with `Accept: application/json`.
+    ///
+    /// See [`WebfingerClientBuilder::accept_fallback`](crate::WebfingerClientBuilder::accept_fallback).
+    NotAcceptable,
+
+    /// The document started with a UTF-8 byte order mark, that was removed.
+    ByteOrderMark,
+
+    /// The document was served with the given `Content-Type`, instead of `application/jrd+json`
+    /// or `application/json`.
+    UnexpectedContentType(String),
+}
+
+/// An invalid link, found while parsing a document leniently.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkParseIssue {
+    /// The position of the link in the `links` array of the document.
+    pub index: usize,
+
+    /// The `rel` of the link, if it had a valid one.
+    pub rel: Option<String>,
+
+    /// A description of the problem.
+    pub error: String,
+}
+
+/// A document whose links are not parsed yet.
+#[derive(Deserialize)]
+struct PartialWebfinger {
+    #[serde(default)]
+    subject: Option<String>,
+    #[serde(default)]
+    aliases: Vec<String>,
+    links: Vec<Value>,
+    #[serde(default)]
+    properties: HashMap<String, Option<String>>,
+    expires: Option<String>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl Webfinger {
+    /// Parses a JSON document, refusing any field that this crate doesn't know.
+    ///
+    /// The default parsing ignores unknown fields, as recommended by the RFC. This stricter mode
+    /// helps to detect documents that were not meant to be WebFinger documents.
+    pub fn from_json_strict(json: &[u8]) -> Result<Webfinger, WebfingerError> {
+        serde_json::from_slice::<StrictWebfinger>(json)
+            .map(Webfinger::from)
+            .map_err(|_| WebfingerError::JsonError(ResponseSnippet::new(None, json)))
+    }
+
+    /// Parses a JSON document, removing its invalid links instead of failing.
+    ///
+    /// The problems of the removed links are returned with the document. The document itself must
+    /// still be valid, with a non-empty subject.
+    pub fn from_json_lenient(
+        json: &[u8],
+    ) -> Result<(Webfinger, Vec<LinkParseIssue>), WebfingerError> {
+        serde_json::from_slice(json)
+            .ok()
+            .and_then(|value| parse_partial(value, None))
+            .map(|(webfinger, issues, _)| (webfinger, issues))
+            .ok_or_else(|| WebfingerError::JsonError(ResponseSnippet::new(None, json)))
+    }
+}
+
+/// A [`Webfinger`] document refusing unknown fields.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrictWebfinger {
+    subject: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    links: Vec<StrictLink>,
+    #[serde(default)]
+    properties: HashMap<String, Option<String>>,
+    expires: Option<String>,
+}
+
+/// A [`Link`] refusing unknown fields.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrictLink {
+    rel: String,
+    href: Option<String>,
+    template: Option<String>,
+    #[serde(rename = "type")]
+    mime_type: Option<String>,
+    #[serde(default)]
+    titles: HashMap<String, String>,
+    #[serde(default)]
+    properties: HashMap<String, Option<String>>,
+}
+
+impl From<StrictWebfinger> for Webfinger {
+    fn from(strict: StrictWebfinger) -> Webfinger {
+        Webfinger {
+            subject: strict.subject,
+            aliases: strict.aliases,
+            links: strict
+                .links
+                .into_iter()
+                .map(|link| Link {
+                    rel: link.rel.into(),
+                    href: link.href,
+                    template: link.template,
+                    mime_type: link.mime_type,
+                    titles: link.titles,
+                    properties: link.properties,
+                    extra: Map::new(),
+                })
+                .collect(),
+            properties: strict.properties,
+            expires: strict.expires,
+            extra: Map::new(),
+        }
+    }
+}
+
+/// Parses a document, removing its invalid links.
+///
+/// A missing or empty subject is replaced with `resource` if there is one, and the last returned
+/// value tells if it happened.
+fn parse_partial(
+    value: Value,
+    resource: Option<&str>,
+) -> Option<(Webfinger, Vec<LinkParseIssue>, bool)> {
+    let partial: PartialWebfinger = serde_json::from_value(value).ok()?;
+    let (subject, synthesized) = match partial.subject {
+        Some(subject) if !subject.is_empty() => (subject, false),
+        _ => (resource?.to_string(), true),
+    };
+    let mut links = Vec::with_capacity(partial.links.len());
+    let mut issues = vec![];
+    for (index, link) in partial.links.into_iter().enumerate() {
+        let rel = link.get("rel").and_then(Value::as_str).map(String::from);
+        match serde_json::from_value::<Link>(link) {
+            Ok(link) => links.push(link),
+            Err(error) => issues.push(LinkParseIssue {
+                index,
+                rel,
+                error: error.to_string(),
+            }),
+        }
+    }
+    Some((
+        Webfinger {
+            subject,
+            aliases: partial.aliases,
+            links,
+            properties: partial.properties,
+            expires: partial.expires,
+            extra: partial.extra,
+        },
+        issues,
+        synthesized,
+    ))
+}
+
+/// Parses a WebFinger document, as received with the given `Content-Type` for the requested
+/// `resource`.
+///
+/// Documents without a subject, or with an empty one, are refused. With the `lenient` feature,
+/// the requested resource is used as their subject instead. Documents wrapped in an array or
+/// under a `webfinger` key, as some broken servers send them, are accepted too, and invalid
+/// links are removed instead of making the whole document invalid.
+///
+/// A leading byte order mark is always removed, and an unexpected `Content-Type` doesn't prevent
+/// the document from being parsed, but both are reported in the warnings.
+#[cfg(feature = "client")]
+#[cfg_attr(not(feature = "lenient"), allow(unused_variables))]
+pub(crate) fn parse_document(
+    content_type: Option<&str>,
+    body: &[u8],
+    resource: Option<&str>,
+) -> Result<(Webfinger, Vec<ParseWarning>), WebfingerError> {
+    let error = || WebfingerError::JsonError(ResponseSnippet::new(content_type, body));
+    let mut warnings = vec![];
+    let json = match body.strip_prefix(b"\xef\xbb\xbf") {
+        Some(json) => {
+            warnings.push(ParseWarning::ByteOrderMark);
+            json
+        }
+        None => body,
+    };
+    if let Some(content_type) = content_type.filter(|c| !is_json_content_type(c)) {
+        warnings.push(ParseWarning::UnexpectedContentType(
+            content_type.to_string(),
+        ));
+    }
+    let (webfinger, lenient_warnings) = match serde_json::from_slice::<Webfinger>(json) {
+        Ok(webfinger) if !webfinger.subject.is_empty() => (webfinger, vec![]),
+        #[cfg(feature = "lenient")]
+        _ => parse_lenient(json, resource).ok_or_else(error)?,
+        #[cfg(not(feature = "lenient"))]
+        _ => return Err(error()),
+    };
+    warnings.extend(lenient_warnings);
+    Ok((webfinger, warnings))
+}
+
+/// Tells if a `Content-Type` is the one of WebFinger documents, or the generic JSON one.
+#[cfg(feature = "client")]
+fn is_json_content_type(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    media_type.eq_ignore_ascii_case(consts::JRD_MIME)
+        || media_type.eq_ignore_ascii_case(consts::JSON_MIME)
+}
+
+/// Looks for a document wrapped in another JSON value, and removes its invalid links.
+#[cfg(all(feature = "lenient", feature = "client"))]
+fn parse_lenient(body: &[u8], resource: Option<&str>) -> Option<(Webfinger, Vec<ParseWarning>)> {
+    let mut warnings = vec![];
+    let value = match serde_json::from_slice(body).ok()? {
+        Value::Array(values) if values.len() == 1 => {
+            warnings.push(ParseWarning::UnwrappedArray);
+            values.into_iter().next()?
+        }
+        Value::Object(mut object) if object.contains_key("webfinger") => {
+            warnings.push(ParseWarning::UnwrappedObject("webfinger".into()));
+            object.remove("webfinger")?
+        }
+        value => value,
+    };
+
+    let (webfinger, issues, synthesized) = parse_partial(value, resource)?;
+    if synthesized {
+        warnings.push(ParseWarning::MissingSubject);
+    }
+    warnings.extend(issues.into_iter().map(ParseWarning::DroppedLink));
+    Some((webfinger, warnings))
+}
@@ -0,0 +1,135 @@
+use crate::WebfingerError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// The SHA-256 hash of a certificate or of its public key, that a host must present.
+///
+/// Pins are given to [`WebfingerClientBuilder::pin_certificate`](crate::WebfingerClientBuilder::pin_certificate)
+/// or in the `pins` of a [`DomainConfig`](crate::DomainConfig), as `spki-sha256:` or
+/// `cert-sha256:` followed by the hash in hexadecimal (colons are ignored, so the output of
+/// `openssl x509 -fingerprint -sha256` can be used for certificates). Public key pins are
+/// usually preferred, since they survive the renewal of certificates with the same key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CertificatePin {
+    /// The hash of the DER-encoded `SubjectPublicKeyInfo` of the certificate.
+    PublicKey([u8; 32]),
+
+    /// The hash of the whole DER-encoded certificate.
+    Certificate([u8; 32]),
+}
+
+impl CertificatePin {
+    /// Parses a pin like `spki-sha256:4e2f…` or `cert-sha256:4E:2F:…`.
+    ///
+    /// Fails with [`WebfingerError::ParseError`] if the prefix is unknown or if the hash is not
+    /// 32 bytes in hexadecimal.
+    pub fn parse(pin: &str) -> Result<CertificatePin, WebfingerError> {
+        let (kind, hex) = pin
+            .trim()
+            .split_once(':')
+            .ok_or(WebfingerError::ParseError)?;
+        let digits: Vec<u8> = hex.bytes().filter(|b| *b != b':').collect();
+        if digits.len() != 64 {
+            return Err(WebfingerError::ParseError);
+        }
+        let mut hash = [0; 32];
+        for (byte, pair) in hash.iter_mut().zip(digits.chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| WebfingerError::ParseError)?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| WebfingerError::ParseError)?;
+        }
+        match kind.to_ascii_lowercase().as_str() {
+            "spki-sha256" => Ok(CertificatePin::PublicKey(hash)),
+            "cert-sha256" => Ok(CertificatePin::Certificate(hash)),
+            _ => Err(WebfingerError::ParseError),
+        }
+    }
+
+    /// Tells if a DER-encoded certificate matches this pin.
+    #[cfg(feature = "ring")]
+    pub fn matches(&self, certificate: &[u8]) -> bool {
+        let sha256 = |data: &[u8]| ring::digest::digest(&ring::digest::SHA256, data);
+        match self {
+            CertificatePin::PublicKey(hash) => {
+                public_key_info(certificate).is_some_and(|spki| sha256(spki).as_ref() == &hash[..])
+            }
+            CertificatePin::Certificate(hash) => sha256(certificate).as_ref() == &hash[..],
+        }
+    }
+}
+
+impl fmt::Display for CertificatePin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (kind, hash) = match self {
+            CertificatePin::PublicKey(hash) => ("spki-sha256", hash),
+            CertificatePin::Certificate(hash) => ("cert-sha256", hash),
+        };
+        write!(f, "{}:", kind)?;
+        hash.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl Serialize for CertificatePin {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CertificatePin {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<CertificatePin, D::Error> {
+        let pin = String::deserialize(deserializer)?;
+        CertificatePin::parse(&pin)
+            .map_err(|_| serde::de::Error::custom(format!("invalid certificate pin: {}", pin)))
+    }
+}
+
+/// The tag of a DER element, the whole element, its content and what follows it.
+#[cfg(feature = "ring")]
+type DerElement<'a> = (u8, &'a [u8], &'a [u8], &'a [u8]);
+
+/// Reads a DER element.
+#[cfg(feature = "ring")]
+fn der_element(input: &[u8]) -> Option<DerElement<'_>> {
+    let tag = *input.first()?;
+    let first = *input.get(1)?;
+    let (length, header) = if first < 0x80 {
+        (first as usize, 2)
+    } else {
+        let bytes = (first & 0x7f) as usize;
+        if bytes == 0 || bytes > 4 {
+            return None;
+        }
+        let length = input
+            .get(2..2 + bytes)?
+            .iter()
+            .fold(0, |length, byte| (length << 8) | *byte as usize);
+        (length, 2 + bytes)
+    };
+    let end = header.checked_add(length)?;
+    let element = input.get(..end)?;
+    Some((tag, element, &element[header..], &input[end..]))
+}
+
+/// Finds the `SubjectPublicKeyInfo` of a DER-encoded X.509 certificate.
+#[cfg(feature = "ring")]
+pub(crate) fn public_key_info(certificate: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const VERSION: u8 = 0xa0;
+
+    let (SEQUENCE, _, certificate, _) = der_element(certificate)? else {
+        return None;
+    };
+    let (SEQUENCE, _, mut fields, _) = der_element(certificate)? else {
+        return None;
+    };
+    if fields.first() == Some(&VERSION) {
+        fields = der_element(fields)?.3;
+    }
+    // Serial number, signature algorithm, issuer, validity and subject
+    for _ in 0..5 {
+        fields = der_element(fields)?.3;
+    }
+    match der_element(fields)? {
+        (SEQUENCE, spki, _, _) => Some(spki),
+        _ => None,
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{is_valid_scheme, parse_resource, Prefix, ResolverError, Resource, WebfingerError};
+#[cfg(feature = "server")]
+use crate::{Caller, InstanceDomain, Resolver, Webfinger};
+use std::{fmt, sync::Arc};
+#[cfg(feature = "server")]
+use url::Url;
+
+type Validator = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// A prefix known by a [`PrefixRegistry`].
+#[derive(Clone)]
+struct RegisteredPrefix {
+    name: String,
+    validate: Option<Validator>,
+}
+
+/// The prefixes that an application accepts, with their canonical form and the identifiers they
+/// allow.
+///
+/// `acct:` and `group:` are always known. Other prefixes must be registered, and are then given
+/// to resolvers as `Prefix::Custom` with the canonical name they were registered with. A registry
+/// can be used by a client with [`WebfingerClientBuilder::prefixes`](crate::WebfingerClientBuilder::prefixes),
+/// and by a server with [`RegisteredPrefixes`].
+///
+/// ```rust
+/// use webfinger::{Prefix, PrefixRegistry};
+///
+/// # fn main() -> Result<(), webfinger::WebfingerError> {
+/// let registry = PrefixRegistry::new()
+///     .register("tag")?
+///     .register_with("xmpp", |id| !id.contains('/'))?;
+/// assert_eq!(registry.parse("XMPP"), Ok(Prefix::Custom("xmpp".into())));
+/// assert!(registry.parse("did").is_err());
+/// assert!(registry.check(Prefix::Custom("xmpp".into()), "user/resource").is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct PrefixRegistry {
+    prefixes: Vec<RegisteredPrefix>,
+}
+
+impl fmt::Debug for PrefixRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.prefixes.iter().map(|prefix| &prefix.name))
+            .finish()
+    }
+}
+
+impl Default for PrefixRegistry {
+    fn default() -> PrefixRegistry {
+        PrefixRegistry::new()
+    }
+}
+
+impl PrefixRegistry {
+    /// Creates a registry that only knows `acct:` and `group:`.
+    pub fn new() -> PrefixRegistry {
+        PrefixRegistry { prefixes: vec![] }
+    }
+
+    /// Registers a prefix, accepting any identifier.
+    ///
+    /// It fails with [`WebfingerError::ParseError`] if `prefix` is not a valid URI scheme (see
+    /// [`Prefix::parse`]).
+    pub fn register(self, prefix: impl Into<String>) -> Result<PrefixRegistry, WebfingerError> {
+        self.insert(prefix.into(), None)
+    }
+
+    /// Registers a prefix, only accepting the identifiers for which `validate` returns `true`.
+    ///
+    /// The identifier is the part of the resource between the prefix and the `@`. Registering
+    /// `acct` or `group` adds a validation to these prefixes.
+    ///
+    /// It fails with [`WebfingerError::ParseError`] if `prefix` is not a valid URI scheme (see
+    /// [`Prefix::parse`]).
+    pub fn register_with(
+        self,
+        prefix: impl Into<String>,
+        validate: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Result<PrefixRegistry, WebfingerError> {
+        self.insert(prefix.into(), Some(Arc::new(validate)))
+    }
+
+    fn insert(
+        mut self,
+        name: String,
+        validate: Option<Validator>,
+    ) -> Result<PrefixRegistry, WebfingerError> {
+        if !is_valid_scheme(&name) {
+            return Err(WebfingerError::ParseError);
+        }
+        self.prefixes
+            .retain(|prefix| !prefix.name.eq_ignore_ascii_case(&name));
+        self.prefixes.push(RegisteredPrefix { name, validate });
+        Ok(self)
+    }
+
+    fn get(&self, name: &str) -> Option<&RegisteredPrefix> {
+        self.prefixes
+            .iter()
+            .find(|prefix| prefix.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns `true` if `prefix` is known, ignoring case.
+    pub fn is_registered(&self, prefix: &str) -> bool {
+        self.parse(prefix).is_ok()
+    }
+
+    /// Parses a known prefix, returning it in its canonical form.
+    ///
+    /// It fails with [`WebfingerError::ParseError`] if the prefix is not known.
+    pub fn parse(&self, prefix: &str) -> Result<Prefix, WebfingerError> {
+        match Prefix::parse(prefix)? {
+            Prefix::Custom(name) => self
+                .get(&name)
+                .map(|prefix| Prefix::Custom(prefix.name.clone()))
+                .ok_or(WebfingerError::ParseError),
+            prefix => Ok(prefix),
+        }
+    }
+
+    /// Checks that `prefix` is known and accepts `identifier`, and returns its canonical form.
+    ///
+    /// It fails with [`WebfingerError::ParseError`] otherwise.
+    pub fn check(&self, prefix: Prefix, identifier: &str) -> Result<Prefix, WebfingerError> {
+        let name: String = prefix.into();
+        let prefix = self.parse(&name)?;
+        let valid = self
+            .get(&name)
+            .and_then(|prefix| prefix.validate.as_ref())
+            .map_or(true, |validate| validate(identifier));
+        if valid {
+            Ok(prefix)
+        } else {
+            Err(WebfingerError::ParseError)
+        }
+    }
+
+    /// Parses a resource like [`parse_resource`], also checking its prefix and identifier with
+    /// this registry.
+    pub fn parse_resource(&self, resource: &str) -> Result<Resource, ResolverError> {
+        let mut resource = parse_resource(resource)?;
+        resource.prefix = self
+            .check(resource.prefix, &resource.user)
+            .map_err(|_| ResolverError::InvalidResource)?;
+        Ok(resource)
+    }
+}
+
+/// A resolver wrapper only accepting the prefixes of a [`PrefixRegistry`].
+///
+/// Unknown prefixes and invalid identifiers are refused with [`ResolverError::InvalidResource`].
+/// The wrapped resolver receives custom prefixes in their canonical form.
+#[cfg(feature = "server")]
+#[derive(Debug, Clone)]
+pub struct RegisteredPrefixes<T> {
+    inner: T,
+    registry: PrefixRegistry,
+}
+
+#[cfg(feature = "server")]
+impl<T> RegisteredPrefixes<T> {
+    /// Wraps a resolver, checking the requested prefixes with `registry`.
+    pub fn new(inner: T, registry: PrefixRegistry) -> RegisteredPrefixes<T> {
+        RegisteredPrefixes { inner, registry }
+    }
+
+    /// Returns the wrapped resolver.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn check(&self, prefix: Prefix, acct: &str) -> Result<Prefix, ResolverError> {
+        self.registry
+            .check(prefix, acct)
+            .map_err(|_| ResolverError::InvalidResource)
+    }
+}
+
+#[cfg(feature = "server")]
+impl<R, T: Resolver<R>> Resolver<R> for RegisteredPrefixes<T> {
+    fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain()
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain)
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let prefix = self.check(prefix, &acct)?;
+        self.inner.find(prefix, acct, resource_repo)
+    }
+
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let prefix = self.check(prefix, &acct)?;
+        self.inner
+            .find_for_caller(prefix, acct, caller, resource_repo)
+    }
+
+    fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.inner.find_by_url(url, resource_repo)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, R> crate::AsyncResolver<R> for RegisteredPrefixes<T>
+where
+    T: crate::AsyncResolver<R> + Send + Sync,
+    R: Send,
+{
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain().await
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain).await
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let prefix = self.check(prefix, &acct)?;
+        self.inner.find(prefix, acct, resource_repo).await
+    }
+
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let prefix = self.check(prefix, &acct)?;
+        self.inner
+            .find_for_caller(prefix, acct, caller, resource_repo)
+            .await
+    }
+
+    async fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.inner.find_by_url(url, resource_repo).await
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{Caller, InstanceDomain, Prefix, Resolver, ResolverError, Webfinger};
+use std::{fmt, sync::Arc};
+use url::Url;
+
+type FindRoute<R> =
+    Arc<dyn Fn(String, Option<&Caller>, R) -> Result<Webfinger, ResolverError> + Send + Sync>;
+type UrlRoute<R> = Arc<dyn Fn(&Url, R) -> Result<Webfinger, ResolverError> + Send + Sync>;
+
+/// A resolver dispatching the requests to a different function for each prefix.
+///
+/// Instead of matching on the prefix in [`find`](Resolver::find), register a route for each kind
+/// of resource the instance serves: users for `acct:`, groups for `group:`, and so on. Routes
+/// receive the identifier of the resource, the caller, and the repository. Resources with a
+/// prefix that has no route are [`NotFound`](ResolverError::NotFound), as are URLs if there is
+/// no [`urls`](PrefixRouter::urls) route.
+///
+/// ```rust
+/// use webfinger::{InstanceDomain, Link, Prefix, PrefixRouter, Resolver, ResolverError, Webfinger};
+///
+/// let router = PrefixRouter::new(InstanceDomain::new("example.org").unwrap())
+///     .route(Prefix::Acct, |user, _, users: &[&str]| match users.contains(&user.as_str()) {
+///         true => Ok(Webfinger {
+///             links: vec![Link {
+///                 href: Some(format!("https://example.org/@{}", user)),
+///                 ..Link::new("http://webfinger.net/rel/profile-page")
+///             }],
+///             ..Webfinger::new(format!("acct:{}@example.org", user))
+///         }),
+///         false => Err(ResolverError::NotFound),
+///     })
+///     .route(Prefix::Group, |group, _, _| Ok(Webfinger::new(format!("group:{}@example.org", group))));
+///
+/// let users: &[&str] = &["alice"];
+/// assert!(router.endpoint("acct:alice@example.org", users).is_ok());
+/// assert!(router.endpoint("group:friends@example.org", users).is_ok());
+/// assert_eq!(router.endpoint("acct:bob@example.org", users), Err(ResolverError::NotFound));
+/// assert_eq!(router.endpoint("xmpp:alice@example.org", users), Err(ResolverError::NotFound));
+/// ```
+///
+/// Routes are blocking functions: wrap the router in [`SyncAsAsync`](crate::SyncAsAsync) to use
+/// it in an async server.
+pub struct PrefixRouter<R> {
+    domain: InstanceDomain,
+    routes: Vec<(String, FindRoute<R>)>,
+    urls: Option<UrlRoute<R>>,
+}
+
+impl<R> PrefixRouter<R> {
+    /// Creates a router for the resources of `domain`, without any route.
+    pub fn new(domain: InstanceDomain) -> PrefixRouter<R> {
+        PrefixRouter {
+            domain,
+            routes: vec![],
+            urls: None,
+        }
+    }
+
+    /// Serves the resources with `prefix` with `find`, replacing the previous route for this
+    /// prefix if there is one.
+    ///
+    /// Custom prefixes are compared without considering case.
+    pub fn route(
+        mut self,
+        prefix: Prefix,
+        find: impl Fn(String, Option<&Caller>, R) -> Result<Webfinger, ResolverError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> PrefixRouter<R> {
+        let prefix = String::from(prefix).to_lowercase();
+        self.routes.retain(|(name, _)| *name != prefix);
+        self.routes.push((prefix, Arc::new(find)));
+        self
+    }
+
+    /// Serves the resources that are URLs on the instance, like `https://example.org/@alice`,
+    /// with `find`. See [`Resolver::find_by_url`].
+    pub fn urls(
+        mut self,
+        find: impl Fn(&Url, R) -> Result<Webfinger, ResolverError> + Send + Sync + 'static,
+    ) -> PrefixRouter<R> {
+        self.urls = Some(Arc::new(find));
+        self
+    }
+
+    fn route_for(&self, prefix: Prefix) -> Option<&FindRoute<R>> {
+        let prefix = String::from(prefix).to_lowercase();
+        self.routes
+            .iter()
+            .find(|(name, _)| *name == prefix)
+            .map(|(_, find)| find)
+    }
+}
+
+impl<R> Clone for PrefixRouter<R> {
+    fn clone(&self) -> Self {
+        PrefixRouter {
+            domain: self.domain.clone(),
+            routes: self.routes.clone(),
+            urls: self.urls.clone(),
+        }
+    }
+}
+
+impl<R> fmt::Debug for PrefixRouter<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PrefixRouter")
+            .field("domain", &self.domain)
+            .field(
+                "routes",
+                &self.routes.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            )
+            .field("urls", &self.urls.is_some())
+            .finish()
+    }
+}
+
+impl<R> Resolver<R> for PrefixRouter<R> {
+    fn instance_domain(&self) -> InstanceDomain {
+        self.domain.clone()
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.find_for_caller(prefix, acct, None, resource_repo)
+    }
+
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        match self.route_for(prefix) {
+            Some(find) => find(acct, caller, resource_repo),
+            None => Err(ResolverError::NotFound),
+        }
+    }
+
+    fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        match self.urls {
+            Some(ref find) => find(url, resource_repo),
+            None => Err(ResolverError::NotFound),
+        }
+    }
+}
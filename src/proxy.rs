@@ -0,0 +1,242 @@
+use crate::{
+    parse_resource, FilteredWebfinger, InstanceDomain, ResponseFormat, Webfinger, WebfingerClient,
+    WebfingerError,
+};
+use std::{collections::HashSet, fmt};
+
+/// The special-use domains that only exist on local networks.
+const LOCAL_DOMAINS: [&str; 4] = ["localhost", "local", "internal", "home.arpa"];
+
+/// The reason why a [`WebfingerProxy`] couldn't serve a resource.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ProxyError {
+    /// The requested resource was not correctly formatted.
+    InvalidResource,
+
+    /// The domain of the resource is not allowed by the policy of the proxy.
+    Denied,
+
+    /// Fetching the resource failed.
+    Upstream(WebfingerError),
+}
+
+impl ProxyError {
+    /// A stable code identifying this error, from `WF0501` to `WF0599`.
+    ///
+    /// Upstream errors have the code of the [`WebfingerError`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            ProxyError::InvalidResource => "WF0501",
+            ProxyError::Denied => "WF0502",
+            ProxyError::Upstream(error) => error.code(),
+        }
+    }
+
+    /// The HTTP status to answer with.
+    pub fn status(&self) -> u16 {
+        match self {
+            ProxyError::InvalidResource => 400,
+            ProxyError::Denied => 403,
+            ProxyError::Upstream(WebfingerError::NotFound) => 404,
+            ProxyError::Upstream(WebfingerError::RateLimited) => 429,
+            ProxyError::Upstream(_) => 502,
+        }
+    }
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyError::InvalidResource => {
+                write!(f, "[{}] the requested resource is invalid", self.code())
+            }
+            ProxyError::Denied => write!(
+                f,
+                "[{}] the domain of the resource is not allowed",
+                self.code()
+            ),
+            ProxyError::Upstream(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ProxyError {}
+
+/// An HTTP response generated by a [`WebfingerProxy`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyResponse {
+    /// The HTTP status.
+    pub status: u16,
+
+    /// The headers to send, including `Content-Type` for successful responses.
+    pub headers: Vec<(String, String)>,
+
+    /// The body of the response, empty in case of error.
+    pub body: String,
+}
+
+/// A WebFinger relay, fetching resources on any domain on behalf of its own clients.
+///
+/// It allows clients that can't make cross-origin requests (browser extensions, web and mobile
+/// apps) to look up resources anywhere. Resources are fetched with a [`WebfingerClient`], so
+/// caching, retries and rate limits are configured on it. The proxy itself only decides which
+/// domains can be looked up.
+///
+/// It doesn't depend on any web framework: [`WebfingerProxy::respond`] takes the parameters of a
+/// request and returns the response to send.
+///
+/// # Security
+///
+/// A proxy makes requests to hosts chosen by its clients, from inside the network it runs in. To
+/// keep it from being used to reach internal services, resources on IP addresses, on names
+/// without a dot and on local domains (like `localhost` or `printer.local`) are refused by
+/// default, see [`WebfingerProxy::allow_local_hosts`]. So are resources on other ports than the
+/// default ones: 443, and 80 if the [`WebfingerClient`] uses plain HTTP.
+///
+/// Responses are read up to [`MAX_BODY_SIZE`](crate::MAX_BODY_SIZE) by the HTTP clients of the
+/// crate. A custom [`HttpFetch`](crate::HttpFetch) should enforce the same limit.
+///
+/// Public names that resolve to private addresses, and redirections to such addresses, are not
+/// detected: when the proxy is reachable from untrusted clients, its [`WebfingerClient`] should
+/// also be configured to go through an outgoing proxy that only allows public addresses.
+#[derive(Debug, Clone)]
+pub struct WebfingerProxy {
+    client: WebfingerClient,
+    allowed: Option<HashSet<String>>,
+    denied: HashSet<String>,
+    allow_local: bool,
+}
+
+impl WebfingerProxy {
+    /// Creates a proxy allowing every public domain.
+    pub fn new(client: WebfingerClient) -> WebfingerProxy {
+        WebfingerProxy {
+            client,
+            allowed: None,
+            denied: HashSet::new(),
+            allow_local: false,
+        }
+    }
+
+    /// Allows resources on IP addresses, on names without a dot and on local domains, and on any
+    /// port, that are refused by default.
+    ///
+    /// They still need to be allowed by [`allow_domain`](WebfingerProxy::allow_domain) if it is
+    /// used. Only use it if the clients of the proxy are trusted, see the
+    /// [security considerations](WebfingerProxy#security).
+    pub fn allow_local_hosts(mut self) -> WebfingerProxy {
+        self.allow_local = true;
+        self
+    }
+
+    /// Only allows resources on `domain` and its subdomains, and the other allowed domains.
+    ///
+    /// Invalid domains are ignored.
+    pub fn allow_domain(mut self, domain: &str) -> WebfingerProxy {
+        if let Ok(domain) = InstanceDomain::new(domain) {
+            self.allowed
+                .get_or_insert_with(HashSet::new)
+                .insert(domain.domain().to_string());
+        }
+        self
+    }
+
+    /// Refuses resources on `domain` and its subdomains, even if they are allowed.
+    ///
+    /// Invalid domains are ignored.
+    pub fn deny_domain(mut self, domain: &str) -> WebfingerProxy {
+        if let Ok(domain) = InstanceDomain::new(domain) {
+            self.denied.insert(domain.domain().to_string());
+        }
+        self
+    }
+
+    /// Fetches a resource, keeping only the links with one of the `rels` if there are some.
+    pub async fn resolve(&self, resource: &str, rels: &[&str]) -> Result<Webfinger, ProxyError> {
+        let parsed = parse_resource(resource).map_err(|_| ProxyError::InvalidResource)?;
+        let domain =
+            InstanceDomain::new(&parsed.domain).map_err(|_| ProxyError::InvalidResource)?;
+        if !self.is_allowed(domain.domain(), parsed.port) {
+            return Err(ProxyError::Denied);
+        }
+
+        let webfinger = self
+            .client
+            .resolve(resource)
+            .await
+            .map_err(ProxyError::Upstream)?;
+        Ok(FilteredWebfinger::new(webfinger, rels).filtered)
+    }
+
+    /// Answers a request for `resource`, in the format chosen according to the `accept` header.
+    ///
+    /// Responses allow cross-origin requests.
+    pub async fn respond(
+        &self,
+        resource: &str,
+        rels: &[&str],
+        accept: Option<&str>,
+    ) -> ProxyResponse {
+        let mut headers = vec![("Access-Control-Allow-Origin".to_string(), "*".to_string())];
+        match self.resolve(resource, rels).await {
+            Ok(webfinger) => {
+                let (content_type, body) =
+                    ResponseFormat::negotiate(accept, false).render(&webfinger);
+                headers.push(("Content-Type".to_string(), content_type.to_string()));
+                ProxyResponse {
+                    status: 200,
+                    headers,
+                    body,
+                }
+            }
+            Err(error) => ProxyResponse {
+                status: error.status(),
+                headers,
+                body: String::new(),
+            },
+        }
+    }
+
+    /// Checks the policy for `domain` and `port`, looking at the parent domains too.
+    fn is_allowed(&self, domain: &str, port: Option<u16>) -> bool {
+        if !self.allow_local {
+            let default_port = match port {
+                None | Some(443) => true,
+                Some(80) => !self.client.with_https,
+                Some(_) => false,
+            };
+            if !default_port || is_local(domain) {
+                return false;
+            }
+        }
+        let matches = |list: &HashSet<String>| {
+            let mut domain = domain;
+            loop {
+                if list.contains(domain) {
+                    return true;
+                }
+                match domain.split_once('.') {
+                    Some((_, parent)) => domain = parent,
+                    None => return false,
+                }
+            }
+        };
+        !matches(&self.denied) && self.allowed.as_ref().map_or(true, matches)
+    }
+}
+
+/// Tells if `domain`, normalized by [`InstanceDomain`], is an IP address or a name that can only
+/// be resolved on a local network.
+fn is_local(domain: &str) -> bool {
+    // IPv6 addresses are between brackets
+    if domain.starts_with('[') || domain.parse::<std::net::Ipv4Addr>().is_ok() {
+        return true;
+    }
+    !domain.contains('.')
+        || LOCAL_DOMAINS.iter().any(|local| {
+            domain
+                .strip_suffix(local)
+                .is_some_and(|rest| rest.is_empty() || rest.ends_with('.'))
+        })
+}
@@ -0,0 +1,149 @@
+use crate::ResolverError;
+use std::fmt;
+
+/// The limits enforced by [`WebfingerQuery::parse_with_limits`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryLimits {
+    /// The maximum number of different `rel` parameters.
+    pub max_rels: usize,
+
+    /// The maximum length of the value of a parameter, in bytes once decoded.
+    pub max_value_length: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> QueryLimits {
+        QueryLimits {
+            max_rels: 20,
+            max_value_length: 1024,
+        }
+    }
+}
+
+/// The parameters of a request to a WebFinger endpoint.
+///
+/// Web frameworks parse query strings in different ways, often keeping only one of the repeated
+/// parameters, while WebFinger uses one `rel` parameter per requested relation. Parse the raw
+/// query string with this type instead, and give its fields to
+/// [`Resolver::endpoint_with_rels`](crate::Resolver::endpoint_with_rels), or to the method of
+/// the same name of the async resolvers:
+///
+/// ```rust
+/// use webfinger::WebfingerQuery;
+///
+/// let query = WebfingerQuery::parse("resource=acct%3Aalice%40example.org&rel=self&rel=self")
+///     .unwrap();
+/// assert_eq!(query.resource, "acct:alice@example.org");
+/// assert_eq!(query.rels(), vec!["self"]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebfingerQuery {
+    /// The requested resource.
+    pub resource: String,
+
+    /// The requested relations, without duplicates, in the order of the query string.
+    pub rels: Vec<String>,
+}
+
+impl WebfingerQuery {
+    /// Parses a query string (without the leading `?`) with the default [`QueryLimits`].
+    pub fn parse(query: &str) -> Result<WebfingerQuery, QueryError> {
+        WebfingerQuery::parse_with_limits(query, &QueryLimits::default())
+    }
+
+    /// Parses a query string (without the leading `?`).
+    ///
+    /// Parameters other than `resource` and `rel` are ignored, as required by RFC 7033, but
+    /// their values must respect the limits too.
+    pub fn parse_with_limits(
+        query: &str,
+        limits: &QueryLimits,
+    ) -> Result<WebfingerQuery, QueryError> {
+        let mut resource = None;
+        let mut rels: Vec<String> = vec![];
+        for (name, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            if value.len() > limits.max_value_length {
+                return Err(QueryError::ValueTooLong(name.into_owned()));
+            }
+            match name.as_ref() {
+                "resource" if resource.is_some() => return Err(QueryError::DuplicateResource),
+                "resource" => resource = Some(value.into_owned()),
+                "rel" if rels.iter().any(|rel| *rel == value) => {}
+                "rel" if rels.len() == limits.max_rels => {
+                    return Err(QueryError::TooManyRels(limits.max_rels))
+                }
+                "rel" => rels.push(value.into_owned()),
+                _ => {}
+            }
+        }
+        match resource {
+            Some(resource) if !resource.is_empty() => Ok(WebfingerQuery { resource, rels }),
+            _ => Err(QueryError::MissingResource),
+        }
+    }
+
+    /// The requested relations, as expected by
+    /// [`Resolver::endpoint_with_rels`](crate::Resolver::endpoint_with_rels).
+    pub fn rels(&self) -> Vec<&str> {
+        self.rels.iter().map(String::as_str).collect()
+    }
+}
+
+/// The reason why a query string was refused by [`WebfingerQuery::parse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QueryError {
+    /// There is no `resource` parameter, or an empty one.
+    MissingResource,
+
+    /// There is more than one `resource` parameter.
+    DuplicateResource,
+
+    /// There are more different `rel` parameters than the given limit.
+    TooManyRels(usize),
+
+    /// The value of the given parameter is longer than the limit.
+    ValueTooLong(String),
+}
+
+impl QueryError {
+    /// A stable code identifying this error, from `WF1001` to `WF1099`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            QueryError::MissingResource => "WF1001",
+            QueryError::DuplicateResource => "WF1002",
+            QueryError::TooManyRels(_) => "WF1003",
+            QueryError::ValueTooLong(_) => "WF1004",
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] ", self.code())?;
+        match self {
+            QueryError::MissingResource => write!(f, "the resource parameter is required"),
+            QueryError::DuplicateResource => {
+                write!(f, "the resource parameter is given more than once")
+            }
+            QueryError::TooManyRels(max) => {
+                write!(f, "more than {} rel parameters were given", max)
+            }
+            QueryError::ValueTooLong(name) => write!(f, "the {} parameter is too long", name),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// Answers a missing resource with [`ResolverError::MissingResource`], and other errors with
+/// [`ResolverError::InvalidResource`], so that [`EndpointResponse::new`](crate::EndpointResponse::new)
+/// gives them a `400` status.
+impl From<QueryError> for ResolverError {
+    fn from(error: QueryError) -> ResolverError {
+        match error {
+            QueryError::MissingResource => ResolverError::MissingResource,
+            _ => ResolverError::InvalidResource,
+        }
+    }
+}
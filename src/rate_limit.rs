@@ -0,0 +1,32 @@
+use futures_util::future::BoxFuture;
+use std::sync::Arc;
+
+/// A budget of requests per host, shared by a [`WebfingerClient`](crate::WebfingerClient) with
+/// other clients.
+///
+/// Implement it to make several worker processes of the same instance share their request budget,
+/// by storing it in Redis for instance. With the `governor` feature, it is implemented for
+/// `governor`'s keyed rate limiters.
+pub trait RateLimit: Send + Sync {
+    /// Asks for the permission to send a request to `host`.
+    ///
+    /// `host` includes the port if there is one. The returned future may wait until a request is
+    /// allowed. If it resolves to `false`, the request is not sent and fails with
+    /// [`WebfingerError::RateLimited`](crate::WebfingerError::RateLimited).
+    fn acquire<'a>(&'a self, host: &'a str) -> BoxFuture<'a, bool>;
+}
+
+impl<T: RateLimit + ?Sized> RateLimit for Arc<T> {
+    fn acquire<'a>(&'a self, host: &'a str) -> BoxFuture<'a, bool> {
+        (**self).acquire(host)
+    }
+}
+
+/// Refuses the requests exceeding the quota of their host, without waiting.
+#[cfg(feature = "governor")]
+impl RateLimit for governor::DefaultKeyedRateLimiter<String> {
+    fn acquire<'a>(&'a self, host: &'a str) -> BoxFuture<'a, bool> {
+        let allowed = self.check_key(&host.to_string()).is_ok();
+        Box::pin(futures_util::future::ready(allowed))
+    }
+}
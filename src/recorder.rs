@@ -0,0 +1,227 @@
+use crate::{format_rfc3339, HttpFetch, HttpRequest, HttpResponse, WebfingerError};
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime},
+};
+
+/// The headers whose values are never recorded.
+const SECRET_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+];
+
+/// A request made by a [`WebfingerClient`](crate::WebfingerClient), as captured by a
+/// [`Recorder`].
+///
+/// Its fields are named like the ones of the HAR format.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedEntry {
+    /// When the request was sent, as an RFC 3339 date.
+    pub started_date_time: String,
+
+    /// How long the request took, in milliseconds.
+    pub time: u64,
+
+    /// The request.
+    pub request: RecordedRequest,
+
+    /// The response, if one was received.
+    pub response: Option<RecordedResponse>,
+
+    /// Why no response was received.
+    pub error: Option<String>,
+}
+
+/// A request captured by a [`Recorder`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecordedRequest {
+    /// Always `GET`.
+    pub method: String,
+
+    /// The complete URL, including the query string.
+    pub url: String,
+
+    /// The headers of the request, with secret values redacted.
+    pub headers: Vec<RecordedHeader>,
+}
+
+/// A response captured by a [`Recorder`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedResponse {
+    /// The HTTP status code.
+    pub status: u16,
+
+    /// The headers of the response, with secret values redacted.
+    pub headers: Vec<RecordedHeader>,
+
+    /// The size of the body, in bytes.
+    pub body_size: usize,
+}
+
+/// A header captured by a [`Recorder`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecordedHeader {
+    /// The name of the header.
+    pub name: String,
+
+    /// Its value, or `[redacted]` for headers that may contain credentials.
+    pub value: String,
+}
+
+impl RecordedHeader {
+    fn list(headers: &[(String, String)]) -> Vec<RecordedHeader> {
+        headers
+            .iter()
+            .map(|(name, value)| RecordedHeader {
+                name: name.clone(),
+                value: if SECRET_HEADERS.contains(&name.to_lowercase().as_str()) {
+                    "[redacted]".to_string()
+                } else {
+                    value.clone()
+                },
+            })
+            .collect()
+    }
+}
+
+/// Captures the requests made by a [`WebfingerClient`](crate::WebfingerClient), to help debugging
+/// interoperability issues.
+///
+/// It is enabled with [`WebfingerClientBuilder::record`](crate::WebfingerClientBuilder::record).
+/// Clones of a recorder share the same entries. Credentials in headers are redacted, but query
+/// parameters are recorded as they are, and bodies are not recorded at all.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    entries: Arc<Mutex<Vec<RecordedEntry>>>,
+}
+
+impl Recorder {
+    /// Creates an empty recorder.
+    pub fn new() -> Recorder {
+        Recorder::default()
+    }
+
+    /// Returns the entries recorded so far.
+    pub fn entries(&self) -> Vec<RecordedEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Removes all the recorded entries.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Exports the recorded entries as a HAR-like JSON log.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": self.entries(),
+            }
+        })
+    }
+
+    fn push(&self, entry: RecordedEntry) {
+        self.entries.lock().unwrap().push(entry);
+    }
+}
+
+/// An [`HttpFetch`] implementation recording the requests made with another one.
+pub(crate) struct RecordingFetch {
+    pub(crate) inner: Arc<dyn HttpFetch>,
+    pub(crate) recorder: Recorder,
+}
+
+impl HttpFetch for RecordingFetch {
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        Box::pin(async move {
+            let started_date_time = format_rfc3339(SystemTime::now());
+            let start = Instant::now();
+            let recorded_request = RecordedRequest {
+                method: "GET".to_string(),
+                url: request.url.clone(),
+                headers: RecordedHeader::list(&request.headers),
+            };
+            let result = self.inner.fetch(request).await;
+            let (response, error) = match result {
+                Ok(ref response) => (
+                    Some(RecordedResponse {
+                        status: response.status,
+                        headers: RecordedHeader::list(&response.headers),
+                        body_size: response.body.len(),
+                    }),
+                    None,
+                ),
+                Err(ref error) => (None, Some(format!("{:?}", error))),
+            };
+            self.recorder.push(RecordedEntry {
+                started_date_time,
+                time: start.elapsed().as_millis() as u64,
+                request: recorded_request,
+                response,
+                error,
+            });
+            result
+        })
+    }
+}
+
+/// A raw response received by a [`WebfingerClient`](crate::WebfingerClient), given to the hook of
+/// [`WebfingerClientBuilder::tee_responses`](crate::WebfingerClientBuilder::tee_responses) before
+/// it is parsed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TeedResponse<'a> {
+    /// The URL that was fetched.
+    pub url: &'a str,
+
+    /// The HTTP status code.
+    pub status: u16,
+
+    /// The headers of the response.
+    pub headers: &'a [(String, String)],
+
+    /// The body of the response, cut to the maximum size given to the client.
+    pub body: &'a [u8],
+
+    /// Wether the body was longer than the maximum size, and was cut.
+    pub truncated: bool,
+}
+
+pub(crate) type ResponseHook = Arc<dyn Fn(&TeedResponse<'_>) + Send + Sync>;
+
+/// An [`HttpFetch`] implementation giving the responses of another one to a hook.
+pub(crate) struct TeeFetch {
+    pub(crate) inner: Arc<dyn HttpFetch>,
+    pub(crate) hook: ResponseHook,
+    pub(crate) max_size: usize,
+}
+
+impl HttpFetch for TeeFetch {
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        Box::pin(async move {
+            let url = request.url.clone();
+            let response = self.inner.fetch(request).await?;
+            let truncated = response.body.len() > self.max_size;
+            (self.hook)(&TeedResponse {
+                url: &url,
+                status: response.status,
+                headers: &response.headers,
+                body: &response.body[..response.body.len().min(self.max_size)],
+                truncated,
+            });
+            Ok(response)
+        })
+    }
+}
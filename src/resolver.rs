@@ -1,4 +1,35 @@
-use crate::{Prefix, ResolverError, Webfinger};
+use crate::{parse_target, Caller, InstanceDomain, Prefix, ResolverError, Target, Webfinger};
+use std::collections::HashMap;
+use url::Url;
+
+/// A WebFinger result filtered by `rel`, along with the complete document it comes from.
+///
+/// It is returned by [`Resolver::endpoint_with_rels`], for servers that need to send the filtered
+/// document while logging or caching the full one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilteredWebfinger {
+    /// The document containing only the requested links, to send to the client.
+    pub filtered: Webfinger,
+
+    /// The complete document.
+    pub full: Webfinger,
+}
+
+impl FilteredWebfinger {
+    /// Filters the links of `full`, keeping the ones with one of the `rels`.
+    ///
+    /// As specified in [RFC 7033](https://tools.ietf.org/html/rfc7033#section-4.3), all the links
+    /// are kept if `rels` is empty.
+    pub fn new(full: Webfinger, rels: &[&str]) -> FilteredWebfinger {
+        let mut filtered = full.clone();
+        if !rels.is_empty() {
+            filtered
+                .links
+                .retain(|link| rels.contains(&link.rel.as_str()));
+        }
+        FilteredWebfinger { filtered, full }
+    }
+}
 
 /// A trait to easily generate a WebFinger endpoint for any resource repository.
 ///
@@ -6,7 +37,21 @@ use crate::{Prefix, ResolverError, Webfinger};
 /// [`find`](Resolver::find) and [`endpoint`](Resolver::endpoint) functions.
 pub trait Resolver<R> {
     /// Returns the domain name of the current instance.
-    fn instance_domain<'a>(&self) -> &'a str;
+    ///
+    /// It should include the port if it is not the default one. Resources on other domains are
+    /// refused with [`ResolverError::WrongDomain`].
+    fn instance_domain(&self) -> InstanceDomain;
+
+    /// Tells if the resources on `domain` are served by this resolver.
+    ///
+    /// By default, only the [`instance_domain`](Resolver::instance_domain) is accepted. Wrappers
+    /// forward it to the resolver they wrap, so that the domains added by a [`WebDomain`] are
+    /// accepted wherever it is in a stack of wrappers.
+    ///
+    /// [`WebDomain`]: crate::WebDomain
+    fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        *domain == self.instance_domain()
+    }
 
     /// Tries to find a resource, `acct`, in the repository `resource_repo`.
     ///
@@ -21,24 +66,107 @@ pub trait Resolver<R> {
         resource_repo: R,
     ) -> Result<Webfinger, ResolverError>;
 
+    /// Tries to find a resource for a given caller.
+    ///
+    /// It allows to give more information (like private links) to some callers. By default, the
+    /// caller is ignored and [`find`](Resolver::find) is used.
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        _caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.find(prefix, acct, resource_repo)
+    }
+
+    /// Tries to find a resource from its URL, like `https://example.org/@someone`.
+    ///
+    /// It is used when the requested resource is an `https:` URL on the current instance, to
+    /// answer requests for the profile page of an account, as Mastodon does. By default, all
+    /// these resources are reported as [`ResolverError::NotFound`].
+    fn find_by_url(&self, _url: &Url, _resource_repo: R) -> Result<Webfinger, ResolverError> {
+        Err(ResolverError::NotFound)
+    }
+
+    /// Tells if [`endpoint_with_rels`](Resolver::endpoint_with_rels) removes the links whose
+    /// `rel` was not requested, as recommended by
+    /// [RFC 7033](https://tools.ietf.org/html/rfc7033#section-4.3).
+    ///
+    /// It is `true` by default. Return `false` to always send all the links of the documents.
+    fn filter_by_rels(&self) -> bool {
+        true
+    }
+
     /// Returns a WebFinger result for a requested resource.
     fn endpoint(
         &self,
         resource: impl Into<String>,
         resource_repo: R,
     ) -> Result<Webfinger, ResolverError> {
-        let resource = resource.into();
-        let mut parsed_query = resource.splitn(2, ':');
-        let res_prefix = Prefix::from(parsed_query.next().ok_or(ResolverError::InvalidResource)?);
-        let res = parsed_query.next().ok_or(ResolverError::InvalidResource)?;
-
-        let mut parsed_res = res.splitn(2, '@');
-        let user = parsed_res.next().ok_or(ResolverError::InvalidResource)?;
-        let domain = parsed_res.next().ok_or(ResolverError::InvalidResource)?;
-        if domain == self.instance_domain() {
-            self.find(res_prefix, user.to_string(), resource_repo)
-        } else {
-            Err(ResolverError::WrongDomain)
+        self.endpoint_for_caller(resource, None, resource_repo)
+    }
+
+    /// Returns a WebFinger result for a requested resource, keeping only the links with one of the
+    /// requested `rels`.
+    ///
+    /// The complete document is returned too, without having to call [`find`](Resolver::find)
+    /// twice. All the links are kept if [`filter_by_rels`](Resolver::filter_by_rels) is `false`.
+    fn endpoint_with_rels(
+        &self,
+        resource: impl Into<String>,
+        rels: &[&str],
+        resource_repo: R,
+    ) -> Result<FilteredWebfinger, ResolverError> {
+        let rels = if self.filter_by_rels() { rels } else { &[] };
+        self.endpoint(resource, resource_repo)
+            .map(|full| FilteredWebfinger::new(full, rels))
+    }
+
+    /// Resolves many resources at once, keeping only the links with one of the requested `rels`.
+    ///
+    /// This is not part of the WebFinger protocol, but it is useful for tools that need to check
+    /// many local accounts, like administration commands or migration scripts. The results are
+    /// the same as the ones of [`endpoint_with_rels`](Resolver::endpoint_with_rels), and are
+    /// indexed by resource as given in `resources`.
+    fn endpoint_batch(
+        &self,
+        resources: &[&str],
+        rels: &[&str],
+        resource_repo: R,
+    ) -> HashMap<String, Result<Webfinger, ResolverError>>
+    where
+        R: Clone,
+    {
+        resources
+            .iter()
+            .map(|resource| {
+                let result = self
+                    .endpoint_with_rels(*resource, rels, resource_repo.clone())
+                    .map(|result| result.filtered);
+                (resource.to_string(), result)
+            })
+            .collect()
+    }
+
+    /// Returns a WebFinger result for a request made by `caller`.
+    ///
+    /// `caller` is `None` for anonymous requests.
+    fn endpoint_for_caller(
+        &self,
+        resource: impl Into<String>,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let (target, host) = parse_target(&resource.into())?;
+        if !self.accepts_domain(&host) {
+            return Err(ResolverError::WrongDomain);
+        }
+        match target {
+            Target::Url(url) => self.find_by_url(&url, resource_repo),
+            Target::Resource(resource) => {
+                self.find_for_caller(resource.prefix, resource.user, caller, resource_repo)
+            }
         }
     }
 }
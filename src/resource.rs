@@ -0,0 +1,193 @@
+#[cfg(feature = "server")]
+use crate::InstanceDomain;
+use crate::{Prefix, ResolverError};
+use std::fmt;
+#[cfg(feature = "server")]
+use url::Url;
+
+/// A parsed WebFinger resource, like `acct:someone@example.org`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resource {
+    /// The prefix of the resource.
+    pub prefix: Prefix,
+
+    /// The identifier of the resource on its instance (e.g. `someone`).
+    ///
+    /// It is kept as it was given: an `@` in it stays percent-encoded (`%40`).
+    pub user: String,
+
+    /// The domain of the instance (e.g. `example.org`).
+    pub domain: String,
+
+    /// The port of the instance, if the resource contains one.
+    pub port: Option<u16>,
+}
+
+impl Resource {
+    /// Parses a resource, as given in the `resource` parameter of a WebFinger request, telling
+    /// what is wrong with it when it is invalid.
+    ///
+    /// The resource must be made of a prefix (see [`Prefix::parse`]), a `:`, a non-empty user,
+    /// a `@` and a non-empty domain, optionally followed by a port. As in
+    /// [RFC 7565](https://tools.ietf.org/html/rfc7565#section-7), an `@` that is part of the user
+    /// (like in an email address) must be percent-encoded: `acct:juliet%40capulet.example@shoppingsite.example`
+    /// is valid, but `acct:juliet@capulet.example@shoppingsite.example` is refused, instead of
+    /// guessing which `@` separates the user from the domain.
+    pub fn parse(resource: &str) -> Result<Resource, ResourceError> {
+        let (prefix, res) = resource
+            .split_once(':')
+            .filter(|(prefix, _)| !prefix.contains('@'))
+            .ok_or(ResourceError::MissingPrefix)?;
+        let prefix = Prefix::parse(prefix).map_err(|_| ResourceError::InvalidPrefix)?;
+        let (user, host) = res.split_once('@').ok_or(ResourceError::MissingAt)?;
+        if user.is_empty() {
+            return Err(ResourceError::EmptyUser);
+        }
+        if host.contains('@') {
+            return Err(ResourceError::MultipleAt);
+        }
+
+        let (domain, port) = match host.rsplit_once(':') {
+            Some((domain, port))
+                if !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                let port = port.parse().map_err(|_| ResourceError::InvalidPort)?;
+                (domain, Some(port))
+            }
+            _ => (host, None),
+        };
+        if domain.is_empty() {
+            return Err(ResourceError::EmptyDomain);
+        }
+
+        Ok(Resource {
+            prefix,
+            user: user.to_string(),
+            domain: domain.to_string(),
+            port,
+        })
+    }
+
+    /// Returns the domain of the instance, followed by its port if there is one.
+    ///
+    /// This is what [`Resolver::instance_domain`](crate::Resolver::instance_domain) is compared
+    /// to.
+    pub fn host(&self) -> String {
+        match self.port {
+            Some(port) => format!("{}:{}", self.domain, port),
+            None => self.domain.clone(),
+        }
+    }
+}
+
+/// Parses a resource, as given in the `resource` parameter of a WebFinger request.
+///
+/// The resource must have a valid prefix (see [`Prefix::parse`]), followed by `user@domain`. The
+/// domain may end with a port. This function is used by [`Resolver::endpoint`](crate::Resolver),
+/// so it can be used to validate user input with the same rules.
+///
+/// Use [`Resource::parse`] to know why a resource is invalid.
+pub fn parse_resource(resource: &str) -> Result<Resource, ResolverError> {
+    Ok(Resource::parse(resource)?)
+}
+
+/// The reason why a resource couldn't be parsed by [`Resource::parse`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ResourceError {
+    /// The resource doesn't start with a prefix followed by `:`.
+    MissingPrefix,
+
+    /// The prefix is not a valid URI scheme.
+    InvalidPrefix,
+
+    /// There is no `@` between the user and the domain.
+    MissingAt,
+
+    /// There is more than one `@`: the ones in the user should be percent-encoded.
+    MultipleAt,
+
+    /// There is nothing before the `@`.
+    EmptyUser,
+
+    /// There is nothing after the `@`.
+    EmptyDomain,
+
+    /// The port is too large.
+    InvalidPort,
+}
+
+impl ResourceError {
+    /// A stable code identifying this error, from `WF0801` to `WF0899`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ResourceError::MissingPrefix => "WF0801",
+            ResourceError::InvalidPrefix => "WF0802",
+            ResourceError::MissingAt => "WF0803",
+            ResourceError::MultipleAt => "WF0804",
+            ResourceError::EmptyUser => "WF0805",
+            ResourceError::EmptyDomain => "WF0806",
+            ResourceError::InvalidPort => "WF0807",
+        }
+    }
+}
+
+impl fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] ", self.code())?;
+        match self {
+            ResourceError::MissingPrefix => write!(f, "the resource has no prefix"),
+            ResourceError::InvalidPrefix => write!(f, "the prefix of the resource is invalid"),
+            ResourceError::MissingAt => write!(f, "the resource has no domain"),
+            ResourceError::MultipleAt => write!(
+                f,
+                "the resource contains more than one @, the ones in the user should be %40"
+            ),
+            ResourceError::EmptyUser => write!(f, "the user of the resource is empty"),
+            ResourceError::EmptyDomain => write!(f, "the domain of the resource is empty"),
+            ResourceError::InvalidPort => write!(f, "the port of the resource is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for ResourceError {}
+
+impl From<ResourceError> for ResolverError {
+    fn from(_: ResourceError) -> ResolverError {
+        ResolverError::InvalidResource
+    }
+}
+
+/// What the `resource` parameter of a request designates.
+#[cfg(feature = "server")]
+pub(crate) enum Target {
+    /// An `https:` URL, like `https://example.org/@someone`.
+    Url(Url),
+
+    /// A resource with a prefix, like `acct:someone@example.org`.
+    Resource(Resource),
+}
+
+/// Parses the `resource` parameter of a request, returning what it designates and the domain it is
+/// on, to be compared to [`Resolver::instance_domain`](crate::Resolver::instance_domain).
+#[cfg(feature = "server")]
+pub(crate) fn parse_target(resource: &str) -> Result<(Target, InstanceDomain), ResolverError> {
+    let invalid = |_| ResolverError::InvalidResource;
+    if resource.is_empty() {
+        return Err(ResolverError::MissingResource);
+    }
+    if resource.starts_with("https://") {
+        if let Ok(url) = Url::parse(resource) {
+            let host = url.host_str().ok_or(ResolverError::InvalidResource)?;
+            let host = match url.port() {
+                Some(port) => InstanceDomain::new(&format!("{}:{}", host, port)),
+                None => InstanceDomain::new(host),
+            };
+            return Ok((Target::Url(url), host.map_err(invalid)?));
+        }
+    }
+
+    let resource = parse_resource(resource)?;
+    let host = InstanceDomain::new(&resource.host()).map_err(invalid)?;
+    Ok((Target::Resource(resource), host))
+}
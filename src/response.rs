@@ -0,0 +1,279 @@
+use crate::{consts, ResolverError, Webfinger};
+use std::collections::HashMap;
+
+/// An HTTP response to a WebFinger request, generated from the result of a [`Resolver`](crate::Resolver).
+///
+/// It doesn't depend on any web framework: send its status, headers and body with the one used by
+/// the application.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndpointResponse {
+    /// The HTTP status.
+    pub status: u16,
+
+    /// The headers to send, including `Content-Type` and `Content-Length` for successful
+    /// responses.
+    pub headers: Vec<(String, String)>,
+
+    /// The body of the response, empty in case of error and for `HEAD` requests.
+    ///
+    /// Requests without a resource are the exception: they get a problem document, see
+    /// [`EndpointResponse::missing_resource`].
+    pub body: String,
+}
+
+impl EndpointResponse {
+    /// Answers a `GET` request with `result`, serialized in `format`.
+    ///
+    /// Responses allow cross-origin requests, as required by RFC 7033.
+    pub fn new(
+        result: &Result<Webfinger, ResolverError>,
+        format: ResponseFormat,
+    ) -> EndpointResponse {
+        let mut headers = vec![("Access-Control-Allow-Origin".to_string(), "*".to_string())];
+        match result {
+            Ok(webfinger) => {
+                let (content_type, body) = format.render(webfinger);
+                headers.push(("Content-Type".to_string(), content_type.to_string()));
+                headers.push(("Content-Length".to_string(), body.len().to_string()));
+                EndpointResponse {
+                    status: 200,
+                    headers,
+                    body,
+                }
+            }
+            Err(ResolverError::MissingResource) => EndpointResponse::missing_resource(),
+            Err(error) => EndpointResponse {
+                status: error.status(),
+                headers,
+                body: String::new(),
+            },
+        }
+    }
+
+    /// Answers a request without a `resource` parameter.
+    ///
+    /// It is a `400` response with a problem document
+    /// ([RFC 7807](https://tools.ietf.org/html/rfc7807)), so that clients can tell it apart
+    /// from an invalid resource. Use it when the parameter is absent from the query string; an
+    /// empty one is reported as [`ResolverError::MissingResource`] by
+    /// [`Resolver::endpoint`](crate::Resolver::endpoint), and answered the same way by
+    /// [`EndpointResponse::new`].
+    pub fn missing_resource() -> EndpointResponse {
+        let error = ResolverError::MissingResource;
+        let body = serde_json::json!({
+            "type": "about:blank",
+            "title": "Missing resource",
+            "status": error.status(),
+            "detail": "The resource parameter is required.",
+        })
+        .to_string();
+        EndpointResponse {
+            status: error.status(),
+            headers: vec![
+                ("Access-Control-Allow-Origin".to_string(), "*".to_string()),
+                (
+                    "Content-Type".to_string(),
+                    consts::PROBLEM_JSON_MIME.to_string(),
+                ),
+                ("Content-Length".to_string(), body.len().to_string()),
+            ],
+            body,
+        }
+    }
+
+    /// Answers a `HEAD` request with `result`.
+    ///
+    /// The status and the headers are the ones a `GET` request would get, including the
+    /// `Content-Length` of the body, but the body is empty. Wrap the resolver in
+    /// [`CacheLookups`](crate::CacheLookups) so that a `HEAD` request followed by a `GET` only
+    /// looks the resource up once.
+    pub fn head(
+        result: &Result<Webfinger, ResolverError>,
+        format: ResponseFormat,
+    ) -> EndpointResponse {
+        let mut response = EndpointResponse::new(result, format);
+        response.body.clear();
+        response
+    }
+}
+
+/// A format in which a WebFinger result can be served.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseFormat {
+    /// JSON Resource Descriptor, served as `application/jrd+json`.
+    ///
+    /// This is the format required by RFC 7033, and the default one.
+    Jrd,
+
+    /// The same document, served as plain `application/json`.
+    Json,
+
+    /// The legacy XML format, served as `application/xrd+xml`.
+    Xrd,
+}
+
+impl ResponseFormat {
+    /// Chooses the format to use according to the `Accept` header of a request.
+    ///
+    /// If there is no `Accept` header, or if none of the formats are acceptable, JRD is used.
+    /// XRD is only considered when `with_xrd` is `true`.
+    pub fn negotiate(accept: Option<&str>, with_xrd: bool) -> ResponseFormat {
+        let accept = match accept {
+            Some(accept) => accept,
+            None => return ResponseFormat::Jrd,
+        };
+
+        let mut candidates = vec![ResponseFormat::Jrd, ResponseFormat::Json];
+        if with_xrd {
+            candidates.push(ResponseFormat::Xrd);
+        }
+
+        let mut best = (ResponseFormat::Jrd, 0.0);
+        for format in candidates {
+            let quality = quality_for(accept, format.content_type());
+            if quality > best.1 {
+                best = (format, quality);
+            }
+        }
+        best.0
+    }
+
+    /// The value of the `Content-Type` header to use with this format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ResponseFormat::Jrd => consts::JRD_MIME,
+            ResponseFormat::Json => consts::JSON_MIME,
+            ResponseFormat::Xrd => consts::XRD_MIME,
+        }
+    }
+
+    /// Serializes a WebFinger result in this format.
+    ///
+    /// Returns the content type of the response, and its body.
+    pub fn render(self, webfinger: &Webfinger) -> (&'static str, String) {
+        let body = match self {
+            ResponseFormat::Jrd | ResponseFormat::Json => {
+                serde_json::to_string(webfinger).expect("Webfinger is always serializable")
+            }
+            ResponseFormat::Xrd => to_xrd(webfinger),
+        };
+        (self.content_type(), body)
+    }
+}
+
+/// Finds the quality value given to `mime` in an `Accept` header.
+///
+/// The most specific matching media range is used, and `0.0` is returned if none matches.
+fn quality_for(accept: &str, mime: &str) -> f32 {
+    let main_type = mime.split('/').next().unwrap_or_default();
+    let mut best: Option<(u8, f32)> = None;
+    for range in accept.split(',') {
+        let mut params = range.split(';');
+        let media_range = params.next().unwrap_or_default().trim().to_lowercase();
+        let specificity = if media_range == mime {
+            2
+        } else if media_range == format!("{}/*", main_type) {
+            1
+        } else if media_range == "*/*" {
+            0
+        } else {
+            continue;
+        };
+
+        let quality = params
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if best.map_or(true, |(s, _)| specificity > s) {
+            best = Some((specificity, quality));
+        }
+    }
+    best.map(|(_, q)| q).unwrap_or(0.0)
+}
+
+fn to_xrd(webfinger: &Webfinger) -> String {
+    let mut xrd = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <XRD xmlns=\"http://docs.oasis-open.org/ns/xri/xrd-1.0\">\n",
+    );
+    // RFC 6415 §3.1: Expires comes before Subject in the XRD schema
+    if let Some(ref expires) = webfinger.expires {
+        xrd.push_str(&format!("  <Expires>{}</Expires>\n", escape_xml(expires)));
+    }
+    xrd.push_str(&format!(
+        "  <Subject>{}</Subject>\n",
+        escape_xml(&webfinger.subject)
+    ));
+    for alias in &webfinger.aliases {
+        xrd.push_str(&format!("  <Alias>{}</Alias>\n", escape_xml(alias)));
+    }
+    push_xrd_properties(&mut xrd, "  ", &webfinger.properties);
+    for link in &webfinger.links {
+        xrd.push_str(&format!("  <Link rel=\"{}\"", escape_xml(&link.rel)));
+        let attributes = [
+            ("type", &link.mime_type),
+            ("href", &link.href),
+            ("template", &link.template),
+        ];
+        for (name, value) in attributes.iter() {
+            if let Some(value) = value {
+                xrd.push_str(&format!(" {}=\"{}\"", name, escape_xml(value)));
+            }
+        }
+        if link.titles.is_empty() && link.properties.is_empty() {
+            xrd.push_str("/>\n");
+        } else {
+            xrd.push_str(">\n");
+            let mut titles: Vec<_> = link.titles.iter().collect();
+            titles.sort();
+            for (lang, title) in titles {
+                xrd.push_str(&format!(
+                    "    <Title xml:lang=\"{}\">{}</Title>\n",
+                    escape_xml(lang),
+                    escape_xml(title)
+                ));
+            }
+            push_xrd_properties(&mut xrd, "    ", &link.properties);
+            xrd.push_str("  </Link>\n");
+        }
+    }
+    xrd.push_str("</XRD>\n");
+    xrd
+}
+
+/// Adds `properties` as `Property` elements, sorted by type so that the output is stable.
+fn push_xrd_properties(
+    xrd: &mut String,
+    indent: &str,
+    properties: &HashMap<String, Option<String>>,
+) {
+    let mut properties: Vec<_> = properties.iter().collect();
+    properties.sort();
+    for (name, value) in properties {
+        match value {
+            Some(value) => xrd.push_str(&format!(
+                "{}<Property type=\"{}\">{}</Property>\n",
+                indent,
+                escape_xml(name),
+                escape_xml(value)
+            )),
+            None => xrd.push_str(&format!(
+                "{}<Property type=\"{}\" xmlns:xsi=\"{}\" xsi:nil=\"true\"/>\n",
+                indent,
+                escape_xml(name),
+                XSI_NAMESPACE
+            )),
+        }
+    }
+}
+
+/// The namespace of the `xsi:nil` attribute, marking properties without value.
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,181 @@
+use crate::{Link, Webfinger};
+use std::iter::FromIterator;
+
+/// A collection of WebFinger results, that may come from different services.
+///
+/// Documents describing the same resource (because the subject of one is the subject or an alias
+/// of the other) are merged when they are inserted, so that each resource appears only once.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WebfingerSet {
+    documents: Vec<Webfinger>,
+}
+
+impl WebfingerSet {
+    /// Creates an empty set.
+    pub fn new() -> WebfingerSet {
+        WebfingerSet::default()
+    }
+
+    /// Adds a document to the set, merging it with the documents describing the same resource.
+    pub fn insert(&mut self, webfinger: Webfinger) {
+        let (same, mut others): (Vec<_>, Vec<_>) = self
+            .documents
+            .drain(..)
+            .partition(|doc| doc.same_resource(&webfinger));
+
+        let mut same = same.into_iter();
+        let merged = match same.next() {
+            Some(mut first) => {
+                for doc in same {
+                    first.merge(doc);
+                }
+                first.merge(webfinger);
+                first
+            }
+            None => webfinger,
+        };
+        others.push(merged);
+        self.documents = others;
+    }
+
+    /// Finds the document whose subject or one of the aliases is `id`.
+    pub fn get(&self, id: &str) -> Option<&Webfinger> {
+        self.documents.iter().find(|doc| doc.is_known_as(id))
+    }
+
+    /// The number of distinct resources in this set.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Returns `true` if there is no document in this set.
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Iterates over the documents of this set.
+    pub fn iter(&self) -> std::slice::Iter<'_, Webfinger> {
+        self.documents.iter()
+    }
+
+    /// Returns the links with the given `rel`, across all the documents.
+    pub fn links_for_rel(&self, rel: impl AsRef<str>) -> Vec<&Link> {
+        let rel = rel.as_ref();
+        self.documents
+            .iter()
+            .flat_map(|doc| doc.links().rel(rel))
+            .collect()
+    }
+}
+
+impl FromIterator<Webfinger> for WebfingerSet {
+    fn from_iter<I: IntoIterator<Item = Webfinger>>(iter: I) -> WebfingerSet {
+        let mut set = WebfingerSet::new();
+        for webfinger in iter {
+            set.insert(webfinger);
+        }
+        set
+    }
+}
+
+impl IntoIterator for WebfingerSet {
+    type Item = Webfinger;
+    type IntoIter = std::vec::IntoIter<Webfinger>;
+
+    fn into_iter(self) -> std::vec::IntoIter<Webfinger> {
+        self.documents.into_iter()
+    }
+}
+
+impl Webfinger {
+    /// Returns `true` if `id` is the subject or one of the aliases of this document.
+    pub fn is_known_as(&self, id: &str) -> bool {
+        self.subject == id || self.aliases.iter().any(|alias| alias == id)
+    }
+
+    /// Merges another document describing the same resource into this one.
+    ///
+    /// The subject of this document is kept, and the subject of `other` becomes an alias if it is
+    /// different. Aliases and links that are not already present are added at the end.
+    /// Properties and extension members of `other` are added when this document doesn't have
+    /// them. The earliest `expires` date of the two documents is kept, since the merged data is
+    /// not valid longer than any of its parts.
+    pub fn merge(&mut self, other: Webfinger) {
+        let Webfinger {
+            subject,
+            aliases,
+            links,
+            properties,
+            expires,
+            extra,
+        } = other;
+        let other_expires = expires.as_deref().and_then(crate::expires::parse_rfc3339);
+        let earlier = match (self.expires_at(), other_expires) {
+            (Some(current), Some(other)) => other < current,
+            (None, Some(_)) => true,
+            (_, None) => self.expires.is_none(),
+        };
+        if earlier {
+            self.expires = expires;
+        }
+        for (key, value) in properties {
+            self.properties.entry(key).or_insert(value);
+        }
+        for (key, value) in extra {
+            self.extra.entry(key).or_insert(value);
+        }
+        for alias in std::iter::once(subject).chain(aliases) {
+            if !self.is_known_as(&alias) {
+                self.aliases.push(alias);
+            }
+        }
+        for link in links {
+            if !self.links.contains(&link) {
+                self.links.push(link);
+            }
+        }
+    }
+
+    /// Tells if two documents describe a resource in the same way.
+    ///
+    /// Unlike `==`, the order of the aliases and of the links doesn't matter, and the `expires`
+    /// field is ignored, so that refreshing a cached document that only got reordered or
+    /// re-dated is not seen as a change. Duplicated aliases or links still count: a document
+    /// with the same link twice is not equal to one with this link once. Extension members (the
+    /// `extra` fields of documents and links) are ignored too, since they are specific to each
+    /// server.
+    pub fn semantically_eq(&self, other: &Webfinger) -> bool {
+        self.subject == other.subject
+            && same_items(&self.aliases, &other.aliases, PartialEq::eq)
+            && same_items(&self.links, &other.links, same_link)
+            && self.properties == other.properties
+    }
+
+    fn same_resource(&self, other: &Webfinger) -> bool {
+        self.is_known_as(&other.subject)
+            || other.is_known_as(&self.subject)
+            || self.aliases.iter().any(|alias| other.is_known_as(alias))
+    }
+}
+
+/// Tells if two lists have the same items according to `eq`, in any order.
+fn same_items<T>(left: &[T], right: &[T], eq: impl Fn(&T, &T) -> bool) -> bool {
+    if left.len() != right.len() {
+        return false;
+    }
+    let mut matched = vec![false; right.len()];
+    left.iter().all(|item| {
+        let found = (0..right.len()).find(|&i| !matched[i] && eq(&right[i], item));
+        found.map(|i| matched[i] = true).is_some()
+    })
+}
+
+/// Compares two links, ignoring their extension members.
+fn same_link(left: &Link, right: &Link) -> bool {
+    left.rel == right.rel
+        && left.href == right.href
+        && left.template == right.template
+        && left.mime_type == right.mime_type
+        && left.titles == right.titles
+        && left.properties == right.properties
+}
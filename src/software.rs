@@ -0,0 +1,200 @@
+use crate::{host_of, HttpFetch, HttpRequest, HttpResponse, RateLimit, StatusAction, StatusPolicy};
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Mutex};
+use url::Url;
+
+/// The path of the NodeInfo discovery document.
+const NODEINFO_PATH: &str = "/.well-known/nodeinfo";
+
+/// The prefix of the relations of NodeInfo documents, followed by their version.
+const NODEINFO_REL_PREFIX: &str = "http://nodeinfo.diaspora.software/ns/schema/";
+
+/// The name and version of a server software, as given by NodeInfo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Software {
+    /// The name of the software, in lowercase (`mastodon` for instance).
+    pub name: String,
+
+    /// The version of the software, if it was given.
+    pub version: Option<String>,
+}
+
+/// Hints about the software of the server a document comes from.
+///
+/// They allow to work around the bugs of specific implementations. Headers are always available
+/// when the server sends them, but [`software`](ServerHints::software) is only filled if the
+/// client was built with
+/// [`detect_software`](crate::WebfingerClientBuilder::detect_software).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServerHints {
+    /// The `Server` header of the response.
+    pub server: Option<String>,
+
+    /// The `X-Powered-By` header of the response.
+    pub powered_by: Option<String>,
+
+    /// The software announced in the NodeInfo document of the server.
+    pub software: Option<Software>,
+}
+
+impl ServerHints {
+    pub(crate) fn from_response(response: &HttpResponse) -> ServerHints {
+        ServerHints {
+            server: response.header("Server").map(String::from),
+            powered_by: response.header("X-Powered-By").map(String::from),
+            software: None,
+        }
+    }
+
+    /// Returns `true` if the server seems to run the software called `name`, ignoring case.
+    ///
+    /// The NodeInfo name is used first. The product names of the `Server` and `X-Powered-By`
+    /// headers (`Mastodon` in `Mastodon/4.2 nginx` for instance) are also compared.
+    pub fn is(&self, name: &str) -> bool {
+        if let Some(ref software) = self.software {
+            return software.name.eq_ignore_ascii_case(name);
+        }
+        self.server
+            .iter()
+            .chain(self.powered_by.iter())
+            .flat_map(|header| header.split_whitespace())
+            .filter_map(|product| product.split('/').next())
+            .any(|product| product.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Deserialize)]
+struct NodeInfoLinks {
+    links: Vec<NodeInfoLink>,
+}
+
+#[derive(Deserialize)]
+struct NodeInfoLink {
+    rel: String,
+    href: String,
+}
+
+#[derive(Deserialize)]
+struct NodeInfo {
+    software: NodeInfoSoftware,
+}
+
+#[derive(Deserialize)]
+struct NodeInfoSoftware {
+    name: String,
+    version: Option<String>,
+}
+
+/// The settings of a [`WebfingerClient`](crate::WebfingerClient) that NodeInfo requests follow
+/// too.
+pub(crate) struct NodeInfoRequests<'a> {
+    pub(crate) http: &'a dyn HttpFetch,
+    pub(crate) headers: &'a [(String, String)],
+    pub(crate) statuses: &'a StatusPolicy,
+    pub(crate) rate_limit: Option<&'a dyn RateLimit>,
+    pub(crate) is_blocked: &'a (dyn Fn(&str) -> bool + Sync),
+}
+
+/// A NodeInfo request that failed for a reason that may not last, like a network error or a
+/// `503` status. Its result is not cached.
+struct TemporaryFailure;
+
+/// The software of the servers a client talked to, found with NodeInfo.
+#[derive(Debug, Default)]
+pub(crate) struct SoftwareCache {
+    origins: Mutex<HashMap<String, Option<Software>>>,
+}
+
+impl SoftwareCache {
+    /// Returns the software of the server at `origin` (like `https://example.org`), fetching its
+    /// NodeInfo document the first time.
+    ///
+    /// Definitive results are kept, even when the server has no NodeInfo document, but temporary
+    /// failures are not: the document is fetched again on the next lookup.
+    pub(crate) async fn get(
+        &self,
+        requests: &NodeInfoRequests<'_>,
+        origin: &str,
+    ) -> Option<Software> {
+        if let Some(software) = self.origins.lock().unwrap().get(origin) {
+            return software.clone();
+        }
+        let software = fetch_nodeinfo(requests, origin).await.ok()?;
+        self.origins
+            .lock()
+            .unwrap()
+            .insert(origin.to_string(), software.clone());
+        software
+    }
+
+    /// Forgets the software of the origins matching `predicate`.
+    pub(crate) fn remove_where(&self, predicate: impl Fn(&str) -> bool) {
+        self.origins
+            .lock()
+            .unwrap()
+            .retain(|origin, _| !predicate(origin));
+    }
+}
+
+/// Fetches a JSON document, returning `Ok(None)` if the server doesn't have it or if it is
+/// invalid.
+async fn fetch_json<T: for<'de> Deserialize<'de>>(
+    requests: &NodeInfoRequests<'_>,
+    url: String,
+) -> Result<Option<T>, TemporaryFailure> {
+    if (requests.is_blocked)(&url) {
+        return Ok(None);
+    }
+    if let Some(rate_limit) = requests.rate_limit {
+        if !rate_limit.acquire(&host_of(&url)).await {
+            return Err(TemporaryFailure);
+        }
+    }
+    let mut headers = requests.headers.to_vec();
+    headers.push(("Accept".to_string(), "application/json".to_string()));
+    let response = requests
+        .http
+        .fetch(HttpRequest { url, headers })
+        .await
+        .map_err(|_| TemporaryFailure)?;
+    match requests.statuses.action(response.status) {
+        StatusAction::Accept => Ok(serde_json::from_slice(&response.body).ok()),
+        StatusAction::NotFound => Ok(None),
+        StatusAction::Retry | StatusAction::Fail => Err(TemporaryFailure),
+    }
+}
+
+/// Fetches the NodeInfo document of the server at `origin`.
+///
+/// The NodeInfo document must be on the same origin as the discovery document, so that a server
+/// can't make the client send requests to other hosts.
+async fn fetch_nodeinfo(
+    requests: &NodeInfoRequests<'_>,
+    origin: &str,
+) -> Result<Option<Software>, TemporaryFailure> {
+    let links: NodeInfoLinks =
+        match fetch_json(requests, format!("{}{}", origin, NODEINFO_PATH)).await? {
+            Some(links) => links,
+            None => return Ok(None),
+        };
+    // Versions are sorted as strings, which is enough for the existing ones (1.0 to 2.1)
+    let link = links
+        .links
+        .into_iter()
+        .filter(|link| link.rel.starts_with(NODEINFO_REL_PREFIX))
+        .max_by(|a, b| a.rel.cmp(&b.rel));
+    let href = match link {
+        Some(link)
+            if Url::parse(&link.href)
+                .is_ok_and(|url| url.origin().ascii_serialization() == origin) =>
+        {
+            link.href
+        }
+        _ => return Ok(None),
+    };
+    let nodeinfo: Option<NodeInfo> = fetch_json(requests, href).await?;
+    Ok(nodeinfo.map(|nodeinfo| Software {
+        name: nodeinfo.software.name.to_lowercase(),
+        version: nodeinfo.software.version,
+    }))
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What a [`WebfingerClient`](crate::WebfingerClient) does with a given HTTP status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusAction {
+    /// The body of the response is parsed as a WebFinger document.
+    Accept,
+
+    /// The request is retried, if the client allows it. Otherwise, it fails with
+    /// [`WebfingerError::HttpStatus`](crate::WebfingerError::HttpStatus).
+    Retry,
+
+    /// The request fails with [`WebfingerError::NotFound`](crate::WebfingerError::NotFound).
+    NotFound,
+
+    /// The request fails with [`WebfingerError::HttpStatus`](crate::WebfingerError::HttpStatus).
+    Fail,
+}
+
+/// Tells how each HTTP status should be handled.
+///
+/// By default:
+///
+/// - `2xx` statuses are accepted;
+/// - `404` and `410` mean the resource doesn't exist;
+/// - `408`, `429`, `502`, `503` and `504` are temporary errors, that can be retried;
+/// - any other status is a failure.
+///
+/// Some instances answer `403` to anonymous requests for instance, which can be treated as
+/// [`StatusAction::NotFound`] with [`StatusPolicy::set`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatusPolicy {
+    overrides: BTreeMap<u16, StatusAction>,
+}
+
+impl StatusPolicy {
+    /// Creates the default policy.
+    pub fn new() -> StatusPolicy {
+        StatusPolicy::default()
+    }
+
+    /// Changes the way a status is handled.
+    pub fn set(mut self, status: u16, action: StatusAction) -> StatusPolicy {
+        self.overrides.insert(status, action);
+        self
+    }
+
+    /// Tells how a status is handled.
+    pub fn action(&self, status: u16) -> StatusAction {
+        if let Some(action) = self.overrides.get(&status) {
+            return *action;
+        }
+        match status {
+            200..=299 => StatusAction::Accept,
+            404 | 410 => StatusAction::NotFound,
+            408 | 429 | 502 | 503 | 504 => StatusAction::Retry,
+            _ => StatusAction::Fail,
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{parse_resource, Caller, InstanceDomain, Prefix, Resolver, ResolverError, Webfinger};
+use url::Url;
+
+/// What [`NormalizeSubjects`] does with incomplete subjects and aliases.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubjectMode {
+    /// Incomplete subjects and aliases are refused with [`ResolverError::InvalidSubject`].
+    Strict,
+
+    /// The missing prefix and domain are added to incomplete subjects and aliases.
+    FixUp,
+}
+
+/// A resolver wrapper making sure the subjects of the results are complete resources.
+///
+/// Resolvers often return `user` or `user@example.org` as their subject, instead of
+/// `acct:user@example.org`. This wrapper detects subjects and aliases that lack a prefix or a
+/// domain, and depending on the [`SubjectMode`], completes them with the requested prefix and the
+/// instance domain, or refuses them. In strict mode, subjects on another domain than the instance
+/// one are refused too.
+#[derive(Debug, Clone)]
+pub struct NormalizeSubjects<T> {
+    inner: T,
+    mode: SubjectMode,
+}
+
+impl<T> NormalizeSubjects<T> {
+    /// Wraps a resolver, normalizing the subjects of its results according to `mode`.
+    pub fn new(inner: T, mode: SubjectMode) -> NormalizeSubjects<T> {
+        NormalizeSubjects { inner, mode }
+    }
+
+    /// Returns the wrapped resolver.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn normalize(
+        &self,
+        mut webfinger: Webfinger,
+        prefix: &Prefix,
+        domain: &InstanceDomain,
+    ) -> Result<Webfinger, ResolverError> {
+        let prefix = String::from(prefix.clone());
+        let complete = |value: &str| -> Result<Option<String>, ResolverError> {
+            let has_prefix = value
+                .split_once(':')
+                .is_some_and(|(p, _)| !p.contains('@') && Prefix::parse(p).is_ok());
+            if has_prefix {
+                Ok(None)
+            } else if self.mode == SubjectMode::Strict {
+                Err(ResolverError::InvalidSubject)
+            } else if value.contains('@') {
+                Ok(Some(format!("{}:{}", prefix, value)))
+            } else {
+                Ok(Some(format!("{}:{}@{}", prefix, value, domain)))
+            }
+        };
+
+        if let Some(subject) = complete(&webfinger.subject)? {
+            webfinger.subject = subject;
+        } else if self.mode == SubjectMode::Strict
+            && !parse_resource(&webfinger.subject)
+                .is_ok_and(|r| InstanceDomain::new(&r.host()).is_ok_and(|d| d == *domain))
+        {
+            return Err(ResolverError::InvalidSubject);
+        }
+        for alias in webfinger.aliases.iter_mut() {
+            if let Some(fixed) = complete(alias)? {
+                *alias = fixed;
+            }
+        }
+        Ok(webfinger)
+    }
+}
+
+impl<R, T: Resolver<R>> Resolver<R> for NormalizeSubjects<T> {
+    fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain()
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain)
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let webfinger = self.inner.find(prefix.clone(), acct, resource_repo)?;
+        self.normalize(webfinger, &prefix, &self.instance_domain())
+    }
+
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let webfinger = self
+            .inner
+            .find_for_caller(prefix.clone(), acct, caller, resource_repo)?;
+        self.normalize(webfinger, &prefix, &self.instance_domain())
+    }
+
+    fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        let webfinger = self.inner.find_by_url(url, resource_repo)?;
+        self.normalize(webfinger, &Prefix::Acct, &self.instance_domain())
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, R> crate::AsyncResolver<R> for NormalizeSubjects<T>
+where
+    T: crate::AsyncResolver<R> + Send + Sync,
+    R: Send,
+{
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain().await
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain).await
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let webfinger = self.inner.find(prefix.clone(), acct, resource_repo).await?;
+        let domain = self.inner.instance_domain().await;
+        self.normalize(webfinger, &prefix, &domain)
+    }
+
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let webfinger = self
+            .inner
+            .find_for_caller(prefix.clone(), acct, caller, resource_repo)
+            .await?;
+        let domain = self.inner.instance_domain().await;
+        self.normalize(webfinger, &prefix, &domain)
+    }
+
+    async fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        let webfinger = self.inner.find_by_url(url, resource_repo).await?;
+        let domain = self.inner.instance_domain().await;
+        self.normalize(webfinger, &Prefix::Acct, &domain)
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{AsyncResolver, Caller, InstanceDomain, Prefix, Resolver, ResolverError, Webfinger};
+use std::sync::Arc;
+use url::Url;
+
+/// An [`AsyncResolver`] running a blocking [`Resolver`] on the thread pool of Tokio.
+///
+/// It lets you mount an existing resolver, that uses a synchronous database driver for
+/// instance, in an async web framework without rewriting it: lookups are run with
+/// [`spawn_blocking`](tokio::task::spawn_blocking), so they don't block the other requests. It
+/// implements [`AsyncResolver`] for all the repositories of the wrapped resolver.
+/// [`instance_domain`](Resolver::instance_domain) is still called on the current thread, so it
+/// should not block.
+///
+/// A panic in the wrapped resolver is propagated to the task awaiting the lookup. If the runtime
+/// shuts down before the lookup starts, it fails with [`ResolverError::Cancelled`].
+pub struct SyncAsAsync<T> {
+    inner: Arc<T>,
+}
+
+impl<T> SyncAsAsync<T> {
+    /// Wraps a blocking resolver.
+    pub fn new(inner: T) -> SyncAsAsync<T> {
+        SyncAsAsync {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Returns the wrapped resolver.
+    ///
+    /// It is shared with the lookups that are still running.
+    pub fn into_inner(self) -> Arc<T> {
+        self.inner
+    }
+}
+
+impl<T> Clone for SyncAsAsync<T> {
+    fn clone(&self) -> Self {
+        SyncAsAsync {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for SyncAsAsync<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncAsAsync")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<T> SyncAsAsync<T>
+where
+    T: Send + Sync + 'static,
+{
+    async fn blocking(
+        &self,
+        lookup: impl FnOnce(&T) -> Result<Webfinger, ResolverError> + Send + 'static,
+    ) -> Result<Webfinger, ResolverError> {
+        let inner = self.inner.clone();
+        match tokio::task::spawn_blocking(move || lookup(&inner)).await {
+            Ok(result) => result,
+            Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+            Err(_) => Err(ResolverError::Cancelled),
+        }
+    }
+}
+
+impl<T, R> AsyncResolver<R> for SyncAsAsync<T>
+where
+    T: Resolver<R> + Send + Sync + 'static,
+    R: Send + 'static,
+{
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain()
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain)
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.blocking(move |inner| inner.find(prefix, acct, resource_repo))
+            .await
+    }
+
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let caller = caller.cloned();
+        self.blocking(move |inner| {
+            inner.find_for_caller(prefix, acct, caller.as_ref(), resource_repo)
+        })
+        .await
+    }
+
+    async fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        let url = url.clone();
+        self.blocking(move |inner| inner.find_by_url(&url, resource_repo))
+            .await
+    }
+}
+
+/// A blocking [`Resolver`] running an [`AsyncResolver`] on a Tokio runtime.
+///
+/// It is the opposite of [`SyncAsAsync`]: it lets you serve an async resolver from a synchronous
+/// web framework, or from a simple blocking handler. Each lookup blocks the current thread until
+/// it ends on the runtime of `handle`.
+///
+/// Like [`Handle::block_on`](tokio::runtime::Handle::block_on), lookups panic when they are
+/// called from an asynchronous context, such as a task of the same runtime: use the async
+/// resolver directly there.
+#[derive(Debug, Clone)]
+pub struct AsyncAsSync<T> {
+    inner: T,
+    handle: tokio::runtime::Handle,
+}
+
+impl<T> AsyncAsSync<T> {
+    /// Wraps an async resolver, running its lookups on the runtime of `handle`.
+    pub fn new(inner: T, handle: tokio::runtime::Handle) -> AsyncAsSync<T> {
+        AsyncAsSync { inner, handle }
+    }
+
+    /// Returns the wrapped resolver.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T, R> Resolver<R> for AsyncAsSync<T>
+where
+    T: AsyncResolver<R>,
+    R: Send,
+{
+    fn instance_domain(&self) -> InstanceDomain {
+        self.handle.block_on(self.inner.instance_domain())
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.handle.block_on(self.inner.accepts_domain(domain))
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.handle
+            .block_on(self.inner.find(prefix, acct, resource_repo))
+    }
+
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.handle.block_on(
+            self.inner
+                .find_for_caller(prefix, acct, caller, resource_repo),
+        )
+    }
+
+    fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.handle
+            .block_on(self.inner.find_by_url(url, resource_repo))
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{Link, Rel};
+use std::fmt;
+
+/// A problem with the `template` of a [`Link`], found by [`Link::validate_template`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum TemplateIssue {
+    /// The link needs a template, but has none.
+    MissingTemplate,
+
+    /// The template has no `{uri}` placeholder.
+    MissingUri,
+
+    /// The template has more than one `{uri}` placeholder.
+    ///
+    /// Contains the number of placeholders.
+    MultipleUris(usize),
+
+    /// The template has a placeholder that clients don't know how to replace.
+    UnknownPlaceholder(String),
+
+    /// A `{` is not closed, or a `}` was not opened.
+    UnbalancedBraces,
+}
+
+impl TemplateIssue {
+    /// A stable code identifying this issue, from `WF0401` to `WF0499`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TemplateIssue::MissingTemplate => "WF0401",
+            TemplateIssue::MissingUri => "WF0402",
+            TemplateIssue::MultipleUris(_) => "WF0403",
+            TemplateIssue::UnknownPlaceholder(_) => "WF0404",
+            TemplateIssue::UnbalancedBraces => "WF0405",
+        }
+    }
+}
+
+impl fmt::Display for TemplateIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] ", self.code())?;
+        match self {
+            TemplateIssue::MissingTemplate => write!(f, "the link has no template"),
+            TemplateIssue::MissingUri => write!(f, "the template has no {{uri}} placeholder"),
+            TemplateIssue::MultipleUris(count) => {
+                write!(f, "the template has {} {{uri}} placeholders", count)
+            }
+            TemplateIssue::UnknownPlaceholder(name) => {
+                write!(f, "the template has an unknown placeholder: {{{}}}", name)
+            }
+            TemplateIssue::UnbalancedBraces => write!(f, "the template has unbalanced braces"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateIssue {}
+
+/// Returns the placeholders of `template`, or `None` if its braces are unbalanced.
+fn placeholders(template: &str) -> Option<Vec<&str>> {
+    let mut placeholders = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with('}') {
+            return None;
+        }
+        let end = start + rest[start..].find('}')?;
+        let name = &rest[start + 1..end];
+        if name.contains('{') {
+            return None;
+        }
+        placeholders.push(name);
+        rest = &rest[end + 1..];
+    }
+    Some(placeholders)
+}
+
+impl Link {
+    /// Checks that the template of this link can be expanded by clients.
+    ///
+    /// Braces must be balanced in every template. Remote follow templates (with the
+    /// [`Rel::Subscribe`] relation) are also required, and must contain exactly one `{uri}`
+    /// placeholder and no other one. An empty list means the template is valid.
+    ///
+    /// [`Link::normalize_template`] can fix some of these issues first.
+    pub fn validate_template(&self) -> Vec<TemplateIssue> {
+        let subscribe = self.rel == Rel::Subscribe;
+        let template = match self.template {
+            Some(ref template) => template,
+            None if subscribe => return vec![TemplateIssue::MissingTemplate],
+            None => return vec![],
+        };
+        let placeholders = match placeholders(template) {
+            Some(placeholders) => placeholders,
+            None => return vec![TemplateIssue::UnbalancedBraces],
+        };
+        if !subscribe {
+            return vec![];
+        }
+
+        let mut issues = vec![];
+        match placeholders.iter().filter(|name| **name == "uri").count() {
+            0 => issues.push(TemplateIssue::MissingUri),
+            1 => {}
+            count => issues.push(TemplateIssue::MultipleUris(count)),
+        }
+        for name in placeholders {
+            if name != "uri" {
+                issues.push(TemplateIssue::UnknownPlaceholder(name.to_string()));
+            }
+        }
+        issues
+    }
+
+    /// Rewrites the common variants of the `{uri}` placeholder in the template of this link.
+    ///
+    /// Percent-encoded braces (`%7Buri%7D`), different cases (`{URI}`) and spaces (`{ uri }`) are
+    /// replaced with `{uri}`. Other placeholders are kept as they are.
+    pub fn normalize_template(&mut self) {
+        let template = match self.template {
+            Some(ref mut template) => template,
+            None => return,
+        };
+        let mut normalized = String::with_capacity(template.len());
+        let mut rest = template.as_str();
+        while !rest.is_empty() {
+            let placeholder = placeholder_at(rest);
+            match placeholder {
+                Some(len) => {
+                    normalized.push_str("{uri}");
+                    rest = &rest[len..];
+                }
+                None => {
+                    let next = rest.chars().next().map_or(1, char::len_utf8);
+                    normalized.push_str(&rest[..next]);
+                    rest = &rest[next..];
+                }
+            }
+        }
+        *template = normalized;
+    }
+}
+
+/// Returns the length of the variant of the `{uri}` placeholder at the start of `s`, if any.
+fn placeholder_at(s: &str) -> Option<usize> {
+    let (open, close) = if s.starts_with('{') {
+        (1, "}")
+    } else if s.get(..3).is_some_and(|p| p.eq_ignore_ascii_case("%7B")) {
+        (3, "%7D")
+    } else {
+        return None;
+    };
+    let inner = &s[open..];
+    let end = inner
+        .char_indices()
+        .find(|(i, _)| {
+            inner[*i..]
+                .get(..close.len())
+                .is_some_and(|c| c.eq_ignore_ascii_case(close))
+        })
+        .map(|(i, _)| i)?;
+    if inner[..end].trim().eq_ignore_ascii_case("uri") {
+        Some(open + end + close.len())
+    } else {
+        None
+    }
+}
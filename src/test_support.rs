@@ -0,0 +1,515 @@
+//! Fake WebFinger servers, to test code using this crate.
+//!
+//! This module is only available with the `test-support` feature. It runs real HTTP servers with
+//! [`wiremock`], so it must be used in a Tokio runtime.
+//!
+//! ```rust,no_run
+//! # async fn run() {
+//! use webfinger::test_support::FakeInstance;
+//!
+//! let instance = FakeInstance::new("example.org")
+//!     .with_account("alice")
+//!     .start()
+//!     .await;
+//! let client = instance.client();
+//! let alice = client.resolve("alice@example.org").await.unwrap();
+//! assert_eq!(alice.subject, "acct:alice@example.org");
+//! # }
+//! ```
+//!
+//! Documents can be compared with [`assert_webfinger_eq!`](crate::assert_webfinger_eq), that
+//! tells which fields differ instead of printing both documents.
+//!
+//! Realistic documents can be generated with [`fake_webfinger`], to load test the code handling
+//! them:
+//!
+//! ```
+//! use webfinger::test_support::fake_webfinger;
+//!
+//! let documents: Vec<_> = (0..1000).map(fake_webfinger).collect();
+//! assert_eq!(documents[42], fake_webfinger(42));
+//! ```
+
+use crate::{
+    consts, default_transport, HttpFetch, HttpRequest, HttpResponse, Link, Rel, TransportSettings,
+    Webfinger, WebfingerClient, WebfingerClientBuilder, WebfingerError,
+};
+use futures_util::future::BoxFuture;
+use serde_json::Map;
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+use wiremock::{
+    matchers::{method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+/// A fake instance, serving WebFinger documents for its accounts.
+///
+/// Resources that were not added are answered with a 404 status.
+#[derive(Debug, Clone)]
+pub struct FakeInstance {
+    domain: String,
+    documents: Vec<(String, Webfinger)>,
+}
+
+impl FakeInstance {
+    /// Creates an instance without any account, for `domain`.
+    pub fn new(domain: impl Into<String>) -> FakeInstance {
+        FakeInstance {
+            domain: domain.into(),
+            documents: vec![],
+        }
+    }
+
+    /// Adds an account, with the document an ActivityPub server usually serves.
+    ///
+    /// The document has a `self` link to `https://<domain>/users/<user>` and a profile page link
+    /// to `https://<domain>/@<user>`.
+    pub fn with_account(self, user: &str) -> FakeInstance {
+        let acct = format!("acct:{}@{}", user, self.domain);
+        let actor_url = format!("https://{}/users/{}", self.domain, user);
+        let profile_url = format!("https://{}/@{}", self.domain, user);
+        let document = Webfinger {
+            subject: acct.clone(),
+            aliases: vec![actor_url.clone(), profile_url.clone()],
+            links: vec![
+                Link {
+                    rel: consts::WEBFINGER_REL_PROFILE_PAGE.into(),
+                    href: Some(profile_url),
+                    template: None,
+                    mime_type: Some("text/html".to_string()),
+                    titles: HashMap::new(),
+                    properties: HashMap::new(),
+                    extra: Map::new(),
+                },
+                Link {
+                    rel: "self".into(),
+                    href: Some(actor_url),
+                    template: None,
+                    mime_type: Some("application/activity+json".to_string()),
+                    titles: HashMap::new(),
+                    properties: HashMap::new(),
+                    extra: Map::new(),
+                },
+            ],
+            properties: HashMap::new(),
+            expires: None,
+            extra: Map::new(),
+        };
+        self.with_document(acct, document)
+    }
+
+    /// Serves `document` for `resource`, like `acct:alice@example.org`.
+    pub fn with_document(
+        mut self,
+        resource: impl Into<String>,
+        document: Webfinger,
+    ) -> FakeInstance {
+        self.documents.push((resource.into(), document));
+        self
+    }
+
+    /// Starts the server.
+    pub async fn start(self) -> RunningInstance {
+        let server = MockServer::start().await;
+        for (resource, document) in &self.documents {
+            Mock::given(method("GET"))
+                .and(path(consts::WELL_KNOWN_PATH))
+                .and(query_param("resource", resource.as_str()))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(document)
+                        .insert_header("Content-Type", consts::JRD_MIME),
+                )
+                .mount(&server)
+                .await;
+        }
+        RunningInstance {
+            domain: self.domain,
+            server,
+        }
+    }
+}
+
+/// A [`FakeInstance`] whose server is running.
+///
+/// The server is stopped when this value is dropped.
+#[derive(Debug)]
+pub struct RunningInstance {
+    domain: String,
+    server: MockServer,
+}
+
+impl RunningInstance {
+    /// The domain of the instance, as used in its resources.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// The base URL of the server, like `http://127.0.0.1:1234`.
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    /// Returns the requests received by the server.
+    pub async fn received_requests(&self) -> Vec<wiremock::Request> {
+        self.server.received_requests().await.unwrap_or_default()
+    }
+
+    /// Creates a client builder sending the requests for the domain of this instance to its
+    /// server, and the other ones to the network as usual.
+    ///
+    /// The transport settings of the builder (timeouts, proxy, etc) are ignored, since it uses a
+    /// custom HTTP client.
+    pub fn client_builder(&self) -> WebfingerClientBuilder {
+        let inner = default_transport(&TransportSettings::default())
+            .expect("Couldn't create the HTTP client");
+        WebfingerClient::builder().http_fetch(InstanceFetch {
+            inner,
+            domain: self.domain.clone(),
+            uri: self.uri(),
+        })
+    }
+
+    /// Creates a client sending the requests for the domain of this instance to its server.
+    pub fn client(&self) -> WebfingerClient {
+        self.client_builder().build()
+    }
+}
+
+/// An [`HttpFetch`] implementation sending the requests for a domain to a local server.
+struct InstanceFetch {
+    inner: Arc<dyn HttpFetch>,
+    domain: String,
+    uri: String,
+}
+
+impl HttpFetch for InstanceFetch {
+    fn fetch(
+        &self,
+        mut request: HttpRequest,
+    ) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        for scheme in ["https://", "http://"] {
+            let origin = format!("{}{}", scheme, self.domain);
+            if let Some(rest) = request.url.strip_prefix(&origin) {
+                if rest.starts_with('/') {
+                    request.url = format!("{}{}", self.uri, rest);
+                    break;
+                }
+            }
+        }
+        self.inner.fetch(request)
+    }
+}
+
+/// Asserts that two [`Webfinger`] documents are equal, printing the fields that differ when they
+/// are not.
+///
+/// Like [`assert_eq!`], a custom message can be given after the documents.
+///
+/// ```rust
+/// use webfinger::{assert_webfinger_eq, Webfinger};
+///
+/// let document = Webfinger {
+///     aliases: vec!["https://example.org/@alice".to_string()],
+///     ..Webfinger::new("acct:alice@example.org")
+/// };
+/// assert_webfinger_eq!(document.clone(), document);
+/// ```
+#[macro_export]
+macro_rules! assert_webfinger_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                let differences = $crate::test_support::diff_documents(left, right);
+                if !differences.is_empty() {
+                    panic!(
+                        "assertion failed: `(left == right)`\n{}",
+                        differences.join("\n")
+                    );
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                let differences = $crate::test_support::diff_documents(left, right);
+                if !differences.is_empty() {
+                    panic!(
+                        "assertion failed: `(left == right)`: {}\n{}",
+                        format_args!($($arg)+),
+                        differences.join("\n")
+                    );
+                }
+            }
+        }
+    };
+}
+
+/// Lists the differences between two documents, one line per field, like
+/// `links[1].href: Some("https://a.example") != Some("https://b.example")`.
+///
+/// Aliases and links are compared by position. It returns an empty list if the documents are
+/// equal.
+pub fn diff_documents(left: &Webfinger, right: &Webfinger) -> Vec<String> {
+    let mut differences = vec![];
+    diff_field(&mut differences, "subject", &left.subject, &right.subject);
+    diff_list(
+        &mut differences,
+        "aliases",
+        &left.aliases,
+        &right.aliases,
+        diff_field,
+    );
+    diff_list(
+        &mut differences,
+        "links",
+        &left.links,
+        &right.links,
+        |differences, name, left, right| {
+            diff_field(differences, &format!("{}.rel", name), &left.rel, &right.rel);
+            diff_field(
+                differences,
+                &format!("{}.href", name),
+                &left.href,
+                &right.href,
+            );
+            diff_field(
+                differences,
+                &format!("{}.template", name),
+                &left.template,
+                &right.template,
+            );
+            diff_field(
+                differences,
+                &format!("{}.mime_type", name),
+                &left.mime_type,
+                &right.mime_type,
+            );
+            diff_field(
+                differences,
+                &format!("{}.titles", name),
+                &left.titles,
+                &right.titles,
+            );
+            diff_field(
+                differences,
+                &format!("{}.properties", name),
+                &left.properties,
+                &right.properties,
+            );
+            diff_field(
+                differences,
+                &format!("{}.extra", name),
+                &left.extra,
+                &right.extra,
+            );
+        },
+    );
+    diff_field(
+        &mut differences,
+        "properties",
+        &left.properties,
+        &right.properties,
+    );
+    diff_field(&mut differences, "expires", &left.expires, &right.expires);
+    diff_field(&mut differences, "extra", &left.extra, &right.extra);
+    differences
+}
+
+fn diff_field<T: Debug + PartialEq>(
+    differences: &mut Vec<String>,
+    name: &str,
+    left: &T,
+    right: &T,
+) {
+    if left != right {
+        differences.push(format!("{}: {:?} != {:?}", name, left, right));
+    }
+}
+
+fn diff_list<T: Debug>(
+    differences: &mut Vec<String>,
+    name: &str,
+    left: &[T],
+    right: &[T],
+    diff_item: impl Fn(&mut Vec<String>, &str, &T, &T),
+) {
+    for i in 0..left.len().max(right.len()) {
+        let item = format!("{}[{}]", name, i);
+        match (left.get(i), right.get(i)) {
+            (Some(left), Some(right)) => diff_item(differences, &item, left, right),
+            (Some(left), None) => {
+                differences.push(format!("{}: {:?} is only on the left", item, left))
+            }
+            (None, Some(right)) => {
+                differences.push(format!("{}: {:?} is only on the right", item, right))
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+const DOMAINS: &[&str] = &[
+    "example.org",
+    "social.example.com",
+    "mastodon.example",
+    "blog.example.net",
+    "xn--caf-dma.example",
+];
+
+const SYLLABLES: &[&str] = &[
+    "al", "ba", "ce", "do", "eli", "fa", "gu", "ha", "iro", "ja", "ko", "li", "ma", "no", "ra",
+    "su", "to", "vi", "ze",
+];
+
+const AVATAR_TYPES: &[&str] = &["image/png", "image/jpeg", "image/webp", "image/gif"];
+
+const ACTOR_TYPES: &[&str] = &[
+    "application/activity+json",
+    "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"",
+];
+
+/// Extension relations found in the wild, with the type of the links using them.
+const EXTENSIONS: &[(&str, Option<&str>)] = &[
+    ("http://openid.net/specs/connect/1.0/issuer", None),
+    (
+        "http://nodeinfo.diaspora.software/ns/schema/2.0",
+        Some("application/json"),
+    ),
+    ("magic-public-key", None),
+    ("salmon", None),
+    (
+        "https://w3id.org/security/v1#publicKey",
+        Some("application/x-pem-file"),
+    ),
+    ("urn:example:rel:custom", Some("text/plain")),
+];
+
+/// A deterministic pseudo-random generator (SplitMix64).
+struct Generator(u64);
+
+impl Generator {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+/// Generates a realistic document, always the same for a given `seed`.
+///
+/// Documents have between zero and two aliases, and up to a dozen links: the usual fediverse
+/// relations with various media types, remote follow templates, and extension relations. Some of
+/// them have an `expires` date. The generator is not cryptographically secure, and its output may
+/// change between versions of this crate: don't store the documents it produces.
+pub fn fake_webfinger(seed: u64) -> Webfinger {
+    let mut gen = Generator(seed);
+    let user: String = (0..2 + gen.below(3)).map(|_| gen.pick(SYLLABLES)).collect();
+    let domain = gen.pick(DOMAINS);
+    let profile = format!("https://{}/@{}", domain, user);
+    let actor = format!("https://{}/users/{}", domain, user);
+
+    let mut aliases = vec![];
+    if gen.chance(70) {
+        aliases.push(profile.clone());
+    }
+    if gen.chance(50) {
+        aliases.push(actor.clone());
+    }
+
+    let mut links = vec![];
+    if gen.chance(90) {
+        links.push(fake_link(
+            Rel::ProfilePage,
+            Some(&profile),
+            Some("text/html"),
+        ));
+    }
+    if gen.chance(90) {
+        links.push(fake_link(
+            Rel::SelfRel,
+            Some(&actor),
+            Some(gen.pick(ACTOR_TYPES)),
+        ));
+    }
+    if gen.chance(60) {
+        let href = format!("https://{}/avatars/{}.png", domain, user);
+        links.push(fake_link(
+            Rel::Avatar,
+            Some(&href),
+            Some(gen.pick(AVATAR_TYPES)),
+        ));
+    }
+    if gen.chance(60) {
+        links.push(Link {
+            template: Some(format!(
+                "https://{}/authorize_interaction?uri={{uri}}",
+                domain
+            )),
+            ..Link::new(Rel::Subscribe)
+        });
+    }
+    if gen.chance(30) {
+        let href = format!("https://{}/users/{}.atom", domain, user);
+        links.push(fake_link(
+            Rel::UpdatesFrom,
+            Some(&href),
+            Some("application/atom+xml"),
+        ));
+    }
+    if gen.chance(20) {
+        let href = format!("https://{}/users/{}.json", domain, user);
+        links.push(fake_link(
+            Rel::DescribedBy,
+            Some(&href),
+            Some("application/json"),
+        ));
+    }
+    for _ in 0..gen.below(5) {
+        let (rel, mime_type) = EXTENSIONS[gen.below(EXTENSIONS.len())];
+        let href = format!("https://{}/ext/{}/{}", domain, user, gen.below(1000));
+        links.push(fake_link(rel.to_string().into(), Some(&href), mime_type));
+    }
+
+    let expires = if gen.chance(25) {
+        Some(format!(
+            "20{:02}-{:02}-{:02}T{:02}:00:00Z",
+            30 + gen.below(20),
+            1 + gen.below(12),
+            1 + gen.below(28),
+            gen.below(24)
+        ))
+    } else {
+        None
+    };
+
+    Webfinger {
+        aliases,
+        links,
+        expires,
+        ..Webfinger::new(format!("acct:{}@{}", user, domain))
+    }
+}
+
+fn fake_link(rel: Rel, href: Option<&str>, mime_type: Option<&str>) -> Link {
+    Link {
+        href: href.map(String::from),
+        mime_type: mime_type.map(String::from),
+        ..Link::new(rel)
+    }
+}
@@ -1,5 +1,45 @@
 use super::*;
+use futures_util::future::{BoxFuture, FutureExt};
+use std::sync::{Arc, Mutex};
 use tokio::runtime::Runtime;
+use url::Url;
+
+/// An HTTP client always returning the same response, and remembering the requests it received.
+pub struct FakeFetch {
+    response: HttpResponse,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl FakeFetch {
+    pub fn new(status: u16, body: &str) -> FakeFetch {
+        FakeFetch {
+            response: HttpResponse {
+                status,
+                headers: vec![("Content-Type".into(), "application/jrd+json".into())],
+                body: body.as_bytes().to_vec(),
+                timings: Timings::default(),
+            },
+            requests: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> FakeFetch {
+        self.response.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl HttpFetch for FakeFetch {
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        self.requests.lock().unwrap().push(request);
+        let response = self.response.clone();
+        async move { Ok(response) }.boxed()
+    }
+}
 
 #[test]
 fn test_url_for() {
@@ -31,6 +71,24 @@ fn test_url_for() {
             "https://example.org/.well-known/webfinger?resource=hey:test@example.org"
         ))
     );
+    assert_eq!(
+        url_for(Prefix::Custom("ht tp".into()), "test@example.org", true),
+        Err(WebfingerError::ParseError)
+    );
+}
+
+#[test]
+fn test_prefix_parse() {
+    assert_eq!(Prefix::parse("acct"), Ok(Prefix::Acct));
+    assert_eq!(Prefix::parse("GROUP"), Ok(Prefix::Group));
+    assert_eq!(
+        Prefix::parse("web+ap.v1-x"),
+        Ok(Prefix::Custom("web+ap.v1-x".into()))
+    );
+    assert_eq!(Prefix::parse(""), Err(WebfingerError::ParseError));
+    assert_eq!(Prefix::parse("ht tp"), Err(WebfingerError::ParseError));
+    assert_eq!(Prefix::parse("1acct"), Err(WebfingerError::ParseError));
+    assert_eq!(Prefix::parse("acct/x"), Err(WebfingerError::ParseError));
 }
 
 #[test]
@@ -75,6 +133,715 @@ fn test_resolve() {
     });
 }
 
+#[test]
+#[cfg(feature = "fetch-reqwest")]
+fn test_connect_to() {
+    let r = Runtime::new().unwrap();
+    let port = mockito::server_address().port();
+    let m = mockito::mock("GET", "/.well-known/webfinger")
+        .match_query(mockito::Matcher::Any)
+        .match_header("host", format!("public.invalid:{}", port).as_str())
+        .with_body(r#"{"subject": "acct:test@public.invalid", "links": []}"#)
+        .create();
+
+    let client = WebfingerClient::builder()
+        .https(false)
+        .connect_to("public.invalid", [127, 0, 0, 1].into())
+        .build();
+    r.block_on(async {
+        let res = client
+            .resolve(format!("test@public.invalid:{}", port))
+            .await
+            .unwrap();
+        assert_eq!(res.subject, "acct:test@public.invalid");
+        m.assert();
+    });
+}
+
+#[test]
+#[cfg(feature = "fetch-ureq")]
+fn test_resolve_blocking() {
+    let m = mockito::mock("GET", "/.well-known/webfinger")
+        .match_query(mockito::Matcher::Any)
+        .with_header("Content-Type", "application/jrd+json")
+        .with_body(r#"{"subject": "acct:blocking@example.org", "links": []}"#)
+        .create();
+
+    let acct = format!("blocking@{}", mockito::server_url()).replace("http://", "");
+    let res = resolve_blocking(acct, false).unwrap();
+    assert_eq!(res.subject, "acct:blocking@example.org");
+    m.assert();
+}
+
+#[test]
+#[cfg(feature = "fetch-hyper")]
+fn test_hyper_client() {
+    use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+
+    let r = Runtime::new().unwrap();
+    let m = mockito::mock("GET", "/.well-known/webfinger")
+        .match_query(mockito::Matcher::Any)
+        .with_header("Content-Type", "application/jrd+json")
+        .with_body(r#"{"subject": "acct:hyper@example.org", "links": []}"#)
+        .create();
+
+    let client = WebfingerClient::builder()
+        .https(false)
+        .http_fetch(Client::builder(TokioExecutor::new()).build_http())
+        .build();
+    let acct = format!("hyper@{}", mockito::server_url()).replace("http://", "");
+    r.block_on(async {
+        let res = client.resolve(acct).await.unwrap();
+        assert_eq!(res.subject, "acct:hyper@example.org");
+    });
+    m.assert();
+}
+
+#[test]
+#[cfg(feature = "fetch-reqwest")]
+fn test_body_limit() {
+    let r = Runtime::new().unwrap();
+    let body = format!(
+        r#"{{"subject": "acct:large@example.org", "links": [], "padding": "{}"}}"#,
+        "a".repeat(MAX_BODY_SIZE)
+    );
+    let _m = mockito::mock("GET", "/.well-known/webfinger")
+        .match_query(mockito::Matcher::Any)
+        .with_header("Content-Type", "application/jrd+json")
+        .with_body(body.as_str())
+        .create();
+    let acct = format!("large@{}", mockito::server_url()).replace("http://", "");
+
+    #[cfg(feature = "fetch-reqwest")]
+    {
+        let client = WebfingerClient::builder().https(false).build();
+        assert_eq!(
+            r.block_on(client.resolve(acct.clone())),
+            Err(WebfingerError::HttpError)
+        );
+    }
+
+    #[cfg(feature = "fetch-hyper")]
+    {
+        use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+
+        let client = WebfingerClient::builder()
+            .https(false)
+            .http_fetch(Client::builder(TokioExecutor::new()).build_http())
+            .build();
+        assert_eq!(
+            r.block_on(client.resolve(acct.clone())),
+            Err(WebfingerError::HttpError)
+        );
+    }
+
+    #[cfg(feature = "fetch-ureq")]
+    assert_eq!(
+        resolve_blocking(acct, false),
+        Err(WebfingerError::HttpError)
+    );
+}
+
+#[test]
+fn test_client_extra_params() {
+    let r = Runtime::new().unwrap();
+    let host = mockito::server_url().replace("http://", "");
+    let m = mockito::mock("GET", "/.well-known/webfinger")
+        .match_query(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("resource".into(), format!("acct:test@{}", host)),
+            mockito::Matcher::UrlEncoded("key".into(), "secret".into()),
+            mockito::Matcher::UrlEncoded("trace".into(), "42".into()),
+        ]))
+        .match_header("x-host", "local")
+        .match_header("x-request", "yes")
+        .with_body(r#"{"subject": "acct:test@example.org", "links": []}"#)
+        .create();
+
+    let client = WebfingerClient::builder()
+        .https(false)
+        .query_param("key", "secret")
+        .host_options(
+            host.clone(),
+            RequestOptions::new().header("x-host", "local"),
+        )
+        .build();
+    r.block_on(async {
+        let res = client
+            .resolve_with_options(
+                format!("test@{}", host),
+                &RequestOptions::new()
+                    .query("trace", "42")
+                    .header("x-request", "yes"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.subject, String::from("acct:test@example.org"));
+
+        m.assert();
+    });
+}
+
+#[test]
+fn test_client_dedup_in_flight() {
+    let r = Runtime::new().unwrap();
+    let m = mockito::mock("GET", "/.well-known/webfinger")
+        .match_query(mockito::Matcher::Any)
+        .with_body(r#"{"subject": "acct:dedup@example.org", "links": []}"#)
+        .expect(1)
+        .create();
+
+    let acct = format!("dedup@{}", mockito::server_url()).replace("http://", "");
+    let client = WebfingerClient::builder()
+        .https(false)
+        .dedup_in_flight(std::time::Duration::from_secs(10))
+        .build();
+    r.block_on(async {
+        let (a, b) = tokio::join!(client.resolve(acct.clone()), client.resolve(acct.clone()));
+        assert_eq!(a, b);
+        assert_eq!(a.unwrap().subject, "acct:dedup@example.org");
+
+        m.assert();
+    });
+}
+
+#[test]
+fn test_resolve_with() {
+    let r = Runtime::new().unwrap();
+    let fetch = FakeFetch::new(200, r#"{"subject": "acct:test@example.org", "links": []}"#);
+    let dyn_fetch: &dyn HttpFetch = &fetch;
+    r.block_on(async {
+        let res = resolve_with(
+            dyn_fetch,
+            "test@example.org",
+            true,
+            &RequestOptions::new()
+                .query("key", "a b")
+                .header("X-Trace", "1"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.subject, "acct:test@example.org");
+    });
+    assert_eq!(
+        fetch.requests(),
+        vec![HttpRequest {
+            url: "https://example.org/.well-known/webfinger?resource=acct:test@example.org&key=a+b"
+                .to_string(),
+            headers: vec![
+                (
+                    "Accept".to_string(),
+                    "application/jrd+json, application/json".to_string()
+                ),
+                ("X-Trace".to_string(), "1".to_string()),
+            ],
+        }]
+    );
+
+    let broken = FakeFetch::new(200, "<html>\n<p>Not found</p>\n</html>");
+    r.block_on(async {
+        assert_eq!(
+            resolve_with(&broken, "test@example.org", true, &RequestOptions::new()).await,
+            Err(WebfingerError::JsonError(ResponseSnippet {
+                content_type: Some("application/jrd+json".to_string()),
+                body: "<html> <p>Not found</p> </html>".to_string(),
+            }))
+        );
+    });
+}
+
+#[test]
+fn test_request_ids() {
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(200, "{}"));
+    let client = WebfingerClient::builder()
+        .http_fetch(fetch.clone())
+        .request_ids(true)
+        .build();
+    let request_id = |request: &HttpRequest| {
+        request
+            .headers
+            .iter()
+            .find(|(name, _)| name == "X-Request-Id")
+            .map(|(_, value)| value.clone())
+    };
+
+    r.block_on(async {
+        assert!(client.resolve("test@example.org").await.is_err());
+        let error = client
+            .resolve_traced("test@example.org", &RequestOptions::new().request_id("abc"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.request_id, "abc");
+        assert!(matches!(error.error, WebfingerError::JsonError(_)));
+        assert!(client
+            .resolve_with_prefix(Prefix::Custom("mailto".into()), "test@example.org")
+            .await
+            .is_err());
+    });
+
+    let requests = fetch.requests();
+    assert!(request_id(&requests[0]).is_some());
+    assert_eq!(request_id(&requests[1]).as_deref(), Some("abc"));
+    assert!(request_id(&requests[2]).is_some());
+}
+
+#[test]
+fn test_client_config() {
+    let config: ClientConfig = serde_json::from_str(
+        r#"{
+            "user_agent": "Test/1.0",
+            "cache_ttl": 60,
+            "negative_cache_ttl": 60,
+            "address_family": "ipv4_only",
+            "domains": {
+                "spam.example": { "blocked": true },
+                "example.org": { "headers": { "Authorization": "Bearer abc" } }
+            }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(config.https, None);
+    assert_eq!(config.address_family, Some(AddressFamily::Ipv4Only));
+
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:test@example.org", "links": []}"#,
+    ));
+    let client = WebfingerClient::builder()
+        .config(&config)
+        .http_fetch(fetch.clone())
+        .build();
+    r.block_on(async {
+        assert!(client.resolve("test@example.org").await.is_ok());
+        assert!(client.resolve("test@example.org").await.is_ok());
+        assert_eq!(
+            client.resolve("test@social.spam.example").await,
+            Err(WebfingerError::BlockedDomain)
+        );
+    });
+    let requests = fetch.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0]
+        .headers
+        .contains(&("User-Agent".to_string(), "Test/1.0".to_string())));
+    assert!(requests[0]
+        .headers
+        .contains(&("Authorization".to_string(), "Bearer abc".to_string())));
+
+    let broken = Arc::new(FakeFetch::new(200, "Not found"));
+    let client = WebfingerClient::builder()
+        .config(&config)
+        .http_fetch(broken.clone())
+        .build();
+    r.block_on(async {
+        assert!(client.resolve("nobody@example.org").await.is_err());
+        assert!(client.resolve("nobody@example.org").await.is_err());
+    });
+    assert_eq!(broken.requests().len(), 1);
+
+    let config = ClientConfig {
+        proxy: Some("not a proxy".to_string()),
+        ..ClientConfig::default()
+    };
+    assert_eq!(
+        WebfingerClient::from_config(&config).unwrap_err(),
+        WebfingerError::ParseError
+    );
+}
+
+#[test]
+fn test_resolve_detailed() {
+    let r = Runtime::new().unwrap();
+    let client = WebfingerClient::builder()
+        .http_fetch(FakeFetch::new(
+            200,
+            r#"{"subject": "acct:test@example.org", "links": []}"#,
+        ))
+        .build();
+    r.block_on(async {
+        let resolved = client
+            .resolve_detailed("test@example.org", &RequestOptions::new().query("a", "b"))
+            .await
+            .unwrap();
+        assert_eq!(resolved.document.subject, "acct:test@example.org");
+        assert_eq!(
+            resolved.metadata,
+            ResolveMetadata {
+                url: "https://example.org/.well-known/webfinger?resource=acct:test@example.org&a=b"
+                    .to_string(),
+                status: 200,
+                content_type: Some("application/jrd+json".to_string()),
+                warnings: vec![],
+                cache_control: None,
+                server: ServerHints::default(),
+                freshness: Freshness::Fresh,
+                timings: resolved.metadata.timings,
+            }
+        );
+    });
+}
+
+#[test]
+fn test_from_json_strict() {
+    let json = br#"{
+        "subject": "acct:test@example.org",
+        "links": [{"rel": "self", "type": "application/activity+json", "href": "https://example.org/test"}]
+    }"#;
+    assert_eq!(
+        Webfinger::from_json_strict(json).unwrap(),
+        serde_json::from_slice::<Webfinger>(json).unwrap()
+    );
+
+    let unknown = br#"{"subject": "acct:test@example.org", "links": [], "extra": true}"#;
+    assert!(serde_json::from_slice::<Webfinger>(unknown).is_ok());
+    assert!(Webfinger::from_json_strict(unknown).is_err());
+    assert!(Webfinger::from_json_strict(
+        br#"{"subject": "acct:test@example.org", "links": [{"rel": "self", "url": "x"}]}"#
+    )
+    .is_err());
+}
+
+#[test]
+fn test_from_json_lenient() {
+    let (webfinger, issues) = Webfinger::from_json_lenient(
+        br#"{
+            "subject": "acct:test@example.org",
+            "links": [
+                {"rel": "self", "href": "https://example.org/test"},
+                {"rel": "avatar", "href": 42},
+                {"href": "https://example.org/"}
+            ]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(webfinger.links.len(), 1);
+    assert_eq!(webfinger.links[0].rel, "self");
+    assert_eq!(issues.len(), 2);
+    assert_eq!(issues[0].index, 1);
+    assert_eq!(issues[0].rel.as_deref(), Some("avatar"));
+    assert_eq!(issues[1].index, 2);
+    assert_eq!(issues[1].rel, None);
+
+    assert!(Webfinger::from_json_lenient(br#"{"links": []}"#).is_err());
+}
+
+#[test]
+#[cfg(feature = "lenient")]
+fn test_lenient_parsing() {
+    let r = Runtime::new().unwrap();
+    let resolve = |body: &str| {
+        let client = WebfingerClient::builder()
+            .http_fetch(FakeFetch::new(200, body))
+            .build();
+        r.block_on(client.resolve_detailed("test@example.org", &RequestOptions::new()))
+    };
+
+    let resolved = resolve(r#"[{"subject": "acct:test@example.org", "links": []}]"#).unwrap();
+    assert_eq!(resolved.document.subject, "acct:test@example.org");
+    assert_eq!(
+        resolved.metadata.warnings,
+        vec![ParseWarning::UnwrappedArray]
+    );
+
+    let resolved =
+        resolve(r#"{"webfinger": {"subject": "acct:test@example.org", "links": []}}"#).unwrap();
+    assert_eq!(resolved.document.subject, "acct:test@example.org");
+    assert_eq!(
+        resolved.metadata.warnings,
+        vec![ParseWarning::UnwrappedObject("webfinger".to_string())]
+    );
+
+    let resolved =
+        resolve(r#"{"subject": "acct:test@example.org", "links": [{"rel": "self"}, {"rel": 1}]}"#)
+            .unwrap();
+    assert_eq!(resolved.document.links.len(), 1);
+    assert!(matches!(
+        resolved.metadata.warnings[..],
+        [ParseWarning::DroppedLink(LinkParseIssue { index: 1, .. })]
+    ));
+
+    assert!(resolve("[]").is_err());
+    assert!(resolve(r#"{"webfinger": 42}"#).is_err());
+}
+
+#[test]
+fn test_status_policy() {
+    let policy = StatusPolicy::new().set(403, StatusAction::NotFound);
+    assert_eq!(policy.action(204), StatusAction::Accept);
+    assert_eq!(policy.action(403), StatusAction::NotFound);
+    assert_eq!(policy.action(410), StatusAction::NotFound);
+    assert_eq!(policy.action(503), StatusAction::Retry);
+    assert_eq!(policy.action(500), StatusAction::Fail);
+
+    let r = Runtime::new().unwrap();
+    let resolve = |fetch: Arc<FakeFetch>| {
+        let client = WebfingerClient::builder()
+            .http_fetch(fetch)
+            .retries(2)
+            .status_policy(policy.clone())
+            .build();
+        r.block_on(client.resolve("test@example.org"))
+    };
+
+    let forbidden = Arc::new(FakeFetch::new(403, "{}"));
+    assert_eq!(resolve(forbidden.clone()), Err(WebfingerError::NotFound));
+    assert_eq!(forbidden.requests().len(), 1);
+
+    let unavailable = Arc::new(FakeFetch::new(503, "{}"));
+    assert_eq!(
+        resolve(unavailable.clone()),
+        Err(WebfingerError::HttpStatus(503))
+    );
+    assert_eq!(unavailable.requests().len(), 3);
+
+    let broken = Arc::new(FakeFetch::new(500, "{}"));
+    assert_eq!(
+        resolve(broken.clone()),
+        Err(WebfingerError::HttpStatus(500))
+    );
+    assert_eq!(broken.requests().len(), 1);
+}
+
+#[test]
+#[cfg(feature = "tokio")]
+fn test_retry_backoff() {
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    assert_eq!(
+        crate::expires::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+        Some(UNIX_EPOCH + Duration::from_secs(784111777))
+    );
+    assert_eq!(
+        crate::expires::parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"),
+        None
+    );
+
+    let r = Runtime::new().unwrap();
+    let resolve = |fetch: Arc<FakeFetch>| {
+        let client = WebfingerClient::builder()
+            .shared_http_fetch(fetch)
+            .retries(2)
+            .retry_backoff(Duration::from_millis(50), Duration::from_secs(1))
+            .build();
+        let start = Instant::now();
+        let result = r.block_on(client.resolve("test@example.org"));
+        (result, start.elapsed())
+    };
+
+    let unavailable = Arc::new(FakeFetch::new(503, "{}"));
+    let (result, elapsed) = resolve(unavailable.clone());
+    assert_eq!(result, Err(WebfingerError::HttpStatus(503)));
+    assert_eq!(unavailable.requests().len(), 3);
+    assert!(elapsed >= Duration::from_millis(150));
+
+    let mut fetch = FakeFetch::new(429, "{}");
+    fetch.response.headers = vec![("Retry-After".into(), "0".into())];
+    let limited = Arc::new(fetch);
+    let (result, elapsed) = resolve(limited.clone());
+    assert_eq!(result, Err(WebfingerError::HttpStatus(429)));
+    assert_eq!(limited.requests().len(), 3);
+    assert!(elapsed < Duration::from_millis(150));
+
+    // Waiting longer than the maximum delay is not worth it
+    let mut fetch = FakeFetch::new(503, "{}");
+    fetch.response.headers = vec![("Retry-After".into(), "3600".into())];
+    let down = Arc::new(fetch);
+    let (result, elapsed) = resolve(down.clone());
+    assert_eq!(result, Err(WebfingerError::HttpStatus(503)));
+    assert_eq!(down.requests().len(), 1);
+    assert!(elapsed < Duration::from_millis(150));
+}
+
+#[test]
+#[cfg(feature = "tokio")]
+fn test_cache_warmer() {
+    use std::time::Duration;
+
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:test@example.org", "links": []}"#,
+    ));
+    let client = WebfingerClient::builder()
+        .http_fetch(fetch.clone())
+        .cache(Duration::from_secs(60))
+        .build();
+    let refreshed = Arc::new(Mutex::new(vec![]));
+    let hook_refreshed = refreshed.clone();
+    let warmer = CacheWarmer::new(client.clone(), vec!["a@example.org", "b@example.org"])
+        .delay(Duration::from_millis(1))
+        .on_refresh(move |acct, result| {
+            assert!(result.is_ok());
+            hook_refreshed.lock().unwrap().push(acct.to_string());
+        });
+
+    r.block_on(async {
+        warmer.refresh_all().await;
+        assert_eq!(fetch.requests().len(), 2);
+        assert!(client.resolve("a@example.org").await.is_ok());
+        assert_eq!(fetch.requests().len(), 2);
+        warmer.refresh_all().await;
+        assert_eq!(fetch.requests().len(), 4);
+    });
+    assert_eq!(
+        *refreshed.lock().unwrap(),
+        vec![
+            "a@example.org",
+            "b@example.org",
+            "a@example.org",
+            "b@example.org"
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "tokio")]
+fn test_cache_warmer_long_interval() {
+    use std::time::Duration;
+
+    let client = WebfingerClient::builder()
+        .http_fetch(Arc::new(FakeFetch::new(200, "{}")))
+        .build();
+    let warmer = CacheWarmer::new(client, vec!["a@example.org"])
+        .interval(Duration::MAX)
+        .jitter(Duration::from_secs(60));
+    for _ in 0..10 {
+        assert_eq!(warmer.next_wait(), Duration::MAX);
+    }
+    let warmer = warmer.interval(Duration::from_secs(10));
+    for _ in 0..10 {
+        let wait = warmer.next_wait();
+        assert!(wait >= Duration::from_secs(10) && wait < Duration::from_secs(70));
+    }
+}
+
+/// Allows a single request per host.
+struct OncePerHost(Mutex<Vec<String>>);
+
+impl RateLimit for OncePerHost {
+    fn acquire<'a>(&'a self, host: &'a str) -> BoxFuture<'a, bool> {
+        let mut hosts = self.0.lock().unwrap();
+        let allowed = !hosts.iter().any(|h| h == host);
+        hosts.push(host.to_string());
+        async move { allowed }.boxed()
+    }
+}
+
+#[test]
+fn test_rate_limit() {
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:test@example.org", "links": []}"#,
+    ));
+    let limit = Arc::new(OncePerHost(Mutex::new(vec![])));
+    let client = WebfingerClient::builder()
+        .http_fetch(fetch.clone())
+        .rate_limit(limit.clone())
+        .build();
+    r.block_on(async {
+        assert!(client.resolve("a@example.org").await.is_ok());
+        assert_eq!(
+            client.resolve("b@example.org").await,
+            Err(WebfingerError::RateLimited)
+        );
+        assert!(client.resolve("a@example.com:8080").await.is_ok());
+    });
+    assert_eq!(fetch.requests().len(), 2);
+    assert_eq!(
+        *limit.0.lock().unwrap(),
+        vec!["example.org", "example.org", "example.com:8080"]
+    );
+}
+
+#[test]
+#[cfg(feature = "governor")]
+fn test_governor_rate_limit() {
+    use governor::{Quota, RateLimiter};
+    use std::num::NonZeroU32;
+
+    let r = Runtime::new().unwrap();
+    let client = WebfingerClient::builder()
+        .http_fetch(FakeFetch::new(
+            200,
+            r#"{"subject": "acct:test@example.org", "links": []}"#,
+        ))
+        .rate_limit(RateLimiter::<String, _, _>::keyed(Quota::per_hour(
+            NonZeroU32::new(1).unwrap(),
+        )))
+        .build();
+    r.block_on(async {
+        assert!(client.resolve("a@example.org").await.is_ok());
+        assert_eq!(
+            client.resolve("a@example.org").await,
+            Err(WebfingerError::RateLimited)
+        );
+    });
+}
+
+#[test]
+fn test_check_instance() {
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:example.org@example.org", "links": [{"rel": "self"}]}"#,
+    ));
+    let client = WebfingerClient::builder().http_fetch(fetch.clone()).build();
+    r.block_on(async {
+        let report = client.check_instance("example.org", None, &["self"]).await;
+        assert_eq!(
+            report.url,
+            "https://example.org/.well-known/webfinger?resource=acct:example.org@example.org"
+        );
+        assert_eq!(report.status, Some(200));
+        assert!(report.valid_document);
+        assert!(report.is_healthy());
+
+        let report = client
+            .check_instance(
+                "example.org",
+                Some("admin@example.org"),
+                &["self", "http://webfinger.net/rel/profile-page"],
+            )
+            .await;
+        assert_eq!(report.probe, "acct:admin@example.org");
+        assert_eq!(
+            report.missing_rels,
+            vec!["http://webfinger.net/rel/profile-page"]
+        );
+        assert!(!report.is_healthy());
+
+        let report = client
+            .check_instance("example.org", Some("nobody"), &[])
+            .await;
+        assert!(report.error.is_some());
+    });
+
+    let broken = WebfingerClient::builder()
+        .http_fetch(FakeFetch::new(404, "Not found"))
+        .build();
+    r.block_on(async {
+        let report = broken.check_instance("example.org", None, &[]).await;
+        assert_eq!(report.status, Some(404));
+        assert!(!report.valid_document);
+        assert!(!report.is_healthy());
+    });
+}
+
+#[test]
+fn test_response_snippet() {
+    let long = "é".repeat(200);
+    let snippet = ResponseSnippet::new(None, long.as_bytes());
+    assert_eq!(snippet.body, "é".repeat(128));
+    assert_eq!(snippet.content_type, None);
+
+    let odd = "é".repeat(127) + "aé";
+    let snippet = ResponseSnippet::new(Some("text/html"), odd.as_bytes());
+    assert_eq!(snippet.body, "é".repeat(127) + "a");
+    assert_eq!(snippet.content_type.as_deref(), Some("text/html"));
+}
+
 #[test]
 fn test_no_aliases() {
     let json = r#"
@@ -125,37 +892,273 @@ fn test_webfinger_parsing() {
     assert_eq!(
         vec![
             Link {
-                rel: "http://webfinger.net/rel/profile-page".to_string(),
+                rel: "http://webfinger.net/rel/profile-page".into(),
                 mime_type: None,
                 href: Some("https://example.org/@test/".to_string()),
-                template: None
+                template: None,
+                titles: HashMap::new(),
+                properties: HashMap::new(),
+                extra: Map::new(),
             },
             Link {
-                rel: "http://schemas.google.com/g/2010#updates-from".to_string(),
+                rel: "http://schemas.google.com/g/2010#updates-from".into(),
                 mime_type: Some("application/atom+xml".to_string()),
                 href: Some("https://example.org/@test/feed.atom".to_string()),
-                template: None
+                template: None,
+                titles: HashMap::new(),
+                properties: HashMap::new(),
+                extra: Map::new(),
             },
             Link {
-                rel: "self".to_string(),
+                rel: "self".into(),
                 mime_type: Some("application/activity+json".to_string()),
                 href: Some("https://example.org/@test/".to_string()),
-                template: None
+                template: None,
+                titles: HashMap::new(),
+                properties: HashMap::new(),
+                extra: Map::new(),
             }
         ],
         webfinger.links
     );
 }
 
-pub struct MyResolver;
-
-// Only one user, represented by a String
-impl Resolver<&'static str> for MyResolver {
-    fn instance_domain<'a>(&self) -> &'a str {
-        "instance.tld"
-    }
-
-    fn find(
+#[test]
+fn test_valid_aliases() {
+    let webfinger = Webfinger {
+        subject: "acct:test@example.org".to_string(),
+        aliases: vec![
+            "HTTPS://Example.org:443/@test".to_string(),
+            "acct:Test@EXAMPLE.org".to_string(),
+            "/@test".to_string(),
+            "javascript:alert(1)".to_string(),
+            "not an alias".to_string(),
+            "acct:@example.org".to_string(),
+        ],
+        links: vec![],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    };
+    let (valid, warnings) = webfinger.valid_aliases();
+    assert_eq!(
+        valid,
+        vec!["https://example.org/@test", "acct:Test@example.org"]
+    );
+    assert_eq!(
+        warnings
+            .iter()
+            .map(|w| (w.alias.as_str(), w.problem.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("/@test", AliasProblem::Relative),
+            ("javascript:alert(1)", AliasProblem::Invalid),
+            ("not an alias", AliasProblem::Relative),
+            ("acct:@example.org", AliasProblem::Invalid),
+        ]
+    );
+}
+
+#[test]
+fn test_link_map() {
+    let webfinger = Webfinger {
+        subject: "acct:test@example.org".to_string(),
+        aliases: vec![],
+        links: vec![
+            Link {
+                rel: "self".into(),
+                mime_type: Some("application/activity+json".to_string()),
+                href: Some("https://example.org/@test/".to_string()),
+                template: None,
+                titles: HashMap::new(),
+                properties: HashMap::new(),
+                extra: Map::new(),
+            },
+            Link {
+                rel: "http://webfinger.net/rel/profile-page".into(),
+                mime_type: None,
+                href: Some("https://example.org/@test/".to_string()),
+                template: None,
+                titles: HashMap::new(),
+                properties: HashMap::new(),
+                extra: Map::new(),
+            },
+            Link {
+                rel: "self".into(),
+                mime_type: Some("application/ld+json".to_string()),
+                href: Some("https://example.org/users/test".to_string()),
+                template: None,
+                titles: HashMap::new(),
+                properties: HashMap::new(),
+                extra: Map::new(),
+            },
+        ],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    };
+
+    let map = webfinger.link_map();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map["self"], vec![&webfinger.links[0], &webfinger.links[2]]);
+    assert_eq!(
+        map["http://webfinger.net/rel/profile-page"],
+        vec![&webfinger.links[1]]
+    );
+    assert!(!map.contains_key("http://webfinger.net/rel/avatar"));
+}
+
+#[test]
+fn test_links_filter() {
+    let webfinger = Webfinger {
+        subject: "acct:test@example.org".to_string(),
+        aliases: vec![],
+        links: vec![
+            Link {
+                rel: "http://webfinger.net/rel/profile-page".into(),
+                mime_type: Some("text/html".to_string()),
+                href: Some("https://example.org/@test/".to_string()),
+                template: None,
+                titles: HashMap::new(),
+                properties: HashMap::new(),
+                extra: Map::new(),
+            },
+            Link {
+                rel: "self".into(),
+                mime_type: Some(
+                    "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\""
+                        .to_string(),
+                ),
+                href: Some("https://example.org/ld/test".to_string()),
+                template: None,
+                titles: HashMap::new(),
+                properties: HashMap::new(),
+                extra: Map::new(),
+            },
+            Link {
+                rel: "self".into(),
+                mime_type: Some("Application/Activity+JSON".to_string()),
+                href: Some("https://example.org/users/test".to_string()),
+                template: None,
+                titles: HashMap::new(),
+                properties: HashMap::new(),
+                extra: Map::new(),
+            },
+        ],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    };
+
+    assert_eq!(
+        webfinger
+            .links()
+            .rel(Rel::SelfRel)
+            .mime("application/activity+json")
+            .first_href(),
+        Some("https://example.org/users/test")
+    );
+    assert_eq!(
+        webfinger.links().mime("application/ld+json").first_href(),
+        Some("https://example.org/ld/test")
+    );
+    assert_eq!(webfinger.links().rel("self").count(), 2);
+    assert_eq!(webfinger.links().rel(Rel::Avatar).first_href(), None);
+    assert_eq!(webfinger.links().first_template(), None);
+    assert_eq!((&webfinger).into_iter().count(), 3);
+    assert_eq!(
+        Rel::from("http://webfinger.net/rel/profile-page"),
+        Rel::ProfilePage
+    );
+    assert_eq!(Rel::from("me"), Rel::Custom("me".to_string()));
+}
+
+#[test]
+fn test_webfinger_set() {
+    let link = |rel: &str, href: &str| Link {
+        rel: rel.into(),
+        mime_type: None,
+        href: Some(href.to_string()),
+        template: None,
+        titles: HashMap::new(),
+        properties: HashMap::new(),
+        extra: Map::new(),
+    };
+    let mastodon = Webfinger {
+        subject: "acct:test@example.org".to_string(),
+        aliases: vec!["https://example.org/@test".to_string()],
+        links: vec![link("self", "https://example.org/users/test")],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    };
+    let plume = Webfinger {
+        subject: "acct:test@blog.example.org".to_string(),
+        aliases: vec![],
+        links: vec![link("self", "https://blog.example.org/@/test")],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    };
+    let alias = Webfinger {
+        subject: "https://example.org/@test".to_string(),
+        aliases: vec!["acct:test@blog.example.org".to_string()],
+        links: vec![
+            link("self", "https://example.org/users/test"),
+            link(
+                "http://webfinger.net/rel/avatar",
+                "https://example.org/test.png",
+            ),
+        ],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    };
+
+    let mut set: WebfingerSet = vec![mastodon, plume].into_iter().collect();
+    assert_eq!(set.len(), 2);
+    assert_eq!(set.links_for_rel(Rel::SelfRel).len(), 2);
+
+    set.insert(alias);
+    assert_eq!(set.len(), 1);
+    let merged = set.get("acct:test@blog.example.org").unwrap();
+    assert!(merged.is_known_as("https://example.org/@test"));
+    assert!(merged.is_known_as("acct:test@example.org"));
+    assert_eq!(set.links_for_rel("self").len(), 2);
+    assert_eq!(set.links_for_rel(Rel::Avatar).len(), 1);
+    assert!(set.get("acct:other@example.org").is_none());
+
+    let dated = |expires: Option<&str>| Webfinger {
+        subject: "acct:test@example.org".to_string(),
+        aliases: vec![],
+        links: vec![],
+        properties: HashMap::new(),
+        expires: expires.map(String::from),
+        extra: Map::new(),
+    };
+    let merge = |left: Option<&str>, right: Option<&str>| {
+        let mut merged = dated(left);
+        merged.merge(dated(right));
+        merged.expires
+    };
+    let early = Some("2030-01-01T00:00:00Z");
+    let late = Some("2031-01-01T00:00:00Z");
+    assert_eq!(merge(late, early).as_deref(), early);
+    assert_eq!(merge(early, late).as_deref(), early);
+    assert_eq!(merge(None, late).as_deref(), late);
+    assert_eq!(merge(early, None).as_deref(), early);
+    assert_eq!(merge(Some("tomorrow"), late).as_deref(), late);
+}
+
+pub struct MyResolver;
+
+// Only one user, represented by a String
+impl Resolver<&'static str> for MyResolver {
+    fn instance_domain(&self) -> InstanceDomain {
+        InstanceDomain::new("instance.tld").unwrap()
+    }
+
+    fn find(
         &self,
         prefix: Prefix,
         acct: String,
@@ -166,16 +1169,35 @@ impl Resolver<&'static str> for MyResolver {
                 subject: acct.clone(),
                 aliases: vec![acct.clone()],
                 links: vec![Link {
-                    rel: "http://webfinger.net/rel/profile-page".to_string(),
+                    rel: "http://webfinger.net/rel/profile-page".into(),
                     mime_type: None,
                     href: Some(format!("https://instance.tld/@{}/", acct)),
                     template: None,
+                    titles: HashMap::new(),
+                    properties: HashMap::new(),
+                    extra: Map::new(),
                 }],
+                properties: HashMap::new(),
+                expires: None,
+                extra: Map::new(),
             })
         } else {
             Err(ResolverError::NotFound)
         }
     }
+
+    fn find_by_url(
+        &self,
+        url: &Url,
+        resource_repo: &'static str,
+    ) -> Result<Webfinger, ResolverError> {
+        match url.path().strip_prefix("/@") {
+            Some(user) if user.trim_end_matches('/') == resource_repo => {
+                self.find(Prefix::Acct, resource_repo.to_string(), resource_repo)
+            }
+            _ => Err(ResolverError::NotFound),
+        }
+    }
 }
 
 #[cfg(feature = "async")]
@@ -183,12 +1205,9 @@ pub struct MyAsyncResolver;
 
 // Only one user, represented by a String
 #[cfg(feature = "async")]
-#[async_trait::async_trait]
-impl AsyncResolver for MyAsyncResolver {
-    type Repo = &'static str;
-
-    async fn instance_domain<'a>(&self) -> &'a str {
-        "instance.tld"
+impl AsyncResolver<&'static str> for MyAsyncResolver {
+    async fn instance_domain(&self) -> InstanceDomain {
+        InstanceDomain::new("instance.tld").unwrap()
     }
 
     async fn find(
@@ -202,11 +1221,17 @@ impl AsyncResolver for MyAsyncResolver {
                 subject: acct.clone(),
                 aliases: vec![acct.clone()],
                 links: vec![Link {
-                    rel: "http://webfinger.net/rel/profile-page".to_string(),
+                    rel: "http://webfinger.net/rel/profile-page".into(),
                     mime_type: None,
                     href: Some(format!("https://instance.tld/@{}/", acct)),
                     template: None,
+                    titles: HashMap::new(),
+                    properties: HashMap::new(),
+                    extra: Map::new(),
                 }],
+                properties: HashMap::new(),
+                expires: None,
+                extra: Map::new(),
             })
         } else {
             Err(ResolverError::NotFound)
@@ -214,6 +1239,51 @@ impl AsyncResolver for MyAsyncResolver {
     }
 }
 
+#[test]
+fn test_parse_resource() {
+    assert_eq!(
+        parse_resource("acct:admin@instance.tld"),
+        Ok(Resource {
+            prefix: Prefix::Acct,
+            user: "admin".to_string(),
+            domain: "instance.tld".to_string(),
+            port: None,
+        })
+    );
+    let with_port = parse_resource("GROUP:devs@localhost:8080").unwrap();
+    assert_eq!(with_port.prefix, Prefix::Group);
+    assert_eq!(with_port.domain, "localhost");
+    assert_eq!(with_port.port, Some(8080));
+    assert_eq!(with_port.host(), "localhost:8080");
+
+    let ipv6 = parse_resource("acct:admin@[::1]").unwrap();
+    assert_eq!(ipv6.domain, "[::1]");
+    assert_eq!(ipv6.port, None);
+    let ipv6 = parse_resource("acct:admin@[::1]:443").unwrap();
+    assert_eq!(ipv6.domain, "[::1]");
+    assert_eq!(ipv6.port, Some(443));
+
+    assert_eq!(
+        parse_resource("x-custom:a@b").unwrap().prefix,
+        Prefix::Custom("x-custom".to_string())
+    );
+    for invalid in &[
+        "admin@instance.tld",
+        "admin",
+        "acct:admin",
+        "ht tp:admin@instance.tld",
+        ":admin@instance.tld",
+        "acct:admin@instance.tld:99999",
+    ] {
+        assert_eq!(
+            parse_resource(invalid),
+            Err(ResolverError::InvalidResource),
+            "{}",
+            invalid
+        );
+    }
+}
+
 #[test]
 fn test_my_resolver() {
     let resolver = MyResolver;
@@ -244,13 +1314,281 @@ fn test_my_resolver() {
         resolver.endpoint("group:admin@instance.tld", "admin"),
         Err(ResolverError::NotFound)
     );
+    assert_eq!(
+        resolver.endpoint("ht tp:admin@instance.tld", "admin"),
+        Err(ResolverError::InvalidResource)
+    );
+}
+
+#[test]
+fn test_find_by_url() {
+    let resolver = MyResolver;
+    let res = resolver
+        .endpoint("https://instance.tld/@admin", "admin")
+        .unwrap();
+    assert_eq!(res.subject, "admin");
+    assert_eq!(
+        resolver.endpoint("https://instance.tld/@test", "admin"),
+        Err(ResolverError::NotFound)
+    );
+    assert_eq!(
+        resolver.endpoint("https://oops.ie/@admin", "admin"),
+        Err(ResolverError::WrongDomain)
+    );
+    assert_eq!(
+        resolver.endpoint("https://instance.tld:8443/@admin", "admin"),
+        Err(ResolverError::WrongDomain)
+    );
+
+    let wrapped = DenyRels::new(MyResolver, vec!["http://webfinger.net/rel/profile-page"]);
+    let res = wrapped
+        .endpoint("https://instance.tld/@admin", "admin")
+        .unwrap();
+    assert!(res.links.is_empty());
+}
+
+#[test]
+fn test_endpoint_with_rels() {
+    let resolver = MyResolver;
+    let res = resolver
+        .endpoint_with_rels("acct:admin@instance.tld", &["self"], "admin")
+        .unwrap();
+    assert!(res.filtered.links.is_empty());
+    assert_eq!(res.full.links.len(), 1);
+    assert_eq!(res.filtered.subject, res.full.subject);
+
+    let res = resolver
+        .endpoint_with_rels(
+            "acct:admin@instance.tld",
+            &["self", "http://webfinger.net/rel/profile-page"],
+            "admin",
+        )
+        .unwrap();
+    assert_eq!(res.filtered, res.full);
+    let res = resolver
+        .endpoint_with_rels("acct:admin@instance.tld", &[], "admin")
+        .unwrap();
+    assert_eq!(res.filtered, res.full);
+    assert_eq!(
+        resolver.endpoint_with_rels("acct:admin@oops.ie", &[], "admin"),
+        Err(ResolverError::WrongDomain)
+    );
+}
+
+#[test]
+fn test_indexed_resolver() {
+    let document = |subject: &str| Webfinger {
+        subject: subject.to_string(),
+        aliases: vec![],
+        links: vec![],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    };
+    let mut resolver = IndexedResolver::new(InstanceDomain::new("instance.tld").unwrap());
+    resolver
+        .insert(document("acct:alice@instance.tld"))
+        .unwrap();
+    let mut resolver = resolver.with_prefix_search();
+    for subject in &[
+        "acct:alicia@instance.tld",
+        "acct:bob@instance.tld",
+        "group:alice@instance.tld",
+    ] {
+        resolver.insert(document(subject)).unwrap();
+    }
+    assert_eq!(
+        resolver.insert(document("acct:alice@oops.ie")),
+        Err(ResolverError::WrongDomain)
+    );
+    assert_eq!(
+        resolver.insert(document("alice")),
+        Err(ResolverError::InvalidResource)
+    );
+    assert_eq!(resolver.len(), 4);
+
+    assert_eq!(
+        Resolver::endpoint(&resolver, "acct:alice@instance.tld", ()),
+        Ok(document("acct:alice@instance.tld"))
+    );
+    assert_eq!(
+        Resolver::endpoint(&resolver, "acct:carol@instance.tld", ()),
+        Err(ResolverError::NotFound)
+    );
+    assert_eq!(
+        resolver
+            .search(&Prefix::Acct, "ali")
+            .map(|w| w.subject.as_str())
+            .collect::<Vec<_>>(),
+        vec!["acct:alice@instance.tld", "acct:alicia@instance.tld"]
+    );
+    assert_eq!(resolver.search(&Prefix::Group, "b").count(), 0);
+    assert_eq!(
+        IndexedResolver::new(InstanceDomain::new("instance.tld").unwrap())
+            .search(&Prefix::Acct, "")
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn test_deny_rels() {
+    let resolver = DenyRels::new(MyResolver, vec!["http://webfinger.net/rel/profile-page"]);
+    let webfinger = resolver
+        .endpoint("acct:admin@instance.tld", "admin")
+        .unwrap();
+    assert_eq!(webfinger.subject, "admin");
+    assert!(webfinger.links.is_empty());
+    assert_eq!(
+        resolver.endpoint("acct:admin@oops.ie", "admin"),
+        Err(ResolverError::WrongDomain)
+    );
+
+    let caller = Caller::new("https://friend.tld/users/friend");
+    assert_eq!(
+        resolver
+            .endpoint_for_caller("acct:admin@instance.tld", Some(&caller), "admin")
+            .unwrap()
+            .links
+            .len(),
+        1
+    );
+
+    let resolver = DenyRels::new(resolver.into_inner(), vec!["self"]);
+    assert_eq!(
+        resolver
+            .endpoint("acct:admin@instance.tld", "admin")
+            .unwrap()
+            .links
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn test_normalize_subjects() {
+    let resolver = NormalizeSubjects::new(MyResolver, SubjectMode::FixUp);
+    let webfinger = resolver
+        .endpoint("acct:admin@instance.tld", "admin")
+        .unwrap();
+    assert_eq!(webfinger.subject, "acct:admin@instance.tld");
+    assert_eq!(webfinger.aliases, vec!["acct:admin@instance.tld"]);
+    assert_eq!(
+        resolver.endpoint("acct:test@instance.tld", "admin"),
+        Err(ResolverError::NotFound)
+    );
+
+    let resolver = NormalizeSubjects::new(resolver.into_inner(), SubjectMode::Strict);
+    assert_eq!(
+        resolver.endpoint("acct:admin@instance.tld", "admin"),
+        Err(ResolverError::InvalidSubject)
+    );
+
+    let fixed = NormalizeSubjects::new(MyResolver, SubjectMode::FixUp);
+    let resolver = NormalizeSubjects::new(fixed, SubjectMode::Strict);
+    assert!(resolver
+        .endpoint("acct:admin@instance.tld", "admin")
+        .is_ok());
+}
+
+#[test]
+fn test_identifier_policy() {
+    let policy = IdentifierPolicy::default();
+    assert_eq!(policy.check("admin"), Ok(()));
+    assert_eq!(policy.check("jean_dupont.42"), Ok(()));
+    assert_eq!(policy.check("élodie"), Ok(()));
+    assert_eq!(policy.check("пётр"), Ok(()));
+    assert_eq!(policy.check(""), Err(ResolverError::InvalidIdentifier));
+    assert_eq!(policy.check("a b"), Err(ResolverError::InvalidIdentifier));
+    assert_eq!(
+        policy.check(&"a".repeat(65)),
+        Err(ResolverError::InvalidIdentifier)
+    );
+    // The first letter is a Cyrillic а
+    assert_eq!(
+        policy.check("\u{0430}dmin"),
+        Err(ResolverError::InvalidIdentifier)
+    );
+
+    let ascii = IdentifierPolicy {
+        charset: Charset::Ascii,
+        ..IdentifierPolicy::default()
+    };
+    assert_eq!(ascii.check("élodie"), Err(ResolverError::InvalidIdentifier));
+
+    let resolver = StrictIdentifiers::new(MyResolver, ascii);
+    assert!(resolver
+        .endpoint("acct:admin@instance.tld", "admin")
+        .is_ok());
+    assert_eq!(
+        resolver.endpoint("acct:\u{0430}dmin@instance.tld", "admin"),
+        Err(ResolverError::InvalidIdentifier)
+    );
+}
+
+#[test]
+#[cfg(feature = "unicode-security")]
+fn test_identifier_policy_all_scripts() {
+    let policy = IdentifierPolicy::default();
+    assert_eq!(policy.check("山田たろう"), Ok(()));
+    assert_eq!(policy.check("user_42"), Ok(()));
+    // The o is an Armenian օ, that the heuristic without unicode-security doesn't know about
+    assert_eq!(
+        policy.check("g\u{0585}\u{0585}gle"),
+        Err(ResolverError::InvalidIdentifier)
+    );
+}
+
+#[test]
+fn test_acct_confusable_with() {
+    assert!(acct_confusable_with(
+        "\u{0430}dmin@example.org",
+        "admin@example.org"
+    ));
+    assert!(acct_confusable_with(
+        "admin@examp1e.org",
+        "acct:admin@example.org"
+    ));
+    assert!(acct_confusable_with(
+        "@rnoderator@example.org",
+        "moderator@example.org"
+    ));
+    assert!(acct_confusable_with(
+        "adm\u{0456}n@ex\u{0430}mple.org",
+        "admin@EXAMPLE.org"
+    ));
+    assert!(acct_confusable_with(
+        "\u{0430}dmin@example.org",
+        "Admin@example.org"
+    ));
+    assert!(acct_confusable_with("Ilya@example.org", "llya@example.org"));
+    assert!(!acct_confusable_with(
+        "admin@example.org",
+        "@admin@Example.org"
+    ));
+    assert!(acct_confusable_with(
+        "@RNoderator@example.org",
+        "moderator@example.org"
+    ));
+    assert!(!acct_confusable_with(
+        "Admin@example.org",
+        "admin@example.org"
+    ));
+    assert!(!acct_confusable_with(
+        "admin@example.org",
+        "root@example.org"
+    ));
+    assert!(!acct_confusable_with(
+        "admin@example.org",
+        "admin@example.com"
+    ));
 }
 
 #[test]
 #[cfg(feature = "async")]
 fn test_my_async_resolver() {
     let resolver = MyAsyncResolver;
-    let mut r = Runtime::new().unwrap();
+    let r = Runtime::new().unwrap();
     r.block_on(async {
         assert!(resolver
             .endpoint("acct:admin@instance.tld", "admin")
@@ -293,4 +1631,3974 @@ fn test_my_async_resolver() {
             Err(ResolverError::NotFound)
         );
     });
+    r.block_on(async {
+        assert_eq!(
+            resolver
+                .endpoint("https://instance.tld/@admin", "admin")
+                .await,
+            Err(ResolverError::NotFound)
+        );
+    });
+    r.block_on(async {
+        let res = resolver
+            .endpoint_with_rels("acct:admin@instance.tld", &["self"], "admin")
+            .await
+            .unwrap();
+        assert!(res.filtered.links.is_empty());
+        assert_eq!(res.full.links.len(), 1);
+    });
+}
+
+#[test]
+#[cfg(feature = "fediverse")]
+fn test_fediverse_helpers() {
+    use crate::fediverse::prelude::*;
+
+    let mut webfinger = Webfinger::for_account(
+        "test@example.org",
+        "https://example.org/users/test",
+        "https://example.org/@test",
+    );
+    assert_eq!(webfinger.subject, "acct:test@example.org");
+    assert_eq!(
+        webfinger.actor_url(),
+        Some("https://example.org/users/test")
+    );
+    assert_eq!(webfinger.profile_url(), Some("https://example.org/@test"));
+    assert_eq!(webfinger.avatar_url(), None);
+    assert_eq!(webfinger.subscribe_url("acct:a@b.c"), None);
+
+    webfinger.links.push(Link {
+        rel: "http://ostatus.org/schema/1.0/subscribe".into(),
+        mime_type: None,
+        href: None,
+        template: Some("https://example.org/authorize_interaction?uri={uri}".to_string()),
+        titles: HashMap::new(),
+        properties: HashMap::new(),
+        extra: Map::new(),
+    });
+    assert_eq!(
+        webfinger.subscribe_url("acct:a@b.c").as_deref(),
+        Some("https://example.org/authorize_interaction?uri=acct%3Aa%40b%2Ec")
+    );
+
+    assert_eq!(
+        normalize_acct(" @test@Example.ORG "),
+        Ok("test@example.org".to_string())
+    );
+    assert_eq!(
+        normalize_acct("acct:Test@example.org"),
+        Ok("Test@example.org".to_string())
+    );
+    assert_eq!(normalize_acct("test"), Err(WebfingerError::ParseError));
+    assert_eq!(
+        normalize_acct("@example.org"),
+        Err(WebfingerError::ParseError)
+    );
+    assert_eq!(
+        normalize_acct("a@b@example.org"),
+        Err(WebfingerError::ParseError)
+    );
+}
+
+#[test]
+#[cfg(feature = "fediverse")]
+fn test_instance_actor() {
+    let webfinger = Webfinger::for_instance_actor("example.org", "https://example.org/actor");
+    assert_eq!(webfinger.subject, "acct:example.org@example.org");
+    assert_eq!(webfinger.actor_url(), Some("https://example.org/actor"));
+
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        &serde_json::to_string(&webfinger).unwrap(),
+    ));
+    let client = WebfingerClient::builder().http_fetch(fetch.clone()).build();
+    r.block_on(async {
+        assert_eq!(
+            client.resolve_instance_actor("example.org").await,
+            Ok(webfinger)
+        );
+    });
+    assert_eq!(fetch.requests().len(), 1);
+
+    let no_actor = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:relay@example.org", "links": []}"#,
+    ));
+    let client = WebfingerClient::builder()
+        .http_fetch(no_actor.clone())
+        .build();
+    r.block_on(async {
+        assert_eq!(
+            client.resolve_instance_actor("example.org").await,
+            Err(WebfingerError::NotFound)
+        );
+    });
+    assert_eq!(
+        no_actor.requests().last().unwrap().url,
+        "https://example.org/.well-known/webfinger?resource=acct:relay@example.org"
+    );
+    assert_eq!(
+        no_actor.requests().len(),
+        1 + fediverse::INSTANCE_ACTOR_NAMES.len()
+    );
+}
+
+#[test]
+fn test_response_format() {
+    assert_eq!(ResponseFormat::negotiate(None, true), ResponseFormat::Jrd);
+    assert_eq!(
+        ResponseFormat::negotiate(Some("application/json"), true),
+        ResponseFormat::Json
+    );
+    assert_eq!(
+        ResponseFormat::negotiate(Some("application/json, application/jrd+json"), true),
+        ResponseFormat::Jrd
+    );
+    assert_eq!(
+        ResponseFormat::negotiate(Some("application/jrd+json;q=0.5, application/json"), true),
+        ResponseFormat::Json
+    );
+    assert_eq!(
+        ResponseFormat::negotiate(Some("application/xrd+xml"), true),
+        ResponseFormat::Xrd
+    );
+    assert_eq!(
+        ResponseFormat::negotiate(Some("application/xrd+xml"), false),
+        ResponseFormat::Jrd
+    );
+    assert_eq!(
+        ResponseFormat::negotiate(Some("*/*"), true),
+        ResponseFormat::Jrd
+    );
+    assert_eq!(
+        ResponseFormat::negotiate(Some("text/html"), true),
+        ResponseFormat::Jrd
+    );
+
+    let webfinger = Webfinger {
+        subject: "acct:test@example.org".to_string(),
+        aliases: vec!["https://example.org/@test?a=1&b=2".to_string()],
+        links: vec![Link {
+            rel: "self".into(),
+            mime_type: Some("application/activity+json".to_string()),
+            href: Some("https://example.org/@test/".to_string()),
+            template: None,
+            titles: HashMap::new(),
+            properties: HashMap::new(),
+            extra: Map::new(),
+        }],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    };
+    let (content_type, body) = ResponseFormat::Json.render(&webfinger);
+    assert_eq!(content_type, "application/json");
+    assert_eq!(serde_json::from_str::<Webfinger>(&body).unwrap(), webfinger);
+
+    let (content_type, body) = ResponseFormat::Xrd.render(&webfinger);
+    assert_eq!(content_type, "application/xrd+xml");
+    assert!(body.contains("<Subject>acct:test@example.org</Subject>"));
+    assert!(body.contains("<Alias>https://example.org/@test?a=1&amp;b=2</Alias>"));
+    assert!(body.contains(
+        "<Link rel=\"self\" type=\"application/activity+json\" href=\"https://example.org/@test/\"/>"
+    ));
+}
+
+#[test]
+fn test_base_url() {
+    let base = BaseUrl::parse("https://example.org:8443/app?x=1").unwrap();
+    assert_eq!(base.domain(), "example.org:8443");
+    assert_eq!(base.path_prefix(), "/app/");
+    assert_eq!(
+        base.url_for("/@/test"),
+        "https://example.org:8443/app/@/test"
+    );
+    assert_eq!(base.endpoint_path(), "/app/.well-known/webfinger");
+    assert_eq!(
+        base.endpoint_url(),
+        "https://example.org:8443/.well-known/webfinger"
+    );
+    assert!(base
+        .host_meta()
+        .contains("template=\"https://example.org:8443/.well-known/webfinger?resource={uri}\""));
+
+    let root = BaseUrl::parse("https://example.org").unwrap();
+    assert_eq!(root.domain(), "example.org");
+    assert_eq!(root.endpoint_path(), "/.well-known/webfinger");
+    assert_eq!(root.url_for("users/test"), "https://example.org/users/test");
+
+    assert!(BaseUrl::parse("example.org/app").is_err());
+    assert!(BaseUrl::parse("mailto:admin@example.org").is_err());
+}
+
+#[test]
+fn test_expires() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let mut webfinger: Webfinger = serde_json::from_str(
+        r#"{"subject": "acct:test@example.org", "links": [], "expires": "2020-02-29T12:30:15.5+01:00"}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        webfinger.expires_at(),
+        Some(UNIX_EPOCH + Duration::from_millis(1_582_975_815_500))
+    );
+    webfinger.set_expires_at(UNIX_EPOCH + Duration::from_secs(1_582_975_815));
+    assert_eq!(webfinger.expires.as_deref(), Some("2020-02-29T11:30:15Z"));
+    assert!(serde_json::to_string(&webfinger)
+        .unwrap()
+        .contains(r#""expires":"2020-02-29T11:30:15Z""#));
+    let (_, xrd) = ResponseFormat::Xrd.render(&webfinger);
+    assert!(xrd.contains(
+        "  <Expires>2020-02-29T11:30:15Z</Expires>\n  <Subject>acct:test@example.org</Subject>\n"
+    ));
+
+    webfinger.expires = Some("tomorrow".into());
+    assert_eq!(webfinger.expires_at(), None);
+    webfinger.expires = None;
+    assert!(!serde_json::to_string(&webfinger)
+        .unwrap()
+        .contains("expires"));
+    assert!(!ResponseFormat::Xrd.render(&webfinger).1.contains("Expires"));
+
+    let r = Runtime::new().unwrap();
+    let fetch_twice = |fetch: Arc<FakeFetch>| {
+        let client = WebfingerClient::builder()
+            .http_fetch(fetch.clone())
+            .cache(Duration::from_secs(60))
+            .build();
+        r.block_on(async {
+            assert!(client.resolve("test@example.org").await.is_ok());
+            assert!(client.resolve("test@example.org").await.is_ok());
+        });
+        fetch.requests().len()
+    };
+
+    let expired =
+        r#"{"subject": "acct:test@example.org", "links": [], "expires": "2000-01-01T00:00:00Z"}"#;
+    let fresh =
+        r#"{"subject": "acct:test@example.org", "links": [], "expires": "3000-01-01T00:00:00Z"}"#;
+    assert_eq!(fetch_twice(Arc::new(FakeFetch::new(200, fresh))), 1);
+    assert_eq!(fetch_twice(Arc::new(FakeFetch::new(200, expired))), 2);
+    // Cache-Control takes precedence over the expires field
+    assert_eq!(
+        fetch_twice(Arc::new(
+            FakeFetch::new(200, expired).with_header("Cache-Control", "public, max-age=60")
+        )),
+        1
+    );
+    assert_eq!(
+        fetch_twice(Arc::new(
+            FakeFetch::new(200, fresh).with_header("Cache-Control", "no-store")
+        )),
+        2
+    );
+}
+
+#[test]
+fn test_typed_links() {
+    let atom = Link::atom("https://example.org/@test/feed.atom").unwrap();
+    assert_eq!(atom.rel, "http://schemas.google.com/g/2010#updates-from");
+    assert_eq!(atom.mime_type.as_deref(), Some("application/atom+xml"));
+    assert_eq!(
+        atom.href.as_deref(),
+        Some("https://example.org/@test/feed.atom")
+    );
+
+    let rss = Link::rss("http://example.org/feed.rss").unwrap();
+    assert_eq!(rss.mime_type.as_deref(), Some("application/rss+xml"));
+
+    let actor = Link::activity_stream("https://example.org/users/test").unwrap();
+    assert_eq!(actor.rel, "self");
+    assert_eq!(
+        actor.mime_type.as_deref(),
+        Some("application/activity+json")
+    );
+
+    assert_eq!(Link::atom("/feed.atom"), Err(LinkError::InvalidUrl));
+    assert_eq!(
+        Link::rss("ftp://example.org/feed.rss"),
+        Err(LinkError::UnsupportedScheme("ftp".into()))
+    );
+}
+
+#[test]
+fn test_transform_responses() {
+    let resolver = TransformResponses::new(MyResolver)
+        .with(AddLinks(vec![Link {
+            rel: "payment".into(),
+            mime_type: None,
+            href: Some("https://instance.tld/donate".to_string()),
+            template: None,
+            titles: HashMap::new(),
+            properties: HashMap::new(),
+            extra: Map::new(),
+        }]))
+        .with(StripHrefs(vec!["https://instance.tld/@".to_string()]))
+        .with(|mut webfinger: Webfinger| {
+            webfinger.aliases.clear();
+            webfinger
+        });
+
+    let res = resolver
+        .endpoint("acct:admin@instance.tld", "admin")
+        .unwrap();
+    assert!(res.aliases.is_empty());
+    assert_eq!(res.links.len(), 1);
+    assert_eq!(res.links[0].rel, "payment");
+    assert_eq!(
+        resolver.endpoint("acct:test@instance.tld", "admin"),
+        Err(ResolverError::NotFound)
+    );
+}
+
+#[test]
+fn test_host_header() {
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:test@example.org", "links": []}"#,
+    ));
+    let client = WebfingerClient::builder()
+        .http_fetch(fetch.clone())
+        .host_header("example.org", "webfinger.internal")
+        .build();
+    r.block_on(async {
+        assert!(client.resolve("test@example.org").await.is_ok());
+        assert!(client.resolve("test@example.com").await.is_ok());
+    });
+    let requests = fetch.requests();
+    let host = ("Host".to_string(), "webfinger.internal".to_string());
+    assert!(requests[0].headers.contains(&host));
+    assert!(!requests[1].headers.contains(&host));
+
+    let config: ClientConfig = serde_json::from_str(
+        r#"{"domains": {"example.org": {"connect_to": "10.0.0.1", "host_header": "internal"}}}"#,
+    )
+    .unwrap();
+    let domain = &config.domains["example.org"];
+    assert_eq!(domain.connect_to, Some([10, 0, 0, 1].into()));
+    assert_eq!(domain.host_header.as_deref(), Some("internal"));
+}
+
+/// Succeeds for the first request, then fails with a network error.
+struct FailsAfterFirst(Mutex<bool>);
+
+impl HttpFetch for FailsAfterFirst {
+    fn fetch(&self, _: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        let first = std::mem::replace(&mut *self.0.lock().unwrap(), false);
+        async move {
+            if first {
+                Ok(HttpResponse {
+                    status: 200,
+                    headers: vec![],
+                    body: br#"{"subject": "acct:test@example.org", "links": []}"#.to_vec(),
+                    timings: Timings::default(),
+                })
+            } else {
+                Err(WebfingerError::HttpError)
+            }
+        }
+        .boxed()
+    }
+}
+
+#[test]
+fn test_serve_stale() {
+    use std::time::Duration;
+
+    let r = Runtime::new().unwrap();
+    let options = RequestOptions::default();
+    let stale_client = WebfingerClient::builder()
+        .http_fetch(FailsAfterFirst(Mutex::new(true)))
+        .cache(Duration::from_millis(20))
+        .serve_stale(Duration::from_secs(60))
+        .build();
+    let client = WebfingerClient::builder()
+        .http_fetch(FailsAfterFirst(Mutex::new(true)))
+        .cache(Duration::from_millis(20))
+        .build();
+    r.block_on(async {
+        let res = stale_client
+            .resolve_detailed("test@example.org", &options)
+            .await
+            .unwrap();
+        assert_eq!(res.metadata.freshness, Freshness::Fresh);
+        assert!(client.resolve("test@example.org").await.is_ok());
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let res = stale_client
+            .resolve_detailed("test@example.org", &options)
+            .await
+            .unwrap();
+        assert_eq!(res.metadata.freshness, Freshness::Stale);
+        assert_eq!(res.document.subject, "acct:test@example.org");
+        assert_eq!(
+            stale_client
+                .refresh("test@example.org")
+                .await
+                .map(|r| r.metadata.freshness),
+            Ok(Freshness::Stale)
+        );
+        assert_eq!(
+            client.resolve("test@example.org").await,
+            Err(WebfingerError::HttpError)
+        );
+    });
+}
+
+#[test]
+fn test_serve_stale_forever() {
+    use std::time::Duration;
+
+    let r = Runtime::new().unwrap();
+    let config: ClientConfig =
+        serde_json::from_str(&format!(r#"{{"max_staleness": {}}}"#, u64::MAX)).unwrap();
+    let client = WebfingerClient::builder()
+        .http_fetch(FailsAfterFirst(Mutex::new(true)))
+        .cache(Duration::from_millis(20))
+        .config(&config)
+        .build();
+    r.block_on(async {
+        assert!(client.resolve("test@example.org").await.is_ok());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let res = client
+            .resolve_detailed("test@example.org", &RequestOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(res.metadata.freshness, Freshness::Stale);
+    });
+}
+
+#[test]
+fn test_cache_without_expiration() {
+    use std::time::Duration;
+
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(404, ""));
+    let config: ClientConfig =
+        serde_json::from_str(&format!(r#"{{"cache_ttl": {}}}"#, u64::MAX)).unwrap();
+    let client = WebfingerClient::builder()
+        .http_fetch(FailsAfterFirst(Mutex::new(true)))
+        .config(&config)
+        .build();
+    let negative_client = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .negative_cache(Duration::MAX)
+        .build();
+    r.block_on(async {
+        assert!(client.resolve("test@example.org").await.is_ok());
+        assert!(client.resolve("test@example.org").await.is_ok());
+
+        for _ in 0..2 {
+            assert_eq!(
+                negative_client.resolve("test@example.org").await,
+                Err(WebfingerError::NotFound)
+            );
+        }
+    });
+    assert_eq!(fetch.requests().len(), 1);
+    assert_eq!(client.cache_stats().entries, 1);
+}
+
+#[test]
+fn test_recorder() {
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(
+        FakeFetch::new(200, r#"{"subject": "acct:test@example.org", "links": []}"#)
+            .with_header("Set-Cookie", "session=abc"),
+    );
+    let recorder = Recorder::new();
+    let client = WebfingerClient::builder()
+        .http_fetch(fetch.clone())
+        .header("Authorization", "Bearer abc")
+        .record(recorder.clone())
+        .build();
+    r.block_on(async {
+        assert!(client.resolve("test@example.org").await.is_ok());
+    });
+
+    let entries = recorder.entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(
+        entries[0].request.url,
+        "https://example.org/.well-known/webfinger?resource=acct:test@example.org"
+    );
+    assert!(entries[0].request.headers.contains(&RecordedHeader {
+        name: "Authorization".to_string(),
+        value: "[redacted]".to_string(),
+    }));
+    let response = entries[0].response.as_ref().unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body_size, 49);
+    assert_eq!(response.headers[1].value, "[redacted]");
+
+    let json = recorder.to_json();
+    assert_eq!(json["log"]["entries"][0]["response"]["bodySize"], 49);
+    assert!(json["log"]["entries"][0]["startedDateTime"].is_string());
+    recorder.clear();
+    assert!(recorder.entries().is_empty());
+}
+
+#[test]
+fn test_consts() {
+    let url = url_for(Prefix::Acct, "test@example.org", true).unwrap();
+    assert!(url.starts_with(&format!("https://example.org{}?", consts::WELL_KNOWN_PATH)));
+    assert_eq!(ResponseFormat::Jrd.content_type(), consts::JRD_MIME);
+    assert_eq!(ResponseFormat::Xrd.content_type(), consts::XRD_MIME);
+    assert_eq!(
+        Rel::ProfilePage.as_str(),
+        consts::WEBFINGER_REL_PROFILE_PAGE
+    );
+    assert_eq!(Rel::Avatar.as_str(), consts::WEBFINGER_REL_AVATAR);
+}
+
+#[test]
+fn test_instance_domain() {
+    let domain = InstanceDomain::new("Example.ORG").unwrap();
+    assert_eq!(domain, "example.org");
+    assert_eq!(domain.port(), None);
+    let domain: InstanceDomain = "example.org:8080".parse().unwrap();
+    assert_eq!(domain.domain(), "example.org");
+    assert_eq!(domain.port(), Some(8080));
+    assert_eq!(domain.to_string(), "example.org:8080");
+    assert_eq!(
+        InstanceDomain::new("bücher.example").unwrap(),
+        "xn--bcher-kva.example"
+    );
+    assert_eq!(InstanceDomain::new("[::1]:80").unwrap(), "[::1]:80");
+
+    assert_eq!(InstanceDomain::new(""), Err(InstanceDomainError::Empty));
+    assert_eq!(
+        InstanceDomain::new("https://example.org"),
+        Err(InstanceDomainError::HasScheme)
+    );
+    assert_eq!(
+        InstanceDomain::new("example.org/"),
+        Err(InstanceDomainError::HasPath)
+    );
+    assert_eq!(
+        InstanceDomain::new("example.org:http"),
+        Err(InstanceDomainError::InvalidPort)
+    );
+    assert_eq!(
+        InstanceDomain::new("exa mple.org"),
+        Err(InstanceDomainError::InvalidHost)
+    );
+    assert!(serde_json::from_str::<InstanceDomain>(r#""https://example.org""#).is_err());
+
+    // Resources are compared to the instance domain after normalization
+    let resolver = MyResolver;
+    assert!(resolver
+        .endpoint("acct:admin@INSTANCE.tld", "admin")
+        .is_ok());
+
+    assert!(WebfingerClient::builder()
+        .block_domain("https://spam.example/")
+        .try_build()
+        .is_err());
+
+    // These settings apply to all the ports of a domain
+    let ip = "192.0.2.1".parse().unwrap();
+    assert!(WebfingerClient::builder()
+        .connect_to("example.org", ip)
+        .try_build()
+        .is_ok());
+    assert_eq!(
+        WebfingerClient::builder()
+            .connect_to("example.org:8443", ip)
+            .try_build()
+            .err(),
+        Some(WebfingerError::ParseError)
+    );
+    let config: ClientConfig =
+        serde_json::from_str(r#"{"domains": {"example.org:8443": {"connect_to": "192.0.2.1"}}}"#)
+            .unwrap();
+    assert_eq!(
+        WebfingerClient::builder().config(&config).try_build().err(),
+        Some(WebfingerError::ParseError)
+    );
+}
+
+#[test]
+fn test_described_by() {
+    let webfinger: Webfinger = serde_json::from_str(
+        r#"{
+            "subject": "acct:test@example.org",
+            "links": [
+                {"rel": "describedby", "type": "application/rdf+xml", "href": "https://example.org/test.rdf"},
+                {"rel": "describedby", "type": "application/json; charset=utf-8", "href": "https://example.org/test.json"},
+                {"rel": "http://www.w3.org/2000/01/rdf-schema#seeAlso", "href": "https://other.example/test"}
+            ]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        webfinger.described_by().and_then(|l| l.href.as_deref()),
+        Some("https://example.org/test.rdf")
+    );
+    assert_eq!(
+        webfinger
+            .described_by_type(&["application/ld+json", "application/json"])
+            .and_then(|l| l.href.as_deref()),
+        Some("https://example.org/test.json")
+    );
+    assert_eq!(webfinger.described_by_type(&["text/turtle"]), None);
+    assert_eq!(
+        webfinger.see_also().first_href(),
+        Some("https://other.example/test")
+    );
+    assert_eq!(Rel::from("describedby"), Rel::DescribedBy);
+
+    let link = Link::described_by("https://example.org/test.json", "application/json").unwrap();
+    assert_eq!(link.rel, "describedby");
+    assert!(Link::described_by("test.json", "application/json").is_err());
+}
+
+/// A resolver borrowing the list of known users.
+#[cfg(feature = "async")]
+struct MyRefResolver;
+
+#[cfg(feature = "async")]
+impl AsyncRefResolver for MyRefResolver {
+    type Repo = [&'static str];
+
+    async fn instance_domain(&self) -> InstanceDomain {
+        InstanceDomain::new("instance.tld").unwrap()
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        users: &[&'static str],
+    ) -> Result<Webfinger, ResolverError> {
+        if prefix == Prefix::Acct && users.contains(&acct.as_str()) {
+            Ok(Webfinger {
+                subject: format!("acct:{}@instance.tld", acct),
+                aliases: vec![],
+                links: vec![],
+                properties: HashMap::new(),
+                expires: None,
+                extra: Map::new(),
+            })
+        } else {
+            Err(ResolverError::NotFound)
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_async_ref_resolver() {
+    let users = ["admin", "test"];
+    let r = Runtime::new().unwrap();
+    r.block_on(async {
+        let resolver = MyRefResolver;
+        assert!(resolver
+            .endpoint("acct:test@instance.tld", &users[..])
+            .await
+            .is_ok());
+        assert_eq!(
+            resolver
+                .endpoint("acct:nobody@instance.tld", &users[..])
+                .await,
+            Err(ResolverError::NotFound)
+        );
+        assert_eq!(
+            resolver.endpoint("acct:test@oops.ie", &users[..]).await,
+            Err(ResolverError::WrongDomain)
+        );
+
+        // Failures are reported for each resource, without failing the whole batch
+        let results = resolver
+            .endpoint_batch(
+                &[
+                    "acct:test@instance.tld",
+                    "acct:nobody@instance.tld",
+                    "acct:test@oops.ie",
+                    "",
+                ],
+                &["self"],
+                &users[..],
+            )
+            .await;
+        assert_eq!(results.len(), 4);
+        assert_eq!(
+            results["acct:test@instance.tld"].as_ref().unwrap().subject,
+            "acct:test@instance.tld"
+        );
+        assert_eq!(
+            results["acct:nobody@instance.tld"],
+            Err(ResolverError::NotFound)
+        );
+        assert_eq!(
+            results["acct:test@oops.ie"],
+            Err(ResolverError::WrongDomain)
+        );
+        assert_eq!(results[""], Err(ResolverError::MissingResource));
+
+        let shim = CloneRepo::new(MyAsyncResolver);
+        assert!(shim
+            .endpoint("acct:admin@instance.tld", &"admin")
+            .await
+            .is_ok());
+        assert_eq!(
+            shim.endpoint("acct:test@instance.tld", &"admin").await,
+            Err(ResolverError::NotFound)
+        );
+    });
+}
+
+/// A resolver borrowing the list of known users, and giving them a profile page and an actor.
+#[cfg(feature = "async")]
+struct LinkedRefResolver {
+    filter_by_rels: bool,
+}
+
+#[cfg(feature = "async")]
+impl AsyncRefResolver for LinkedRefResolver {
+    type Repo = [&'static str];
+
+    async fn instance_domain(&self) -> InstanceDomain {
+        InstanceDomain::new("instance.tld").unwrap()
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        users: &[&'static str],
+    ) -> Result<Webfinger, ResolverError> {
+        if prefix == Prefix::Acct && users.contains(&acct.as_str()) {
+            Ok(Webfinger::for_account(
+                format!("{}@instance.tld", acct),
+                format!("https://instance.tld/users/{}", acct),
+                format!("https://instance.tld/@{}", acct),
+            ))
+        } else {
+            Err(ResolverError::NotFound)
+        }
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.filter_by_rels
+    }
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_async_ref_resolver_batch_rels() {
+    let users = ["admin", "test"];
+    let resources = ["acct:admin@instance.tld", "acct:nobody@instance.tld"];
+    let r = Runtime::new().unwrap();
+    r.block_on(async {
+        let rels = |results: &HashMap<String, Result<Webfinger, ResolverError>>| {
+            let links = &results["acct:admin@instance.tld"].as_ref().unwrap().links;
+            links
+                .iter()
+                .map(|link| link.rel.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let filtering = LinkedRefResolver {
+            filter_by_rels: true,
+        };
+        let results = filtering
+            .endpoint_batch(&resources, &["self"], &users[..])
+            .await;
+        assert_eq!(rels(&results), vec!["self"]);
+        assert_eq!(
+            results["acct:nobody@instance.tld"],
+            Err(ResolverError::NotFound)
+        );
+        // Without rels, all the links are kept
+        let results = filtering.endpoint_batch(&resources, &[], &users[..]).await;
+        assert_eq!(rels(&results).len(), 2);
+
+        let unfiltered = LinkedRefResolver {
+            filter_by_rels: false,
+        };
+        let results = unfiltered
+            .endpoint_batch(&resources, &["self"], &users[..])
+            .await;
+        assert_eq!(
+            rels(&results),
+            vec!["http://webfinger.net/rel/profile-page", "self"]
+        );
+        assert_eq!(
+            results["acct:nobody@instance.tld"],
+            Err(ResolverError::NotFound)
+        );
+    });
+}
+
+#[test]
+fn test_prefix_validity() {
+    assert_eq!(Prefix::parse("did"), Ok(Prefix::Custom("did".to_string())));
+    assert_eq!(Prefix::parse(""), Err(WebfingerError::ParseError));
+    assert!(Prefix::Acct.is_valid());
+    assert!(Prefix::from("did").is_valid());
+    // The infallible conversion is still available, but its result can be checked
+    assert_eq!(Prefix::from(""), Prefix::Custom(String::new()));
+    assert!(!Prefix::from("").is_valid());
+    assert!(!Prefix::from("ht tp").is_valid());
+    assert_eq!(
+        url_for(Prefix::from(""), "test@example.org", true),
+        Err(WebfingerError::ParseError)
+    );
+}
+
+#[test]
+fn test_proxy() {
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:test@example.org", "links": [
+            {"rel": "self", "type": "application/activity+json", "href": "https://example.org/users/test"},
+            {"rel": "http://webfinger.net/rel/avatar", "href": "https://example.org/avatar.png"}
+        ]}"#,
+    ));
+    let client = WebfingerClient::builder().http_fetch(fetch.clone()).build();
+    let proxy = WebfingerProxy::new(client)
+        .allow_domain("example.org")
+        .deny_domain("spam.example.org");
+
+    r.block_on(async {
+        let res = proxy
+            .respond("acct:test@example.org", &["self"], None)
+            .await;
+        assert_eq!(res.status, 200);
+        assert!(res
+            .headers
+            .contains(&("Access-Control-Allow-Origin".to_string(), "*".to_string())));
+        assert!(res.headers.contains(&(
+            "Content-Type".to_string(),
+            "application/jrd+json".to_string()
+        )));
+        let webfinger: Webfinger = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(webfinger.links.len(), 1);
+
+        assert_eq!(
+            proxy
+                .resolve("acct:test@social.example.org", &[])
+                .await
+                .map(|w| w.links.len()),
+            Ok(2)
+        );
+        assert_eq!(
+            proxy.resolve("acct:test@spam.example.org", &[]).await,
+            Err(ProxyError::Denied)
+        );
+
+        let open =
+            WebfingerProxy::new(WebfingerClient::builder().http_fetch(fetch.clone()).build());
+        for resource in &[
+            "acct:test@127.0.0.1",
+            "acct:test@0x7f.1",
+            "acct:test@[::1]:8080",
+            "acct:test@10.0.0.1",
+            "acct:test@localhost",
+            "acct:test@db.localhost",
+            "acct:test@intranet",
+            "acct:test@printer.local",
+            "acct:test@metadata.google.internal",
+            "acct:test@example.org:22",
+            "acct:test@example.org:80",
+        ] {
+            assert_eq!(
+                open.resolve(resource, &[]).await,
+                Err(ProxyError::Denied),
+                "{}",
+                resource
+            );
+        }
+        assert!(open
+            .resolve("acct:test@example.local.org", &[])
+            .await
+            .is_ok());
+        assert!(open.resolve("acct:test@example.org:443", &[]).await.is_ok());
+        let plain = WebfingerProxy::new(
+            WebfingerClient::builder()
+                .http_fetch(fetch.clone())
+                .https(false)
+                .build(),
+        );
+        assert!(plain.resolve("acct:test@example.org:80", &[]).await.is_ok());
+        assert_eq!(
+            plain.resolve("acct:test@example.org:8080", &[]).await,
+            Err(ProxyError::Denied)
+        );
+        assert!(open
+            .clone()
+            .allow_local_hosts()
+            .resolve("acct:test@10.0.0.1", &[])
+            .await
+            .is_ok());
+        assert_eq!(
+            proxy.resolve("acct:test@example.com", &[]).await,
+            Err(ProxyError::Denied)
+        );
+        assert_eq!(proxy.respond("test", &[], None).await.status, 400);
+    });
+    assert_eq!(fetch.requests().len(), 6);
+    assert_eq!(ProxyError::Upstream(WebfingerError::NotFound).status(), 404);
+}
+
+/// Never answers the first request, and answers the next ones immediately.
+#[cfg(feature = "tokio")]
+struct HangsFirst(Mutex<u32>);
+
+#[cfg(feature = "tokio")]
+impl HttpFetch for HangsFirst {
+    fn fetch(&self, _: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        let count = {
+            let mut count = self.0.lock().unwrap();
+            *count += 1;
+            *count
+        };
+        async move {
+            if count == 1 {
+                futures_util::future::pending::<()>().await;
+            }
+            Ok(HttpResponse {
+                status: 200,
+                headers: vec![],
+                body: br#"{"subject": "acct:test@example.org", "links": []}"#.to_vec(),
+                timings: Timings::default(),
+            })
+        }
+        .boxed()
+    }
+}
+
+#[test]
+#[cfg(feature = "tokio")]
+fn test_hedge() {
+    use std::time::Duration;
+
+    let r = Runtime::new().unwrap();
+    let client = WebfingerClient::builder()
+        .http_fetch(HangsFirst(Mutex::new(0)))
+        .hedge(0.95, Duration::from_millis(10))
+        .build();
+    r.block_on(async {
+        let res = tokio::time::timeout(Duration::from_secs(5), client.resolve("test@example.org"))
+            .await
+            .expect("the hedged request should answer");
+        assert_eq!(res.unwrap().subject, "acct:test@example.org");
+    });
+}
+
+/// Fails the first request after a while, and answers the other ones after a longer while.
+#[cfg(feature = "tokio")]
+struct FailsFirst(Mutex<u32>);
+
+#[cfg(feature = "tokio")]
+impl HttpFetch for FailsFirst {
+    fn fetch(&self, _: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        let count = {
+            let mut count = self.0.lock().unwrap();
+            *count += 1;
+            *count
+        };
+        async move {
+            if count == 1 {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                return Err(WebfingerError::HttpError);
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            Ok(HttpResponse {
+                status: 200,
+                headers: vec![],
+                body: br#"{"subject": "acct:test@example.org", "links": []}"#.to_vec(),
+                timings: Timings::default(),
+            })
+        }
+        .boxed()
+    }
+}
+
+#[test]
+#[cfg(feature = "tokio")]
+fn test_hedge_failures() {
+    use std::time::Duration;
+
+    // The hedged request answers after the first one failed
+    let r = Runtime::new().unwrap();
+    let client = WebfingerClient::builder()
+        .http_fetch(FailsFirst(Mutex::new(0)))
+        .retries(0)
+        .hedge(0.95, Duration::from_millis(10))
+        .build();
+    r.block_on(async {
+        let res = client.resolve("test@example.org").await;
+        assert_eq!(res.unwrap().subject, "acct:test@example.org");
+    });
+
+    // Latencies are kept by host
+    let hedge = crate::Hedge::new(0.5, Duration::from_millis(10));
+    for _ in 0..10 {
+        hedge.record("slow.example", Duration::from_secs(2));
+    }
+    assert_eq!(hedge.delay("slow.example"), Duration::from_secs(2));
+    assert_eq!(hedge.delay("example.org"), Duration::from_millis(10));
+}
+
+#[test]
+fn test_config_from_env() {
+    use std::time::Duration;
+
+    let vars = |vars: &'static [(&'static str, &'static str)]| {
+        move |name: &str| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    };
+    assert_eq!(ClientConfig::from_vars(vars(&[])), ClientConfig::default());
+
+    let config = ClientConfig::from_vars(vars(&[
+        ("WEBFINGER_TIMEOUT", "12"),
+        ("WEBFINGER_PROXY", "http://proxy.local:3128"),
+        ("WEBFINGER_USER_AGENT", "MyInstance/1.0"),
+        ("WEBFINGER_INSECURE_HTTP", "true"),
+    ]));
+    assert_eq!(config.https, Some(false));
+    assert_eq!(config.timeout, Some(12));
+    assert_eq!(config.proxy.as_deref(), Some("http://proxy.local:3128"));
+    assert_eq!(config.user_agent.as_deref(), Some("MyInstance/1.0"));
+
+    let config = ClientConfig::from_vars(vars(&[
+        ("WEBFINGER_TIMEOUT", "soon"),
+        ("WEBFINGER_PROXY", ""),
+        ("WEBFINGER_INSECURE_HTTP", "0"),
+    ]));
+    assert_eq!(config, ClientConfig::default());
+    let config = ClientConfig::from_vars(vars(&[("WEBFINGER_PROXY", "not a proxy")]));
+    assert_eq!(config.proxy, None);
+    assert!(WebfingerClient::builder()
+        .config(&config)
+        .try_build()
+        .is_ok());
+
+    // explicit builder settings take precedence
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:test@example.org", "links": []}"#,
+    ));
+    let client = WebfingerClient::builder()
+        .config(&ClientConfig::from_vars(vars(&[
+            ("WEBFINGER_USER_AGENT", "FromEnv/1.0"),
+            ("WEBFINGER_INSECURE_HTTP", "yes"),
+        ])))
+        .user_agent("Explicit/1.0")
+        .https(true)
+        .timeout(Duration::from_secs(5))
+        .http_fetch(fetch.clone())
+        .build();
+    r.block_on(client.resolve("test@example.org")).unwrap();
+    let request = &fetch.requests()[0];
+    assert!(request.url.starts_with("https://"));
+    let agents: Vec<_> = request
+        .headers
+        .iter()
+        .filter(|(name, _)| name == "User-Agent")
+        .map(|(_, value)| value.as_str())
+        .collect();
+    assert_eq!(agents, vec!["Explicit/1.0"]);
+}
+
+#[test]
+fn test_config_override_order() {
+    let r = Runtime::new().unwrap();
+    let request = |builder: WebfingerClientBuilder| {
+        let fetch = Arc::new(FakeFetch::new(
+            200,
+            r#"{"subject": "acct:test@example.org", "links": []}"#,
+        ));
+        let client = builder.shared_http_fetch(fetch.clone()).build();
+        r.block_on(client.resolve("test@example.org")).unwrap();
+        let request = fetch.requests().remove(0);
+        let request_id = request
+            .headers
+            .iter()
+            .any(|(name, _)| name == "X-Request-Id");
+        (request.url.starts_with("https://"), request_id)
+    };
+
+    // Settings missing from the configuration are left unchanged
+    let empty = ClientConfig {
+        user_agent: Some("Test/1.0".to_string()),
+        ..ClientConfig::default()
+    };
+    let builder = WebfingerClient::builder()
+        .https(false)
+        .request_ids(true)
+        .config(&empty);
+    assert_eq!(request(builder), (false, true));
+
+    // The last setting wins, wether it comes from the configuration or from the builder
+    let config = ClientConfig {
+        https: Some(true),
+        request_ids: Some(false),
+        ..ClientConfig::default()
+    };
+    let builder = WebfingerClient::builder()
+        .https(false)
+        .request_ids(true)
+        .config(&config);
+    assert_eq!(request(builder), (true, false));
+    let builder = WebfingerClient::builder().config(&config).https(false);
+    assert_eq!(request(builder), (false, false));
+}
+
+#[test]
+fn test_preferred_link() {
+    let json = br#"{
+        "subject": "acct:test@example.org",
+        "links": [
+            {"rel": "self", "type": "text/html", "href": "https://example.org/1"},
+            {"rel": "self", "type": "bad", "href": 42},
+            {"rel": "self", "type": "application/activity+json", "href": "https://example.org/2"},
+            {"rel": "self", "type": "application/ld+json", "href": "https://example.org/3"},
+            {"rel": "self", "type": "application/activity+json", "href": "https://example.org/4"}
+        ]
+    }"#;
+    let (mut webfinger, issues) = Webfinger::from_json_lenient(json).unwrap();
+    assert_eq!(issues.len(), 1);
+    webfinger.merge(Webfinger {
+        subject: "acct:test@example.org".into(),
+        aliases: vec![],
+        links: vec![Link {
+            rel: "self".into(),
+            mime_type: Some("application/activity+json".into()),
+            href: Some("https://example.org/5".into()),
+            template: None,
+            titles: HashMap::new(),
+            properties: HashMap::new(),
+            extra: Map::new(),
+        }],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    });
+    let reparsed: Webfinger =
+        serde_json::from_str(&serde_json::to_string(&webfinger).unwrap()).unwrap();
+    let hrefs: Vec<_> = reparsed.links().filter_map(|l| l.href.as_deref()).collect();
+    assert_eq!(
+        hrefs,
+        vec![
+            "https://example.org/1",
+            "https://example.org/2",
+            "https://example.org/3",
+            "https://example.org/4",
+            "https://example.org/5"
+        ]
+    );
+
+    let href = |link: Option<&Link>| link.and_then(|l| l.href.clone());
+    assert_eq!(
+        href(reparsed.preferred_link(
+            Rel::SelfRel,
+            &["application/ld+json", "application/activity+json"]
+        )),
+        Some("https://example.org/3".into())
+    );
+    assert_eq!(
+        href(reparsed.preferred_link("self", &["application/activity+json"])),
+        Some("https://example.org/2".into())
+    );
+    assert_eq!(
+        href(reparsed.preferred_link("self", &["image/png"])),
+        Some("https://example.org/1".into())
+    );
+    assert_eq!(reparsed.preferred_link("avatar", &[]), None);
+}
+
+#[test]
+fn test_endpoint_batch() {
+    let resources = [
+        "acct:admin@instance.tld",
+        "acct:test@instance.tld",
+        "acct:admin@oops.ie",
+    ];
+    let results = MyResolver.endpoint_batch(&resources, &["self"], "admin");
+    assert_eq!(results.len(), 3);
+    let admin = results["acct:admin@instance.tld"].as_ref().unwrap();
+    assert_eq!(admin.subject, "admin");
+    assert!(admin.links.is_empty());
+    assert_eq!(
+        results["acct:test@instance.tld"],
+        Err(ResolverError::NotFound)
+    );
+    assert_eq!(
+        results["acct:admin@oops.ie"],
+        Err(ResolverError::WrongDomain)
+    );
+
+    #[cfg(feature = "async")]
+    {
+        let r = Runtime::new().unwrap();
+        let async_results =
+            r.block_on(MyAsyncResolver.endpoint_batch(&resources, &["self"], "admin"));
+        assert_eq!(async_results, results);
+        let ref_results = r.block_on(CloneRepo::new(MyAsyncResolver).endpoint_batch(
+            &resources,
+            &["self"],
+            &"admin",
+        ));
+        assert_eq!(ref_results, results);
+    }
+}
+
+#[test]
+fn test_validate_template() {
+    let subscribe = |template: Option<&str>| Link {
+        rel: Rel::Subscribe.as_str().into(),
+        href: None,
+        template: template.map(String::from),
+        mime_type: None,
+        titles: HashMap::new(),
+        properties: HashMap::new(),
+        extra: Map::new(),
+    };
+    assert_eq!(
+        subscribe(Some("https://example.org/follow?acct={uri}")).validate_template(),
+        vec![]
+    );
+    assert_eq!(
+        subscribe(None).validate_template(),
+        vec![TemplateIssue::MissingTemplate]
+    );
+    assert_eq!(
+        subscribe(Some("https://example.org/follow")).validate_template(),
+        vec![TemplateIssue::MissingUri]
+    );
+    assert_eq!(
+        subscribe(Some("https://example.org/{uri}?acct={uri}&lang={lang}")).validate_template(),
+        vec![
+            TemplateIssue::MultipleUris(2),
+            TemplateIssue::UnknownPlaceholder("lang".into())
+        ]
+    );
+    assert_eq!(
+        subscribe(Some("https://example.org/follow?acct={uri")).validate_template(),
+        vec![TemplateIssue::UnbalancedBraces]
+    );
+    let lrdd = Link {
+        rel: "lrdd".into(),
+        href: None,
+        template: Some("https://example.org/.well-known/webfinger?resource={uri}}".into()),
+        mime_type: None,
+        titles: HashMap::new(),
+        properties: HashMap::new(),
+        extra: Map::new(),
+    };
+    assert_eq!(
+        lrdd.validate_template(),
+        vec![TemplateIssue::UnbalancedBraces]
+    );
+
+    let mut link = subscribe(Some(
+        "https://example.org/follow?acct=%7BURI%7d&é={ Uri }&x={y}",
+    ));
+    link.normalize_template();
+    assert_eq!(
+        link.template.as_deref(),
+        Some("https://example.org/follow?acct={uri}&é={uri}&x={y}")
+    );
+    assert_eq!(
+        link.validate_template(),
+        vec![
+            TemplateIssue::MultipleUris(2),
+            TemplateIssue::UnknownPlaceholder("y".into())
+        ]
+    );
+}
+
+/// Answers with the document of the account requested in the URL.
+#[cfg(feature = "fediverse")]
+struct RoutedFetch(Vec<(&'static str, Webfinger)>);
+
+#[cfg(feature = "fediverse")]
+impl HttpFetch for RoutedFetch {
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        let document = self
+            .0
+            .iter()
+            .find(|(acct, _)| request.url.ends_with(&format!("resource=acct:{}", acct)))
+            .map(|(_, document)| serde_json::to_vec(document).unwrap());
+        async move {
+            Ok(match document {
+                Some(body) => HttpResponse {
+                    status: 200,
+                    headers: vec![],
+                    body,
+                    timings: Timings::default(),
+                },
+                None => HttpResponse {
+                    status: 404,
+                    headers: vec![],
+                    body: vec![],
+                    timings: Timings::default(),
+                },
+            })
+        }
+        .boxed()
+    }
+}
+
+#[test]
+#[cfg(feature = "fediverse")]
+fn test_resolve_identity() {
+    use crate::fediverse::prelude::*;
+
+    let mut alice = Webfinger::for_account(
+        "alice@example.org",
+        "https://social.example.org/users/alice",
+        "https://social.example.org/@alice",
+    );
+    alice
+        .links
+        .push(Link::atom("https://social.example.org/@alice.atom").unwrap());
+    let impostor = Webfinger::for_account(
+        "alice@example.org",
+        "https://evil.example/users/mallory",
+        "https://evil.example/@mallory",
+    );
+    let fetch = RoutedFetch(vec![
+        ("alice@example.org", alice.clone()),
+        ("alice@social.example.org", alice.clone()),
+        ("mallory@evil.example", impostor),
+    ]);
+    let client = WebfingerClient::builder().http_fetch(fetch).build();
+    let r = Runtime::new().unwrap();
+
+    let identity = r
+        .block_on(client.resolve_identity("@alice@Example.org"))
+        .unwrap();
+    assert_eq!(identity.acct, "alice@example.org");
+    assert_eq!(
+        identity.actor_url.as_deref(),
+        Some("https://social.example.org/users/alice")
+    );
+    assert_eq!(
+        identity.profile_url.as_deref(),
+        Some("https://social.example.org/@alice")
+    );
+    assert_eq!(identity.avatar_url, None);
+    assert_eq!(identity.feeds.len(), 1);
+    assert_eq!(identity.webfinger, alice);
+
+    let identity = r
+        .block_on(client.resolve_identity("alice@social.example.org"))
+        .unwrap();
+    assert_eq!(identity.acct, "alice@example.org");
+
+    assert_eq!(
+        r.block_on(client.resolve_identity("mallory@evil.example")),
+        Err(IdentityError::SubjectMismatch {
+            requested: "mallory@evil.example".into(),
+            subject: "acct:alice@example.org".into(),
+        })
+    );
+    assert_eq!(
+        r.block_on(client.resolve_identity("nobody@example.org")),
+        Err(IdentityError::Webfinger(WebfingerError::NotFound))
+    );
+    assert_eq!(
+        r.block_on(client.resolve_identity("nobody")),
+        Err(IdentityError::Webfinger(WebfingerError::ParseError))
+    );
+}
+
+#[test]
+fn test_matrix_id() {
+    let webfinger: Webfinger = serde_json::from_str(
+        r#"{
+            "subject": "mailto:alice@example.org",
+            "aliases": ["@alias:example.org"],
+            "links": [
+                {"rel": "http://webfinger.net/rel/profile-page", "href": "https://example.org/@alice"},
+                {"rel": "http://example.org/chat", "href": "xmpp:alice@example.org"},
+                {"rel": "http://example.org/chat", "href": "MATRIX:u/other:example.org"},
+                {"rel": "im.vector", "href": "https://matrix.to/#/%40alice%3Aexample.org"},
+                {"rel": "m.identity", "href": "matrix:u/alice:matrix.example.org?action=chat"}
+            ]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        webfinger.matrix_id().as_deref(),
+        Some("@alice:matrix.example.org")
+    );
+    assert_eq!(
+        webfinger.service_links("xmpp").first_href(),
+        Some("xmpp:alice@example.org")
+    );
+    assert_eq!(
+        webfinger.service_links("matrix").first_href(),
+        Some("MATRIX:u/other:example.org")
+    );
+    assert_eq!(webfinger.service_links("mailto").next(), None);
+
+    let mut webfinger = webfinger;
+    webfinger.links.pop();
+    assert_eq!(webfinger.matrix_id().as_deref(), Some("@alice:example.org"));
+    webfinger.links.clear();
+    assert_eq!(webfinger.matrix_id().as_deref(), Some("@alias:example.org"));
+    webfinger.aliases = vec![
+        "@nope".into(),
+        "https://matrix.to/#/!room:example.org".into(),
+    ];
+    assert_eq!(webfinger.matrix_id(), None);
+
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "mailto:bob@example.org", "links": [{"rel": "m.identity", "href": "matrix:u/bob:example.org"}]}"#,
+    ));
+    let client = WebfingerClient::builder().http_fetch(fetch.clone()).build();
+    assert_eq!(
+        r.block_on(client.resolve_matrix_id("bob@example.org")),
+        Ok(Some("@bob:example.org".into()))
+    );
+    assert!(fetch.requests()[0]
+        .url
+        .ends_with("resource=mailto:bob@example.org"));
+}
+
+#[test]
+fn test_prefix_registry() {
+    let registry = PrefixRegistry::new()
+        .register("tag")
+        .and_then(|registry| registry.register_with("xmpp", |id| !id.contains('/')))
+        .and_then(|registry| registry.register_with("acct", |id| id != "root"))
+        .unwrap();
+    assert_eq!(
+        PrefixRegistry::new().register("ht tp").err(),
+        Some(WebfingerError::ParseError)
+    );
+    assert_eq!(registry.parse("acct"), Ok(Prefix::Acct));
+    assert_eq!(registry.parse("Group"), Ok(Prefix::Group));
+    assert_eq!(registry.parse("TAG"), Ok(Prefix::Custom("tag".into())));
+    assert_eq!(registry.parse("did"), Err(WebfingerError::ParseError));
+    assert_eq!(registry.parse("ht tp"), Err(WebfingerError::ParseError));
+    assert!(registry.is_registered("xmpp"));
+    assert!(!registry.is_registered("mailto"));
+    assert_eq!(
+        registry.check(Prefix::Acct, "root"),
+        Err(WebfingerError::ParseError)
+    );
+    assert_eq!(
+        registry
+            .parse_resource("XMPP:alice@example.org")
+            .map(|r| r.prefix),
+        Ok(Prefix::Custom("xmpp".into()))
+    );
+    assert_eq!(
+        registry.parse_resource("xmpp:alice/phone@example.org"),
+        Err(ResolverError::InvalidResource)
+    );
+    assert_eq!(
+        registry.parse_resource("did:alice@example.org"),
+        Err(ResolverError::InvalidResource)
+    );
+
+    let resolver = RegisteredPrefixes::new(MyResolver, registry.clone());
+    assert!(resolver
+        .endpoint("acct:admin@instance.tld", "admin")
+        .is_ok());
+    assert_eq!(
+        resolver.endpoint("acct:root@instance.tld", "root"),
+        Err(ResolverError::InvalidResource)
+    );
+    assert_eq!(
+        resolver.endpoint("did:admin@instance.tld", "admin"),
+        Err(ResolverError::InvalidResource)
+    );
+    assert_eq!(
+        resolver.endpoint("tag:admin@instance.tld", "admin"),
+        Err(ResolverError::NotFound)
+    );
+
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "xmpp:alice@example.org", "links": []}"#,
+    ));
+    let client = WebfingerClient::builder()
+        .http_fetch(fetch.clone())
+        .prefixes(registry)
+        .build();
+    r.block_on(async {
+        assert!(client.resolve("XMPP:alice@example.org").await.is_ok());
+        assert_eq!(
+            client.resolve("did:alice@example.org").await,
+            Err(WebfingerError::ParseError)
+        );
+        assert_eq!(
+            client
+                .resolve_with_prefix(Prefix::Custom("xmpp".into()), "alice/phone@example.org")
+                .await,
+            Err(WebfingerError::ParseError)
+        );
+    });
+    let requests = fetch.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].url.ends_with("resource=xmpp:alice@example.org"));
+}
+
+#[test]
+fn test_tee_responses() {
+    let r = Runtime::new().unwrap();
+    let teed = Arc::new(Mutex::new(vec![]));
+    let hook_teed = teed.clone();
+    let client = WebfingerClient::builder()
+        .http_fetch(FakeFetch::new(
+            200,
+            r#"{"subject": "acct:test@example.org", "links": []}"#,
+        ))
+        .tee_responses(10, move |response| {
+            hook_teed.lock().unwrap().push((
+                response.url.to_string(),
+                response.status,
+                response.body.to_vec(),
+                response.truncated,
+            ));
+        })
+        .build();
+    r.block_on(client.resolve("test@example.org")).unwrap();
+    assert_eq!(
+        *teed.lock().unwrap(),
+        vec![(
+            "https://example.org/.well-known/webfinger?resource=acct:test@example.org".to_string(),
+            200,
+            br#"{"subject""#.to_vec(),
+            true
+        )]
+    );
+}
+
+#[test]
+fn test_error_codes() {
+    let errors: Vec<(&str, String)> = vec![
+        (
+            WebfingerError::HttpError.code(),
+            WebfingerError::HttpError.to_string(),
+        ),
+        (
+            WebfingerError::HttpStatus(503).code(),
+            WebfingerError::HttpStatus(503).to_string(),
+        ),
+        (
+            WebfingerError::RateLimited.code(),
+            WebfingerError::RateLimited.to_string(),
+        ),
+        (
+            ResolverError::NotFound.code(),
+            ResolverError::NotFound.to_string(),
+        ),
+        (
+            InstanceDomainError::Empty.code(),
+            InstanceDomainError::Empty.to_string(),
+        ),
+        (
+            LinkError::InvalidUrl.code(),
+            LinkError::InvalidUrl.to_string(),
+        ),
+        (
+            TemplateIssue::MissingUri.code(),
+            TemplateIssue::MissingUri.to_string(),
+        ),
+        (ProxyError::Denied.code(), ProxyError::Denied.to_string()),
+    ];
+    for (code, message) in &errors {
+        assert!(code.starts_with("WF") && code.len() == 6);
+        assert!(message.starts_with(&format!("[{}] ", code)));
+    }
+    let mut codes: Vec<_> = errors.iter().map(|(code, _)| *code).collect();
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), errors.len());
+
+    assert_eq!(
+        WebfingerError::HttpStatus(503).to_string(),
+        "[WF0006] the server answered with the HTTP status 503"
+    );
+    let upstream = ProxyError::Upstream(WebfingerError::NotFound);
+    assert_eq!(upstream.code(), "WF0005");
+    assert_eq!(upstream.to_string(), WebfingerError::NotFound.to_string());
+    let traced = TracedError {
+        request_id: "abc".into(),
+        error: WebfingerError::NotFound,
+    };
+    assert_eq!(
+        traced.to_string(),
+        "[WF0005] the resource doesn't exist (request abc)"
+    );
+}
+
+#[test]
+#[cfg(feature = "test-support")]
+fn test_fake_instance() {
+    use crate::test_support::FakeInstance;
+
+    let r = Runtime::new().unwrap();
+    r.block_on(async {
+        let instance = FakeInstance::new("example.org")
+            .with_account("alice")
+            .with_document(
+                "group:devs@example.org",
+                Webfinger {
+                    subject: "group:devs@example.org".into(),
+                    aliases: vec![],
+                    links: vec![],
+                    properties: HashMap::new(),
+                    expires: None,
+                    extra: Map::new(),
+                },
+            )
+            .start()
+            .await;
+        let client = instance.client();
+
+        let alice = client.resolve("alice@example.org").await.unwrap();
+        assert_eq!(alice.subject, "acct:alice@example.org");
+        assert_eq!(alice.links.len(), 2);
+        assert!(client.resolve("group:devs@example.org").await.is_ok());
+        assert_eq!(
+            client.resolve("bob@example.org").await,
+            Err(WebfingerError::NotFound)
+        );
+        assert_eq!(instance.received_requests().await.len(), 3);
+    });
+}
+
+#[test]
+fn test_ndjson() {
+    use futures_util::StreamExt;
+
+    let documents = vec![
+        Webfinger {
+            subject: "acct:alice@example.org".into(),
+            aliases: vec!["https://example.org/@alice".into()],
+            links: vec![],
+            properties: HashMap::new(),
+            expires: None,
+            extra: Map::new(),
+        },
+        Webfinger {
+            subject: "acct:bob@example.org".into(),
+            aliases: vec![],
+            links: vec![],
+            properties: HashMap::new(),
+            expires: Some("2030-01-01T00:00:00Z".into()),
+            extra: Map::new(),
+        },
+    ];
+    let mut writer = NdjsonWriter::new(vec![]);
+    writer.write_all(&documents).unwrap();
+    let mut output = writer.into_inner();
+    assert_eq!(output.iter().filter(|b| **b == b'\n').count(), 2);
+    output.extend_from_slice(b"\nnot json\n");
+
+    let read: Vec<_> = NdjsonReader::new(&output[..]).collect();
+    assert_eq!(read.len(), 3);
+    assert_eq!(read[0].as_ref().unwrap(), &documents[0]);
+    assert_eq!(read[1].as_ref().unwrap(), &documents[1]);
+    match read[2] {
+        Err(ref error @ NdjsonError::InvalidDocument { line, .. }) => {
+            assert_eq!(line, 4);
+            assert_eq!(error.code(), "WF0702");
+        }
+        _ => panic!("the last line should be invalid"),
+    }
+
+    let r = Runtime::new().unwrap();
+    let streamed: Vec<_> = r.block_on(
+        NdjsonReader::new(futures_util::io::Cursor::new(output))
+            .into_stream()
+            .collect(),
+    );
+    assert_eq!(streamed.len(), 3);
+    assert_eq!(streamed[1].as_ref().unwrap(), &documents[1]);
+    assert!(streamed[2].is_err());
+}
+
+/// Answers with the body associated with the end of the URL, or with a 404 status.
+struct SuffixFetch {
+    routes: Vec<(&'static str, &'static str)>,
+    headers: Vec<(String, String)>,
+    count: Mutex<usize>,
+}
+
+impl HttpFetch for SuffixFetch {
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        *self.count.lock().unwrap() += 1;
+        let response = match self
+            .routes
+            .iter()
+            .find(|(suffix, _)| request.url.ends_with(suffix))
+        {
+            Some((_, body)) => HttpResponse {
+                status: 200,
+                headers: self.headers.clone(),
+                body: body.as_bytes().to_vec(),
+                timings: Timings::default(),
+            },
+            None => HttpResponse {
+                status: 404,
+                headers: vec![],
+                body: vec![],
+                timings: Timings::default(),
+            },
+        };
+        async move { Ok(response) }.boxed()
+    }
+}
+
+#[test]
+fn test_server_hints() {
+    let fetch = Arc::new(SuffixFetch {
+        routes: vec![
+            (
+                "resource=acct:test@example.org",
+                r#"{"subject": "acct:test@example.org", "links": []}"#,
+            ),
+            (
+                "resource=acct:other@example.org",
+                r#"{"subject": "acct:other@example.org", "links": []}"#,
+            ),
+            (
+                "/.well-known/nodeinfo",
+                r#"{"links": [
+                    {"rel": "http://nodeinfo.diaspora.software/ns/schema/2.0", "href": "https://example.org/nodeinfo/2.0"},
+                    {"rel": "http://nodeinfo.diaspora.software/ns/schema/2.1", "href": "https://example.org/nodeinfo/2.1"}
+                ]}"#,
+            ),
+            (
+                "/nodeinfo/2.1",
+                r#"{"software": {"name": "Plume", "version": "0.7.2"}}"#,
+            ),
+        ],
+        headers: vec![
+            ("Server".into(), "nginx/1.24".into()),
+            ("X-Powered-By".into(), "Plume/0.7".into()),
+        ],
+        count: Mutex::new(0),
+    });
+    let options = RequestOptions::default();
+    let r = Runtime::new().unwrap();
+
+    let client = WebfingerClient::builder().http_fetch(fetch.clone()).build();
+    let hints = r
+        .block_on(client.resolve_detailed("test@example.org", &options))
+        .unwrap()
+        .metadata
+        .server;
+    assert_eq!(hints.server.as_deref(), Some("nginx/1.24"));
+    assert_eq!(hints.powered_by.as_deref(), Some("Plume/0.7"));
+    assert_eq!(hints.software, None);
+    assert!(hints.is("plume"));
+    assert!(hints.is("NGINX"));
+    assert!(!hints.is("mastodon"));
+    assert_eq!(*fetch.count.lock().unwrap(), 1);
+
+    let client = WebfingerClient::builder()
+        .http_fetch(fetch.clone())
+        .detect_software(true)
+        .build();
+    let hints = r
+        .block_on(client.resolve_detailed("test@example.org", &options))
+        .unwrap()
+        .metadata
+        .server;
+    assert_eq!(
+        hints.software,
+        Some(Software {
+            name: "plume".into(),
+            version: Some("0.7.2".into())
+        })
+    );
+    assert!(hints.is("Plume"));
+    assert!(!hints.is("nginx"));
+    assert_eq!(*fetch.count.lock().unwrap(), 4);
+    r.block_on(client.resolve_detailed("other@example.org", &options))
+        .unwrap();
+    assert_eq!(*fetch.count.lock().unwrap(), 5);
+}
+
+/// Serves a document and a NodeInfo document, answering `503` to the first NodeInfo discovery
+/// request.
+struct FlakyNodeInfo(Mutex<Vec<String>>);
+
+impl HttpFetch for FlakyNodeInfo {
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        let mut requests = self.0.lock().unwrap();
+        let discoveries = requests
+            .iter()
+            .filter(|url| url.ends_with("/.well-known/nodeinfo"))
+            .count();
+        let (status, body) = if request.url.contains("resource=") {
+            (200, r#"{"subject": "acct:test@example.org", "links": []}"#)
+        } else if request.url.ends_with("/.well-known/nodeinfo") && discoveries == 0 {
+            (503, "")
+        } else if request.url.ends_with("/.well-known/nodeinfo") {
+            (
+                200,
+                r#"{"links": [{"rel": "http://nodeinfo.diaspora.software/ns/schema/2.1", "href": "https://example.org/nodeinfo/2.1"}]}"#,
+            )
+        } else {
+            (200, r#"{"software": {"name": "plume"}}"#)
+        };
+        requests.push(request.url);
+        let response = HttpResponse {
+            status,
+            headers: vec![],
+            body: body.as_bytes().to_vec(),
+            timings: Timings::default(),
+        };
+        async move { Ok(response) }.boxed()
+    }
+}
+
+#[test]
+fn test_software_detection_failures() {
+    let options = RequestOptions::default();
+    let r = Runtime::new().unwrap();
+
+    // Temporary failures are not remembered
+    let fetch = Arc::new(FlakyNodeInfo(Mutex::new(vec![])));
+    let client = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .detect_software(true)
+        .build();
+    let software = |client: &WebfingerClient| {
+        r.block_on(client.resolve_detailed("test@example.org", &options))
+            .unwrap()
+            .metadata
+            .server
+            .software
+    };
+    assert_eq!(software(&client), None);
+    assert_eq!(software(&client).unwrap().name, "plume");
+    assert_eq!(software(&client).unwrap().name, "plume");
+    assert_eq!(fetch.0.lock().unwrap().len(), 6);
+
+    // NodeInfo documents on other hosts are not fetched
+    let fetch = Arc::new(SuffixFetch {
+        routes: vec![
+            (
+                "resource=acct:test@example.org",
+                r#"{"subject": "acct:test@example.org", "links": []}"#,
+            ),
+            (
+                "/.well-known/nodeinfo",
+                r#"{"links": [{"rel": "http://nodeinfo.diaspora.software/ns/schema/2.1", "href": "https://10.0.0.1/nodeinfo/2.1"}]}"#,
+            ),
+            (
+                "/nodeinfo/2.1",
+                r#"{"software": {"name": "Plume", "version": "0.7.2"}}"#,
+            ),
+        ],
+        headers: vec![],
+        count: Mutex::new(0),
+    });
+    let client = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .detect_software(true)
+        .build();
+    assert_eq!(software(&client), None);
+    assert_eq!(software(&client), None);
+    // The discovery document is only fetched once, since its answer is definitive
+    assert_eq!(*fetch.count.lock().unwrap(), 3);
+}
+
+#[test]
+fn test_case_policy() {
+    use std::time::Duration;
+
+    assert_eq!(
+        CasePolicy::Preserve.normalize_resource("acct:Alice@Example.org"),
+        "acct:Alice@Example.org"
+    );
+    assert_eq!(
+        CasePolicy::Lowercase.normalize_resource("acct:Élise@Example.org"),
+        "acct:Élise@Example.org"
+    );
+    assert_eq!(
+        CasePolicy::Lowercase.normalize_resource("ALICE@example.org"),
+        "alice@example.org"
+    );
+    assert_eq!(
+        CasePolicy::Fold.normalize_resource("acct:Élise@example.org"),
+        "acct:élise@example.org"
+    );
+    assert_eq!(
+        CasePolicy::Fold.normalize_resource("https://example.org/@Alice"),
+        "https://example.org/@Alice"
+    );
+    assert!(CasePolicy::Lowercase.same_user("Alice", "aLICE"));
+    assert!(!CasePolicy::Preserve.same_user("Alice", "alice"));
+
+    let resolver = ApplyCasePolicy::new(MyResolver, CasePolicy::Lowercase);
+    let webfinger = resolver
+        .endpoint("acct:ADMIN@instance.tld", "admin")
+        .unwrap();
+    assert_eq!(webfinger.subject, "admin");
+    assert_eq!(
+        MyResolver.endpoint("acct:ADMIN@instance.tld", "admin"),
+        Err(ResolverError::NotFound)
+    );
+
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:Test@example.org", "links": []}"#,
+    ));
+    let client = WebfingerClient::builder()
+        .http_fetch(fetch.clone())
+        .case_policy(CasePolicy::Lowercase)
+        .cache(Duration::from_secs(60))
+        .build();
+    r.block_on(async {
+        let webfinger = client.resolve("Test@example.org").await.unwrap();
+        assert_eq!(webfinger.subject, "acct:test@example.org");
+        client.resolve("acct:TEST@example.org").await.unwrap();
+    });
+    let requests = fetch.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].url.ends_with("resource=acct:test@example.org"));
+}
+
+#[test]
+fn test_shared_transport() {
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:test@example.org", "links": []}"#,
+    ));
+    let shared: Arc<dyn HttpFetch> = fetch.clone();
+    let first = WebfingerClient::builder()
+        .shared_http_fetch(shared.clone())
+        .build();
+    let second = WebfingerClient::builder()
+        .shared_http_fetch(shared)
+        .user_agent("Second/1.0")
+        .build();
+    let r = Runtime::new().unwrap();
+    r.block_on(async {
+        first.resolve("test@example.org").await.unwrap();
+        second.resolve("test@example.org").await.unwrap();
+    });
+    let requests = fetch.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1]
+        .headers
+        .contains(&("User-Agent".to_string(), "Second/1.0".to_string())));
+
+    #[cfg(feature = "fetch-reqwest")]
+    {
+        let http = reqwest::Client::new();
+        let _client = WebfingerClient::with_http_client(http.clone());
+    }
+}
+
+#[test]
+fn test_cache_capacity_and_purge() {
+    use std::time::Duration;
+
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:test@example.org", "links": []}"#,
+    ));
+    let client = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .cache(Duration::from_secs(60))
+        .cache_capacity(2)
+        .build();
+    r.block_on(async {
+        for acct in &[
+            "test@example.org",
+            "test@example.org",
+            "other@example.org",
+            "test@sub.example.org",
+            "test@example.com",
+        ] {
+            assert!(client.resolve(*acct).await.is_ok());
+        }
+    });
+    assert_eq!(
+        client.cache_stats(),
+        CacheStats {
+            hits: 1,
+            misses: 4,
+            evictions: 2,
+            entries: 2,
+        }
+    );
+
+    assert_eq!(client.purge_host("EXAMPLE.org"), 1);
+    assert_eq!(client.purge_host("example.org"), 0);
+    assert_eq!(client.purge("acct:test@example.com"), Ok(1));
+    assert_eq!(client.purge("test@example.com"), Ok(0));
+    assert_eq!(client.cache_stats().entries, 0);
+
+    // Purged entries are not evicted again
+    r.block_on(async {
+        for acct in &["a@example.org", "b@example.org", "c@example.org"] {
+            assert!(client.resolve(*acct).await.is_ok());
+        }
+    });
+    let stats = client.cache_stats();
+    assert_eq!((stats.evictions, stats.entries), (3, 2));
+    assert!(!format!("{:?}", client).contains("a@example.org"));
+    assert_eq!(WebfingerClient::new().cache_stats(), CacheStats::default());
+}
+
+#[test]
+fn test_malformed_resources() {
+    let encoded = Resource::parse("acct:juliet%40capulet.example@shoppingsite.example").unwrap();
+    assert_eq!(encoded.user, "juliet%40capulet.example");
+    assert_eq!(encoded.domain, "shoppingsite.example");
+    assert_eq!(
+        url_for(Prefix::Acct, "juliet%40capulet.example@shoppingsite.example", true),
+        Ok(String::from(
+            "https://shoppingsite.example/.well-known/webfinger?resource=acct:juliet%2540capulet.example@shoppingsite.example"
+        ))
+    );
+
+    // Queries seen in the logs of actual servers
+    for (resource, error) in &[
+        ("acct:user@sub@example.org", ResourceError::MultipleAt),
+        ("acct:@user@example.org", ResourceError::EmptyUser),
+        ("acct:user@example.org@", ResourceError::MultipleAt),
+        ("acct:user@@example.org", ResourceError::MultipleAt),
+        ("@user@example.org", ResourceError::MissingPrefix),
+        ("user@example.org:443", ResourceError::MissingPrefix),
+        ("acct:user@", ResourceError::EmptyDomain),
+        ("acct:user@:8080", ResourceError::EmptyDomain),
+        ("acct:user", ResourceError::MissingAt),
+        ("acct:user@example.org:65536", ResourceError::InvalidPort),
+        ("a cct:user@example.org", ResourceError::InvalidPrefix),
+    ] {
+        assert_eq!(Resource::parse(resource), Err(*error), "{}", resource);
+        assert_eq!(
+            parse_resource(resource),
+            Err(ResolverError::InvalidResource)
+        );
+    }
+    assert_eq!(
+        ResourceError::MultipleAt.to_string(),
+        "[WF0804] the resource contains more than one @, the ones in the user should be %40"
+    );
+
+    for acct in &[
+        "user@sub@example.org",
+        "@user@example.org",
+        "user@",
+        "user@@example.org",
+    ] {
+        assert_eq!(
+            url_for(Prefix::Acct, *acct, true),
+            Err(WebfingerError::ParseError),
+            "{}",
+            acct
+        );
+    }
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(200, "{}"));
+    let client = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .build();
+    assert_eq!(
+        r.block_on(client.resolve("acct:user@sub@example.org")),
+        Err(WebfingerError::ParseError)
+    );
+    assert!(fetch.requests().is_empty());
+}
+
+#[test]
+fn test_well_known_url() {
+    assert_eq!(
+        well_known_url("example.org", "https", "acct:test@example.org", &[]),
+        Ok(String::from(
+            "https://example.org/.well-known/webfinger?resource=acct:test@example.org"
+        ))
+    );
+    assert_eq!(
+        well_known_url("localhost:8080", "http", "acct:test@localhost:8080", &[]),
+        url_for(Prefix::Acct, "test@localhost:8080", false)
+    );
+    for invalid in &["", "example.org/path", "example.org?x=y"] {
+        assert_eq!(
+            well_known_url(invalid, "https", "acct:test@example.org", &[]),
+            Err(WebfingerError::ParseError),
+            "{}",
+            invalid
+        );
+    }
+
+    // The same URL as the one the client requests
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(200, "{}"));
+    let client = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .build();
+    let options = RequestOptions::new()
+        .query("rel", "self")
+        .query("rel", "http://webfinger.net/rel/profile-page");
+    let _ = r.block_on(client.resolve_with_options("juliet%40capulet@example.org", &options));
+    assert_eq!(
+        fetch.requests()[0].url,
+        well_known_url(
+            "example.org",
+            "https",
+            "acct:juliet%40capulet@example.org",
+            &["self", "http://webfinger.net/rel/profile-page"]
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+#[cfg(feature = "test-support")]
+fn test_assert_webfinger_eq() {
+    use crate::test_support::diff_documents;
+
+    let left: Webfinger = serde_json::from_str(
+        r#"{
+            "subject": "acct:test@example.org",
+            "aliases": ["https://example.org/@test"],
+            "links": [
+                {"rel": "self", "type": "application/activity+json", "href": "https://example.org/users/test"}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let mut right = left.clone();
+    assert_webfinger_eq!(left, right);
+    assert!(diff_documents(&left, &right).is_empty());
+
+    right.links[0].href = Some("https://example.org/u/test".to_string());
+    right
+        .aliases
+        .push("https://example.org/users/test".to_string());
+    assert_eq!(
+        diff_documents(&left, &right),
+        vec![
+            r#"aliases[1]: "https://example.org/users/test" is only on the right"#,
+            r#"links[0].href: Some("https://example.org/users/test") != Some("https://example.org/u/test")"#,
+        ]
+    );
+
+    let panic = std::panic::catch_unwind(|| assert_webfinger_eq!(left, right, "for {}", "test"))
+        .unwrap_err();
+    let message = panic.downcast_ref::<String>().unwrap();
+    assert!(message.starts_with("assertion failed: `(left == right)`: for test\naliases[1]"));
+}
+
+#[test]
+#[cfg(feature = "fediverse")]
+fn test_redirect_to() {
+    let webfinger = Webfinger::redirect_to(
+        "alice@old.example",
+        "alice@example.org",
+        "https://example.org/users/alice",
+    );
+    assert_eq!(webfinger.subject, "acct:alice@old.example");
+    assert!(webfinger.is_known_as("acct:alice@example.org"));
+    assert_eq!(
+        webfinger.actor_url(),
+        Some("https://example.org/users/alice")
+    );
+    assert_eq!(webfinger.profile_url(), None);
+    assert_eq!(
+        serde_json::to_value(&webfinger).unwrap(),
+        serde_json::json!({
+            "subject": "acct:alice@old.example",
+            "aliases": ["acct:alice@example.org"],
+            "links": [{
+                "rel": "self",
+                "type": "application/activity+json",
+                "href": "https://example.org/users/alice"
+            }]
+        })
+    );
+}
+
+#[test]
+fn test_handle() {
+    let document = |subject: &str, aliases: &[&str]| Webfinger {
+        subject: subject.to_string(),
+        aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+        links: vec![],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    };
+    assert_eq!(
+        document("acct:Alice@Example.ORG", &[]).handle(),
+        Some("Alice@example.org".to_string())
+    );
+    assert_eq!(
+        document("ACCT:alice@example.org:8080", &[]).handle(),
+        Some("alice@example.org:8080".to_string())
+    );
+    assert_eq!(
+        document("@alice@example.org", &[]).handle(),
+        Some("alice@example.org".to_string())
+    );
+    assert_eq!(
+        document(
+            "https://example.org/users/alice",
+            &["https://example.org/@alice", "acct:alice@example.org"]
+        )
+        .handle(),
+        Some("alice@example.org".to_string())
+    );
+    assert_eq!(
+        document("acct:alice@sub@example.org", &["alice@example.org"]).handle(),
+        Some("alice@example.org".to_string())
+    );
+    for subject in &[
+        "https://example.org/@alice",
+        "group:devs@example.org",
+        "alice",
+        "acct:@example.org",
+    ] {
+        assert_eq!(document(subject, &[]).handle(), None, "{}", subject);
+    }
+}
+
+#[test]
+fn test_hash_cache_keys() {
+    use std::time::Duration;
+
+    struct LengthHasher;
+
+    impl CacheKeyHasher for LengthHasher {
+        fn hash(&self, url: &str) -> String {
+            format!("{:x}", url.len())
+        }
+    }
+
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:test@example.org", "links": []}"#,
+    ));
+    let client = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .cache(Duration::from_secs(60))
+        .negative_cache(Duration::from_secs(60))
+        .hash_cache_keys(LengthHasher)
+        .build();
+    r.block_on(async {
+        assert!(client.resolve("test@example.org").await.is_ok());
+        assert!(client.resolve("test@example.org").await.is_ok());
+        assert!(client.resolve("other@example.org:8080").await.is_ok());
+    });
+    assert_eq!(fetch.requests().len(), 2);
+    assert_eq!(client.cache_stats().hits, 1);
+    assert!(format!("{:?}", client).contains(r#"("https://example.org/48", "#));
+
+    assert_eq!(client.purge("test@example.org"), Ok(1));
+    assert_eq!(client.purge_host("example.org"), 1);
+    assert_eq!(client.cache_stats().entries, 0);
+}
+
+#[test]
+#[cfg(feature = "ring")]
+fn test_hmac_keys() {
+    // Test case 2 of RFC 4231
+    assert_eq!(
+        HmacKeys::new(b"Jefe").hash("what do ya want for nothing?"),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+}
+
+struct CountingResolver;
+
+impl<'a> Resolver<&'a Mutex<usize>> for CountingResolver {
+    fn instance_domain(&self) -> InstanceDomain {
+        InstanceDomain::new("instance.tld").unwrap()
+    }
+
+    fn find(
+        &self,
+        _prefix: Prefix,
+        acct: String,
+        resource_repo: &'a Mutex<usize>,
+    ) -> Result<Webfinger, ResolverError> {
+        *resource_repo.lock().unwrap() += 1;
+        if acct == "admin" {
+            Ok(Webfinger {
+                subject: "acct:admin@instance.tld".to_string(),
+                aliases: vec![],
+                links: vec![],
+                properties: HashMap::new(),
+                expires: None,
+                extra: Map::new(),
+            })
+        } else {
+            Err(ResolverError::NotFound)
+        }
+    }
+}
+
+#[test]
+fn test_head_requests() {
+    use std::time::Duration;
+
+    let queries = Mutex::new(0);
+    let resolver = CacheLookups::new(CountingResolver, Duration::from_secs(60));
+    let format = ResponseFormat::negotiate(None, false);
+
+    let head = EndpointResponse::head(
+        &resolver.endpoint("acct:admin@instance.tld", &queries),
+        format,
+    );
+    let get = EndpointResponse::new(
+        &resolver.endpoint("acct:admin@instance.tld", &queries),
+        format,
+    );
+    assert_eq!(*queries.lock().unwrap(), 1);
+    assert_eq!(head.status, 200);
+    assert!(head.body.is_empty());
+    assert_eq!(head.headers, get.headers);
+    assert!(get
+        .headers
+        .contains(&("Content-Length".to_string(), get.body.len().to_string())));
+    assert!(get.headers.contains(&(
+        "Content-Type".to_string(),
+        "application/jrd+json".to_string()
+    )));
+
+    for _ in 0..2 {
+        let missing = EndpointResponse::head(
+            &resolver.endpoint("acct:nobody@instance.tld", &queries),
+            format,
+        );
+        assert_eq!(missing.status, 404);
+        assert!(missing.body.is_empty());
+    }
+    assert_eq!(*queries.lock().unwrap(), 2);
+    assert_eq!(
+        EndpointResponse::new(&resolver.endpoint("acct:admin@other.tld", &queries), format).status,
+        404
+    );
+    assert_eq!(
+        EndpointResponse::new(&resolver.endpoint("admin", &queries), format).status,
+        400
+    );
+
+    resolver.clear();
+    assert!(resolver
+        .endpoint("acct:admin@instance.tld", &queries)
+        .is_ok());
+    assert_eq!(*queries.lock().unwrap(), 3);
+}
+
+#[test]
+fn test_cache_lookups_forever() {
+    use std::time::Duration;
+
+    let queries = Mutex::new(0);
+    let resolver = CacheLookups::new(CountingResolver, Duration::MAX);
+    for _ in 0..2 {
+        assert!(resolver
+            .endpoint("acct:admin@instance.tld", &queries)
+            .is_ok());
+    }
+    assert_eq!(*queries.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_resolve_many() {
+    let accts = [
+        "a1@a.example",
+        "a2@a.example",
+        "a3@A.example",
+        "b1@b.example",
+        "invalid",
+        "c1@c.example",
+    ];
+    let r = Runtime::new().unwrap();
+    for (scheduling, expected) in &[
+        (
+            Scheduling::InOrder,
+            [
+                "a1@a.example",
+                "a2@a.example",
+                "a3@A.example",
+                "b1@b.example",
+                "c1@c.example",
+            ],
+        ),
+        (
+            Scheduling::RoundRobin,
+            [
+                "a1@a.example",
+                "b1@b.example",
+                "c1@c.example",
+                "a2@a.example",
+                "a3@A.example",
+            ],
+        ),
+    ] {
+        let fetch = Arc::new(FakeFetch::new(
+            200,
+            r#"{"subject": "acct:test@example.org", "links": []}"#,
+        ));
+        let client = WebfingerClient::builder()
+            .shared_http_fetch(fetch.clone())
+            .build();
+        let results = r.block_on(client.resolve_many(accts.iter().copied(), 1, *scheduling));
+
+        let requested: Vec<_> = fetch
+            .requests()
+            .iter()
+            .map(|request| request.url.split("acct:").nth(1).unwrap().to_string())
+            .collect();
+        assert_eq!(requested, expected.to_vec(), "{:?}", scheduling);
+        assert_eq!(
+            results
+                .iter()
+                .map(|(acct, _)| acct.as_str())
+                .collect::<Vec<_>>(),
+            accts.to_vec()
+        );
+        assert_eq!(results[4].1, Err(WebfingerError::ParseError));
+        assert!(results[5].1.is_ok());
+    }
+    assert_eq!(Scheduling::default(), Scheduling::RoundRobin);
+}
+
+#[test]
+fn test_error_conversions() {
+    use std::convert::TryFrom;
+
+    for (resolver, client) in &[
+        (ResolverError::InvalidResource, WebfingerError::ParseError),
+        (ResolverError::InvalidIdentifier, WebfingerError::ParseError),
+        (ResolverError::WrongDomain, WebfingerError::NotFound),
+        (ResolverError::NotFound, WebfingerError::NotFound),
+        (
+            ResolverError::InvalidSubject,
+            WebfingerError::HttpStatus(500),
+        ),
+    ] {
+        assert_eq!(WebfingerError::from(resolver.clone()), *client);
+    }
+
+    assert_eq!(
+        ResolverError::try_from(WebfingerError::ParseError),
+        Ok(ResolverError::InvalidResource)
+    );
+    assert_eq!(
+        ResolverError::try_from(WebfingerError::NotFound),
+        Ok(ResolverError::NotFound)
+    );
+    for error in &[
+        WebfingerError::HttpError,
+        WebfingerError::BlockedDomain,
+        WebfingerError::HttpStatus(503),
+        WebfingerError::RateLimited,
+    ] {
+        assert_eq!(ResolverError::try_from(error.clone()), Err(error.clone()));
+    }
+}
+
+#[test]
+#[cfg(feature = "fediverse")]
+fn test_constructors_with_base_url() {
+    let onion = BaseUrl::parse("http://abcdef.onion").unwrap();
+    let webfinger = Webfinger::for_account_at(&onion, "alice", "/users/alice", "@alice");
+    assert_eq!(webfinger.subject, "acct:alice@abcdef.onion");
+    assert_eq!(
+        webfinger.actor_url(),
+        Some("http://abcdef.onion/users/alice")
+    );
+    assert_eq!(webfinger.profile_url(), Some("http://abcdef.onion/@alice"));
+    assert_eq!(
+        webfinger,
+        Webfinger::for_account(
+            "alice@abcdef.onion",
+            "http://abcdef.onion/users/alice",
+            "http://abcdef.onion/@alice"
+        )
+    );
+
+    let dev = BaseUrl::parse("http://localhost:8080/app").unwrap();
+    let actor = Webfinger::for_instance_actor_at(&dev, "actor");
+    assert_eq!(actor.subject, "acct:localhost:8080@localhost:8080");
+    assert_eq!(actor.actor_url(), Some("http://localhost:8080/app/actor"));
+}
+
+#[test]
+fn test_timings() {
+    use std::time::Duration;
+
+    struct SlowFetch;
+
+    impl HttpFetch for SlowFetch {
+        fn fetch(
+            &self,
+            _request: HttpRequest,
+        ) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+            async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok(HttpResponse {
+                    status: 200,
+                    headers: vec![],
+                    body: br#"{"subject": "acct:test@example.org", "links": []}"#.to_vec(),
+                    timings: Timings {
+                        dns: Some(Duration::from_millis(1)),
+                        tls: Some(Duration::from_millis(3)),
+                        ttfb: Some(Duration::from_millis(10)),
+                        total: Duration::from_secs(3600),
+                        ..Timings::default()
+                    },
+                })
+            }
+            .boxed()
+        }
+    }
+
+    let r = Runtime::new().unwrap();
+    let client = WebfingerClient::builder().http_fetch(SlowFetch).build();
+    let timings = r
+        .block_on(client.resolve_detailed("test@example.org", &RequestOptions::default()))
+        .unwrap()
+        .metadata
+        .timings;
+    assert_eq!(timings.dns, Some(Duration::from_millis(1)));
+    assert_eq!(timings.connect, None);
+    assert_eq!(timings.tls, Some(Duration::from_millis(3)));
+    assert_eq!(timings.ttfb, Some(Duration::from_millis(10)));
+    assert!(timings.total >= Duration::from_millis(20));
+    assert!(timings.total < Duration::from_secs(3600));
+}
+
+#[test]
+fn test_validate_handle() {
+    let acct = validate_handle("  ACCT:Alice@Example.org:8080 ").unwrap();
+    assert_eq!(acct.user(), "Alice");
+    assert_eq!(
+        acct.domain(),
+        &InstanceDomain::new("example.org:8080").unwrap()
+    );
+    assert_eq!(acct.to_resource(), "acct:Alice@example.org:8080");
+    assert_eq!(
+        "juliet%40capulet.example@shoppingsite.example"
+            .parse::<Acct>()
+            .map(|a| a.to_string()),
+        Ok("juliet%40capulet.example@shoppingsite.example".to_string())
+    );
+
+    for (input, error) in &[
+        ("", HandleError::Resource(ResourceError::MissingAt)),
+        ("alice", HandleError::Resource(ResourceError::MissingAt)),
+        (
+            "@@alice@example.org",
+            HandleError::Resource(ResourceError::EmptyUser),
+        ),
+        (
+            "alice@sub@example.org",
+            HandleError::Resource(ResourceError::MultipleAt),
+        ),
+        ("alice@", HandleError::Resource(ResourceError::EmptyDomain)),
+        (
+            "alice@example.org/users",
+            HandleError::Domain(InstanceDomainError::HasPath),
+        ),
+        (
+            "alice@exa mple.org",
+            HandleError::Domain(InstanceDomainError::InvalidHost),
+        ),
+        ("al ice@example.org", HandleError::InvalidCharacter(' ')),
+        ("alice#1@example.org", HandleError::InvalidCharacter('#')),
+        ("alice%4@example.org", HandleError::InvalidPercentEncoding),
+        ("alice%zz@example.org", HandleError::InvalidPercentEncoding),
+    ] {
+        assert_eq!(validate_handle(input), Err(error.clone()), "{}", input);
+    }
+    assert_eq!(
+        HandleError::InvalidCharacter(' ').to_string(),
+        "[WF0902] the user contains an invalid character: ' '"
+    );
+    assert_eq!(
+        HandleError::Resource(ResourceError::MultipleAt).code(),
+        "WF0804"
+    );
+}
+
+#[derive(Default)]
+struct MapNegativeStore(Mutex<std::collections::HashMap<String, WebfingerError>>);
+
+impl NegativeStore for MapNegativeStore {
+    fn get(&self, key: &str) -> Option<WebfingerError> {
+        self.0.lock().unwrap().get(key).cloned()
+    }
+
+    fn insert(&self, key: &str, error: &WebfingerError, _ttl: std::time::Duration) {
+        self.0.lock().unwrap().insert(key.into(), error.clone());
+    }
+
+    fn remove_where(&self, predicate: &dyn Fn(&str) -> bool) -> usize {
+        let mut errors = self.0.lock().unwrap();
+        let before = errors.len();
+        errors.retain(|key, _| !predicate(key));
+        before - errors.len()
+    }
+}
+
+#[test]
+fn test_negative_store() {
+    use std::time::Duration;
+
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(404, ""));
+    let store = Arc::new(MapNegativeStore::default());
+    let client = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .cache(Duration::from_secs(60))
+        .negative_cache(Duration::from_secs(3600))
+        .negative_store(store.clone())
+        .build();
+    r.block_on(async {
+        for _ in 0..2 {
+            assert_eq!(
+                client.resolve("gone@example.org").await,
+                Err(WebfingerError::NotFound)
+            );
+        }
+    });
+    assert_eq!(fetch.requests().len(), 1);
+    assert_eq!(store.0.lock().unwrap().len(), 1);
+    let stats = client.cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 0));
+
+    assert_eq!(client.purge("gone@example.org"), Ok(1));
+    assert!(store.0.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_negative_store_options() {
+    use std::time::Duration;
+
+    struct LengthHasher;
+
+    impl CacheKeyHasher for LengthHasher {
+        fn hash(&self, url: &str) -> String {
+            format!("{:x}", url.len())
+        }
+    }
+
+    let r = Runtime::new().unwrap();
+    for hashed in [false, true] {
+        let fetch = Arc::new(FakeFetch::new(404, ""));
+        let store = Arc::new(MapNegativeStore::default());
+        let mut builder = WebfingerClient::builder()
+            .shared_http_fetch(fetch.clone())
+            .cache(Duration::from_secs(60))
+            .negative_cache(Duration::from_secs(3600))
+            .negative_store(store.clone());
+        if hashed {
+            builder = builder.hash_cache_keys(LengthHasher);
+        }
+        let client = builder.build();
+        let with_token = RequestOptions::new().header("Authorization", "Bearer secret");
+        r.block_on(async {
+            for options in [&with_token, &RequestOptions::new(), &with_token] {
+                assert_eq!(
+                    client
+                        .resolve_with_options("gone@example.org", options)
+                        .await,
+                    Err(WebfingerError::NotFound)
+                );
+            }
+        });
+        assert_eq!(fetch.requests().len(), 2);
+        let mut keys: Vec<_> = store.0.lock().unwrap().keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys.len(), 2);
+        assert!(!keys[0].contains('#'));
+        assert!(keys[1].starts_with(&format!("{}#", keys[0])));
+        assert_eq!(keys[1].contains("secret"), !hashed);
+
+        assert_eq!(client.purge("gone@example.org"), Ok(2));
+        assert!(store.0.lock().unwrap().is_empty());
+    }
+}
+
+/// Refuses the first request, and allows all the others.
+struct RefusesFirst(Mutex<bool>);
+
+impl RateLimit for RefusesFirst {
+    fn acquire<'a>(&'a self, _host: &'a str) -> BoxFuture<'a, bool> {
+        let allowed = std::mem::replace(&mut *self.0.lock().unwrap(), true);
+        async move { allowed }.boxed()
+    }
+}
+
+#[test]
+fn test_temporary_errors_not_cached() {
+    use std::time::Duration;
+
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:test@example.org", "links": []}"#,
+    ));
+    let store = Arc::new(MapNegativeStore::default());
+    let client = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .rate_limit(RefusesFirst(Mutex::new(false)))
+        .cache(Duration::from_secs(60))
+        .negative_cache(Duration::from_secs(3600))
+        .negative_store(store.clone())
+        .build();
+    r.block_on(async {
+        assert_eq!(
+            client.resolve("test@example.org").await,
+            Err(WebfingerError::RateLimited)
+        );
+        assert!(client.resolve("test@example.org").await.is_ok());
+    });
+    assert_eq!(fetch.requests().len(), 1);
+    assert!(store.0.lock().unwrap().is_empty());
+
+    let fetch = Arc::new(FakeFetch::new(503, ""));
+    let client = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .cache(Duration::from_secs(60))
+        .negative_cache(Duration::from_secs(3600))
+        .build();
+    r.block_on(async {
+        for _ in 0..2 {
+            assert_eq!(
+                client.resolve("test@example.org").await,
+                Err(WebfingerError::HttpStatus(503))
+            );
+        }
+    });
+    assert_eq!(fetch.requests().len(), 2);
+    assert_eq!(client.cache_stats().entries, 0);
+}
+
+#[test]
+fn test_web_domain() {
+    let resolver = WebDomain::new(
+        NormalizeSubjects::new(MyResolver, SubjectMode::FixUp),
+        InstanceDomain::new("social.instance.tld").unwrap(),
+    );
+    let expected = vec![
+        "acct:admin@instance.tld".to_string(),
+        "acct:admin@social.instance.tld".to_string(),
+    ];
+    for resource in &[
+        "acct:admin@instance.tld",
+        "acct:admin@social.instance.tld",
+        "https://social.instance.tld/@admin",
+    ] {
+        let webfinger = resolver.endpoint(*resource, "admin").unwrap();
+        assert_eq!(webfinger.subject, "acct:admin@instance.tld");
+        assert_eq!(webfinger.aliases, expected);
+    }
+    assert_eq!(
+        resolver.endpoint("acct:admin@other.tld", "admin"),
+        Err(ResolverError::WrongDomain)
+    );
+    assert_eq!(
+        resolver.endpoint("acct:nobody@social.instance.tld", "admin"),
+        Err(ResolverError::NotFound)
+    );
+
+    // The web domain is still accepted when other wrappers are added around it
+    let web_domain = || {
+        WebDomain::new(
+            NormalizeSubjects::new(MyResolver, SubjectMode::FixUp),
+            InstanceDomain::new("social.instance.tld").unwrap(),
+        )
+    };
+    let wrapped = CacheLookups::new(
+        DenyRels::new(web_domain(), vec!["self"]),
+        std::time::Duration::from_secs(60),
+    );
+    let webfinger = wrapped
+        .endpoint("acct:admin@social.instance.tld", "admin")
+        .unwrap();
+    assert_eq!(webfinger.subject, "acct:admin@instance.tld");
+    assert!(webfinger.links.iter().all(|link| link.rel != "self"));
+    assert_eq!(
+        wrapped.endpoint("acct:admin@other.tld", "admin"),
+        Err(ResolverError::WrongDomain)
+    );
+
+    #[cfg(all(feature = "async", feature = "tokio"))]
+    {
+        let r = Runtime::new().unwrap();
+        let resolver = DenyRels::new(SyncAsAsync::new(web_domain()), vec!["self"]);
+        let webfinger = r
+            .block_on(resolver.endpoint("acct:admin@social.instance.tld", "admin"))
+            .unwrap();
+        assert_eq!(webfinger.subject, "acct:admin@instance.tld");
+    }
+}
+
+#[test]
+fn test_missing_resource() {
+    assert_eq!(
+        MyResolver.endpoint("", "admin"),
+        Err(ResolverError::MissingResource)
+    );
+    let response = EndpointResponse::new(&Err(ResolverError::MissingResource), ResponseFormat::Jrd);
+    assert_eq!(response, EndpointResponse::missing_resource());
+    assert_eq!(response.status, 400);
+    assert!(response
+        .headers
+        .contains(&("Content-Type".into(), consts::PROBLEM_JSON_MIME.into())));
+    let problem: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(problem["status"], 400);
+    assert_eq!(
+        WebfingerError::from(ResolverError::MissingResource),
+        WebfingerError::MissingResource
+    );
+
+    let r = Runtime::new().unwrap();
+    let resolve = |mut fetch: FakeFetch| {
+        if fetch.response.status == 400 {
+            fetch.response.headers =
+                vec![("Content-Type".into(), consts::PROBLEM_JSON_MIME.into())];
+        }
+        let client = WebfingerClient::builder().http_fetch(fetch).build();
+        r.block_on(client.resolve("test@example.org"))
+    };
+    let problem = FakeFetch::new(400, &response.body);
+    assert_eq!(resolve(problem), Err(WebfingerError::MissingResource));
+    let directory = FakeFetch::new(200, r#"["acct:a@example.org", "acct:b@example.org"]"#);
+    assert_eq!(resolve(directory), Err(WebfingerError::MissingResource));
+    let invalid = FakeFetch::new(400, r#"{"title": "Invalid resource"}"#);
+    assert_eq!(resolve(invalid), Err(WebfingerError::HttpStatus(400)));
+}
+
+struct SlowResolver(std::time::Duration);
+
+impl Resolver<WithContext<&'static str>> for SlowResolver {
+    fn instance_domain(&self) -> InstanceDomain {
+        InstanceDomain::new("instance.tld").unwrap()
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: WithContext<&'static str>,
+    ) -> Result<Webfinger, ResolverError> {
+        assert!(resource_repo.context.remaining().is_some());
+        std::thread::sleep(self.0);
+        MyResolver.find(prefix, acct, resource_repo.repo)
+    }
+}
+
+#[test]
+fn test_deadlines() {
+    use std::time::Duration;
+
+    let resolver = Deadlines::new(SlowResolver(Duration::from_millis(50)));
+    let repo = |context: &LookupContext| WithContext::new("admin", context.clone());
+
+    let context = LookupContext::new().with_timeout(Duration::from_secs(10));
+    assert!(resolver
+        .endpoint("acct:admin@instance.tld", repo(&context))
+        .is_ok());
+
+    let forever = LookupContext::new().with_timeout(Duration::MAX);
+    assert_eq!(forever.deadline(), None);
+    assert_eq!(forever.remaining(), None);
+
+    let expired = LookupContext::new().with_timeout(Duration::from_millis(10));
+    assert_eq!(
+        resolver.endpoint("acct:admin@instance.tld", repo(&expired)),
+        Err(ResolverError::Cancelled)
+    );
+
+    let guard = context.cancel_on_drop();
+    guard.disarm();
+    assert!(!context.is_cancelled());
+    drop(context.cancel_on_drop());
+    assert!(context.is_cancelled());
+    assert_eq!(context.check(), Err(ResolverError::Cancelled));
+    assert_eq!(
+        resolver.endpoint("acct:admin@instance.tld", repo(&context)),
+        Err(ResolverError::Cancelled)
+    );
+}
+
+#[test]
+fn test_semantically_eq() {
+    let link = |rel: &str| Link {
+        rel: rel.into(),
+        href: Some(format!("https://example.org/{}", rel)),
+        template: None,
+        mime_type: None,
+        titles: HashMap::new(),
+        properties: HashMap::new(),
+        extra: Map::new(),
+    };
+    let document = Webfinger {
+        subject: "acct:test@example.org".to_string(),
+        aliases: vec![
+            "https://example.org/@test".into(),
+            "acct:t@example.org".into(),
+        ],
+        links: vec![link("a"), link("b")],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    };
+    let refreshed: Webfinger = serde_json::from_str(
+        r#"{
+            "subject": "acct:test@example.org",
+            "aliases": ["acct:t@example.org", "https://example.org/@test"],
+            "links": [
+                {"rel": "b", "href": "https://example.org/b", "label": "B"},
+                {"rel": "a", "href": "https://example.org/a"}
+            ],
+            "expires": "2030-01-01T00:00:00Z",
+            "unknown": {"http://example.org/ns": "value"}
+        }"#,
+    )
+    .unwrap();
+    assert_ne!(document, refreshed);
+    assert!(document.semantically_eq(&refreshed));
+    assert!(refreshed.semantically_eq(&document));
+
+    let mut duplicated = document.clone();
+    duplicated.links = vec![link("a"), link("a")];
+    assert!(!document.semantically_eq(&duplicated));
+    assert!(!duplicated.semantically_eq(&document));
+    let mut moved = document.clone();
+    moved.links[0].href = Some("https://example.com/a".into());
+    assert!(!document.semantically_eq(&moved));
+    let mut described = document.clone();
+    described
+        .properties
+        .insert("http://example.org/ns".into(), None);
+    assert!(!document.semantically_eq(&described));
+}
+
+#[test]
+fn test_rel_wire_format() {
+    let json = r#"{"rel":"self","href":"https://example.org/a"}"#;
+    let link: Link = serde_json::from_str(json).unwrap();
+    assert!(matches!(link.rel, Rel::SelfRel));
+    assert_eq!(link.rel, "self");
+    assert_eq!(serde_json::to_string(&link).unwrap(), json);
+
+    let custom: Link = serde_json::from_str(r#"{"rel":"payment"}"#).unwrap();
+    assert_eq!(custom.rel, Rel::Custom("payment".into()));
+    assert!(custom.rel.starts_with("pay"));
+    assert_eq!(Rel::Custom("self".into()), Rel::SelfRel);
+    assert_eq!(
+        String::from(Rel::ProfilePage),
+        consts::WEBFINGER_REL_PROFILE_PAGE
+    );
+    assert_eq!(std::mem::size_of::<Rel>(), std::mem::size_of::<String>());
+}
+
+/// Answers `406 Not Acceptable` unless only plain JSON is accepted.
+struct PickyFetch(Mutex<Vec<String>>);
+
+impl HttpFetch for PickyFetch {
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        let accept = request
+            .headers
+            .iter()
+            .filter(|(name, _)| name == "Accept")
+            .map(|(_, value)| value.clone())
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let status = if accept == "application/json" {
+            200
+        } else {
+            406
+        };
+        self.0.lock().unwrap().push(accept);
+        let response = HttpResponse {
+            status,
+            headers: vec![("Content-Type".into(), "application/json".into())],
+            body: br#"{"subject": "acct:test@example.org", "links": []}"#.to_vec(),
+            timings: Timings::default(),
+        };
+        async move { Ok(response) }.boxed()
+    }
+}
+
+#[test]
+fn test_accept_fallback() {
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(PickyFetch(Mutex::new(vec![])));
+    let client = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .build();
+    let resolved = r
+        .block_on(client.resolve_detailed("test@example.org", &RequestOptions::new()))
+        .unwrap();
+    assert_eq!(
+        resolved.metadata.warnings,
+        vec![ParseWarning::NotAcceptable]
+    );
+    assert_eq!(
+        *fetch.0.lock().unwrap(),
+        vec!["application/jrd+json, application/json", "application/json"]
+    );
+
+    let strict = WebfingerClient::builder()
+        .shared_http_fetch(fetch.clone())
+        .accept_fallback(false)
+        .build();
+    assert_eq!(
+        r.block_on(strict.resolve("test@example.org")),
+        Err(WebfingerError::HttpStatus(406))
+    );
+    assert_eq!(fetch.0.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn test_crate_error() {
+    use std::error::Error as _;
+
+    fn lookup(resource: &str) -> Result<Webfinger, Error> {
+        let resource = Resource::parse(resource)?;
+        InstanceDomain::new(&resource.host())?;
+        Ok(MyResolver.find(resource.prefix, resource.user, "admin")?)
+    }
+
+    assert!(lookup("acct:admin@instance.tld").is_ok());
+    let error = lookup("acct:nobody@instance.tld").unwrap_err();
+    assert!(matches!(error, Error::Resolver(ResolverError::NotFound)));
+    assert_eq!(error.code(), "WF0103");
+    assert_eq!(error.to_string(), ResolverError::NotFound.to_string());
+    assert_eq!(lookup("admin").unwrap_err().code(), "WF0801");
+
+    let io = std::io::Error::other("disk");
+    let error = Error::from(NdjsonError::Io(io));
+    assert_eq!(error.code(), "WF0701");
+    assert_eq!(error.source().unwrap().to_string(), "disk");
+
+    let traced = Error::from(TracedError {
+        request_id: "abc".into(),
+        error: WebfingerError::NotFound,
+    });
+    assert_eq!(traced.code(), "WF0005");
+    assert!(traced.to_string().ends_with("(request abc)"));
+}
+
+#[test]
+fn test_missing_subject() {
+    let r = Runtime::new().unwrap();
+    let resolve = |body: &str| {
+        let client = WebfingerClient::builder()
+            .http_fetch(FakeFetch::new(200, body))
+            .build();
+        r.block_on(client.resolve_detailed("test@example.org", &RequestOptions::new()))
+    };
+    let links = r#""links": [{"rel": "self", "href": "https://example.org/test"}]"#;
+
+    for body in &[
+        format!("{{{}}}", links),
+        format!(r#"{{"subject": "", {}}}"#, links),
+    ] {
+        let result = resolve(body);
+        if cfg!(feature = "lenient") {
+            let resolved = result.unwrap();
+            assert_eq!(resolved.document.subject, "acct:test@example.org");
+            assert_eq!(resolved.document.links.len(), 1);
+            assert_eq!(
+                resolved.metadata.warnings,
+                vec![ParseWarning::MissingSubject]
+            );
+        } else {
+            assert!(matches!(result, Err(WebfingerError::JsonError(_))));
+        }
+    }
+    assert!(Webfinger::from_json_lenient(format!("{{{}}}", links).as_bytes()).is_err());
+}
+
+#[test]
+#[cfg(feature = "test-support")]
+fn test_fake_webfinger() {
+    use crate::test_support::fake_webfinger;
+
+    assert_eq!(fake_webfinger(7), fake_webfinger(7));
+    assert_ne!(fake_webfinger(7), fake_webfinger(8));
+
+    let documents: Vec<Webfinger> = (0..200).map(fake_webfinger).collect();
+    for document in &documents {
+        assert!(document.subject.starts_with("acct:"));
+        let json = serde_json::to_string(document).unwrap();
+        assert_eq!(&serde_json::from_str::<Webfinger>(&json).unwrap(), document);
+        for link in &document.links {
+            assert!(link.href.is_some() || link.template.is_some());
+            assert!(link.validate_template().is_empty());
+        }
+    }
+    for rel in &[Rel::SelfRel, Rel::Avatar, Rel::Subscribe, Rel::DescribedBy] {
+        assert!(documents
+            .iter()
+            .any(|d| d.links.iter().any(|l| l.rel == *rel)));
+    }
+    assert!(documents
+        .iter()
+        .any(|d| d.links.iter().any(|l| matches!(l.rel, Rel::Custom(_)))));
+    assert!(documents.iter().any(|d| d.expires.is_some()));
+    assert!(documents.iter().any(|d| d.aliases.is_empty()));
+}
+
+#[test]
+fn test_properties() {
+    let json = r#"{
+        "subject": "acct:alice@example.org",
+        "properties": {
+            "http://example.org/ns/name": "Alice",
+            "http://example.org/ns/unset": null
+        },
+        "links": [{
+            "rel": "http://openid.net/specs/connect/1.0/issuer",
+            "href": "https://sso.example.org/realms/main",
+            "properties": {"http://example.org/ns/realm": "main"}
+        }]
+    }"#;
+    let webfinger: Webfinger = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        webfinger.properties.get("http://example.org/ns/name"),
+        Some(&Some("Alice".to_string()))
+    );
+    assert_eq!(
+        webfinger.properties.get("http://example.org/ns/unset"),
+        Some(&None)
+    );
+    assert_eq!(
+        webfinger.links[0]
+            .properties
+            .get("http://example.org/ns/realm"),
+        Some(&Some("main".to_string()))
+    );
+
+    let serialized = serde_json::to_value(&webfinger).unwrap();
+    assert_eq!(
+        serialized["properties"]["http://example.org/ns/unset"],
+        serde_json::Value::Null
+    );
+    assert_eq!(
+        serde_json::from_value::<Webfinger>(serialized).unwrap(),
+        webfinger
+    );
+    assert_eq!(
+        Webfinger::from_json_strict(json.as_bytes()).unwrap(),
+        webfinger
+    );
+    assert_eq!(
+        Webfinger::from_json_lenient(json.as_bytes()).unwrap().0,
+        webfinger
+    );
+
+    let mut bare = webfinger.clone();
+    bare.properties.clear();
+    bare.links[0].properties.clear();
+    let serialized = serde_json::to_string(&bare).unwrap();
+    assert!(!serialized.contains("properties"));
+    assert!(!bare.semantically_eq(&webfinger));
+    bare.merge(webfinger.clone());
+    assert_eq!(bare.properties, webfinger.properties);
+
+    let (_, xrd) = ResponseFormat::Xrd.render(&webfinger);
+    assert!(xrd.contains("<Property type=\"http://example.org/ns/name\">Alice</Property>"));
+    assert!(xrd.contains("xsi:nil=\"true\"/>"));
+    assert!(xrd
+        .contains("    <Property type=\"http://example.org/ns/realm\">main</Property>\n  </Link>"));
+}
+
+#[test]
+#[cfg(all(feature = "async", feature = "tokio"))]
+fn test_sync_as_async() {
+    use std::thread::{self, ThreadId};
+
+    /// Remembers the threads on which lookups ran.
+    struct ThreadResolver(Mutex<Vec<ThreadId>>);
+
+    impl Resolver<&'static str> for ThreadResolver {
+        fn instance_domain(&self) -> InstanceDomain {
+            MyResolver.instance_domain()
+        }
+
+        fn find(
+            &self,
+            prefix: Prefix,
+            acct: String,
+            resource_repo: &'static str,
+        ) -> Result<Webfinger, ResolverError> {
+            self.0.lock().unwrap().push(thread::current().id());
+            MyResolver.find(prefix, acct, resource_repo)
+        }
+    }
+
+    let r = Runtime::new().unwrap();
+    let resolver = SyncAsAsync::new(MyResolver);
+    r.block_on(async {
+        assert_eq!(
+            resolver
+                .endpoint("acct:admin@instance.tld", "admin")
+                .await
+                .unwrap(),
+            MyResolver
+                .endpoint("acct:admin@instance.tld", "admin")
+                .unwrap()
+        );
+        assert!(resolver
+            .endpoint("https://instance.tld/@admin", "admin")
+            .await
+            .is_ok());
+        assert_eq!(
+            resolver.endpoint("acct:test@instance.tld", "admin").await,
+            Err(ResolverError::NotFound)
+        );
+        assert_eq!(
+            resolver.endpoint("acct:admin@oops.ie", "admin").await,
+            Err(ResolverError::WrongDomain)
+        );
+    });
+
+    let resolver = SyncAsAsync::new(ThreadResolver(Mutex::new(vec![])));
+    r.block_on(resolver.endpoint("acct:admin@instance.tld", "admin"))
+        .unwrap();
+    let threads = resolver.into_inner().0.lock().unwrap().clone();
+    assert_eq!(threads.len(), 1);
+    assert_ne!(threads[0], thread::current().id());
+}
+
+#[test]
+fn test_link_titles() {
+    let json = r#"{
+        "subject": "acct:alice@example.org",
+        "links": [{
+            "rel": "http://webfinger.net/rel/profile-page",
+            "href": "https://example.org/@alice",
+            "titles": {"en-us": "Profile", "fr": "Profil", "und": "Alice"}
+        }]
+    }"#;
+    let webfinger: Webfinger = serde_json::from_str(json).unwrap();
+    let link = &webfinger.links[0];
+    assert_eq!(link.titles.len(), 3);
+    assert_eq!(link.title("en-US"), Some("Profile"));
+    assert_eq!(link.title("en"), Some("Profile"));
+    assert_eq!(link.title("fr-CA"), Some("Profil"));
+    assert_eq!(link.title("de"), Some("Alice"));
+
+    let serialized = serde_json::to_string(&webfinger).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Webfinger>(&serialized).unwrap(),
+        webfinger
+    );
+    assert_eq!(
+        Webfinger::from_json_strict(json.as_bytes()).unwrap(),
+        webfinger
+    );
+    let bare = Link::atom("https://example.org/@alice.atom").unwrap();
+    assert!(!serde_json::to_string(&bare).unwrap().contains("titles"));
+    assert_eq!(bare.title("en"), None);
+
+    let (_, xrd) = ResponseFormat::Xrd.render(&webfinger);
+    assert!(xrd.contains("    <Title xml:lang=\"fr\">Profil</Title>\n"));
+}
+
+#[test]
+#[cfg(all(feature = "async", feature = "tokio"))]
+fn test_async_as_sync() {
+    let r = Runtime::new().unwrap();
+    let resolver = AsyncAsSync::new(MyAsyncResolver, r.handle().clone());
+    assert_eq!(resolver.instance_domain(), MyResolver.instance_domain());
+    assert!(resolver
+        .endpoint("acct:admin@instance.tld", "admin")
+        .is_ok());
+    assert_eq!(
+        resolver.endpoint("acct:test@instance.tld", "admin"),
+        Err(ResolverError::NotFound)
+    );
+    assert_eq!(
+        resolver.endpoint("acct:admin@oops.ie", "admin"),
+        Err(ResolverError::WrongDomain)
+    );
+
+    let round_trip = AsyncAsSync::new(SyncAsAsync::new(MyResolver), r.handle().clone());
+    assert_eq!(
+        round_trip.endpoint("https://instance.tld/@admin", "admin"),
+        MyResolver.endpoint("https://instance.tld/@admin", "admin")
+    );
+}
+
+#[test]
+fn test_extension_members() {
+    let json = r#"{
+        "subject": "acct:alice@example.org",
+        "aliases": [],
+        "links": [{
+            "rel": "self",
+            "type": "application/activity+json",
+            "href": "https://example.org/users/alice",
+            "_misskey_is_bot": false
+        }],
+        "_misskey_reactions": {"like": 3},
+        "x-origin": "example"
+    }"#;
+    let webfinger: Webfinger = serde_json::from_str(json).unwrap();
+    assert_eq!(webfinger.extra["x-origin"], "example");
+    assert_eq!(webfinger.extra["_misskey_reactions"]["like"], 3);
+    assert_eq!(webfinger.links[0].extra["_misskey_is_bot"], false);
+    assert!(!webfinger.extra.contains_key("subject"));
+
+    let original: serde_json::Value = serde_json::from_str(json).unwrap();
+    assert_eq!(serde_json::to_value(&webfinger).unwrap(), original);
+    assert_eq!(
+        Webfinger::from_json_lenient(json.as_bytes()).unwrap().0,
+        webfinger
+    );
+    assert!(Webfinger::from_json_strict(json.as_bytes()).is_err());
+
+    let mut plain = webfinger.clone();
+    plain.extra.clear();
+    plain.links[0].extra.clear();
+    assert!(plain.semantically_eq(&webfinger));
+    assert_eq!(
+        serde_json::to_string(&plain).unwrap(),
+        r#"{"subject":"acct:alice@example.org","aliases":[],"links":[{"rel":"self","href":"https://example.org/users/alice","type":"application/activity+json"}]}"#
+    );
+    plain.merge(webfinger.clone());
+    assert_eq!(plain.extra, webfinger.extra);
+}
+
+#[test]
+fn test_webfinger_query() {
+    let query = WebfingerQuery::parse(
+        "resource=acct%3Aalice%40example.org&rel=self&lang=fr&rel=http%3A%2F%2Fwebfinger.net%2Frel%2Favatar&rel=self",
+    )
+    .unwrap();
+    assert_eq!(query.resource, "acct:alice@example.org");
+    assert_eq!(
+        query.rels(),
+        vec!["self", "http://webfinger.net/rel/avatar"]
+    );
+
+    let limits = QueryLimits {
+        max_rels: 2,
+        max_value_length: 32,
+    };
+    let many = "resource=acct:a@example.org&rel=a&rel=b&rel=a&rel=b";
+    assert_eq!(
+        WebfingerQuery::parse_with_limits(many, &limits)
+            .unwrap()
+            .rels,
+        vec!["a", "b"]
+    );
+    let error = WebfingerQuery::parse_with_limits(&format!("{}&rel=c", many), &limits).unwrap_err();
+    assert_eq!(error, QueryError::TooManyRels(2));
+    assert_eq!(error.code(), "WF1003");
+    let long = format!("resource=acct:a@example.org&x={}", "a".repeat(33));
+    assert_eq!(
+        WebfingerQuery::parse_with_limits(&long, &limits),
+        Err(QueryError::ValueTooLong("x".into()))
+    );
+
+    assert_eq!(
+        WebfingerQuery::parse("rel=self"),
+        Err(QueryError::MissingResource)
+    );
+    assert_eq!(
+        WebfingerQuery::parse("resource=&rel=self"),
+        Err(QueryError::MissingResource)
+    );
+    assert_eq!(
+        WebfingerQuery::parse("resource=acct:a@example.org&resource=acct:b@example.org"),
+        Err(QueryError::DuplicateResource)
+    );
+    assert_eq!(
+        ResolverError::from(QueryError::MissingResource),
+        ResolverError::MissingResource
+    );
+    assert_eq!(
+        ResolverError::from(QueryError::TooManyRels(20)).status(),
+        400
+    );
+    assert_eq!(
+        crate::Error::from(QueryError::DuplicateResource).code(),
+        "WF1002"
+    );
+}
+
+#[test]
+fn test_filter_by_rels() {
+    /// Always sends all its links.
+    struct RawResolver;
+
+    impl Resolver<&'static str> for RawResolver {
+        fn instance_domain(&self) -> InstanceDomain {
+            MyResolver.instance_domain()
+        }
+
+        fn find(
+            &self,
+            prefix: Prefix,
+            acct: String,
+            resource_repo: &'static str,
+        ) -> Result<Webfinger, ResolverError> {
+            let mut webfinger = MyResolver.find(prefix, acct, resource_repo)?;
+            webfinger
+                .links
+                .push(Link::activity_stream("https://instance.tld/users/admin").unwrap());
+            Ok(webfinger)
+        }
+
+        fn filter_by_rels(&self) -> bool {
+            false
+        }
+    }
+
+    let resource = "acct:admin@instance.tld";
+    let filtered = MyResolver
+        .endpoint_with_rels(resource, &["self"], "admin")
+        .unwrap();
+    assert!(filtered.filtered.links.is_empty());
+    assert_eq!(filtered.full.links.len(), 1);
+
+    let raw = RawResolver
+        .endpoint_with_rels(resource, &["self"], "admin")
+        .unwrap();
+    assert_eq!(raw.filtered, raw.full);
+    assert_eq!(raw.filtered.links.len(), 2);
+
+    let wrapped = DenyRels::new(RawResolver, vec!["http://webfinger.net/rel/profile-page"]);
+    assert!(!wrapped.filter_by_rels());
+    let raw = wrapped
+        .endpoint_with_rels(resource, &["http://webfinger.net/rel/avatar"], "admin")
+        .unwrap();
+    assert_eq!(raw.filtered.links.len(), 1);
+    assert_eq!(raw.filtered.links[0].rel, Rel::SelfRel);
+}
+
+#[test]
+fn test_certificate_pins() {
+    use std::convert::TryInto;
+
+    let hex = "4e2f".repeat(16);
+    let pin = CertificatePin::parse(&format!("spki-sha256:{}", hex)).unwrap();
+    assert_eq!(
+        pin,
+        CertificatePin::PublicKey([0x4e, 0x2f].repeat(16).try_into().unwrap())
+    );
+    assert_eq!(pin.to_string(), format!("spki-sha256:{}", hex));
+    let fingerprint = vec!["4E:2F"; 16].join(":");
+    let pin = CertificatePin::parse(&format!("CERT-SHA256:{}", fingerprint)).unwrap();
+    assert_eq!(pin.to_string(), format!("cert-sha256:{}", hex));
+    for invalid in [
+        "",
+        "spki-sha256:",
+        "spki-sha256:4e2f",
+        "md5-sha256:4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f",
+        "spki-sha256:zz2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f4e2f",
+    ] {
+        assert_eq!(
+            CertificatePin::parse(invalid),
+            Err(WebfingerError::ParseError)
+        );
+    }
+
+    let config: ClientConfig = serde_json::from_str(&format!(
+        r#"{{"domains": {{"example.org": {{"pins": ["spki-sha256:{}"]}}}}}}"#,
+        hex
+    ))
+    .unwrap();
+    assert_eq!(
+        config.domains["example.org"].pins,
+        vec![CertificatePin::parse(&format!("spki-sha256:{}", hex)).unwrap()]
+    );
+    assert!(serde_json::from_str::<ClientConfig>(
+        r#"{"domains": {"example.org": {"pins": ["spki-sha256:4e2f"]}}}"#
+    )
+    .is_err());
+
+    let result = WebfingerClient::builder()
+        .pin_certificate("example.org:8443", pin)
+        .try_build();
+    assert_eq!(result.err(), Some(WebfingerError::ParseError));
+
+    // Pins can't be checked by custom HTTP clients
+    let result = WebfingerClient::builder()
+        .http_fetch(FakeFetch::new(200, "{}"))
+        .pin_certificate("example.org", pin)
+        .try_build();
+    assert_eq!(result.err(), Some(WebfingerError::HttpError));
+    let result = WebfingerClient::builder().config(&config).try_build();
+    assert_eq!(result.is_ok(), cfg!(feature = "ring"));
+
+    #[cfg(feature = "ring")]
+    {
+        fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+            let mut element = vec![tag, content.len() as u8];
+            element.extend_from_slice(content);
+            element
+        }
+        fn sha256(data: &[u8]) -> [u8; 32] {
+            ring::digest::digest(&ring::digest::SHA256, data)
+                .as_ref()
+                .try_into()
+                .unwrap()
+        }
+
+        let spki = der(
+            0x30,
+            &[der(0x30, &[0x06, 0x01, 0x2a]), der(0x03, &[0, 1, 2])].concat(),
+        );
+        let tbs = [
+            der(0xa0, &der(0x02, &[2])),
+            der(0x02, &[42]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            spki.clone(),
+        ]
+        .concat();
+        let certificate = der(
+            0x30,
+            &[der(0x30, &tbs), der(0x30, &[]), der(0x03, &[0])].concat(),
+        );
+
+        assert!(CertificatePin::PublicKey(sha256(&spki)).matches(&certificate));
+        assert!(CertificatePin::Certificate(sha256(&certificate)).matches(&certificate));
+        assert!(!CertificatePin::PublicKey(sha256(&certificate)).matches(&certificate));
+        assert!(!CertificatePin::Certificate(sha256(&spki)).matches(&certificate));
+        assert!(!CertificatePin::PublicKey(sha256(&spki)).matches(&certificate[..20]));
+
+        #[cfg(feature = "fetch-reqwest")]
+        {
+            use crate::transport::{is_pin_mismatch, PinVerifier};
+            use std::convert::TryFrom;
+
+            // Any certificate of the chain can match, and only the pins of the host apply
+            let pins = vec![(
+                "example.org".to_string(),
+                vec![CertificatePin::PublicKey(sha256(&spki))],
+            )]
+            .into_iter()
+            .collect();
+            let leaf = rustls::Certificate(certificate[..20].to_vec());
+            let intermediate = rustls::Certificate(certificate.clone());
+            let pinned = rustls::ServerName::try_from("example.org").unwrap();
+            let other = rustls::ServerName::try_from("example.com").unwrap();
+            assert_eq!(
+                PinVerifier::check_pins(&pins, &pinned, &[&leaf, &intermediate]),
+                Ok(())
+            );
+            let error = PinVerifier::check_pins(&pins, &pinned, &[&leaf]).unwrap_err();
+            assert_eq!(PinVerifier::check_pins(&pins, &other, &[&leaf]), Ok(()));
+
+            // The error of the handshake is reported as a pin mismatch
+            let wrapped = std::io::Error::new(std::io::ErrorKind::InvalidData, error);
+            assert!(is_pin_mismatch(&wrapped));
+            let unrelated = std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid");
+            assert!(!is_pin_mismatch(&unrelated));
+
+            // Nothing is sent to pinned hosts over plain HTTP
+            let client = WebfingerClient::builder()
+                .https(false)
+                .pin_certificate("example.org", pin)
+                .build();
+            Runtime::new().unwrap().block_on(async {
+                assert_eq!(
+                    client.resolve("acct:test@example.org").await,
+                    Err(WebfingerError::PinMismatch)
+                );
+            });
+        }
+    }
+}
+
+#[test]
+fn test_txt_discovery() {
+    let r = Runtime::new().unwrap();
+    let fetch = Arc::new(FakeFetch::new(
+        200,
+        r#"{"subject": "acct:test@example.org", "links": []}"#,
+    ));
+    let records: HashMap<String, Vec<String>> = vec![
+        (
+            "_webfinger.example.org".to_string(),
+            vec![
+                "v=spf1 -all".to_string(),
+                "http://insecure.example/wf".to_string(),
+                "https://files.example.net/wf?site=1".to_string(),
+            ],
+        ),
+        (
+            "_webfinger.example.com".to_string(),
+            vec!["https://files.example.net/wf/{uri}.json".to_string()],
+        ),
+        (
+            "_webfinger.blocked.example".to_string(),
+            vec!["https://spam.example/wf".to_string()],
+        ),
+    ]
+    .into_iter()
+    .collect();
+    let client = WebfingerClient::builder()
+        .http_fetch(fetch.clone())
+        .txt_discovery(records)
+        .block_domain("spam.example")
+        .build();
+    r.block_on(async {
+        assert!(client.resolve("test@example.org").await.is_ok());
+        assert!(client.resolve("test@example.com").await.is_ok());
+        assert!(client.resolve("test@example.net").await.is_ok());
+        assert_eq!(
+            client.resolve("test@blocked.example").await,
+            Err(WebfingerError::BlockedDomain)
+        );
+    });
+    let urls: Vec<_> = fetch.requests().into_iter().map(|r| r.url).collect();
+    assert_eq!(
+        urls,
+        vec![
+            "https://files.example.net/wf?site=1&resource=acct:test@example.org",
+            "https://files.example.net/wf/acct:test@example.com.json",
+            "https://example.net/.well-known/webfinger?resource=acct:test@example.net",
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_async_resolver_repos() {
+    /// A database of users, used in place of the usual `&'static str` repository.
+    struct Users(Vec<&'static str>);
+
+    /// Accepts both repositories.
+    struct TwoRepos;
+
+    impl AsyncResolver<&'static str> for TwoRepos {
+        async fn instance_domain(&self) -> InstanceDomain {
+            InstanceDomain::new("instance.tld").unwrap()
+        }
+
+        async fn find(
+            &self,
+            prefix: Prefix,
+            acct: String,
+            resource_repo: &'static str,
+        ) -> Result<Webfinger, ResolverError> {
+            MyAsyncResolver.find(prefix, acct, resource_repo).await
+        }
+    }
+
+    impl AsyncResolver<Arc<Users>> for TwoRepos {
+        async fn instance_domain(&self) -> InstanceDomain {
+            InstanceDomain::new("instance.tld").unwrap()
+        }
+
+        async fn find(
+            &self,
+            prefix: Prefix,
+            acct: String,
+            resource_repo: Arc<Users>,
+        ) -> Result<Webfinger, ResolverError> {
+            match resource_repo.0.iter().find(|user| **user == acct) {
+                Some(user) => MyAsyncResolver.find(prefix, acct, *user).await,
+                None => Err(ResolverError::NotFound),
+            }
+        }
+    }
+
+    // The repository can be borrowed, for the duration of a request only
+    impl<'a> AsyncResolver<&'a Users> for TwoRepos {
+        async fn instance_domain(&self) -> InstanceDomain {
+            InstanceDomain::new("instance.tld").unwrap()
+        }
+
+        async fn find(
+            &self,
+            prefix: Prefix,
+            acct: String,
+            resource_repo: &'a Users,
+        ) -> Result<Webfinger, ResolverError> {
+            match resource_repo.0.iter().find(|user| **user == acct) {
+                Some(user) => MyAsyncResolver.find(prefix, acct, *user).await,
+                None => Err(ResolverError::NotFound),
+            }
+        }
+    }
+
+    let r = Runtime::new().unwrap();
+    let users = Arc::new(Users(vec!["admin", "test"]));
+    r.block_on(async {
+        let by_name = TwoRepos
+            .endpoint("acct:test@instance.tld", "test")
+            .await
+            .unwrap();
+        let by_users = TwoRepos
+            .endpoint("acct:test@instance.tld", users.clone())
+            .await
+            .unwrap();
+        assert_eq!(by_name, by_users);
+        assert_eq!(
+            TwoRepos
+                .endpoint("acct:alice@instance.tld", users.clone())
+                .await,
+            Err(ResolverError::NotFound)
+        );
+
+        let borrowed = Users(vec!["test"]);
+        assert_eq!(
+            TwoRepos.endpoint("acct:test@instance.tld", &borrowed).await,
+            Ok(by_name)
+        );
+        assert_eq!(
+            DenyRels::new(TwoRepos, vec!["self"])
+                .endpoint("acct:admin@instance.tld", &borrowed)
+                .await,
+            Err(ResolverError::NotFound)
+        );
+    });
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_local_async_resolver() {
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Counts the lookups in a repository that can't be shared between threads.
+    struct Counting;
+
+    impl LocalAsyncResolver<Rc<RefCell<Vec<String>>>> for Counting {
+        async fn instance_domain(&self) -> InstanceDomain {
+            InstanceDomain::new("instance.tld").unwrap()
+        }
+
+        async fn find(
+            &self,
+            prefix: Prefix,
+            acct: String,
+            resource_repo: Rc<RefCell<Vec<String>>>,
+        ) -> Result<Webfinger, ResolverError> {
+            resource_repo.borrow_mut().push(acct.clone());
+            MyAsyncResolver.find(prefix, acct, "admin").await
+        }
+    }
+
+    let lookups = Rc::new(RefCell::new(vec![]));
+    let r = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    r.block_on(async {
+        let found = Counting
+            .endpoint("acct:admin@instance.tld", lookups.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            found,
+            MyAsyncResolver
+                .endpoint("acct:admin@instance.tld", "admin")
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            Counting
+                .endpoint("acct:admin@other.tld", lookups.clone())
+                .await,
+            Err(ResolverError::WrongDomain)
+        );
+        let results = Counting
+            .endpoint_batch(
+                &["acct:admin@instance.tld", "acct:test@instance.tld"],
+                &[],
+                lookups.clone(),
+            )
+            .await;
+        assert!(results["acct:admin@instance.tld"].is_ok());
+        assert_eq!(
+            results["acct:test@instance.tld"],
+            Err(ResolverError::NotFound)
+        );
+    });
+    assert_eq!(*lookups.borrow(), vec!["admin", "admin", "test"]);
+}
+
+#[test]
+fn test_prefix_router() {
+    let document = |subject: String| Webfinger {
+        subject,
+        aliases: vec![],
+        links: vec![],
+        properties: HashMap::new(),
+        expires: None,
+        extra: Map::new(),
+    };
+    let router = PrefixRouter::new(InstanceDomain::new("instance.tld").unwrap())
+        .route(Prefix::Acct, |_, _, _| Err(ResolverError::NotFound))
+        .route(
+            Prefix::Acct,
+            move |user, caller, repo: &'static str| match (user == repo, caller) {
+                (true, Some(caller)) => Ok(document(caller.id.clone())),
+                (true, None) => Ok(document(format!("acct:{}@instance.tld", user))),
+                (false, _) => Err(ResolverError::NotFound),
+            },
+        )
+        .route(Prefix::Custom("XMPP".into()), move |user, _, _| {
+            Ok(document(format!("xmpp:{}@instance.tld", user)))
+        })
+        .urls(move |url, _| Ok(document(url.to_string())));
+
+    assert_eq!(
+        router
+            .endpoint("acct:admin@instance.tld", "admin")
+            .unwrap()
+            .subject,
+        "acct:admin@instance.tld"
+    );
+    let caller = Caller::new("https://other.tld/users/bob");
+    assert_eq!(
+        router
+            .endpoint_for_caller("acct:admin@instance.tld", Some(&caller), "admin")
+            .unwrap()
+            .subject,
+        caller.id
+    );
+    assert_eq!(
+        router.endpoint("acct:test@instance.tld", "admin"),
+        Err(ResolverError::NotFound)
+    );
+    assert_eq!(
+        router
+            .endpoint("xmpp:admin@instance.tld", "admin")
+            .unwrap()
+            .subject,
+        "xmpp:admin@instance.tld"
+    );
+    assert_eq!(
+        router.endpoint("group:admin@instance.tld", "admin"),
+        Err(ResolverError::NotFound)
+    );
+    assert_eq!(
+        router
+            .endpoint("https://instance.tld/@admin", "admin")
+            .unwrap()
+            .subject,
+        "https://instance.tld/@admin"
+    );
+    assert_eq!(
+        router.endpoint("acct:admin@oops.ie", "admin"),
+        Err(ResolverError::WrongDomain)
+    );
+    assert!(format!("{:?}", router).contains("[\"acct\", \"xmpp\"]"));
+
+    #[cfg(all(feature = "async", feature = "tokio"))]
+    {
+        let r = Runtime::new().unwrap();
+        let resolver = SyncAsAsync::new(router);
+        assert!(r
+            .block_on(resolver.endpoint("acct:admin@instance.tld", "admin"))
+            .is_ok());
+    }
+}
+
+#[test]
+fn test_parse_warnings() {
+    let r = Runtime::new().unwrap();
+    let body = r#"{"subject": "acct:test@example.org", "links": []}"#;
+    let resolve = |fetch: FakeFetch| {
+        let client = WebfingerClient::builder().http_fetch(fetch).build();
+        r.block_on(client.resolve_detailed("test@example.org", &RequestOptions::new()))
+    };
+
+    let mut fetch = FakeFetch::new(200, &format!("\u{feff}{}", body));
+    fetch.response.headers = vec![("Content-Type".into(), "text/plain".into())];
+    let resolved = resolve(fetch).unwrap();
+    assert_eq!(resolved.document.subject, "acct:test@example.org");
+    assert_eq!(
+        resolved.metadata.warnings,
+        vec![
+            ParseWarning::ByteOrderMark,
+            ParseWarning::UnexpectedContentType("text/plain".to_string())
+        ]
+    );
+
+    let mut fetch = FakeFetch::new(200, body);
+    fetch.response.headers = vec![(
+        "Content-Type".into(),
+        "Application/JSON; charset=utf-8".into(),
+    )];
+    assert!(resolve(fetch).unwrap().metadata.warnings.is_empty());
+    assert!(resolve(FakeFetch::new(200, body))
+        .unwrap()
+        .metadata
+        .warnings
+        .is_empty());
+
+    let mut fetch = FakeFetch::new(200, "\u{feff}not json");
+    fetch.response.headers = vec![];
+    assert!(matches!(resolve(fetch), Err(WebfingerError::JsonError(_))));
+}
+
+#[test]
+#[cfg(not(feature = "lenient"))]
+fn test_parse_warnings_without_lenient() {
+    let r = Runtime::new().unwrap();
+    let body = r#"{"subject": "acct:test@example.org", "links": []}"#;
+    let resolve = |body: &str| {
+        let client = WebfingerClient::builder()
+            .http_fetch(FakeFetch::new(200, body))
+            .build();
+        r.block_on(client.resolve_detailed("test@example.org", &RequestOptions::new()))
+    };
+
+    let resolved = resolve(&format!("\u{feff}{}", body)).unwrap();
+    assert_eq!(
+        resolved.metadata.warnings,
+        vec![ParseWarning::ByteOrderMark]
+    );
+    assert!(matches!(
+        resolve(&format!("[{}]", body)),
+        Err(WebfingerError::JsonError(_))
+    ));
+}
+
+#[test]
+#[cfg(feature = "tokio")]
+fn test_dedup_cancelled_waiters() {
+    use std::time::Duration;
+
+    let r = Runtime::new().unwrap();
+    let client = WebfingerClient::builder()
+        .http_fetch(HangsFirst(Mutex::new(0)))
+        .dedup_in_flight(Duration::from_secs(60))
+        .build();
+    r.block_on(async {
+        let first = tokio::time::timeout(
+            Duration::from_millis(20),
+            client.resolve("test@example.org"),
+        )
+        .await;
+        assert!(first.is_err());
+        let second =
+            tokio::time::timeout(Duration::from_secs(5), client.resolve("test@example.org"))
+                .await
+                .expect("the cancelled request should not be shared anymore");
+        assert_eq!(second.unwrap().subject, "acct:test@example.org");
+    });
 }
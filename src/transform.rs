@@ -0,0 +1,190 @@
+use crate::{Caller, InstanceDomain, Link, Prefix, Resolver, ResolverError, Webfinger};
+use std::{fmt, sync::Arc};
+use url::Url;
+
+/// A change made to every document returned by a [`TransformResponses`] resolver.
+///
+/// It is implemented for closures taking and returning a [`Webfinger`].
+pub trait ResponseTransform: Send + Sync {
+    /// Changes a document before it is sent.
+    fn transform(&self, webfinger: Webfinger) -> Webfinger;
+}
+
+impl<F: Fn(Webfinger) -> Webfinger + Send + Sync> ResponseTransform for F {
+    fn transform(&self, webfinger: Webfinger) -> Webfinger {
+        self(webfinger)
+    }
+}
+
+/// A [`ResponseTransform`] adding the same links to every document.
+///
+/// It can be used for instance-wide links, like a donation page or an OpenID issuer.
+#[derive(Debug, Clone)]
+pub struct AddLinks(pub Vec<Link>);
+
+impl ResponseTransform for AddLinks {
+    fn transform(&self, mut webfinger: Webfinger) -> Webfinger {
+        webfinger.links.extend(self.0.iter().cloned());
+        webfinger
+    }
+}
+
+/// A [`ResponseTransform`] removing the links whose `href` starts with one of the given prefixes.
+///
+/// It can be used to avoid leaking internal URLs.
+#[derive(Debug, Clone)]
+pub struct StripHrefs(pub Vec<String>);
+
+impl ResponseTransform for StripHrefs {
+    fn transform(&self, mut webfinger: Webfinger) -> Webfinger {
+        webfinger.links.retain(|link| {
+            link.href.as_ref().map_or(true, |href| {
+                !self.0.iter().any(|prefix| href.starts_with(prefix))
+            })
+        });
+        webfinger
+    }
+}
+
+/// A resolver wrapper applying a chain of [`ResponseTransform`]s to the results of another
+/// resolver.
+///
+/// The transforms are applied in the order in which they were added, after the document was
+/// found.
+#[derive(Clone)]
+pub struct TransformResponses<T> {
+    inner: T,
+    transforms: Vec<Arc<dyn ResponseTransform>>,
+}
+
+impl<T> TransformResponses<T> {
+    /// Wraps a resolver, without any transform.
+    pub fn new(inner: T) -> TransformResponses<T> {
+        TransformResponses {
+            inner,
+            transforms: vec![],
+        }
+    }
+
+    /// Adds a transform at the end of the chain.
+    pub fn with(mut self, transform: impl ResponseTransform + 'static) -> TransformResponses<T> {
+        self.transforms.push(Arc::new(transform));
+        self
+    }
+
+    /// Returns the wrapped resolver.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn apply(&self, webfinger: Webfinger) -> Webfinger {
+        self.transforms
+            .iter()
+            .fold(webfinger, |webfinger, transform| {
+                transform.transform(webfinger)
+            })
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for TransformResponses<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransformResponses")
+            .field("inner", &self.inner)
+            .field("transforms", &self.transforms.len())
+            .finish()
+    }
+}
+
+impl<R, T: Resolver<R>> Resolver<R> for TransformResponses<T> {
+    fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain()
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain)
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find(prefix, acct, resource_repo)
+            .map(|webfinger| self.apply(webfinger))
+    }
+
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find_for_caller(prefix, acct, caller, resource_repo)
+            .map(|webfinger| self.apply(webfinger))
+    }
+
+    fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find_by_url(url, resource_repo)
+            .map(|webfinger| self.apply(webfinger))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, R> crate::AsyncResolver<R> for TransformResponses<T>
+where
+    T: crate::AsyncResolver<R> + Send + Sync,
+    R: Send,
+{
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain().await
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        self.inner.accepts_domain(domain).await
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find(prefix, acct, resource_repo)
+            .await
+            .map(|webfinger| self.apply(webfinger))
+    }
+
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find_for_caller(prefix, acct, caller, resource_repo)
+            .await
+            .map(|webfinger| self.apply(webfinger))
+    }
+
+    async fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        self.inner
+            .find_by_url(url, resource_repo)
+            .await
+            .map(|webfinger| self.apply(webfinger))
+    }
+}
@@ -0,0 +1,610 @@
+use crate::{
+    consts, parse_document, split_prefix, url_for, AddressFamily, Freshness, RequestOptions,
+    ResolveMetadata, Resolved, ServerHints, StatusAction, StatusPolicy, Timings, Webfinger,
+    WebfingerError,
+};
+use futures_util::future::BoxFuture;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use url::Url;
+
+/// An HTTP `GET` request, made to fetch a WebFinger resource.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    /// The complete URL to fetch, including the query string.
+    pub url: String,
+
+    /// The headers to send.
+    pub headers: Vec<(String, String)>,
+}
+
+/// The response to an [`HttpRequest`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    /// The HTTP status code.
+    pub status: u16,
+
+    /// The headers of the response.
+    pub headers: Vec<(String, String)>,
+
+    /// The raw body of the response.
+    pub body: Vec<u8>,
+
+    /// The durations measured by the HTTP client, if any.
+    ///
+    /// The [`total`](Timings::total) duration is ignored, since it is measured by the client.
+    pub timings: Timings,
+}
+
+impl HttpResponse {
+    /// Returns the value of a header, ignoring the case of its name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// The maximum size of the response bodies read by the HTTP clients of the crate, 1 MiB.
+///
+/// WebFinger documents are much smaller: a larger response is refused with
+/// [`WebfingerError::HttpError`] as soon as it goes over the limit, without reading the rest.
+pub const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Something that can make HTTP requests.
+///
+/// It is implemented for `reqwest::Client` with the `fetch-reqwest` feature, for `ureq::Agent`
+/// with the `fetch-ureq` feature, and for `hyper_util::client::legacy::Client` with the
+/// `fetch-hyper` feature, but you can implement it for any other HTTP client. This trait is object
+/// safe, so it can be used as `&dyn HttpFetch` when the actual client isn't known at compile time,
+/// for instance in plugin systems.
+pub trait HttpFetch: Send + Sync {
+    /// Sends a request and returns its response.
+    ///
+    /// Responses with an error status should be returned as any other response: only failures to
+    /// get a response at all should be reported as errors. Bodies larger than [`MAX_BODY_SIZE`]
+    /// should be refused with [`WebfingerError::HttpError`], so that a remote host can't exhaust
+    /// the memory of the application.
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>>;
+}
+
+#[cfg(feature = "fetch-reqwest")]
+impl HttpFetch for reqwest::Client {
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        Box::pin(fetch_reqwest(self, request))
+    }
+}
+
+/// Sends a request with `reqwest`.
+#[cfg(feature = "fetch-reqwest")]
+async fn fetch_reqwest(
+    client: &reqwest::Client,
+    request: HttpRequest,
+) -> Result<HttpResponse, WebfingerError> {
+    let start = Instant::now();
+    let mut builder = client.get(&request.url[..]);
+    for (name, value) in &request.headers {
+        builder = builder.header(&name[..], &value[..]);
+    }
+
+    let response = builder.send().await.map_err(|_error| {
+        #[cfg(feature = "ring")]
+        if is_pin_mismatch(&_error) {
+            return WebfingerError::PinMismatch;
+        }
+        WebfingerError::HttpError
+    })?;
+    let ttfb = start.elapsed();
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|value| (name.as_str().to_string(), value.to_string()))
+        })
+        .collect();
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_BODY_SIZE as u64)
+    {
+        return Err(WebfingerError::HttpError);
+    }
+    let mut response = response;
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|_| WebfingerError::HttpError)?
+    {
+        if body.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(WebfingerError::HttpError);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(HttpResponse {
+        status,
+        headers,
+        body,
+        timings: Timings {
+            ttfb: Some(ttfb),
+            ..Timings::default()
+        },
+    })
+}
+
+/// A `reqwest` client checking the certificates of the hosts that have pins.
+///
+/// The certificates are checked by [`PinVerifier`] during the TLS handshake. This client only
+/// refuses to send requests to these hosts over plain HTTP.
+#[cfg(all(feature = "fetch-reqwest", feature = "ring"))]
+struct PinnedClient {
+    client: reqwest::Client,
+    pins: Arc<HashMap<String, Vec<crate::CertificatePin>>>,
+}
+
+#[cfg(all(feature = "fetch-reqwest", feature = "ring"))]
+impl HttpFetch for PinnedClient {
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        let url = Url::parse(&request.url).ok();
+        if url.is_some_and(|url| !is_pinned_url(&self.pins, &url)) {
+            return Box::pin(futures_util::future::ready(Err(
+                WebfingerError::PinMismatch,
+            )));
+        }
+        Box::pin(fetch_reqwest(&self.client, request))
+    }
+}
+
+/// Tells if `url` can be fetched without bypassing pins: pinned hosts must use HTTPS.
+#[cfg(all(feature = "fetch-reqwest", feature = "ring"))]
+fn is_pinned_url(pins: &HashMap<String, Vec<crate::CertificatePin>>, url: &Url) -> bool {
+    url.scheme() == "https" || url.host_str().map_or(true, |host| !pins.contains_key(host))
+}
+
+/// Validates certificates as usual, then checks the pins of the host being connected to.
+///
+/// The name of the host is the one used for the handshake, so redirections and endpoints
+/// discovered in DNS are checked too. A certificate matches if any certificate of the chain
+/// presented by the host, from the one of the host to the intermediate ones, matches one of the
+/// pins.
+#[cfg(all(feature = "fetch-reqwest", feature = "ring"))]
+pub(crate) struct PinVerifier {
+    inner: rustls::client::WebPkiVerifier,
+    pins: Arc<HashMap<String, Vec<crate::CertificatePin>>>,
+}
+
+#[cfg(all(feature = "fetch-reqwest", feature = "ring"))]
+impl PinVerifier {
+    /// Checks the chain of certificates presented by `server_name`.
+    ///
+    /// Fails with [`rustls::CertificateError::ApplicationVerificationFailure`] when the host
+    /// has pins and no certificate of the chain matches them.
+    pub(crate) fn check_pins(
+        pins: &HashMap<String, Vec<crate::CertificatePin>>,
+        server_name: &rustls::ServerName,
+        chain: &[&rustls::Certificate],
+    ) -> Result<(), rustls::Error> {
+        let host = match server_name {
+            rustls::ServerName::DnsName(name) => name.as_ref().to_string(),
+            rustls::ServerName::IpAddress(ip) => ip.to_string(),
+            _ => return Ok(()),
+        };
+        match pins.get(host.trim_end_matches('.')) {
+            Some(pins)
+                if !chain
+                    .iter()
+                    .any(|certificate| pins.iter().any(|pin| pin.matches(&certificate.0))) =>
+            {
+                Err(rustls::Error::InvalidCertificate(
+                    rustls::CertificateError::ApplicationVerificationFailure,
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(all(feature = "fetch-reqwest", feature = "ring"))]
+impl rustls::client::ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        server_name: &rustls::ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        let chain: Vec<_> = std::iter::once(end_entity).chain(intermediates).collect();
+        PinVerifier::check_pins(&self.pins, server_name, &chain)?;
+        Ok(verified)
+    }
+}
+
+/// Tells if a request failed because the certificate of the host didn't match its pins.
+///
+/// It is the error of [`PinVerifier`], or the one of the redirection policy for redirections to
+/// plain HTTP. The former is wrapped in an I/O error by the TLS connector, which doesn't report
+/// it as its source, so I/O errors are opened too.
+#[cfg(all(feature = "fetch-reqwest", feature = "ring"))]
+pub(crate) fn is_pin_mismatch(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if let Some(rustls::Error::InvalidCertificate(
+            rustls::CertificateError::ApplicationVerificationFailure,
+        )) = error.downcast_ref()
+        {
+            return true;
+        }
+        if error.downcast_ref() == Some(&WebfingerError::PinMismatch) {
+            return true;
+        }
+        let wrapped = error
+            .downcast_ref::<std::io::Error>()
+            .and_then(std::io::Error::get_ref);
+        if let Some(wrapped) = wrapped {
+            if is_pin_mismatch(wrapped) {
+                return true;
+            }
+        }
+        current = error.source();
+    }
+    false
+}
+
+/// Creates the TLS configuration of the clients checking `pins`.
+///
+/// It trusts the same root certificates as the default client, those of the system.
+#[cfg(all(feature = "fetch-reqwest", feature = "ring"))]
+fn pinned_tls_config(
+    pins: Arc<HashMap<String, Vec<crate::CertificatePin>>>,
+) -> Result<rustls::ClientConfig, WebfingerError> {
+    let mut roots = rustls::RootCertStore::empty();
+    for certificate in
+        rustls_native_certs::load_native_certs().map_err(|_| WebfingerError::HttpError)?
+    {
+        // Like the other clients, skip the system certificates that can't be parsed
+        let _ = roots.add(&rustls::Certificate(certificate.0));
+    }
+    let verifier = PinVerifier {
+        inner: rustls::client::WebPkiVerifier::new(roots, None),
+        pins,
+    };
+    Ok(rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth())
+}
+
+impl<T: HttpFetch + ?Sized> HttpFetch for Arc<T> {
+    fn fetch(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        (**self).fetch(request)
+    }
+}
+
+/// An [`HttpFetch`] implementation for builds without any HTTP client.
+#[cfg(not(any(feature = "fetch-reqwest", feature = "fetch-ureq")))]
+struct NoTransport;
+
+#[cfg(not(any(feature = "fetch-reqwest", feature = "fetch-ureq")))]
+impl HttpFetch for NoTransport {
+    fn fetch(&self, _: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, WebfingerError>> {
+        Box::pin(futures_util::future::ready(Err(WebfingerError::HttpError)))
+    }
+}
+
+/// The settings used to create the HTTP client when none is provided.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TransportSettings {
+    pub(crate) address_family: AddressFamily,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) proxy: Option<String>,
+    pub(crate) connect_to: HashMap<String, IpAddr>,
+    pub(crate) pins: HashMap<String, Vec<crate::CertificatePin>>,
+}
+
+/// Tells if `url` can be used as the proxy of the HTTP clients this crate creates.
+#[allow(unused_variables, unused_mut)]
+pub(crate) fn is_valid_proxy(url: &str) -> bool {
+    let mut valid = true;
+    #[cfg(feature = "fetch-reqwest")]
+    {
+        valid &= reqwest::Proxy::all(url).is_ok();
+    }
+    #[cfg(feature = "fetch-ureq")]
+    {
+        valid &= ureq::Proxy::new(url).is_ok();
+    }
+    valid
+}
+
+/// Creates the HTTP client to use when none is provided.
+///
+/// Fails with [`WebfingerError::ParseError`] if the proxy URL is invalid, and with
+/// [`WebfingerError::HttpError`] if there are certificate pins that can't be checked.
+#[allow(unused_variables)]
+pub(crate) fn default_transport(
+    settings: &TransportSettings,
+) -> Result<Arc<dyn HttpFetch>, WebfingerError> {
+    #[cfg(feature = "fetch-reqwest")]
+    {
+        use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+        let mut builder = reqwest::Client::builder();
+        builder = match settings.address_family {
+            AddressFamily::Any => builder,
+            AddressFamily::Ipv4Only => builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            AddressFamily::Ipv6Only => builder.local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        };
+        if let Some(timeout) = settings.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = settings.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(ref proxy) = settings.proxy {
+            let proxy = reqwest::Proxy::all(proxy).map_err(|_| WebfingerError::ParseError)?;
+            builder = builder.proxy(proxy);
+        }
+        for (domain, ip) in &settings.connect_to {
+            // The port is ignored by reqwest, the one of the URL is used
+            builder = builder.resolve(domain, std::net::SocketAddr::new(*ip, 0));
+        }
+        #[cfg(feature = "ring")]
+        if !settings.pins.is_empty() {
+            let pins = Arc::new(settings.pins.clone());
+            let redirect_pins = pins.clone();
+            let client = builder
+                .use_preconfigured_tls(pinned_tls_config(pins.clone())?)
+                .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                    if !is_pinned_url(&redirect_pins, attempt.url()) {
+                        attempt.error(WebfingerError::PinMismatch)
+                    } else if attempt.previous().len() >= 10 {
+                        attempt.error(WebfingerError::HttpError)
+                    } else {
+                        attempt.follow()
+                    }
+                }))
+                .build()
+                .map_err(|_| WebfingerError::HttpError)?;
+            return Ok(Arc::new(PinnedClient { client, pins }));
+        }
+        #[cfg(not(feature = "ring"))]
+        if !settings.pins.is_empty() {
+            return Err(WebfingerError::HttpError);
+        }
+        Ok(Arc::new(
+            builder.build().map_err(|_| WebfingerError::HttpError)?,
+        ))
+    }
+
+    #[cfg(not(feature = "fetch-reqwest"))]
+    if !settings.pins.is_empty() {
+        return Err(WebfingerError::HttpError);
+    }
+
+    #[cfg(all(feature = "fetch-ureq", not(feature = "fetch-reqwest")))]
+    {
+        Ok(Arc::new(crate::ureq_agent(settings)?))
+    }
+
+    #[cfg(not(any(feature = "fetch-reqwest", feature = "fetch-ureq")))]
+    {
+        Ok(Arc::new(NoTransport))
+    }
+}
+
+/// Fetches a WebFinger resource with the given HTTP client.
+///
+/// If the resource doesn't have a prefix, `acct:` will be used. The extra query parameters and
+/// headers of `options` are sent with the request.
+pub async fn resolve_with(
+    fetch: &dyn HttpFetch,
+    acct: impl Into<String>,
+    with_https: bool,
+    options: &RequestOptions,
+) -> Result<Webfinger, WebfingerError> {
+    let acct = acct.into();
+    let (prefix, acct) = split_prefix(&acct)?;
+    let url = url_for(prefix, acct, with_https)?;
+    Ok(
+        fetch_document(fetch, url, options, &StatusPolicy::default())
+            .await
+            .map_err(|failure| failure.error)?
+            .document,
+    )
+}
+
+/// A failed request, with the delay the server asked to wait for before retrying it.
+pub(crate) struct FetchFailure {
+    pub(crate) error: WebfingerError,
+    /// The `Retry-After` header of the response.
+    pub(crate) retry_after: Option<Duration>,
+}
+
+impl From<WebfingerError> for FetchFailure {
+    fn from(error: WebfingerError) -> FetchFailure {
+        FetchFailure {
+            error,
+            retry_after: None,
+        }
+    }
+}
+
+/// Parses a `Retry-After` header, either a number of seconds or a date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    match value.parse() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => Some(
+            crate::expires::parse_http_date(value)?
+                .duration_since(SystemTime::now())
+                .unwrap_or_default(),
+        ),
+    }
+}
+
+/// Fetches and parses the document at `url`.
+pub(crate) async fn fetch_document(
+    fetch: &dyn HttpFetch,
+    url: String,
+    options: &RequestOptions,
+    statuses: &StatusPolicy,
+) -> Result<Resolved, FetchFailure> {
+    let request = prepare_request(url, options)?;
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!(
+        "webfinger_fetch",
+        url = %request.url,
+        request_id = options.request_id.as_deref().unwrap_or_default(),
+    );
+
+    let request = async move {
+        let url = request.url.clone();
+        let start = Instant::now();
+        let response = fetch.fetch(request).await?;
+        if reports_missing_resource(&response) {
+            return Err(WebfingerError::MissingResource.into());
+        }
+        match statuses.action(response.status) {
+            StatusAction::Accept => {}
+            StatusAction::NotFound => return Err(WebfingerError::NotFound.into()),
+            StatusAction::Retry | StatusAction::Fail => {
+                return Err(FetchFailure {
+                    error: WebfingerError::HttpStatus(response.status),
+                    retry_after: response.header("Retry-After").and_then(parse_retry_after),
+                })
+            }
+        }
+        let content_type = response.header("Content-Type");
+        let resource = Url::parse(&url).ok().and_then(|url| {
+            url.query_pairs()
+                .find(|(name, _)| name == "resource")
+                .map(|(_, value)| value.into_owned())
+        });
+        let (document, warnings) =
+            parse_document(content_type, &response.body, resource.as_deref())?;
+        Ok(Resolved {
+            document,
+            metadata: ResolveMetadata {
+                url,
+                status: response.status,
+                content_type: content_type.map(String::from),
+                cache_control: response.header("Cache-Control").map(String::from),
+                server: ServerHints::from_response(&response),
+                freshness: Freshness::Fresh,
+                warnings,
+                timings: Timings {
+                    total: start.elapsed(),
+                    ..response.timings
+                },
+            },
+        })
+    };
+
+    #[cfg(feature = "tracing")]
+    let request = {
+        use tracing::Instrument;
+        async move {
+            let result = request.await;
+            match result {
+                Err(ref failure) => {
+                    tracing::debug!(error = ?failure.error, "WebFinger request failed")
+                }
+                Ok(ref resolved) if !resolved.metadata.warnings.is_empty() => {
+                    tracing::debug!(warnings = ?resolved.metadata.warnings, "Broken WebFinger document")
+                }
+                Ok(_) => {}
+            }
+            result
+        }
+        .instrument(span)
+    };
+
+    request.await
+}
+
+/// Tells if a server answered as if the request had no `resource` parameter.
+///
+/// Servers either refuse such requests with a problem document saying that the resource is
+/// missing or required, or
+/// list the resources they know as a JSON array of strings.
+fn reports_missing_resource(response: &HttpResponse) -> bool {
+    let json = match serde_json::from_slice::<serde_json::Value>(&response.body) {
+        Ok(json) => json,
+        Err(_) => return false,
+    };
+    match response.status {
+        // An array of documents is accepted by lenient parsing, only lists of resources are
+        // directories
+        200..=299 => json
+            .as_array()
+            .is_some_and(|items| items.iter().all(serde_json::Value::is_string)),
+        400 => {
+            let is_problem = response
+                .header("Content-Type")
+                .is_some_and(|t| t.starts_with(consts::PROBLEM_JSON_MIME));
+            is_problem
+                && ["title", "detail"].iter().any(|field| {
+                    json[field]
+                        .as_str()
+                        .map(str::to_lowercase)
+                        .is_some_and(|text| {
+                            text.contains("resource")
+                                && (text.contains("missing") || text.contains("required"))
+                        })
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Builds the request to send to fetch the document at `url`.
+pub(crate) fn prepare_request(
+    url: String,
+    options: &RequestOptions,
+) -> Result<HttpRequest, WebfingerError> {
+    let mut url = Url::parse(&url).map_err(|_| WebfingerError::ParseError)?;
+    if !options.query.is_empty() {
+        url.query_pairs_mut().extend_pairs(&options.query);
+    }
+
+    // An `Accept` header in the options replaces the default one
+    let mut headers = vec![];
+    if !options
+        .headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("Accept"))
+    {
+        headers.push((
+            "Accept".to_string(),
+            format!("{}, {}", consts::JRD_MIME, consts::JSON_MIME),
+        ));
+    }
+    headers.extend(options.headers.iter().cloned());
+    if let Some(ref id) = options.request_id {
+        headers.push(("X-Request-Id".to_string(), id.clone()));
+    }
+
+    Ok(HttpRequest {
+        url: url.into(),
+        headers,
+    })
+}
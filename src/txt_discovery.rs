@@ -0,0 +1,77 @@
+use crate::WebfingerError;
+use futures_util::future::BoxFuture;
+use std::{collections::HashMap, sync::Arc};
+use url::Url;
+
+/// The label prepended to a domain to find the TXT records describing its WebFinger endpoint.
+pub const TXT_DISCOVERY_LABEL: &str = "_webfinger";
+
+/// Something that can look up DNS TXT records, to discover alternate WebFinger endpoints.
+///
+/// This crate doesn't depend on a DNS library: implement this trait with the resolver of your
+/// application (`hickory-resolver` for instance), and give it to
+/// [`WebfingerClientBuilder::txt_discovery`](crate::WebfingerClientBuilder::txt_discovery). It
+/// is also implemented for a map of names to records, for static configurations and tests.
+pub trait TxtLookup: Send + Sync {
+    /// Returns the TXT records of `name`, each one as a single string.
+    ///
+    /// `name` is a fully qualified domain name like `_webfinger.example.org`, without the trailing
+    /// dot. A name without records should resolve to an empty list, not to an error.
+    fn lookup_txt<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, WebfingerError>>;
+}
+
+impl<T: TxtLookup + ?Sized> TxtLookup for Arc<T> {
+    fn lookup_txt<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, WebfingerError>> {
+        (**self).lookup_txt(name)
+    }
+}
+
+impl TxtLookup for HashMap<String, Vec<String>> {
+    fn lookup_txt<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, WebfingerError>> {
+        let records = self.get(name).cloned().unwrap_or_default();
+        Box::pin(futures_util::future::ready(Ok(records)))
+    }
+}
+
+/// Finds the alternate endpoint of `url`, the standard WebFinger URL of a resource, in the TXT
+/// records of its host.
+///
+/// The first record that is an absolute URL with the same scheme as `url` is used. If it contains
+/// a `{uri}` placeholder, it is replaced with the resource, so that static hosts can serve one
+/// file per resource. Otherwise, the `resource` parameter is added to its query string. Records
+/// that don't match and lookup failures are ignored: `None` is returned, and the standard URL
+/// should be used.
+pub(crate) async fn discover_endpoint(lookup: &dyn TxtLookup, url: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok()?;
+    let host = parsed.host_str()?;
+    // The resource is kept as it is encoded in the standard URL
+    let resource = parsed
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("resource="))?;
+    let name = format!("{}.{}", TXT_DISCOVERY_LABEL, host.trim_end_matches('.'));
+    let records = lookup.lookup_txt(&name).await.ok()?;
+    records.iter().find_map(|record| {
+        let record = record.trim();
+        let endpoint = if record.contains("{uri}") {
+            record.replace("{uri}", resource)
+        } else {
+            let separator = if record.contains('?') { '&' } else { '?' };
+            format!("{}{}resource={}", record, separator, resource)
+        };
+        let endpoint = Url::parse(&endpoint).ok()?;
+        if endpoint.scheme() != parsed.scheme() || endpoint.host_str().is_none() {
+            return None;
+        }
+        Some(endpoint.into())
+    })
+}
@@ -0,0 +1,31 @@
+//! Tests of the data model, built with every set of features, including
+//! `--no-default-features`: they only use what a types-only build provides.
+
+use crate::{Link, Prefix, Rel, Webfinger};
+
+#[test]
+fn test_types_only_roundtrip() {
+    let json = r#"{
+        "subject": "acct:test@example.org",
+        "aliases": ["https://example.org/@test"],
+        "links": [
+            {"rel": "self", "type": "application/activity+json", "href": "https://example.org/users/test"},
+            {"rel": "http://ostatus.org/schema/1.0/subscribe", "template": "https://example.org/authorize_interaction?uri={uri}"}
+        ]
+    }"#;
+    let webfinger: Webfinger = serde_json::from_str(json).unwrap();
+    assert_eq!(webfinger.subject, "acct:test@example.org");
+    assert_eq!(webfinger.links[0].rel, Rel::SelfRel);
+    assert_eq!(webfinger.links[1].rel, Rel::Subscribe);
+
+    let serialized = serde_json::to_string(&webfinger).unwrap();
+    let parsed: Webfinger = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(parsed, webfinger);
+}
+
+#[test]
+fn test_types_only_helpers() {
+    let link: Link = serde_json::from_str(r#"{"rel": "self"}"#).unwrap();
+    assert_eq!(link.rel, "self");
+    assert_eq!(Prefix::from("acct"), Prefix::Acct);
+}
@@ -0,0 +1,118 @@
+use crate::{Resolved, WebfingerClient, WebfingerError};
+use std::{collections::hash_map::RandomState, fmt, hash::BuildHasher, sync::Arc, time::Duration};
+
+type RefreshHook = Arc<dyn Fn(&str, &Result<Resolved, WebfingerError>) + Send + Sync>;
+
+/// Refreshes the cached documents of a list of resources in the background.
+///
+/// It allows instances to keep the documents of the accounts they often need (the remote accounts
+/// followed by local users for instance) fresh, without making users wait for them. The client
+/// should have a cache (see [`WebfingerClientBuilder::cache`](crate::WebfingerClientBuilder::cache))
+/// for the warmer to be useful, or you can persist the results with
+/// [`CacheWarmer::on_refresh`].
+///
+/// It requires the `tokio` feature.
+#[derive(Clone)]
+pub struct CacheWarmer {
+    client: WebfingerClient,
+    accts: Vec<String>,
+    interval: Duration,
+    jitter: Duration,
+    delay: Duration,
+    on_refresh: Option<RefreshHook>,
+}
+
+impl fmt::Debug for CacheWarmer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CacheWarmer")
+            .field("client", &self.client)
+            .field("accts", &self.accts)
+            .field("interval", &self.interval)
+            .field("jitter", &self.jitter)
+            .field("delay", &self.delay)
+            .finish()
+    }
+}
+
+impl CacheWarmer {
+    /// Creates a warmer refreshing `accts` every hour, making at most one request per second.
+    pub fn new<S: Into<String>>(
+        client: WebfingerClient,
+        accts: impl IntoIterator<Item = S>,
+    ) -> CacheWarmer {
+        CacheWarmer {
+            client,
+            accts: accts.into_iter().map(Into::into).collect(),
+            interval: Duration::from_secs(3600),
+            jitter: Duration::default(),
+            delay: Duration::from_secs(1),
+            on_refresh: None,
+        }
+    }
+
+    /// Sets the time between two refreshes of all the resources.
+    pub fn interval(mut self, interval: Duration) -> CacheWarmer {
+        self.interval = interval;
+        self
+    }
+
+    /// Adds a random delay, up to `jitter`, to each interval.
+    ///
+    /// It avoids refreshing many resources at the same time when several warmers are started
+    /// together.
+    pub fn jitter(mut self, jitter: Duration) -> CacheWarmer {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the minimum delay between two requests, to avoid overloading remote servers.
+    pub fn delay(mut self, delay: Duration) -> CacheWarmer {
+        self.delay = delay;
+        self
+    }
+
+    /// Calls `hook` with the result of every refresh, to persist it for instance.
+    pub fn on_refresh(
+        mut self,
+        hook: impl Fn(&str, &Result<Resolved, WebfingerError>) + Send + Sync + 'static,
+    ) -> CacheWarmer {
+        self.on_refresh = Some(Arc::new(hook));
+        self
+    }
+
+    /// Refreshes all the resources once.
+    pub async fn refresh_all(&self) {
+        for (i, acct) in self.accts.iter().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(self.delay).await;
+            }
+            let result = self.client.refresh(acct.as_str()).await;
+            if let Some(ref hook) = self.on_refresh {
+                hook(acct, &result);
+            }
+        }
+    }
+
+    /// Refreshes the resources forever, waiting for the configured interval between each round.
+    pub async fn run(&self) {
+        loop {
+            self.refresh_all().await;
+            tokio::time::sleep(self.next_wait()).await;
+        }
+    }
+
+    /// The time to wait before the next round, saturating for very long intervals.
+    pub(crate) fn next_wait(&self) -> Duration {
+        self.interval.saturating_add(self.random_jitter())
+    }
+
+    fn random_jitter(&self) -> Duration {
+        let jitter_ms = self.jitter.as_millis() as u64;
+        if jitter_ms == 0 {
+            return Duration::default();
+        }
+        // RandomState is seeded randomly, which is enough for jitter
+        let random = RandomState::new().hash_one(self.accts.len());
+        Duration::from_millis(random % jitter_ms)
+    }
+}
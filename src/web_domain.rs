@@ -0,0 +1,155 @@
+use crate::{parse_resource, Caller, InstanceDomain, Prefix, Resolver, ResolverError, Webfinger};
+use url::Url;
+
+/// A resolver wrapper for instances whose accounts and web interface are on different domains.
+///
+/// Like with the `LOCAL_DOMAIN` and `WEB_DOMAIN` settings of Mastodon, accounts may be named
+/// `someone@example.org` while the instance is served from `social.example.org`. The wrapped
+/// resolver returns the accounts domain as its [`instance_domain`](Resolver::instance_domain),
+/// and this wrapper accepts requests for both domains: `acct:someone@social.example.org` and
+/// `https://social.example.org/@someone` are resolved too, instead of being refused with
+/// [`ResolverError::WrongDomain`].
+///
+/// Subjects are always rewritten to the accounts domain, and the same resource on the web domain
+/// is added to the aliases, so that both names lead to the same account.
+///
+/// The web domain is accepted through [`Resolver::accepts_domain`], that the other wrappers of
+/// this crate forward: a `WebDomain` can be wrapped in them, or wrap them. Custom wrappers should
+/// forward it too.
+#[derive(Debug, Clone)]
+pub struct WebDomain<T> {
+    inner: T,
+    web_domain: InstanceDomain,
+}
+
+impl<T> WebDomain<T> {
+    /// Wraps a resolver, also accepting the resources on `web_domain`.
+    pub fn new(inner: T, web_domain: InstanceDomain) -> WebDomain<T> {
+        WebDomain { inner, web_domain }
+    }
+
+    /// Returns the wrapped resolver.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn rewrite(&self, mut webfinger: Webfinger, local: &InstanceDomain) -> Webfinger {
+        let resource = match parse_resource(&webfinger.subject) {
+            Ok(resource) => resource,
+            Err(_) => return webfinger,
+        };
+        let on = |domain: &InstanceDomain| {
+            format!(
+                "{}:{}@{}",
+                String::from(resource.prefix.clone()),
+                resource.user,
+                domain
+            )
+        };
+        let host = InstanceDomain::new(&resource.host());
+        if host.as_ref() == Ok(&self.web_domain) {
+            webfinger.subject = on(local);
+        } else if host.as_ref() != Ok(local) {
+            return webfinger;
+        }
+
+        let alias = on(&self.web_domain);
+        if !webfinger.aliases.contains(&alias) {
+            webfinger.aliases.push(alias);
+        }
+        webfinger
+    }
+}
+
+impl<R, T: Resolver<R>> Resolver<R> for WebDomain<T> {
+    fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain()
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        *domain == self.web_domain || self.inner.accepts_domain(domain)
+    }
+
+    fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let webfinger = self.inner.find(prefix, acct, resource_repo)?;
+        Ok(self.rewrite(webfinger, &self.instance_domain()))
+    }
+
+    fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let webfinger = self
+            .inner
+            .find_for_caller(prefix, acct, caller, resource_repo)?;
+        Ok(self.rewrite(webfinger, &self.instance_domain()))
+    }
+
+    fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        let webfinger = self.inner.find_by_url(url, resource_repo)?;
+        Ok(self.rewrite(webfinger, &self.instance_domain()))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, R> crate::AsyncResolver<R> for WebDomain<T>
+where
+    T: crate::AsyncResolver<R> + Send + Sync,
+    R: Send,
+{
+    async fn instance_domain(&self) -> InstanceDomain {
+        self.inner.instance_domain().await
+    }
+
+    fn filter_by_rels(&self) -> bool {
+        self.inner.filter_by_rels()
+    }
+
+    async fn accepts_domain(&self, domain: &InstanceDomain) -> bool {
+        *domain == self.web_domain || self.inner.accepts_domain(domain).await
+    }
+
+    async fn find(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let webfinger = self.inner.find(prefix, acct, resource_repo).await?;
+        let domain = self.inner.instance_domain().await;
+        Ok(self.rewrite(webfinger, &domain))
+    }
+
+    async fn find_for_caller(
+        &self,
+        prefix: Prefix,
+        acct: String,
+        caller: Option<&Caller>,
+        resource_repo: R,
+    ) -> Result<Webfinger, ResolverError> {
+        let webfinger = self
+            .inner
+            .find_for_caller(prefix, acct, caller, resource_repo)
+            .await?;
+        let domain = self.inner.instance_domain().await;
+        Ok(self.rewrite(webfinger, &domain))
+    }
+
+    async fn find_by_url(&self, url: &Url, resource_repo: R) -> Result<Webfinger, ResolverError> {
+        let webfinger = self.inner.find_by_url(url, resource_repo).await?;
+        let domain = self.inner.instance_domain().await;
+        Ok(self.rewrite(webfinger, &domain))
+    }
+}